@@ -15,6 +15,16 @@ use crate::{
 use itertools::Itertools;
 use std::collections::HashMap;
 
+mod analysis;
+mod dot;
+mod named;
+mod optimize;
+pub use analysis::{CircuitAnalysis, GateCounts};
+pub use dot::DotCollapse;
+pub(crate) use named::{decode_named, encode_named};
+pub use named::{NamedGroup, ValueType};
+pub use optimize::OptimizerStats;
+
 /// The index and modulus of a gate in a circuit.
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
@@ -46,6 +56,18 @@ pub struct Circuit {
     pub(crate) const_refs: Vec<CircuitRef>,
     pub(crate) output_refs: Vec<CircuitRef>,
     pub(crate) num_nonfree_gates: usize,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) garbler_input_groups: Vec<NamedGroup>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) evaluator_input_groups: Vec<NamedGroup>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) output_groups: Vec<NamedGroup>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) scopes: Vec<String>,
+    /// The index into `scopes` of the scope each gate was created in. Empty if
+    /// the circuit does not record scopes.
+    #[cfg_attr(feature = "serde1", serde(default))]
+    pub(crate) gate_scopes: Vec<Option<usize>>,
 }
 
 /// The most basic types of computation supported by fancy garbling.
@@ -91,6 +113,13 @@ pub(crate) enum Gate {
         id: usize,
         out: Option<usize>,
     },
+    Proj2 {
+        xref: CircuitRef,
+        yref: CircuitRef,
+        tt: Vec<u16>,
+        id: usize,
+        out: Option<usize>,
+    },
 }
 
 impl std::fmt::Display for Gate {
@@ -111,6 +140,17 @@ impl std::fmt::Display for Gate {
             Gate::Proj { xref, tt, id, out } => {
                 write!(f, "Proj ( {}, {:?}, {}, {:?} )", xref, tt, id, out)
             }
+            Gate::Proj2 {
+                xref,
+                yref,
+                tt,
+                id,
+                out,
+            } => write!(
+                f,
+                "Proj2 ( {}, {}, {:?}, {}, {:?} )",
+                xref, yref, tt, id, out
+            ),
         }
     }
 }
@@ -127,6 +167,11 @@ impl Circuit {
             output_refs: Vec::new(),
             gate_moduli: Vec::new(),
             num_nonfree_gates: 0,
+            garbler_input_groups: Vec::new(),
+            evaluator_input_groups: Vec::new(),
+            output_groups: Vec::new(),
+            scopes: Vec::new(),
+            gate_scopes: Vec::new(),
         }
     }
 
@@ -195,6 +240,25 @@ impl Circuit {
                         Some(tt.to_vec()),
                     )?,
                 ),
+                Gate::Proj2 {
+                    xref,
+                    yref,
+                    ref tt,
+                    out,
+                    ..
+                } => (
+                    out,
+                    f.proj2(
+                        cache[xref.ix]
+                            .as_ref()
+                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
+                        cache[yref.ix]
+                            .as_ref()
+                            .ok_or_else(|| F::Error::from(FancyError::UninitializedValue))?,
+                        q,
+                        Some(tt.to_vec()),
+                    )?,
+                ),
                 Gate::Mul {
                     xref, yref, out, ..
                 } => (
@@ -320,6 +384,7 @@ pub struct CircuitBuilder {
     next_garbler_input_id: usize,
     next_evaluator_input_id: usize,
     const_map: HashMap<(u16, u16), CircuitRef>,
+    scope: Option<usize>,
     circ: Circuit,
 }
 
@@ -394,6 +459,28 @@ impl Fancy for CircuitBuilder {
         Ok(self.gate(gate, output_modulus))
     }
 
+    fn proj2(
+        &mut self,
+        xref: &CircuitRef,
+        yref: &CircuitRef,
+        output_modulus: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<CircuitRef, Self::Error> {
+        let tt = tt.ok_or_else(|| Self::Error::from(FancyError::NoTruthTable))?;
+        let nrows = xref.modulus() as usize * yref.modulus() as usize;
+        if tt.len() < nrows || !tt.iter().all(|&x| x < output_modulus) {
+            return Err(Self::Error::from(FancyError::InvalidTruthTable));
+        }
+        let gate = Gate::Proj2 {
+            xref: *xref,
+            yref: *yref,
+            tt: tt[..nrows].to_vec(),
+            id: self.get_next_ciphertext_id(),
+            out: None,
+        };
+        Ok(self.gate(gate, output_modulus))
+    }
+
     fn mul(&mut self, xref: &CircuitRef, yref: &CircuitRef) -> Result<CircuitRef, Self::Error> {
         if xref.modulus() < yref.modulus() {
             return self.mul(yref, xref);
@@ -424,6 +511,7 @@ impl CircuitBuilder {
             next_garbler_input_id: 0,
             next_evaluator_input_id: 0,
             const_map: HashMap::new(),
+            scope: None,
             circ: Circuit::new(None),
         }
     }
@@ -460,6 +548,7 @@ impl CircuitBuilder {
     fn gate(&mut self, gate: Gate, modulus: u16) -> CircuitRef {
         self.circ.gates.push(gate);
         self.circ.gate_moduli.push(modulus);
+        self.circ.gate_scopes.push(self.scope);
         let ix = self.get_next_ref_ix();
         CircuitRef { ix, modulus }
     }
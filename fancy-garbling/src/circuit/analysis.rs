@@ -0,0 +1,372 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Depth and cost analysis of `Circuit`s.
+//!
+//! Gates created inside `CircuitBuilder::scope` remember the scope they belong
+//! to, so that the cost of a circuit can be attributed to the gadgets that
+//! built it.
+
+use super::{Circuit, CircuitBuilder, CircuitRef, Gate};
+use crate::{fancy::HasModulus, garble::mul_table_size};
+use std::collections::BTreeMap;
+
+impl Gate {
+    /// The wires this gate reads.
+    pub(super) fn args(&self) -> Vec<CircuitRef> {
+        match *self {
+            Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } | Gate::Constant { .. } => {
+                Vec::new()
+            }
+            Gate::Cmul { xref, .. } | Gate::Proj { xref, .. } => vec![xref],
+            Gate::Add { xref, yref, .. }
+            | Gate::Sub { xref, yref, .. }
+            | Gate::Mul { xref, yref, .. }
+            | Gate::Proj2 { xref, yref, .. } => vec![xref, yref],
+        }
+    }
+
+    /// The wire this gate writes, if it is not the gate's own index.
+    pub(super) fn out(&self) -> Option<usize> {
+        match *self {
+            Gate::Add { out, .. }
+            | Gate::Sub { out, .. }
+            | Gate::Cmul { out, .. }
+            | Gate::Mul { out, .. }
+            | Gate::Proj { out, .. }
+            | Gate::Proj2 { out, .. } => out,
+            _ => None,
+        }
+    }
+
+    /// Whether this gate needs a garbled table.
+    pub(super) fn is_nonfree(&self) -> bool {
+        matches!(
+            self,
+            Gate::Mul { .. } | Gate::Proj { .. } | Gate::Proj2 { .. }
+        )
+    }
+
+    /// The number of ciphertexts in this gate's garbled table.
+    pub(super) fn num_ciphertexts(&self) -> usize {
+        match self {
+            Gate::Mul { xref, yref, .. } => {
+                let (p, q) = (xref.modulus(), yref.modulus());
+                mul_table_size(p.max(q), p.min(q))
+            }
+            Gate::Proj { xref, .. } => xref.modulus() as usize - 1,
+            Gate::Proj2 { xref, yref, .. } => xref.modulus() as usize * yref.modulus() as usize - 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Counts of the non-free gates with a given output modulus.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GateCounts {
+    nmuls: usize,
+    nprojs: usize,
+    nciphertexts: usize,
+}
+
+impl GateCounts {
+    /// Number of multiplication gates.
+    pub fn num_muls(&self) -> usize {
+        self.nmuls
+    }
+
+    /// Number of projection gates, including two-argument projections.
+    pub fn num_projs(&self) -> usize {
+        self.nprojs
+    }
+
+    /// Number of garbled-table ciphertexts of these gates.
+    pub fn num_ciphertexts(&self) -> usize {
+        self.nciphertexts
+    }
+}
+
+/// The shape of a circuit: its depth, the width of each level, and where its
+/// garbled tables come from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitAnalysis {
+    multiplicative_depth: usize,
+    critical_path: usize,
+    level_widths: Vec<usize>,
+    moduli: BTreeMap<u16, GateCounts>,
+}
+
+impl CircuitAnalysis {
+    /// The largest number of non-free gates on any path from an input to an
+    /// output.
+    pub fn multiplicative_depth(&self) -> usize {
+        self.multiplicative_depth
+    }
+
+    /// The largest number of gates on any path from an input to an output.
+    pub fn critical_path(&self) -> usize {
+        self.critical_path
+    }
+
+    /// The number of gates at each topological level. Inputs and constants
+    /// are at level 0, and every other gate is one level above its deepest
+    /// argument.
+    pub fn level_widths(&self) -> &[usize] {
+        &self.level_widths
+    }
+
+    /// Counts of non-free gates, keyed by output modulus.
+    pub fn moduli(&self) -> &BTreeMap<u16, GateCounts> {
+        &self.moduli
+    }
+}
+
+impl std::fmt::Display for CircuitAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "circuit analysis:")?;
+        writeln!(
+            f,
+            "  multiplicative depth: {:10}",
+            self.multiplicative_depth
+        )?;
+        writeln!(f, "  critical path:        {:10}", self.critical_path)?;
+        if let Some((level, width)) = self
+            .level_widths
+            .iter()
+            .enumerate()
+            .max_by_key(|(level, &width)| (width, std::cmp::Reverse(*level)))
+        {
+            writeln!(
+                f,
+                "  levels:               {:10} // widest is level {} with {} gates",
+                self.level_widths.len(),
+                level,
+                width
+            )?;
+        }
+        writeln!(f, "  modulus       muls      projs  ciphertexts")?;
+        for (q, counts) in self.moduli.iter() {
+            writeln!(
+                f,
+                "  {:7} {:10} {:10} {:12}",
+                q, counts.nmuls, counts.nprojs, counts.nciphertexts
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Circuit {
+    /// The number of wire slots the gates of this circuit write to.
+    pub(super) fn num_slots(&self) -> usize {
+        self.gates
+            .iter()
+            .filter_map(Gate::out)
+            .map(|ix| ix + 1)
+            .chain(std::iter::once(self.gates.len()))
+            .max()
+            .unwrap()
+    }
+
+    /// The topological level of each gate. See `CircuitAnalysis::level_widths`.
+    pub(super) fn gate_levels(&self) -> Vec<usize> {
+        let mut slots = vec![0; self.num_slots()];
+        let mut levels = Vec::with_capacity(self.gates.len());
+        for (i, gate) in self.gates.iter().enumerate() {
+            let args = gate.args();
+            let level = args.iter().map(|r| slots[r.ix] + 1).max().unwrap_or(0);
+            slots[gate.out().unwrap_or(i)] = level;
+            levels.push(level);
+        }
+        levels
+    }
+
+    /// The names of the scopes gates were created in, with nested scopes
+    /// separated by `/`.
+    pub fn scopes(&self) -> &[String] {
+        &self.scopes
+    }
+
+    /// The name of the scope the gate indexed by `i` was created in, if any.
+    pub fn gate_scope(&self, i: usize) -> Option<&str> {
+        self.gate_scopes
+            .get(i)
+            .cloned()
+            .flatten()
+            .map(|s| self.scopes[s].as_str())
+    }
+
+    /// Compute the depth of the circuit, the width of each of its levels, and
+    /// the number of non-free gates per modulus.
+    pub fn analyze(&self) -> CircuitAnalysis {
+        let levels = self.gate_levels();
+        let mut slot_levels = vec![0; self.num_slots()];
+        let mut slot_depths = vec![0; self.num_slots()];
+        let mut level_widths = vec![0; levels.iter().max().map_or(0, |l| l + 1)];
+        let mut moduli = BTreeMap::new();
+
+        for (i, gate) in self.gates.iter().enumerate() {
+            let depth = gate
+                .args()
+                .iter()
+                .map(|r| slot_depths[r.ix])
+                .max()
+                .unwrap_or(0)
+                + gate.is_nonfree() as usize;
+            let slot = gate.out().unwrap_or(i);
+            slot_levels[slot] = levels[i];
+            slot_depths[slot] = depth;
+            level_widths[levels[i]] += 1;
+
+            if gate.is_nonfree() {
+                let counts: &mut GateCounts = moduli.entry(self.modulus(i)).or_default();
+                if let Gate::Mul { .. } = gate {
+                    counts.nmuls += 1;
+                } else {
+                    counts.nprojs += 1;
+                }
+                counts.nciphertexts += gate.num_ciphertexts();
+            }
+        }
+
+        let max_over_outputs =
+            |xs: &[usize]| self.output_refs.iter().map(|r| xs[r.ix]).max().unwrap_or(0);
+        CircuitAnalysis {
+            multiplicative_depth: max_over_outputs(&slot_depths),
+            critical_path: max_over_outputs(&slot_levels),
+            level_widths,
+            moduli,
+        }
+    }
+}
+
+impl CircuitBuilder {
+    /// Run `f`, attributing the gates it creates to a scope named `name`.
+    ///
+    /// Scopes nest: a scope `"sbox"` opened inside a scope `"aes"` is named
+    /// `"aes/sbox"`. Opening a scope with the same name again adds to it.
+    pub fn scope<T, G: FnOnce(&mut Self) -> T>(&mut self, name: &str, f: G) -> T {
+        let outer = self.scope;
+        let name = match outer {
+            Some(s) => format!("{}/{}", self.circ.scopes[s], name),
+            None => name.to_string(),
+        };
+        let ix = match self.circ.scopes.iter().position(|s| *s == name) {
+            Some(ix) => ix,
+            None => {
+                self.circ.scopes.push(name);
+                self.circ.scopes.len() - 1
+            }
+        };
+        self.scope = Some(ix);
+        let result = f(self);
+        self.scope = outer;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::{Circuit, CircuitBuilder},
+        dummy::Dummy,
+        fancy::{BinaryGadgets, Fancy, FancyInput},
+        informer::Informer,
+    };
+
+    // z = (x * y + x) projected to mod 5, with an unused multiplication.
+    fn circuit() -> Circuit {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let y = b.evaluator_input(3);
+        let z = b
+            .scope("mul", |b| {
+                let xy = b.mul(&x, &y)?;
+                b.scope("inner", |b| b.mul(&x, &x))?;
+                b.add(&xy, &x)
+            })
+            .unwrap();
+        let z = b.proj(&z, 5, Some(vec![0, 1, 4])).unwrap();
+        b.output(&z).unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn analyze() {
+        let a = circuit().analyze();
+        assert_eq!(a.multiplicative_depth(), 2);
+        assert_eq!(a.critical_path(), 3);
+        assert_eq!(a.level_widths(), &[2, 2, 1, 1]);
+        assert_eq!(a.moduli()[&3].num_muls(), 2);
+        assert_eq!(a.moduli()[&3].num_ciphertexts(), 8);
+        assert_eq!(a.moduli()[&5].num_projs(), 1);
+        assert_eq!(a.moduli()[&5].num_ciphertexts(), 2);
+        assert!(a.to_string().contains("widest is level 0 with 2 gates"));
+
+        // Bristol circuits write their gates to explicit wires.
+        let c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let a = c.analyze();
+        assert_eq!(a.level_widths().iter().sum::<usize>(), c.gates.len());
+        assert_eq!(a.moduli()[&2].num_muls(), 6800);
+        assert!(a.multiplicative_depth() <= a.critical_path());
+    }
+
+    #[test]
+    fn unequal_moduli() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(17);
+        let y = b.evaluator_input(11);
+        let z = b.mul(&x, &y).unwrap();
+        b.output(&z).unwrap();
+        let c = b.finish();
+
+        // The minitable of 11 entries takes two extra ciphertexts, as in the
+        // garbler, the optimizer statistics and the informer.
+        assert_eq!(c.analyze().moduli()[&17].num_ciphertexts(), 28);
+        assert_eq!(c.optimized().1.num_ciphertexts_before(), 28);
+        let mut informer = Informer::new(Dummy::new());
+        let gb = informer.receive(17).unwrap();
+        let ev = informer.receive(11).unwrap();
+        c.eval(&mut informer, &[gb], &[ev]).unwrap();
+        assert_eq!(informer.stats().num_ciphertexts(), 28);
+    }
+
+    #[test]
+    fn scopes() {
+        let c = circuit();
+        assert_eq!(c.scopes(), &["mul".to_string(), "mul/inner".to_string()]);
+        let scopes = (0..c.gates.len())
+            .map(|i| c.gate_scope(i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scopes,
+            vec![
+                None,
+                None,
+                Some("mul"),
+                Some("mul/inner"),
+                Some("mul"),
+                None
+            ]
+        );
+
+        // The optimizer drops the unused gate but keeps the scopes of the rest.
+        let opt = c.optimized().0;
+        let scopes = (0..opt.gates.len())
+            .map(|i| opt.gate_scope(i))
+            .collect::<Vec<_>>();
+        assert_eq!(scopes, vec![None, None, Some("mul"), Some("mul"), None]);
+
+        let mut b = CircuitBuilder::new();
+        let xs = b.bin_garbler_input(8);
+        let ys = b.bin_evaluator_input(8);
+        b.scope("add", |b| b.bin_addition_no_carry(&xs, &ys))
+            .unwrap();
+        b.scope("add", |b| b.bin_addition_no_carry(&xs, &ys))
+            .unwrap();
+        assert_eq!(b.finish().scopes().len(), 1);
+    }
+}
@@ -0,0 +1,257 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Export of `Circuit`s as Graphviz DOT graphs.
+
+use super::{Circuit, Gate};
+use std::{collections::BTreeMap, io::Write};
+
+/// How `Circuit::write_dot` turns gates into nodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DotCollapse {
+    /// One node per gate, with the gates of each scope drawn in a cluster.
+    Gates,
+    /// One node per scope, as created by `CircuitBuilder::scope`.
+    Scopes,
+    /// One node per topological level.
+    Levels,
+}
+
+/// Escape `s` for use inside a quoted DOT string.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn gate_label(gate: &Gate) -> String {
+    match gate {
+        Gate::GarblerInput { id } => format!("garbler input {}", id),
+        Gate::EvaluatorInput { id } => format!("evaluator input {}", id),
+        Gate::Constant { val } => format!("constant {}", val),
+        Gate::Add { .. } => "add".to_string(),
+        Gate::Sub { .. } => "sub".to_string(),
+        Gate::Cmul { c, .. } => format!("cmul {}", c),
+        Gate::Mul { .. } => "mul".to_string(),
+        Gate::Proj { .. } => "proj".to_string(),
+        Gate::Proj2 { .. } => "proj2".to_string(),
+    }
+}
+
+/// The gate writing each wire slot.
+fn producers(c: &Circuit) -> Vec<usize> {
+    let mut producers = vec![0; c.num_slots()];
+    for (i, gate) in c.gates.iter().enumerate() {
+        producers[gate.out().unwrap_or(i)] = i;
+    }
+    producers
+}
+
+impl Circuit {
+    /// Write the circuit to `writer` as a Graphviz DOT graph, with gates
+    /// collapsed into nodes according to `collapse`.
+    ///
+    /// Non-free gates are shaded. Collapsed nodes are labelled with their
+    /// number of gates and ciphertexts, and edges between them with the number
+    /// of wires they carry.
+    pub fn write_dot<W: Write>(
+        &self,
+        writer: &mut W,
+        collapse: DotCollapse,
+    ) -> std::io::Result<()> {
+        writeln!(writer, "digraph circuit {{")?;
+        match collapse {
+            DotCollapse::Gates => self.write_dot_gates(writer)?,
+            DotCollapse::Scopes => {
+                let groups = (0..self.gates.len())
+                    .map(|i| {
+                        self.gate_scopes
+                            .get(i)
+                            .cloned()
+                            .flatten()
+                            .map_or(0, |s| s + 1)
+                    })
+                    .collect::<Vec<usize>>();
+                let names = std::iter::once("top level".to_string())
+                    .chain(self.scopes.iter().cloned())
+                    .collect::<Vec<String>>();
+                self.write_dot_groups(writer, &groups, &names)?
+            }
+            DotCollapse::Levels => {
+                let groups = self.gate_levels();
+                let names = (0..groups.iter().max().map_or(0, |l| l + 1))
+                    .map(|l| format!("level {}", l))
+                    .collect::<Vec<String>>();
+                self.write_dot_groups(writer, &groups, &names)?
+            }
+        }
+        writeln!(writer, "}}")
+    }
+
+    /// Return the circuit as a Graphviz DOT graph. See `Circuit::write_dot`.
+    pub fn to_dot(&self, collapse: DotCollapse) -> String {
+        let mut bytes = Vec::new();
+        self.write_dot(&mut bytes, collapse)
+            .expect("writing to a vector never fails");
+        String::from_utf8(bytes).expect("DOT output is valid UTF-8")
+    }
+
+    fn write_dot_gates<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        let producers = producers(self);
+        for (i, gate) in self.gates.iter().enumerate() {
+            let style = match gate {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } => ", shape=box",
+                _ if gate.is_nonfree() => ", style=filled, fillcolor=lightgray",
+                _ => "",
+            };
+            writeln!(
+                writer,
+                "  g{} [label=\"{}\\nmod {}\"{}];",
+                i,
+                gate_label(gate),
+                self.modulus(i),
+                style
+            )?;
+            for r in gate.args() {
+                writeln!(writer, "  g{} -> g{};", producers[r.ix], i)?;
+            }
+        }
+        for (s, name) in self.scopes.iter().enumerate() {
+            let gates = (0..self.gates.len())
+                .filter(|&i| self.gate_scopes.get(i) == Some(&Some(s)))
+                .collect::<Vec<usize>>();
+            if gates.is_empty() {
+                continue;
+            }
+            writeln!(writer, "  subgraph cluster_{} {{", s)?;
+            writeln!(writer, "    label=\"{}\";", escape(name))?;
+            for i in gates {
+                writeln!(writer, "    g{};", i)?;
+            }
+            writeln!(writer, "  }}")?;
+        }
+        for (j, r) in self.output_refs.iter().enumerate() {
+            writeln!(
+                writer,
+                "  out{} [label=\"output {}\", shape=plaintext];",
+                j, j
+            )?;
+            writeln!(writer, "  g{} -> out{};", producers[r.ix], j)?;
+        }
+        Ok(())
+    }
+
+    /// Write one node per group, where gate `i` belongs to group `groups[i]`
+    /// named `names[groups[i]]`.
+    fn write_dot_groups<W: Write>(
+        &self,
+        writer: &mut W,
+        groups: &[usize],
+        names: &[String],
+    ) -> std::io::Result<()> {
+        let producers = producers(self);
+        let mut ngates = vec![0; names.len()];
+        let mut nciphertexts = vec![0; names.len()];
+        let mut edges = BTreeMap::new();
+        for (i, gate) in self.gates.iter().enumerate() {
+            ngates[groups[i]] += 1;
+            nciphertexts[groups[i]] += gate.num_ciphertexts();
+            for r in gate.args() {
+                let from = groups[producers[r.ix]];
+                if from != groups[i] {
+                    *edges.entry((from, groups[i])).or_insert(0) += 1;
+                }
+            }
+        }
+        let mut outputs = BTreeMap::new();
+        for r in self.output_refs.iter() {
+            *outputs.entry(groups[producers[r.ix]]).or_insert(0) += 1;
+        }
+
+        for (k, name) in names.iter().enumerate() {
+            if ngates[k] == 0 {
+                continue;
+            }
+            let style = if nciphertexts[k] > 0 {
+                ", style=filled, fillcolor=lightgray"
+            } else {
+                ""
+            };
+            writeln!(
+                writer,
+                "  n{} [label=\"{}\\n{} gates, {} ciphertexts\", shape=box{}];",
+                k,
+                escape(name),
+                ngates[k],
+                nciphertexts[k],
+                style
+            )?;
+        }
+        for ((from, to), nwires) in edges {
+            writeln!(writer, "  n{} -> n{} [label=\"{}\"];", from, to, nwires)?;
+        }
+        writeln!(writer, "  outputs [shape=plaintext];")?;
+        for (from, nwires) in outputs {
+            writeln!(writer, "  n{} -> outputs [label=\"{}\"];", from, nwires)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DotCollapse;
+    use crate::{
+        circuit::{Circuit, CircuitBuilder},
+        fancy::Fancy,
+    };
+
+    fn circuit() -> Circuit {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let y = b.evaluator_input(3);
+        let z = b
+            .scope("\"square\"", |b| {
+                let xx = b.mul(&x, &x)?;
+                b.add(&xx, &y)
+            })
+            .unwrap();
+        let z = b.proj(&z, 2, Some(vec![0, 1, 1])).unwrap();
+        b.output(&z).unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn gates() {
+        let dot = circuit().to_dot(DotCollapse::Gates);
+        assert!(dot.starts_with("digraph circuit {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("  g2 [label=\"mul\\nmod 3\", style=filled, fillcolor=lightgray];"));
+        assert!(dot.contains("  g0 -> g2;\n  g0 -> g2;\n"));
+        assert!(dot.contains("    label=\"\\\"square\\\"\";\n    g2;\n    g3;\n"));
+        assert!(dot.contains("  g4 -> out0;"));
+
+        // Bristol circuits write their gates to explicit wires.
+        let c = Circuit::parse("circuits/adder_32bit.txt").unwrap();
+        let dot = c.to_dot(DotCollapse::Gates);
+        assert_eq!(dot.matches("-> out").count(), c.noutputs());
+    }
+
+    #[test]
+    fn collapsed() {
+        let c = circuit();
+        let dot = c.to_dot(DotCollapse::Scopes);
+        assert!(dot.contains("  n0 [label=\"top level\\n3 gates, 2 ciphertexts\""));
+        assert!(dot.contains("  n1 [label=\"\\\"square\\\"\\n2 gates, 4 ciphertexts\""));
+        assert!(dot.contains("  n0 -> n1 [label=\"3\"];"));
+        assert!(dot.contains("  n1 -> n0 [label=\"1\"];"));
+        assert!(dot.contains("  n0 -> outputs [label=\"1\"];"));
+
+        let dot = c.to_dot(DotCollapse::Levels);
+        assert!(dot.contains("  n0 [label=\"level 0\\n2 gates, 0 ciphertexts\", shape=box];"));
+        assert!(dot.contains("  n0 -> n1 [label=\"2\"];"));
+        assert!(dot.contains("  n0 -> n2 [label=\"1\"];"));
+        assert!(dot.contains("  n3 -> outputs [label=\"1\"];"));
+    }
+}
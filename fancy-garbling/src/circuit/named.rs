@@ -0,0 +1,414 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Named, typed groups of circuit inputs and outputs.
+//!
+//! `CircuitBuilder` records a `NamedGroup` for each input or output created by
+//! name, so that callers can pass and receive integers by name instead of
+//! keeping track of which wires hold which value.
+
+use super::{Circuit, CircuitBuilder, CircuitRef};
+use crate::{
+    errors::{CircuitBuilderError, DummyError, FancyError},
+    fancy::{Bundle, Fancy},
+    util,
+};
+use std::{collections::HashMap, fmt, ops::Range};
+
+/// How the value of a named group is laid out on its wires.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValueType {
+    /// A single wire with the given modulus.
+    Mod(u16),
+    /// An unsigned integer of the given number of bits, least significant bit
+    /// first.
+    Binary(usize),
+    /// An integer modulo the given composite modulus, in CRT representation.
+    Crt(u128),
+}
+
+impl ValueType {
+    /// The moduli of the wires holding a value of this type.
+    pub fn moduli(&self) -> Vec<u16> {
+        match *self {
+            ValueType::Mod(q) => vec![q],
+            ValueType::Binary(nbits) => vec![2; nbits],
+            ValueType::Crt(q) => util::factor(q),
+        }
+    }
+
+    /// Encode `value` as the values of the wires.
+    pub fn encode(&self, value: u128) -> Result<Vec<u16>, FancyError> {
+        let fits = match *self {
+            ValueType::Mod(q) => value < q as u128,
+            ValueType::Binary(nbits) => nbits >= 128 || value >> nbits == 0,
+            ValueType::Crt(q) => value < q,
+        };
+        if !fits {
+            return Err(FancyError::InvalidArg(format!(
+                "{} does not fit in {}",
+                value, self
+            )));
+        }
+        Ok(match *self {
+            ValueType::Mod(_) => vec![value as u16],
+            ValueType::Binary(nbits) => util::u128_to_bits(value, nbits),
+            ValueType::Crt(q) => util::crt_factor(value, q),
+        })
+    }
+
+    /// Decode a value from the values of its wires.
+    pub fn decode(&self, xs: &[u16]) -> u128 {
+        match *self {
+            ValueType::Mod(_) => xs[0] as u128,
+            ValueType::Binary(_) => util::u128_from_bits(xs),
+            ValueType::Crt(q) => util::crt_inv_factor(xs, q),
+        }
+    }
+}
+
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueType::Mod(q) => write!(f, "mod {}", q),
+            ValueType::Binary(nbits) => write!(f, "{}-bit binary", nbits),
+            ValueType::Crt(q) => write!(f, "CRT mod {}", q),
+        }
+    }
+}
+
+/// A named group of consecutive inputs or outputs of a circuit.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde1", serde(try_from = "UncheckedNamedGroup"))]
+pub struct NamedGroup {
+    name: String,
+    ty: ValueType,
+    start: usize,
+}
+
+/// A `NamedGroup` as deserialized, before it is checked by `NamedGroup::new`.
+#[cfg(feature = "serde1")]
+#[derive(serde::Deserialize)]
+struct UncheckedNamedGroup {
+    name: String,
+    ty: ValueType,
+    start: usize,
+}
+
+#[cfg(feature = "serde1")]
+impl std::convert::TryFrom<UncheckedNamedGroup> for NamedGroup {
+    type Error = FancyError;
+
+    fn try_from(g: UncheckedNamedGroup) -> Result<Self, FancyError> {
+        NamedGroup::new(g.name, g.ty, g.start)
+    }
+}
+
+impl NamedGroup {
+    /// Make a group of type `ty` starting at position `start`, checking that
+    /// binary values are at most 128 bits and that the positions of the
+    /// group's wires do not overflow.
+    pub(crate) fn new(name: String, ty: ValueType, start: usize) -> Result<Self, FancyError> {
+        if let ValueType::Binary(nbits) = ty {
+            if nbits > 128 {
+                return Err(FancyError::InvalidArg(format!(
+                    "{} values are wider than 128 bits",
+                    ty
+                )));
+            }
+        }
+        if start.checked_add(ty.moduli().len()).is_none() {
+            return Err(FancyError::InvalidArg(format!(
+                "group \"{}\" starts at {}, past the last position",
+                name, start
+            )));
+        }
+        Ok(NamedGroup { name, ty, start })
+    }
+
+    /// The name of the group.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The type of the value held by the group.
+    pub fn value_type(&self) -> ValueType {
+        self.ty
+    }
+
+    /// The positions of the group's wires among the inputs or outputs.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.start + self.ty.moduli().len()
+    }
+}
+
+impl fmt::Display for NamedGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.ty)
+    }
+}
+
+/// Append a group named `name` to `groups`, starting at position `start`.
+fn push_group(
+    groups: &mut Vec<NamedGroup>,
+    name: &str,
+    ty: ValueType,
+    start: usize,
+) -> Result<(), FancyError> {
+    if groups.iter().any(|g| g.name == name) {
+        return Err(FancyError::InvalidArg(format!(
+            "duplicate name \"{}\"",
+            name
+        )));
+    }
+    groups.push(NamedGroup::new(name.to_string(), ty, start)?);
+    Ok(())
+}
+
+/// Encode the named `values` as the values of `nwires` wires, which must be
+/// covered by `groups`, with a value for each group.
+pub(crate) fn encode_named(
+    groups: &[NamedGroup],
+    nwires: usize,
+    values: &[(&str, u128)],
+) -> Result<Vec<u16>, FancyError> {
+    if groups.iter().map(|g| g.range().len()).sum::<usize>() != nwires {
+        return Err(FancyError::InvalidArg(
+            "not every wire belongs to a named group".to_string(),
+        ));
+    }
+    if let Some((name, _)) = values
+        .iter()
+        .find(|(name, _)| groups.iter().all(|g| g.name != *name))
+    {
+        return Err(FancyError::InvalidArg(format!("unknown name \"{}\"", name)));
+    }
+    let mut xs = vec![0; nwires];
+    for g in groups {
+        let mut matches = values.iter().filter(|(name, _)| *name == g.name);
+        let value = match (matches.next(), matches.next()) {
+            (Some((_, value)), None) => *value,
+            (None, _) => {
+                return Err(FancyError::InvalidArg(format!(
+                    "no value for \"{}\"",
+                    g.name
+                )))
+            }
+            (Some(_), Some(_)) => {
+                return Err(FancyError::InvalidArg(format!(
+                    "more than one value for \"{}\"",
+                    g.name
+                )))
+            }
+        };
+        xs[g.range()].copy_from_slice(&g.ty.encode(value)?);
+    }
+    Ok(xs)
+}
+
+/// Decode the values of the named `groups` from the values of their wires.
+pub(crate) fn decode_named(groups: &[NamedGroup], xs: &[u16]) -> HashMap<String, u128> {
+    groups
+        .iter()
+        .map(|g| (g.name.clone(), g.ty.decode(&xs[g.range()])))
+        .collect()
+}
+
+impl Circuit {
+    /// The named groups of garbler inputs.
+    pub fn garbler_input_groups(&self) -> &[NamedGroup] {
+        &self.garbler_input_groups
+    }
+
+    /// The named groups of evaluator inputs.
+    pub fn evaluator_input_groups(&self) -> &[NamedGroup] {
+        &self.evaluator_input_groups
+    }
+
+    /// The named groups of outputs.
+    pub fn output_groups(&self) -> &[NamedGroup] {
+        &self.output_groups
+    }
+
+    /// Evaluate the circuit in plaintext on named inputs, returning the values
+    /// of the named outputs.
+    ///
+    /// Every input must belong to a named group, and every group needs a value.
+    pub fn eval_plain_named(
+        &self,
+        garbler_inputs: &[(&str, u128)],
+        evaluator_inputs: &[(&str, u128)],
+    ) -> Result<HashMap<String, u128>, DummyError> {
+        let gb = encode_named(
+            &self.garbler_input_groups,
+            self.num_garbler_inputs(),
+            garbler_inputs,
+        )?;
+        let ev = encode_named(
+            &self.evaluator_input_groups,
+            self.num_evaluator_inputs(),
+            evaluator_inputs,
+        )?;
+        let outputs = self.eval_plain(&gb, &ev)?;
+        Ok(decode_named(&self.output_groups, &outputs))
+    }
+}
+
+impl CircuitBuilder {
+    /// Create a garbler input of type `ty` named `name`.
+    pub fn named_garbler_input(
+        &mut self,
+        name: &str,
+        ty: ValueType,
+    ) -> Result<Bundle<CircuitRef>, CircuitBuilderError> {
+        let start = self.circ.num_garbler_inputs();
+        push_group(&mut self.circ.garbler_input_groups, name, ty, start)?;
+        Ok(Bundle::new(self.garbler_inputs(&ty.moduli())))
+    }
+
+    /// Create an evaluator input of type `ty` named `name`.
+    pub fn named_evaluator_input(
+        &mut self,
+        name: &str,
+        ty: ValueType,
+    ) -> Result<Bundle<CircuitRef>, CircuitBuilderError> {
+        let start = self.circ.num_evaluator_inputs();
+        push_group(&mut self.circ.evaluator_input_groups, name, ty, start)?;
+        Ok(Bundle::new(self.evaluator_inputs(&ty.moduli())))
+    }
+
+    /// Output `x` as a value of type `ty` named `name`.
+    pub fn named_output(
+        &mut self,
+        name: &str,
+        ty: ValueType,
+        x: &Bundle<CircuitRef>,
+    ) -> Result<(), CircuitBuilderError> {
+        if x.moduli() != ty.moduli() {
+            return Err(CircuitBuilderError::from(FancyError::InvalidArg(format!(
+                "output \"{}\" does not have the moduli of {}",
+                name, ty
+            ))));
+        }
+        let start = self.circ.noutputs();
+        push_group(&mut self.circ.output_groups, name, ty, start)?;
+        for w in x.wires() {
+            self.output(w)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fancy::{BinaryBundle, BinaryGadgets, CrtBundle, CrtGadgets};
+
+    fn circuit(q: u128) -> Circuit {
+        let mut b = CircuitBuilder::new();
+        let balance = b
+            .named_garbler_input("balance", ValueType::Binary(32))
+            .unwrap();
+        let deposit = b
+            .named_evaluator_input("deposit", ValueType::Binary(32))
+            .unwrap();
+        let score = b.named_evaluator_input("score", ValueType::Crt(q)).unwrap();
+        let total = b
+            .bin_addition_no_carry(&BinaryBundle::from(balance), &BinaryBundle::from(deposit))
+            .unwrap();
+        let score = CrtBundle::from(score);
+        let doubled = b.crt_add(&score, &score).unwrap();
+        b.named_output("total", ValueType::Binary(32), &total)
+            .unwrap();
+        b.named_output("doubled", ValueType::Crt(q), &doubled)
+            .unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn eval_named() {
+        let q = util::modulus_with_width(10);
+        let circ = circuit(q);
+        assert_eq!(
+            circ.garbler_input_groups()[0].to_string(),
+            "balance: 32-bit binary"
+        );
+        assert_eq!(
+            circ.evaluator_input_groups()[1].range(),
+            32..32 + util::factor(q).len()
+        );
+        let outputs = circ
+            .eval_plain_named(&[("balance", 1000)], &[("score", 300), ("deposit", 234)])
+            .unwrap();
+        assert_eq!(outputs["total"], 1234);
+        assert_eq!(outputs["doubled"], 600 % q);
+        // Optimization keeps the named groups.
+        let opt = circ.optimized().0;
+        assert_eq!(opt.output_groups(), circ.output_groups());
+        assert_eq!(
+            opt.eval_plain_named(&[("balance", 1)], &[("score", 2), ("deposit", 3)])
+                .unwrap()["total"],
+            4
+        );
+    }
+
+    #[test]
+    fn eval_named_errors() {
+        let circ = circuit(util::modulus_with_width(10));
+        let ev = [("score", 1), ("deposit", 2)];
+        assert!(circ.eval_plain_named(&[], &ev).is_err());
+        assert!(circ.eval_plain_named(&[("balance", 1 << 32)], &ev).is_err());
+        assert!(circ
+            .eval_plain_named(&[("balance", 1), ("other", 1)], &ev)
+            .is_err());
+        assert!(circ
+            .eval_plain_named(&[("balance", 1), ("balance", 2)], &ev)
+            .is_err());
+        let mut b = CircuitBuilder::new();
+        b.named_garbler_input("x", ValueType::Mod(3)).unwrap();
+        assert!(b.named_garbler_input("x", ValueType::Mod(3)).is_err());
+        let y = b.garbler_input(2);
+        assert!(b
+            .named_output("y", ValueType::Mod(3), &Bundle::new(vec![y]))
+            .is_err());
+        // Binary values wider than a u128 are rejected.
+        assert!(b.named_garbler_input("z", ValueType::Binary(129)).is_err());
+        assert!(NamedGroup::new("z".to_string(), ValueType::Mod(3), usize::MAX).is_err());
+        // Inputs outside of any named group cannot be given by name.
+        b.output(&y).unwrap();
+        assert!(b.finish().eval_plain_named(&[("x", 1)], &[]).is_err());
+    }
+
+    #[cfg(feature = "serde1")]
+    #[test]
+    fn serde_round_trip() {
+        let circ = circuit(util::modulus_with_width(10));
+        let json = serde_json::to_string(&circ).unwrap();
+        let circ_: Circuit = serde_json::from_str(&json).unwrap();
+        assert_eq!(circ_.garbler_input_groups(), circ.garbler_input_groups());
+        assert_eq!(
+            circ_.evaluator_input_groups(),
+            circ.evaluator_input_groups()
+        );
+        assert_eq!(circ_.output_groups(), circ.output_groups());
+        assert_eq!(
+            circ_
+                .eval_plain_named(&[("balance", 1)], &[("score", 2), ("deposit", 3)])
+                .unwrap()["total"],
+            4
+        );
+        // Deserialization checks groups like `NamedGroup::new`.
+        let bad = NamedGroup {
+            name: "x".to_string(),
+            ty: ValueType::Binary(129),
+            start: 0,
+        };
+        let json = serde_json::to_string(&bad).unwrap();
+        assert!(serde_json::from_str::<NamedGroup>(&json).is_err());
+    }
+}
@@ -0,0 +1,752 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Optimization passes for `Circuit`s.
+//!
+//! The optimizer performs constant propagation, common-subexpression
+//! elimination, projection chain fusion, and dead-gate elimination, producing
+//! an equivalent circuit with the same inputs and outputs.
+
+use super::{Circuit, CircuitRef, Gate};
+use crate::fancy::HasModulus;
+use std::collections::HashMap;
+
+/// Statistics about a circuit before and after optimization.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptimizerStats {
+    ngates_before: usize,
+    ngates_after: usize,
+    nonfree_before: usize,
+    nonfree_after: usize,
+    nciphertexts_before: usize,
+    nciphertexts_after: usize,
+}
+
+impl OptimizerStats {
+    fn new(before: &Circuit, after: &Circuit) -> Self {
+        OptimizerStats {
+            ngates_before: before.gates.len(),
+            ngates_after: after.gates.len(),
+            nonfree_before: count_nonfree(before),
+            nonfree_after: count_nonfree(after),
+            nciphertexts_before: count_ciphertexts(before),
+            nciphertexts_after: count_ciphertexts(after),
+        }
+    }
+
+    /// Number of gates before optimization.
+    pub fn num_gates_before(&self) -> usize {
+        self.ngates_before
+    }
+
+    /// Number of gates after optimization.
+    pub fn num_gates_after(&self) -> usize {
+        self.ngates_after
+    }
+
+    /// Number of non-free (multiplication and projection) gates before optimization.
+    pub fn num_nonfree_gates_before(&self) -> usize {
+        self.nonfree_before
+    }
+
+    /// Number of non-free (multiplication and projection) gates after optimization.
+    pub fn num_nonfree_gates_after(&self) -> usize {
+        self.nonfree_after
+    }
+
+    /// Number of non-free gates removed by the optimizer.
+    pub fn num_nonfree_gates_removed(&self) -> usize {
+        self.nonfree_before - self.nonfree_after
+    }
+
+    /// Number of garbled-table ciphertexts before optimization.
+    pub fn num_ciphertexts_before(&self) -> usize {
+        self.nciphertexts_before
+    }
+
+    /// Number of garbled-table ciphertexts after optimization.
+    pub fn num_ciphertexts_after(&self) -> usize {
+        self.nciphertexts_after
+    }
+}
+
+impl std::fmt::Display for OptimizerStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "optimization info:")?;
+        writeln!(
+            f,
+            "  gates:          {:10} -> {:10}",
+            self.ngates_before, self.ngates_after
+        )?;
+        writeln!(
+            f,
+            "  nonfree gates:  {:10} -> {:10} // removed {}",
+            self.nonfree_before,
+            self.nonfree_after,
+            self.num_nonfree_gates_removed()
+        )?;
+        let kb = (self.nciphertexts_before - self.nciphertexts_after) as f64 * 128.0 / 1000.0;
+        writeln!(
+            f,
+            "  ciphertexts:    {:10} -> {:10} // saves {:.2} Kb",
+            self.nciphertexts_before, self.nciphertexts_after, kb
+        )
+    }
+}
+
+fn count_nonfree(c: &Circuit) -> usize {
+    c.gates
+        .iter()
+        .filter(|g| matches!(g, Gate::Mul { .. } | Gate::Proj { .. } | Gate::Proj2 { .. }))
+        .count()
+}
+
+fn count_ciphertexts(c: &Circuit) -> usize {
+    c.gates.iter().map(Gate::num_ciphertexts).sum()
+}
+
+/// The result of a gate during optimization: either a known constant `(val,
+/// modulus)`, or a reference into the optimized circuit.
+#[derive(Clone, Debug)]
+enum Val {
+    Const(u16, u16),
+    Ref(CircuitRef),
+}
+
+/// Key used for common-subexpression elimination.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Add(usize, usize),
+    Sub(usize, usize),
+    Cmul(usize, u16),
+    Mul(usize, usize),
+    Proj(usize, u16, Vec<u16>),
+    Proj2(usize, usize, u16, Vec<u16>),
+}
+
+struct Optimizer {
+    circ: Circuit,
+    scope: Option<usize>,
+    const_map: HashMap<(u16, u16), CircuitRef>,
+    cse: HashMap<Key, CircuitRef>,
+}
+
+impl Optimizer {
+    fn gate(&mut self, gate: Gate, modulus: u16) -> CircuitRef {
+        let ix = self.circ.gates.len();
+        self.circ.gates.push(gate);
+        self.circ.gate_moduli.push(modulus);
+        self.circ.gate_scopes.push(self.scope);
+        CircuitRef { ix, modulus }
+    }
+
+    fn constant(&mut self, val: u16, modulus: u16) -> CircuitRef {
+        if let Some(&r) = self.const_map.get(&(val, modulus)) {
+            return r;
+        }
+        let r = self.gate(Gate::Constant { val }, modulus);
+        self.const_map.insert((val, modulus), r);
+        r
+    }
+
+    fn materialize(&mut self, v: &Val) -> CircuitRef {
+        match *v {
+            Val::Const(val, q) => self.constant(val, q),
+            Val::Ref(r) => r,
+        }
+    }
+
+    /// Emit `gate` unless an identical gate already exists.
+    fn emit(&mut self, key: Key, gate: Gate, modulus: u16) -> Val {
+        if let Some(&r) = self.cse.get(&key) {
+            return Val::Ref(r);
+        }
+        let r = self.gate(gate, modulus);
+        self.cse.insert(key, r);
+        Val::Ref(r)
+    }
+
+    fn add(&mut self, x: &Val, y: &Val, q: u16) -> Val {
+        match (x, y) {
+            (Val::Const(a, _), Val::Const(b, _)) => {
+                Val::Const(((*a as u32 + *b as u32) % q as u32) as u16, q)
+            }
+            (Val::Const(0, _), v) | (v, Val::Const(0, _)) => v.clone(),
+            _ => {
+                let x = self.materialize(x);
+                let y = self.materialize(y);
+                let (x, y) = if x.ix <= y.ix { (x, y) } else { (y, x) };
+                let gate = Gate::Add {
+                    xref: x,
+                    yref: y,
+                    out: None,
+                };
+                self.emit(Key::Add(x.ix, y.ix), gate, q)
+            }
+        }
+    }
+
+    fn sub(&mut self, x: &Val, y: &Val, q: u16) -> Val {
+        match (x, y) {
+            (Val::Const(a, _), Val::Const(b, _)) => {
+                Val::Const(((q as u32 + *a as u32 - *b as u32) % q as u32) as u16, q)
+            }
+            (v, Val::Const(0, _)) => v.clone(),
+            (Val::Ref(a), Val::Ref(b)) if a.ix == b.ix => Val::Const(0, q),
+            _ => {
+                let x = self.materialize(x);
+                let y = self.materialize(y);
+                let gate = Gate::Sub {
+                    xref: x,
+                    yref: y,
+                    out: None,
+                };
+                self.emit(Key::Sub(x.ix, y.ix), gate, q)
+            }
+        }
+    }
+
+    fn cmul(&mut self, x: &Val, c: u16, q: u16) -> Val {
+        let c = c % q;
+        match x {
+            Val::Const(a, _) => Val::Const((*a as u32 * c as u32 % q as u32) as u16, q),
+            _ if c == 0 => Val::Const(0, q),
+            _ if c == 1 => x.clone(),
+            Val::Ref(x) => {
+                let gate = Gate::Cmul {
+                    xref: *x,
+                    c,
+                    out: None,
+                };
+                self.emit(Key::Cmul(x.ix, c), gate, q)
+            }
+        }
+    }
+
+    /// Multiply `x` and `y`, where `x` has the (larger) output modulus `q`.
+    fn mul(&mut self, x: &Val, y: &Val, q: u16) -> Val {
+        match (x, y) {
+            (Val::Const(a, _), Val::Const(b, _)) => {
+                Val::Const((*a as u32 * *b as u32 % q as u32) as u16, q)
+            }
+            (Val::Ref(_), Val::Const(b, _)) => self.cmul(x, *b, q),
+            (Val::Const(a, _), Val::Ref(y)) => {
+                if y.modulus() == q {
+                    self.cmul(&Val::Ref(*y), *a, q)
+                } else if *a == 0 {
+                    Val::Const(0, q)
+                } else {
+                    // A projection on the smaller wire is cheaper than a half-gate.
+                    let tt = (0..y.modulus())
+                        .map(|v| (v as u32 * *a as u32 % q as u32) as u16)
+                        .collect::<Vec<u16>>();
+                    self.proj(&Val::Ref(*y), q, &tt)
+                }
+            }
+            (Val::Ref(x), Val::Ref(y)) => {
+                let (x, y) = if x.modulus() == y.modulus() && y.ix < x.ix {
+                    (*y, *x)
+                } else {
+                    (*x, *y)
+                };
+                let gate = Gate::Mul {
+                    xref: x,
+                    yref: y,
+                    id: 0,
+                    out: None,
+                };
+                self.emit(Key::Mul(x.ix, y.ix), gate, q)
+            }
+        }
+    }
+
+    fn proj(&mut self, x: &Val, q: u16, tt: &[u16]) -> Val {
+        let x = match x {
+            Val::Const(a, _) => return Val::Const(tt[*a as usize], q),
+            Val::Ref(x) => *x,
+        };
+        let tt = &tt[..x.modulus() as usize];
+
+        // Fuse chains of projections, as long as that does not make the
+        // resulting gate more expensive.
+        let (src, tt) = match self.circ.gates[x.ix] {
+            Gate::Proj {
+                xref,
+                tt: ref inner,
+                ..
+            } if xref.modulus() <= x.modulus() => (
+                xref,
+                inner[..xref.modulus() as usize]
+                    .iter()
+                    .map(|&v| tt[v as usize])
+                    .collect::<Vec<u16>>(),
+            ),
+            _ => (x, tt.to_vec()),
+        };
+
+        if tt.iter().all(|&v| v == tt[0]) {
+            return Val::Const(tt[0], q);
+        }
+        if src.modulus() == q && tt.iter().enumerate().all(|(i, &v)| i as u16 == v) {
+            return Val::Ref(src);
+        }
+        let gate = Gate::Proj {
+            xref: src,
+            tt: tt.clone(),
+            id: 0,
+            out: None,
+        };
+        self.emit(Key::Proj(src.ix, q, tt), gate, q)
+    }
+
+    fn proj2(&mut self, x: &Val, y: &Val, q: u16, tt: &[u16]) -> Val {
+        // A constant argument selects a row or column of the truth table.
+        let (x, y) = match (x, y) {
+            (Val::Const(a, _), Val::Const(b, py)) => {
+                return Val::Const(tt[*a as usize * *py as usize + *b as usize], q)
+            }
+            (Val::Const(a, _), Val::Ref(y)) => {
+                let py = y.modulus() as usize;
+                let row = &tt[*a as usize * py..(*a as usize + 1) * py];
+                return self.proj(&Val::Ref(*y), q, row);
+            }
+            (Val::Ref(x), Val::Const(b, py)) => {
+                let col = (0..x.modulus() as usize)
+                    .map(|a| tt[a * *py as usize + *b as usize])
+                    .collect::<Vec<u16>>();
+                return self.proj(&Val::Ref(*x), q, &col);
+            }
+            (Val::Ref(x), Val::Ref(y)) => (*x, *y),
+        };
+        let py = y.modulus() as usize;
+        let tt = &tt[..x.modulus() as usize * py];
+
+        // Gates which only depend on one of their arguments are cheaper as
+        // projections.
+        if tt.chunks(py).all(|row| row == &tt[..py]) {
+            return self.proj(&Val::Ref(y), q, &tt[..py]);
+        }
+        if tt.chunks(py).all(|row| row.iter().all(|&v| v == row[0])) {
+            let col = tt.iter().step_by(py).cloned().collect::<Vec<u16>>();
+            return self.proj(&Val::Ref(x), q, &col);
+        }
+        let gate = Gate::Proj2 {
+            xref: x,
+            yref: y,
+            tt: tt.to_vec(),
+            id: 0,
+            out: None,
+        };
+        self.emit(Key::Proj2(x.ix, y.ix, q, tt.to_vec()), gate, q)
+    }
+}
+
+/// Remove gates which do not contribute to any output, renumbering the
+/// remaining gates and non-free gate ids.
+fn eliminate_dead_gates(c: Circuit) -> Circuit {
+    let n = c.gates.len();
+    let mut live = vec![false; n];
+    for r in c.output_refs.iter() {
+        live[r.ix] = true;
+    }
+    for i in (0..n).rev() {
+        match c.gates[i] {
+            Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } => live[i] = true,
+            _ if !live[i] => (),
+            Gate::Constant { .. } => (),
+            Gate::Add { xref, yref, .. }
+            | Gate::Sub { xref, yref, .. }
+            | Gate::Mul { xref, yref, .. }
+            | Gate::Proj2 { xref, yref, .. } => {
+                live[xref.ix] = true;
+                live[yref.ix] = true;
+            }
+            Gate::Cmul { xref, .. } | Gate::Proj { xref, .. } => live[xref.ix] = true,
+        }
+    }
+
+    let mut map = vec![0; n];
+    let mut circ = Circuit::new(Some(n));
+    let remap = |map: &[usize], r: CircuitRef| CircuitRef {
+        ix: map[r.ix],
+        modulus: r.modulus,
+    };
+    for (i, gate) in c.gates.into_iter().enumerate() {
+        if !live[i] {
+            continue;
+        }
+        let gate = match gate {
+            Gate::Add { xref, yref, out } => Gate::Add {
+                xref: remap(&map, xref),
+                yref: remap(&map, yref),
+                out,
+            },
+            Gate::Sub { xref, yref, out } => Gate::Sub {
+                xref: remap(&map, xref),
+                yref: remap(&map, yref),
+                out,
+            },
+            Gate::Cmul { xref, c, out } => Gate::Cmul {
+                xref: remap(&map, xref),
+                c,
+                out,
+            },
+            Gate::Mul {
+                xref, yref, out, ..
+            } => {
+                let id = circ.num_nonfree_gates;
+                circ.num_nonfree_gates += 1;
+                Gate::Mul {
+                    xref: remap(&map, xref),
+                    yref: remap(&map, yref),
+                    id,
+                    out,
+                }
+            }
+            Gate::Proj { xref, tt, out, .. } => {
+                let id = circ.num_nonfree_gates;
+                circ.num_nonfree_gates += 1;
+                Gate::Proj {
+                    xref: remap(&map, xref),
+                    tt,
+                    id,
+                    out,
+                }
+            }
+            Gate::Proj2 {
+                xref,
+                yref,
+                tt,
+                out,
+                ..
+            } => {
+                let id = circ.num_nonfree_gates;
+                circ.num_nonfree_gates += 1;
+                Gate::Proj2 {
+                    xref: remap(&map, xref),
+                    yref: remap(&map, yref),
+                    tt,
+                    id,
+                    out,
+                }
+            }
+            g => g,
+        };
+        map[i] = circ.gates.len();
+        circ.gates.push(gate);
+        circ.gate_moduli.push(c.gate_moduli[i]);
+        circ.gate_scopes
+            .push(c.gate_scopes.get(i).cloned().flatten());
+    }
+    circ.garbler_input_refs = c
+        .garbler_input_refs
+        .into_iter()
+        .map(|r| remap(&map, r))
+        .collect();
+    circ.evaluator_input_refs = c
+        .evaluator_input_refs
+        .into_iter()
+        .map(|r| remap(&map, r))
+        .collect();
+    circ.const_refs = c
+        .const_refs
+        .into_iter()
+        .filter(|r| live[r.ix])
+        .map(|r| remap(&map, r))
+        .collect();
+    circ.output_refs = c.output_refs.into_iter().map(|r| remap(&map, r)).collect();
+    circ.garbler_input_groups = c.garbler_input_groups;
+    circ.evaluator_input_groups = c.evaluator_input_groups;
+    circ.output_groups = c.output_groups;
+    circ.scopes = c.scopes;
+    circ
+}
+
+impl Circuit {
+    /// Optimize this circuit, returning the optimized circuit alongside
+    /// statistics about how much smaller it got.
+    ///
+    /// The optimized circuit has the same inputs and outputs as `self`, and
+    /// computes the same function. Constants are propagated through `Add`,
+    /// `Sub`, `Cmul`, `Mul`, `Proj` and `Proj2` gates, duplicate gates are merged,
+    /// chains of projections are fused, and gates which do not contribute to
+    /// any output are removed.
+    pub fn optimized(&self) -> (Circuit, OptimizerStats) {
+        let mut opt = Optimizer {
+            circ: Circuit::new(Some(self.gates.len())),
+            scope: None,
+            const_map: HashMap::new(),
+            cse: HashMap::new(),
+        };
+
+        let mut cache: Vec<Option<Val>> = vec![None; self.num_slots()];
+        let mut gb_refs = vec![None; self.num_garbler_inputs()];
+        let mut ev_refs = vec![None; self.num_evaluator_inputs()];
+
+        let get = |cache: &[Option<Val>], r: &CircuitRef| -> Val {
+            cache[r.ix]
+                .clone()
+                .expect("uninitialized value in circuit. is the circuit topologically sorted?")
+        };
+
+        for (i, gate) in self.gates.iter().enumerate() {
+            let q = self.modulus(i);
+            opt.scope = self.gate_scopes.get(i).cloned().flatten();
+            let (out, val) = match *gate {
+                Gate::GarblerInput { id } => {
+                    let r = opt.gate(Gate::GarblerInput { id }, q);
+                    gb_refs[id] = Some(r);
+                    (None, Val::Ref(r))
+                }
+                Gate::EvaluatorInput { id } => {
+                    let r = opt.gate(Gate::EvaluatorInput { id }, q);
+                    ev_refs[id] = Some(r);
+                    (None, Val::Ref(r))
+                }
+                Gate::Constant { val } => (None, Val::Const(val, q)),
+                Gate::Add { xref, yref, out } => {
+                    (out, opt.add(&get(&cache, &xref), &get(&cache, &yref), q))
+                }
+                Gate::Sub { xref, yref, out } => {
+                    (out, opt.sub(&get(&cache, &xref), &get(&cache, &yref), q))
+                }
+                Gate::Cmul { xref, c, out } => (out, opt.cmul(&get(&cache, &xref), c, q)),
+                Gate::Mul {
+                    xref, yref, out, ..
+                } => (out, opt.mul(&get(&cache, &xref), &get(&cache, &yref), q)),
+                Gate::Proj {
+                    xref, ref tt, out, ..
+                } => (out, opt.proj(&get(&cache, &xref), q, tt)),
+                Gate::Proj2 {
+                    xref,
+                    yref,
+                    ref tt,
+                    out,
+                    ..
+                } => (
+                    out,
+                    opt.proj2(&get(&cache, &xref), &get(&cache, &yref), q, tt),
+                ),
+            };
+            cache[out.unwrap_or(i)] = Some(val);
+        }
+
+        let outputs = self
+            .output_refs
+            .iter()
+            .map(|r| get(&cache, r))
+            .collect::<Vec<Val>>();
+        opt.circ.output_refs = outputs.iter().map(|v| opt.materialize(v)).collect();
+        opt.circ.garbler_input_refs = gb_refs.into_iter().map(Option::unwrap).collect();
+        opt.circ.evaluator_input_refs = ev_refs.into_iter().map(Option::unwrap).collect();
+        opt.circ.const_refs = opt.const_map.values().cloned().collect();
+        opt.circ.const_refs.sort_by_key(|r| r.ix);
+        opt.circ.garbler_input_groups = self.garbler_input_groups.clone();
+        opt.circ.evaluator_input_groups = self.evaluator_input_groups.clone();
+        opt.circ.output_groups = self.output_groups.clone();
+        opt.circ.scopes = self.scopes.clone();
+
+        let circ = eliminate_dead_gates(opt.circ);
+        let stats = OptimizerStats::new(self, &circ);
+        (circ, stats)
+    }
+
+    /// Optimize this circuit in place, returning statistics about how much
+    /// smaller it got. See `Circuit::optimized`.
+    pub fn optimize(&mut self) -> OptimizerStats {
+        let (circ, stats) = self.optimized();
+        *self = circ;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::{Circuit, CircuitBuilder},
+        fancy::{BinaryGadgets, BundleGadgets, CrtGadgets, Fancy},
+        util::{self, RngExt},
+    };
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    fn check_equivalent(c: &Circuit, opt: &Circuit, niters: usize) {
+        let mut rng = thread_rng();
+        for _ in 0..niters {
+            let gb = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect_vec();
+            let ev = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect_vec();
+            assert_eq!(
+                c.eval_plain(&gb, &ev).unwrap(),
+                opt.eval_plain(&gb, &ev).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn constant_folding() {
+        let mut b = CircuitBuilder::new();
+        let q = 11;
+        let x = b.garbler_input(q);
+        let c3 = b.constant(3, q).unwrap();
+        let c4 = b.constant(4, q).unwrap();
+        let c7 = b.add(&c3, &c4).unwrap();
+        let c1 = b.cmul(&c7, 8).unwrap(); // 56 = 1 mod 11
+        let y = b.mul(&x, &c1).unwrap();
+        let z = b.mul(&y, &c4).unwrap();
+        let w = b
+            .proj(&c7, 2, Some((0..q).map(|v| v % 2).collect()))
+            .unwrap();
+        b.output(&z).unwrap();
+        b.output(&w).unwrap();
+        let c = b.finish();
+
+        let (opt, stats) = c.optimized();
+        assert_eq!(stats.num_nonfree_gates_before(), 3);
+        assert_eq!(stats.num_nonfree_gates_after(), 0);
+        assert_eq!(stats.num_nonfree_gates_removed(), 3);
+        check_equivalent(&c, &opt, 16);
+    }
+
+    #[test]
+    fn constant_times_small_modulus() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        let c = b.constant(5, 7).unwrap();
+        let z = b.mul(&c, &x).unwrap();
+        b.output(&z).unwrap();
+        let c = b.finish();
+
+        let (opt, stats) = c.optimized();
+        assert_eq!(stats.num_ciphertexts_before(), 3 + 7 - 2 + 1);
+        assert_eq!(stats.num_ciphertexts_after(), 2);
+        check_equivalent(&c, &opt, 16);
+    }
+
+    #[test]
+    fn common_subexpressions() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(2);
+        let y = b.evaluator_input(2);
+        let z1 = b.and(&x, &y).unwrap();
+        let z2 = b.and(&y, &x).unwrap();
+        let s1 = b.xor(&z1, &x).unwrap();
+        let s2 = b.xor(&x, &z2).unwrap();
+        let t = b.and(&s1, &s2).unwrap();
+        b.output(&t).unwrap();
+        let c = b.finish();
+
+        let (opt, stats) = c.optimized();
+        assert_eq!(stats.num_nonfree_gates_before(), 3);
+        assert_eq!(stats.num_nonfree_gates_after(), 2);
+        assert_eq!(opt.num_nonfree_gates, 2);
+        check_equivalent(&c, &opt, 16);
+    }
+
+    #[test]
+    fn dead_gates() {
+        let mut b = CircuitBuilder::new();
+        let xs = b.evaluator_inputs(&[5, 5, 5]);
+        let _ = b.mul(&xs[0], &xs[1]).unwrap();
+        let _ = b.proj(&xs[2], 3, Some(vec![0, 1, 2, 0, 1])).unwrap();
+        let z = b.add_many(&xs).unwrap();
+        b.output(&z).unwrap();
+        let c = b.finish();
+
+        let (opt, stats) = c.optimized();
+        assert_eq!(stats.num_nonfree_gates_after(), 0);
+        assert_eq!(opt.num_evaluator_inputs(), 3);
+        check_equivalent(&c, &opt, 16);
+    }
+
+    #[test]
+    fn proj_chains() {
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let p = rng.gen_prime();
+            let q = p + 1 + rng.gen_u16() % 20;
+            let mut b = CircuitBuilder::new();
+            let x = b.garbler_input(p);
+            let y = b.mod_change(&x, q).unwrap();
+            let z = b.mod_change(&y, p).unwrap();
+            let w = b
+                .proj(&z, 2, Some((0..p).map(|v| (v + 1) & 1).collect()))
+                .unwrap();
+            b.output(&z).unwrap();
+            b.output(&w).unwrap();
+            let c = b.finish();
+
+            let (opt, stats) = c.optimized();
+            assert_eq!(stats.num_nonfree_gates_after(), 1);
+            check_equivalent(&c, &opt, 16);
+        }
+    }
+
+    #[test]
+    fn proj2() {
+        let (p, q) = (5, 7);
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(p);
+        let y = b.evaluator_input(q);
+        let c = b.constant(3, p).unwrap();
+        let sum = (0..p * q).map(|v| (v / q + v % q) % 4).collect_vec();
+        let first = (0..p * q).map(|v| v / q).collect_vec();
+        // a real two-input gate, and a duplicate of it
+        let z1 = b.proj2(&x, &y, 4, Some(sum.clone())).unwrap();
+        let z2 = b.proj2(&x, &y, 4, Some(sum.clone())).unwrap();
+        // a constant argument, which leaves a projection of `y`
+        let z3 = b.proj2(&c, &y, 4, Some(sum)).unwrap();
+        // a gate which only depends on `x`
+        let z4 = b.proj2(&x, &y, p, Some(first)).unwrap();
+        for z in [z1, z2, z3, z4].iter() {
+            b.output(z).unwrap();
+        }
+        let c = b.finish();
+
+        let (opt, stats) = c.optimized();
+        assert_eq!(stats.num_nonfree_gates_before(), 4);
+        assert_eq!(stats.num_nonfree_gates_after(), 2);
+        assert_eq!(
+            stats.num_ciphertexts_after(),
+            p as usize * q as usize - 1 + q as usize - 1
+        );
+        check_equivalent(&c, &opt, 64);
+    }
+
+    #[test]
+    fn gadgets() {
+        let q = util::modulus_with_width(10);
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let one = b.crt_constant_bundle(1, q).unwrap();
+        let z = b.crt_mul(&x, &one).unwrap();
+        let z = b.crt_add(&z, &y).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        let u = b.bin_garbler_input(8);
+        let v = b.bin_constant_bundle(5, 8).unwrap();
+        let w = b.bin_multiplication_lower_half(&u, &v).unwrap();
+        b.output_bundle(&w).unwrap();
+        let mut c = b.finish();
+
+        let c_ = c.clone();
+        let stats = c.optimize();
+        assert!(stats.num_nonfree_gates_after() < stats.num_nonfree_gates_before());
+        check_equivalent(&c_, &c, 64);
+    }
+
+    #[test]
+    fn aes() {
+        let c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let (opt, stats) = c.optimized();
+        assert!(stats.num_nonfree_gates_after() <= 6800);
+        check_equivalent(&c, &opt, 4);
+    }
+}
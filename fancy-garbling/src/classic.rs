@@ -6,22 +6,34 @@
 
 //! Provides objects and functions for statically garbling and evaluating a
 //! circuit without streaming.
+//!
+//! Both `GarbledCircuit` and `Encoder` can be written to and read from disk
+//! using a stable, versioned binary format. This allows the garbler to garble
+//! circuits offline, ship them to the evaluator ahead of time, and only encode
+//! inputs online.
 
 use crate::{
-    circuit::Circuit,
-    errors::{EvaluatorError, GarblerError},
+    circuit::{decode_named, encode_named, Circuit, NamedGroup, ValueType},
+    errors::{ClassicError, EvaluatorError, FancyError, GarblerError},
     fancy::HasModulus,
     garble::{Evaluator, Garbler},
+    util,
     wire::Wire,
 };
 use itertools::Itertools;
 use scuttlebutt::{AbstractChannel, AesRng, Block, Channel};
-use std::{collections::HashMap, convert::TryInto, rc::Rc};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    rc::Rc,
+};
 
 /// Static evaluator for a circuit, created by the `garble` function.
 ///
 /// Uses `Evaluator` under the hood to actually implement the evaluation.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct GarbledCircuit {
     blocks: Vec<Block>,
@@ -50,6 +62,53 @@ impl GarbledCircuit {
         let outputs = c.eval(&mut evaluator, garbler_inputs, evaluator_inputs)?;
         Ok(outputs.expect("evaluator outputs always are Some(u16)"))
     }
+
+    /// Evaluate the garbled circuit, returning the values of the named outputs
+    /// of `c`.
+    pub fn eval_named(
+        &self,
+        c: &Circuit,
+        garbler_inputs: &[Wire],
+        evaluator_inputs: &[Wire],
+    ) -> Result<HashMap<String, u128>, EvaluatorError> {
+        let outputs = self.eval(c, garbler_inputs, evaluator_inputs)?;
+        Ok(decode_named(c.output_groups(), &outputs))
+    }
+
+    /// Write the garbled circuit, including its output decoding information,
+    /// to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ClassicError> {
+        write_header(writer, GARBLED_CIRCUIT_MAGIC)?;
+        write_u64(writer, self.blocks.len() as u64)?;
+        for block in self.blocks.iter() {
+            write_block(writer, block)?;
+        }
+        Ok(())
+    }
+
+    /// Read a garbled circuit written by `GarbledCircuit::write_to`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ClassicError> {
+        read_header(reader, GARBLED_CIRCUIT_MAGIC)?;
+        let n = read_u64(reader)? as usize;
+        let blocks = (0..n)
+            .map(|_| read_block(reader))
+            .collect::<Result<Vec<Block>, ClassicError>>()?;
+        Ok(GarbledCircuit { blocks })
+    }
+
+    /// Save the garbled circuit to `filename`.
+    pub fn save(&self, filename: &str) -> Result<(), ClassicError> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load a garbled circuit saved by `GarbledCircuit::save`.
+    pub fn load(filename: &str) -> Result<Self, ClassicError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        Self::read_from(&mut reader)
+    }
 }
 
 /// Garble a circuit without streaming.
@@ -82,7 +141,10 @@ pub fn garble(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
 
     c.eval(&mut garbler, &gb_inps, &ev_inps)?;
 
-    let en = Encoder::new(gb_inps, ev_inps, garbler.get_deltas());
+    let en = Encoder::new(gb_inps, ev_inps, garbler.get_deltas()).with_groups(
+        c.garbler_input_groups().to_vec(),
+        c.evaluator_input_groups().to_vec(),
+    );
 
     let gc = GarbledCircuit::new(
         Rc::try_unwrap(channel.writer())
@@ -98,12 +160,16 @@ pub fn garble(c: &Circuit) -> Result<(Encoder, GarbledCircuit), GarblerError> {
 // Encoder
 
 /// Encode inputs statically.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "serde1", derive(serde::Serialize, serde::Deserialize))]
 pub struct Encoder {
     garbler_inputs: Vec<Wire>,
     evaluator_inputs: Vec<Wire>,
     deltas: HashMap<u16, Wire>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    garbler_input_groups: Vec<NamedGroup>,
+    #[cfg_attr(feature = "serde1", serde(default))]
+    evaluator_input_groups: Vec<NamedGroup>,
 }
 
 impl Encoder {
@@ -118,9 +184,23 @@ impl Encoder {
             garbler_inputs,
             evaluator_inputs,
             deltas,
+            garbler_input_groups: Vec::new(),
+            evaluator_input_groups: Vec::new(),
         }
     }
 
+    /// Attach the named groups of garbler and evaluator inputs of the circuit,
+    /// used by `encode_garbler_inputs_named` and `encode_evaluator_inputs_named`.
+    pub fn with_groups(
+        mut self,
+        garbler_input_groups: Vec<NamedGroup>,
+        evaluator_input_groups: Vec<NamedGroup>,
+    ) -> Self {
+        self.garbler_input_groups = garbler_input_groups;
+        self.evaluator_input_groups = evaluator_input_groups;
+        self
+    }
+
     /// Output the number of garbler inputs.
     pub fn num_garbler_inputs(&self) -> usize {
         self.garbler_inputs.len()
@@ -162,6 +242,272 @@ impl Encoder {
             .map(|(id, &x)| self.encode_evaluator_input(x, id))
             .collect()
     }
+
+    /// Encode named garbler inputs into their associated wire-labels. Every
+    /// garbler input must belong to a named group, and every group needs a
+    /// value.
+    pub fn encode_garbler_inputs_named(
+        &self,
+        inputs: &[(&str, u128)],
+    ) -> Result<Vec<Wire>, FancyError> {
+        let xs = encode_named(
+            &self.garbler_input_groups,
+            self.num_garbler_inputs(),
+            inputs,
+        )?;
+        Ok(self.encode_garbler_inputs(&xs))
+    }
+
+    /// Encode named evaluator inputs into their associated wire-labels. Every
+    /// evaluator input must belong to a named group, and every group needs a
+    /// value.
+    pub fn encode_evaluator_inputs_named(
+        &self,
+        inputs: &[(&str, u128)],
+    ) -> Result<Vec<Wire>, FancyError> {
+        let xs = encode_named(
+            &self.evaluator_input_groups,
+            self.num_evaluator_inputs(),
+            inputs,
+        )?;
+        Ok(self.encode_evaluator_inputs(&xs))
+    }
+
+    /// Write the encoder, including the input zero-wires, deltas and named
+    /// groups of inputs, to `writer`.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ClassicError> {
+        write_header(writer, ENCODER_MAGIC)?;
+        write_wires(writer, &self.garbler_inputs)?;
+        write_wires(writer, &self.evaluator_inputs)?;
+        // Sort the deltas so that the output does not depend on hash order.
+        let deltas = self
+            .deltas
+            .iter()
+            .sorted_by_key(|(q, _)| **q)
+            .map(|(_, delta)| delta.clone())
+            .collect_vec();
+        write_wires(writer, &deltas)?;
+        write_groups(writer, &self.garbler_input_groups)?;
+        write_groups(writer, &self.evaluator_input_groups)
+    }
+
+    /// Read an encoder written by `Encoder::write_to`.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ClassicError> {
+        let version = read_header(reader, ENCODER_MAGIC)?;
+        let garbler_inputs = read_wires(reader)?;
+        let evaluator_inputs = read_wires(reader)?;
+        let deltas = read_wires(reader)?
+            .into_iter()
+            .map(|delta| (delta.modulus(), delta))
+            .collect::<HashMap<u16, Wire>>();
+        for w in garbler_inputs.iter().chain(evaluator_inputs.iter()) {
+            if !deltas.contains_key(&w.modulus()) {
+                return Err(ClassicError::MissingDelta(w.modulus()));
+            }
+        }
+        // Named groups were added in version 2.
+        let (garbler_input_groups, evaluator_input_groups) = if version >= 2 {
+            (
+                read_groups(reader, &garbler_inputs)?,
+                read_groups(reader, &evaluator_inputs)?,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        Ok(Encoder::new(garbler_inputs, evaluator_inputs, deltas)
+            .with_groups(garbler_input_groups, evaluator_input_groups))
+    }
+
+    /// Save the encoder to `filename`.
+    pub fn save(&self, filename: &str) -> Result<(), ClassicError> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Load an encoder saved by `Encoder::save`.
+    pub fn load(filename: &str) -> Result<Self, ClassicError> {
+        let mut reader = BufReader::new(File::open(filename)?);
+        Self::read_from(&mut reader)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Serialization
+//
+// Format (version 2), with all integers little-endian:
+//
+//   header:         4-byte magic, u16 version
+//   GarbledCircuit: header, u64 number of blocks, 16-byte blocks
+//   Encoder:        header, garbler input wires, evaluator input wires, deltas
+//                   (sorted by modulus), each as a u64 length followed by
+//                   (u16 modulus, 16-byte block) pairs, then the named groups
+//                   of garbler and evaluator inputs
+//   named groups:   u64 number of groups, each as a u64 name length, the UTF-8
+//                   name, a u8 type tag (0 for a single wire, 1 for binary, 2
+//                   for CRT), a u128 type parameter and the u64 index of its
+//                   first input
+//
+// Version 1 encoders have no named groups, and can still be read.
+
+const GARBLED_CIRCUIT_MAGIC: &[u8; 4] = b"FGGC";
+const ENCODER_MAGIC: &[u8; 4] = b"FGEN";
+const FORMAT_VERSION: u16 = 2;
+
+fn write_header<W: Write>(writer: &mut W, magic: &[u8; 4]) -> Result<(), ClassicError> {
+    writer.write_all(magic)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_header<R: Read>(reader: &mut R, magic: &[u8; 4]) -> Result<u16, ClassicError> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    if &buf != magic {
+        return Err(ClassicError::InvalidHeader);
+    }
+    let version = read_u16(reader)?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(ClassicError::UnsupportedVersion(version));
+    }
+    Ok(version)
+}
+
+fn write_u64<W: Write>(writer: &mut W, x: u64) -> Result<(), ClassicError> {
+    writer.write_all(&x.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, ClassicError> {
+    let mut buf = [0u8; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, ClassicError> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write_block<W: Write>(writer: &mut W, block: &Block) -> Result<(), ClassicError> {
+    let bytes: [u8; 16] = (*block).into();
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_block<R: Read>(reader: &mut R) -> Result<Block, ClassicError> {
+    let mut bytes = [0u8; 16];
+    reader.read_exact(&mut bytes)?;
+    Ok(Block::from(bytes))
+}
+
+fn write_wires<W: Write>(writer: &mut W, wires: &[Wire]) -> Result<(), ClassicError> {
+    write_u64(writer, wires.len() as u64)?;
+    for w in wires.iter() {
+        writer.write_all(&w.modulus().to_le_bytes())?;
+        write_block(writer, &w.as_block())?;
+    }
+    Ok(())
+}
+
+fn read_wires<R: Read>(reader: &mut R) -> Result<Vec<Wire>, ClassicError> {
+    let n = read_u64(reader)? as usize;
+    let mut wires = Vec::new();
+    for _ in 0..n {
+        let q = read_u16(reader)?;
+        if q < 2 {
+            return Err(ClassicError::InvalidModulus(q));
+        }
+        let block = read_block(reader)?;
+        // Mod-3 wires must not have both bits of any digit set.
+        if q == 3 {
+            let x = u128::from(block);
+            if (x as u64) & ((x >> 64) as u64) != 0 {
+                return Err(ClassicError::InvalidWire);
+            }
+        }
+        let w = Wire::from_block(block, q);
+        if w.as_block() != block {
+            return Err(ClassicError::InvalidWire);
+        }
+        wires.push(w);
+    }
+    Ok(wires)
+}
+
+fn write_groups<W: Write>(writer: &mut W, groups: &[NamedGroup]) -> Result<(), ClassicError> {
+    write_u64(writer, groups.len() as u64)?;
+    for g in groups.iter() {
+        write_u64(writer, g.name().len() as u64)?;
+        writer.write_all(g.name().as_bytes())?;
+        let (tag, param) = match g.value_type() {
+            ValueType::Mod(q) => (0u8, q as u128),
+            ValueType::Binary(nbits) => (1, nbits as u128),
+            ValueType::Crt(q) => (2, q),
+        };
+        writer.write_all(&[tag])?;
+        writer.write_all(&param.to_le_bytes())?;
+        write_u64(writer, g.range().start as u64)?;
+    }
+    Ok(())
+}
+
+/// Read named groups, checking that they lie on `wires` with matching moduli.
+fn read_groups<R: Read>(reader: &mut R, wires: &[Wire]) -> Result<Vec<NamedGroup>, ClassicError> {
+    let n = read_u64(reader)? as usize;
+    let mut groups = Vec::new();
+    for _ in 0..n {
+        let len = read_u64(reader)? as usize;
+        let mut name = Vec::new();
+        reader.take(len as u64).read_to_end(&mut name)?;
+        if name.len() != len {
+            return Err(ClassicError::IoError(
+                std::io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        let name = String::from_utf8(name).map_err(|_| ClassicError::InvalidGroup)?;
+        let mut tag = [0u8; 1];
+        reader.read_exact(&mut tag)?;
+        let mut param = [0u8; 16];
+        reader.read_exact(&mut param)?;
+        let param = u128::from_le_bytes(param);
+        let ty = match tag[0] {
+            0 if param < 1 << 16 => ValueType::Mod(param as u16),
+            1 if param <= 128 => ValueType::Binary(param as usize),
+            2 if is_crt_modulus(param) => ValueType::Crt(param),
+            _ => return Err(ClassicError::InvalidGroup),
+        };
+        let start = read_u64(reader)? as usize;
+        let g = NamedGroup::new(name, ty, start).map_err(|_| ClassicError::InvalidGroup)?;
+        let moduli = wires
+            .get(g.range())
+            .ok_or(ClassicError::InvalidGroup)?
+            .iter()
+            .map(|w| w.modulus())
+            .collect_vec();
+        if moduli != ty.moduli() {
+            return Err(ClassicError::InvalidGroup);
+        }
+        groups.push(g);
+    }
+    Ok(groups)
+}
+
+/// Whether `q` is a product of distinct primes from `util::PRIMES`.
+fn is_crt_modulus(q: u128) -> bool {
+    let rest = util::PRIMES.iter().fold(
+        q,
+        |x, &p| {
+            if x % p as u128 == 0 {
+                x / p as u128
+            } else {
+                x
+            }
+        },
+    );
+    q > 1 && rest == 1
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -235,3 +581,144 @@ impl std::io::Write for GarbledWriter {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        fancy::{BundleGadgets, CrtBundle, CrtGadgets},
+        util::{self, RngExt},
+    };
+    use rand::thread_rng;
+
+    fn circuit() -> Circuit {
+        let q = util::modulus_with_width(10);
+        let mut b = CircuitBuilder::new();
+        let x = b.crt_garbler_input(q);
+        let y = b.crt_evaluator_input(q);
+        let z = b.crt_mul(&x, &y).unwrap();
+        let z = b.crt_relu(&z, "100%", None).unwrap();
+        b.output_bundle(&z).unwrap();
+        b.finish()
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut rng = thread_rng();
+        let c = circuit();
+        let (en, gc) = garble(&c).unwrap();
+
+        let mut bytes = Vec::new();
+        en.write_to(&mut bytes).unwrap();
+        let en_ = Encoder::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(en, en_);
+
+        let mut bytes = Vec::new();
+        gc.write_to(&mut bytes).unwrap();
+        let gc_ = GarbledCircuit::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(gc, gc_);
+
+        for _ in 0..16 {
+            let gb_inps = (0..c.num_garbler_inputs())
+                .map(|i| rng.gen_u16() % c.garbler_input_mod(i))
+                .collect_vec();
+            let ev_inps = (0..c.num_evaluator_inputs())
+                .map(|i| rng.gen_u16() % c.evaluator_input_mod(i))
+                .collect_vec();
+            let xs = en_.encode_garbler_inputs(&gb_inps);
+            let ys = en_.encode_evaluator_inputs(&ev_inps);
+            assert_eq!(
+                gc_.eval(&c, &xs, &ys).unwrap(),
+                c.eval_plain(&gb_inps, &ev_inps).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn files() {
+        let c = circuit();
+        let (en, gc) = garble(&c).unwrap();
+        let dir = std::env::temp_dir();
+        let en_file = dir.join(format!("fancy-garbling-encoder-{}", std::process::id()));
+        let gc_file = dir.join(format!("fancy-garbling-gc-{}", std::process::id()));
+        let en_file = en_file.to_str().unwrap();
+        let gc_file = gc_file.to_str().unwrap();
+
+        en.save(en_file).unwrap();
+        gc.save(gc_file).unwrap();
+        assert_eq!(Encoder::load(en_file).unwrap(), en);
+        assert_eq!(GarbledCircuit::load(gc_file).unwrap(), gc);
+
+        // files of the wrong kind are rejected
+        assert!(match Encoder::load(gc_file) {
+            Err(ClassicError::InvalidHeader) => true,
+            _ => false,
+        });
+        std::fs::remove_file(en_file).unwrap();
+        std::fs::remove_file(gc_file).unwrap();
+    }
+
+    #[test]
+    fn named() {
+        let q = util::modulus_with_width(10);
+        let mut b = CircuitBuilder::new();
+        let x = b.named_garbler_input("x", ValueType::Crt(q)).unwrap();
+        let y = b.named_evaluator_input("y", ValueType::Crt(q)).unwrap();
+        let z = b.crt_mul(&CrtBundle::from(x), &CrtBundle::from(y)).unwrap();
+        b.named_output("z", ValueType::Crt(q), &z).unwrap();
+        let c = b.finish();
+        let (en, gc) = garble(&c).unwrap();
+
+        let mut bytes = Vec::new();
+        en.write_to(&mut bytes).unwrap();
+        let en_ = Encoder::read_from(&mut bytes.as_slice()).unwrap();
+        assert_eq!(en, en_);
+
+        let xs = en_.encode_garbler_inputs_named(&[("x", 12)]).unwrap();
+        let ys = en_.encode_evaluator_inputs_named(&[("y", 34)]).unwrap();
+        assert_eq!(gc.eval_named(&c, &xs, &ys).unwrap()["z"], 12 * 34 % q);
+        assert!(en_.encode_garbler_inputs_named(&[("y", 12)]).is_err());
+
+        // The last eight bytes are the start of the group "y". A group ending
+        // past the last position is rejected rather than overflowing.
+        let len = bytes.len();
+        bytes[len - 8..].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(match Encoder::read_from(&mut bytes.as_slice()) {
+            Err(ClassicError::InvalidGroup) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn version1_encoder() {
+        let (en, _) = garble(&circuit()).unwrap();
+        let mut bytes = Vec::new();
+        en.write_to(&mut bytes).unwrap();
+        // Version 1 is the same without the two empty lists of named groups.
+        bytes[4] = 1;
+        bytes.truncate(bytes.len() - 16);
+        assert_eq!(Encoder::read_from(&mut bytes.as_slice()).unwrap(), en);
+    }
+
+    #[test]
+    fn bad_data() {
+        let (en, gc) = garble(&circuit()).unwrap();
+
+        let mut bytes = Vec::new();
+        gc.write_to(&mut bytes).unwrap();
+        bytes[4] = 0xff;
+        assert!(match GarbledCircuit::read_from(&mut bytes.as_slice()) {
+            Err(ClassicError::UnsupportedVersion(0xff)) => true,
+            _ => false,
+        });
+
+        let mut bytes = Vec::new();
+        en.write_to(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        assert!(match Encoder::read_from(&mut bytes.as_slice()) {
+            Err(ClassicError::IoError(_)) => true,
+            _ => false,
+        });
+    }
+}
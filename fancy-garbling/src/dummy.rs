@@ -132,6 +132,22 @@ impl Fancy for Dummy {
         Ok(DummyVal { val, modulus })
     }
 
+    fn proj2(
+        &mut self,
+        x: &DummyVal,
+        y: &DummyVal,
+        modulus: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<DummyVal, Self::Error> {
+        let tt = tt.ok_or_else(|| Self::Error::from(FancyError::NoTruthTable))?;
+        let nrows = x.modulus() as usize * y.modulus() as usize;
+        if tt.len() < nrows || !tt.iter().all(|&x| x < modulus) {
+            return Err(Self::Error::from(FancyError::InvalidTruthTable));
+        }
+        let val = tt[x.val as usize * y.modulus() as usize + y.val as usize];
+        Ok(DummyVal { val, modulus })
+    }
+
     fn output(&mut self, x: &DummyVal) -> Result<Option<u16>, Self::Error> {
         Ok(Some(x.val))
     }
@@ -148,6 +164,7 @@ mod bundle {
     use super::*;
     use crate::{
         fancy::{BinaryGadgets, Bundle, BundleGadgets, CrtGadgets},
+        informer::Informer,
         util::{self, RngExt},
     };
     use itertools::Itertools;
@@ -232,6 +249,67 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn binary_mul() {
+        let mut rng = thread_rng();
+        for &nbits in [1, 5, 16, 33].iter() {
+            let q = 1 << nbits;
+            for _ in 0..64 {
+                let x = rng.gen_u128() % q;
+                let y = rng.gen_u128() % q;
+                let mut d = Dummy::new();
+                let out;
+                {
+                    let x = d.bin_encode(x, nbits).unwrap();
+                    let y = d.bin_encode(y, nbits).unwrap();
+                    let z = d.bin_mul(&x, &y).unwrap();
+                    assert_eq!(z.size(), 2 * nbits);
+                    out = d.bin_output(&z).unwrap().unwrap();
+                }
+                assert_eq!(out, x * y, "x={} y={}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_mul_signed() {
+        let mut rng = thread_rng();
+        for &nbits in [2, 8, 16, 24].iter() {
+            let q = 1 << nbits;
+            let to_signed = |x: u128, nbits: usize| ((x as i128) << (128 - nbits)) >> (128 - nbits);
+            for _ in 0..64 {
+                let x = rng.gen_u128() % q;
+                let y = rng.gen_u128() % q;
+                let should_be = to_signed(x, nbits) * to_signed(y, nbits);
+                let mut d = Dummy::new();
+                let out;
+                {
+                    let x = d.bin_encode(x, nbits).unwrap();
+                    let y = d.bin_encode(y, nbits).unwrap();
+                    let z = d.bin_mul_signed(&x, &y).unwrap();
+                    out = d.bin_output(&z).unwrap().unwrap();
+                }
+                assert_eq!(to_signed(out, 2 * nbits), should_be, "x={} y={}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_mul_karatsuba_cost() {
+        // full-width Karatsuba multiplication is cheaper than schoolbook
+        // multiplication computing only the lower half
+        let nbits = 64;
+        let mut full = Informer::new(Dummy::new());
+        let x = full.bin_encode(0, nbits).unwrap();
+        let y = full.bin_encode(0, nbits).unwrap();
+        full.bin_mul(&x, &y).unwrap();
+        let mut half = Informer::new(Dummy::new());
+        let x = half.bin_encode(0, nbits).unwrap();
+        let y = half.bin_encode(0, nbits).unwrap();
+        half.bin_multiplication_lower_half(&x, &y).unwrap();
+        assert!(full.stats().num_muls() < half.stats().num_muls());
+    }
+
     #[test]
     fn max() {
         let mut rng = thread_rng();
@@ -254,6 +332,24 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn crt_multiplex() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(10);
+        for _ in 0..NITERS {
+            let (x, y) = (rng.gen_u128() % q, rng.gen_u128() % q);
+            for b in 0..2 {
+                let mut d = Dummy::new();
+                let bw = d.encode(b, 2).unwrap();
+                let xs = d.crt_encode(x, q).unwrap();
+                let ys = d.crt_encode(y, q).unwrap();
+                let z = d.crt_multiplex(&bw, &xs, &ys).unwrap();
+                let out = d.crt_output(&z).unwrap().unwrap();
+                assert_eq!(out, if b == 0 { x } else { y });
+            }
+        }
+    }
+
     #[test]
     fn twos_complement() {
         let mut rng = thread_rng();
@@ -366,6 +462,139 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn binary_divrem() {
+        let mut rng = thread_rng();
+        let nbits = 16;
+        let q = 1 << nbits;
+        for i in 0..NITERS {
+            let x = rng.gen_u128() % q;
+            // exercise small divisors and division by zero
+            let y = match i % 4 {
+                0 => 0,
+                1 => rng.gen_u128() % 16,
+                _ => rng.gen_u128() % q,
+            };
+            let should_be = if y == 0 { (q - 1, x) } else { (x / y, x % y) };
+            let mut d = Dummy::new();
+            let out;
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let y = d.bin_encode(y, nbits).unwrap();
+                let (z, r) = d.bin_divrem(&x, &y).unwrap();
+                out = (
+                    d.bin_output(&z).unwrap().unwrap(),
+                    d.bin_output(&r).unwrap().unwrap(),
+                );
+            }
+            assert_eq!(out, should_be, "x={} y={}", x, y);
+        }
+    }
+
+    #[test]
+    fn binary_divrem_signed() {
+        let mut rng = thread_rng();
+        let nbits = 16;
+        let q = 1 << nbits;
+        let to_signed = |x: u128| x as u16 as i16;
+        for i in 0..NITERS {
+            let x = match i % 8 {
+                0 => q / 2,
+                _ => rng.gen_u128() % q,
+            };
+            let y = match i % 4 {
+                0 => 0,
+                1 => q - 1 - rng.gen_u128() % 16,
+                _ => rng.gen_u128() % q,
+            };
+            let (sx, sy) = (to_signed(x), to_signed(y));
+            let should_be = if sy == 0 {
+                (if sx >= 0 { -1 } else { 1 }, sx)
+            } else {
+                (sx.wrapping_div(sy), sx.wrapping_rem(sy))
+            };
+            let mut d = Dummy::new();
+            let out;
+            {
+                let x = d.bin_encode(x, nbits).unwrap();
+                let y = d.bin_encode(y, nbits).unwrap();
+                let (z, r) = d.bin_divrem_signed(&x, &y).unwrap();
+                out = (
+                    to_signed(d.bin_output(&z).unwrap().unwrap()),
+                    to_signed(d.bin_output(&r).unwrap().unwrap()),
+                );
+            }
+            assert_eq!(out, should_be, "x={} y={}", sx, sy);
+        }
+    }
+
+    #[test]
+    fn binary_isqrt() {
+        let mut rng = thread_rng();
+        for &nbits in [1, 7, 16].iter() {
+            let q = 1 << nbits;
+            for _ in 0..NITERS {
+                let x = rng.gen_u128() % q;
+                let mut should_be = (x as f64).sqrt() as u128;
+                while should_be * should_be > x {
+                    should_be -= 1;
+                }
+                let mut d = Dummy::new();
+                let out;
+                {
+                    let x = d.bin_encode(x, nbits).unwrap();
+                    let z = d.bin_isqrt(&x).unwrap();
+                    assert_eq!(z.size(), nbits);
+                    out = d.bin_output(&z).unwrap().unwrap();
+                }
+                assert_eq!(out, should_be, "x={}", x);
+            }
+        }
+    }
+
+    #[test]
+    fn binary_shifts() {
+        let mut rng = thread_rng();
+        // Shift amounts wider than 64 bits are reduced modulo the width of `x`
+        // when rotating.
+        for &(nbits, sbits) in [(12, 5), (16, 5), (12, 100), (16, 100)].iter() {
+            let q = 1 << nbits;
+            for _ in 0..NITERS {
+                let x = rng.gen_u128() % q;
+                let s = rng.gen_u128() % (1 << sbits);
+                let s = if rng.gen_bool() { s } else { s % 32 };
+                let sign = if x >> (nbits - 1) == 1 { q - 1 } else { 0 };
+                let k = (s % nbits as u128) as usize;
+                let should_be = [
+                    if s < nbits as u128 { (x << s) % q } else { 0 },
+                    if s < nbits as u128 { x >> s } else { 0 },
+                    if s < nbits as u128 {
+                        (x >> s) | (sign << (nbits as u128 - s)) % q
+                    } else {
+                        sign
+                    },
+                    ((x << k) | (x >> (nbits - k))) % q,
+                    ((x >> k) | (x << (nbits - k))) % q,
+                ];
+                let mut d = Dummy::new();
+                let out;
+                {
+                    let x = d.bin_encode(x, nbits).unwrap();
+                    let s = d.bin_encode(s, sbits).unwrap();
+                    let zs = [
+                        d.bin_shl(&x, &s).unwrap(),
+                        d.bin_shr(&x, &s).unwrap(),
+                        d.bin_shr_arithmetic(&x, &s).unwrap(),
+                        d.bin_rotl(&x, &s).unwrap(),
+                        d.bin_rotr(&x, &s).unwrap(),
+                    ];
+                    out = d.bin_outputs(&zs).unwrap().unwrap();
+                }
+                assert_eq!(out, should_be, "x={} s={}", x, s);
+            }
+        }
+    }
+
     #[test] // bundle relu
     fn test_relu() {
         let mut rng = thread_rng();
@@ -412,6 +641,61 @@ mod bundle {
         }
     }
 
+    #[test]
+    fn crt_to_binary() {
+        let mut rng = thread_rng();
+        for _ in 0..128 {
+            let q = util::modulus_with_nprimes(2 + rng.gen_usize() % 12);
+            let x = rng.gen_u128() % q;
+            let nbits = 1 + rng.gen_usize() % 80;
+            let mut d = Dummy::new();
+            let xs = d.crt_encode(x, q).unwrap();
+            let z = d.crt_to_bin(&xs, nbits).unwrap();
+            let out = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(out, x % (1 << nbits), "x={} q={} nbits={}", x, q, nbits);
+
+            let z = d.crt_to_bin_signed(&xs, nbits).unwrap();
+            let out = d.bin_output(&z).unwrap().unwrap();
+            let signed = if x > q / 2 {
+                x as i128 - q as i128
+            } else {
+                x as i128
+            };
+            assert_eq!(out, signed as u128 % (1 << nbits), "x={} q={}", x, q);
+        }
+    }
+
+    #[test]
+    fn binary_to_crt() {
+        let mut rng = thread_rng();
+        for _ in 0..128 {
+            let q = util::modulus_with_nprimes(1 + rng.gen_usize() % 12);
+            let nbits = 1 + rng.gen_usize() % 64;
+            let x = rng.gen_u128() % (1 << nbits);
+            let mut d = Dummy::new();
+            let xs = d.bin_encode(x, nbits).unwrap();
+            let z = d.bin_to_crt(&xs, q).unwrap();
+            let out = d.crt_output(&z).unwrap().unwrap();
+            assert_eq!(out, x % q, "x={} q={}", x, q);
+
+            let z = d.bin_to_crt_signed(&xs, q).unwrap();
+            let out = d.crt_output(&z).unwrap().unwrap();
+            let signed = if x >> (nbits - 1) == 1 {
+                x as i128 - (1 << nbits)
+            } else {
+                x as i128
+            };
+            assert_eq!(out, signed.rem_euclid(q as i128) as u128, "x={} q={}", x, q);
+
+            // and back again
+            let z = d.crt_to_bin_signed(&z, nbits).unwrap();
+            let out = d.bin_output(&z).unwrap().unwrap();
+            if signed.abs() < q as i128 / 2 {
+                assert_eq!(out, x, "x={} q={}", x, q);
+            }
+        }
+    }
+
     #[test]
     fn binary_abs() {
         let mut rng = thread_rng();
@@ -544,3 +828,71 @@ mod bundle {
         }
     }
 }
+
+#[cfg(test)]
+mod proj2 {
+    use super::*;
+    use crate::util::RngExt;
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    /// A `Dummy` which uses the default implementation of `proj2`.
+    struct Unspecialized(Dummy);
+
+    impl Fancy for Unspecialized {
+        type Item = DummyVal;
+        type Error = DummyError;
+
+        fn constant(&mut self, val: u16, q: u16) -> Result<DummyVal, DummyError> {
+            self.0.constant(val, q)
+        }
+        fn add(&mut self, x: &DummyVal, y: &DummyVal) -> Result<DummyVal, DummyError> {
+            self.0.add(x, y)
+        }
+        fn sub(&mut self, x: &DummyVal, y: &DummyVal) -> Result<DummyVal, DummyError> {
+            self.0.sub(x, y)
+        }
+        fn cmul(&mut self, x: &DummyVal, c: u16) -> Result<DummyVal, DummyError> {
+            self.0.cmul(x, c)
+        }
+        fn mul(&mut self, x: &DummyVal, y: &DummyVal) -> Result<DummyVal, DummyError> {
+            self.0.mul(x, y)
+        }
+        fn proj(
+            &mut self,
+            x: &DummyVal,
+            q: u16,
+            tt: Option<Vec<u16>>,
+        ) -> Result<DummyVal, DummyError> {
+            self.0.proj(x, q, tt)
+        }
+        fn output(&mut self, x: &DummyVal) -> Result<Option<u16>, DummyError> {
+            self.0.output(x)
+        }
+    }
+
+    #[test]
+    fn default_matches_dummy() {
+        let mut rng = thread_rng();
+        let mut d = Dummy::new();
+        let mut u = Unspecialized(Dummy::new());
+        for _ in 0..64 {
+            let px = 2 + rng.gen_u16() % 12;
+            let py = 2 + rng.gen_u16() % 12;
+            let q = 2 + rng.gen_u16() % 12;
+            let tt = (0..px * py).map(|_| rng.gen_u16() % q).collect_vec();
+            for a in 0..px {
+                for b in 0..py {
+                    let x = DummyVal::new(a, px);
+                    let y = DummyVal::new(b, py);
+                    let z = d.proj2(&x, &y, q, Some(tt.clone())).unwrap();
+                    assert_eq!(z.val(), tt[(a * py + b) as usize]);
+                    assert_eq!(z.modulus(), q);
+                    let z = u.proj2(&x, &y, q, Some(tt.clone())).unwrap();
+                    assert_eq!(z.val(), tt[(a * py + b) as usize]);
+                    assert_eq!(z.modulus(), q);
+                }
+            }
+        }
+    }
+}
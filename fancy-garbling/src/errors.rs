@@ -65,6 +65,8 @@ pub enum EvaluatorError {
     DecodingFailed,
     /// A communication error has occurred.
     CommunicationError(String),
+    /// The garbler uses a different garbling scheme.
+    SchemeMismatch,
     /// A fancy error has occurred.
     FancyError(FancyError),
 }
@@ -74,8 +76,6 @@ pub enum EvaluatorError {
 pub enum GarblerError {
     /// An error occurred while processing a message.
     CommunicationError(String),
-    /// Asymmetric moduli error.
-    AsymmetricHalfGateModuliMax8(u16),
     /// A truth table was missing.
     TruthTableRequired,
     /// Delta required for wire reuse.
@@ -153,6 +153,7 @@ impl Display for EvaluatorError {
             EvaluatorError::NotEnoughEvaluatorInputs => "not enough evaluator inputs".fmt(f),
             EvaluatorError::DecodingFailed => write!(f, "decodiing failed"),
             EvaluatorError::CommunicationError(s) => write!(f, "communication error: {}", s),
+            EvaluatorError::SchemeMismatch => "garbler uses a different garbling scheme".fmt(f),
             EvaluatorError::FancyError(e) => write!(f, "fancy error: {}", e),
         }
     }
@@ -183,11 +184,6 @@ impl Display for GarblerError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             GarblerError::CommunicationError(s) => write!(f, "{}", s),
-            GarblerError::AsymmetricHalfGateModuliMax8(q) => write!(
-                f,
-                "the small modulus in a half gate with asymmetric moduli is capped at 8, got {}",
-                q
-            ),
             GarblerError::TruthTableRequired => {
                 "truth table required for garbler projection gates".fmt(f)
             }
@@ -243,7 +239,7 @@ impl From<FancyError> for CircuitBuilderError {
     }
 }
 
-/// Errors emitted by the circuit parser.
+/// Errors emitted by the circuit parser and writer.
 #[derive(Debug)]
 pub enum CircuitParserError {
     /// An I/O error occurred.
@@ -256,6 +252,12 @@ pub enum CircuitParserError {
     ParseLineError(String),
     /// An error occurred parsing a gate type.
     ParseGateError(String),
+    /// The circuit contains a wire whose modulus the file format cannot express.
+    UnsupportedModulus(u16),
+    /// The circuit contains a gate which the file format cannot express.
+    UnsupportedGate(String),
+    /// The circuit refers to a wire before it is computed.
+    UninitializedWire(usize),
 }
 
 impl Display for CircuitParserError {
@@ -266,6 +268,17 @@ impl Display for CircuitParserError {
             CircuitParserError::ParseIntError => write!(f, "unable to parse integer"),
             CircuitParserError::ParseLineError(s) => write!(f, "unable to parse line '{}'", s),
             CircuitParserError::ParseGateError(s) => write!(f, "unable to parse gate '{}'", s),
+            CircuitParserError::UnsupportedModulus(q) => {
+                write!(
+                    f,
+                    "unsupported modulus {}: only mod 2 wires are supported",
+                    q
+                )
+            }
+            CircuitParserError::UnsupportedGate(s) => write!(f, "unsupported gate '{}'", s),
+            CircuitParserError::UninitializedWire(ix) => {
+                write!(f, "wire {} is used before it is computed", ix)
+            }
         }
     }
 }
@@ -304,10 +317,25 @@ pub enum TwopacError {
     EvaluatorError(EvaluatorError),
     /// Processing the garbled circuit produced an error.
     FancyError(FancyError),
+    /// The other party deviated from the protocol.
+    CheatingDetected(String),
+    /// The outputs of the two executions of a dual execution do not match.
+    OutputMismatch,
 }
 
 impl std::error::Error for TwopacError {}
 
+impl From<scuttlebutt::cointoss::Error> for TwopacError {
+    fn from(e: scuttlebutt::cointoss::Error) -> TwopacError {
+        match e {
+            scuttlebutt::cointoss::Error::IoError(e) => TwopacError::IoError(e),
+            scuttlebutt::cointoss::Error::CommitmentCheckFailed => {
+                TwopacError::CheatingDetected("coin toss commitment check failed".to_string())
+            }
+        }
+    }
+}
+
 impl From<ocelot::Error> for TwopacError {
     fn from(e: ocelot::Error) -> TwopacError {
         TwopacError::OtError(e)
@@ -346,6 +374,8 @@ impl std::fmt::Display for TwopacError {
             TwopacError::EvaluatorError(e) => write!(f, "evaluator error: {}", e),
             TwopacError::GarblerError(e) => write!(f, "garbler error: {}", e),
             TwopacError::FancyError(e) => write!(f, "fancy error: {}", e),
+            TwopacError::CheatingDetected(s) => write!(f, "cheating detected: {}", s),
+            TwopacError::OutputMismatch => write!(f, "dual execution outputs do not match"),
         }
     }
 }
@@ -361,3 +391,129 @@ impl From<TwopacError> for EvaluatorError {
         EvaluatorError::CommunicationError(e.to_string())
     }
 }
+
+////////////////////////////////////////////////////////////////////////////////
+// classic errors
+
+/// Errors produced when reading or writing garbled circuits and encoders.
+#[derive(Debug)]
+pub enum ClassicError {
+    /// An I/O error has occurred.
+    IoError(std::io::Error),
+    /// The data does not start with the expected header.
+    InvalidHeader,
+    /// The data was written using an unsupported format version.
+    UnsupportedVersion(u16),
+    /// The data contains an invalid modulus.
+    InvalidModulus(u16),
+    /// The data contains an invalid wire-label.
+    InvalidWire,
+    /// An input wire has no associated delta.
+    MissingDelta(u16),
+    /// The data contains an invalid named group of inputs.
+    InvalidGroup,
+}
+
+impl std::error::Error for ClassicError {}
+
+impl Display for ClassicError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ClassicError::IoError(e) => write!(f, "IO error: {}", e),
+            ClassicError::InvalidHeader => "invalid header".fmt(f),
+            ClassicError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            ClassicError::InvalidModulus(q) => write!(f, "invalid modulus {}", q),
+            ClassicError::InvalidWire => "invalid wire-label".fmt(f),
+            ClassicError::MissingDelta(q) => write!(f, "no delta for modulus {}", q),
+            ClassicError::InvalidGroup => "invalid named group".fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for ClassicError {
+    fn from(e: std::io::Error) -> ClassicError {
+        ClassicError::IoError(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// zero-knowledge errors
+
+/// Errors produced by the zero-knowledge prover and verifier.
+#[derive(Debug)]
+pub enum ZkError {
+    /// An I/O error has occurred.
+    IoError(std::io::Error),
+    /// An oblivious transfer error has occurred.
+    OtError(ocelot::Error),
+    /// Processing the computation produced an error.
+    FancyError(FancyError),
+    /// Some output of the prover's computation is nonzero.
+    InvalidWitness,
+    /// The prover failed to convince the verifier.
+    InvalidProof,
+    /// The verifier did not garble the computation honestly.
+    CheatingDetected,
+}
+
+impl std::error::Error for ZkError {}
+
+impl Display for ZkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ZkError::IoError(e) => write!(f, "IO error: {}", e),
+            ZkError::OtError(e) => write!(f, "oblivious transfer error: {}", e),
+            ZkError::FancyError(e) => write!(f, "fancy error: {}", e),
+            ZkError::InvalidWitness => "witness does not satisfy the statement".fmt(f),
+            ZkError::InvalidProof => "invalid proof".fmt(f),
+            ZkError::CheatingDetected => "verifier garbled dishonestly".fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for ZkError {
+    fn from(e: std::io::Error) -> ZkError {
+        ZkError::IoError(e)
+    }
+}
+
+impl From<ocelot::Error> for ZkError {
+    fn from(e: ocelot::Error) -> ZkError {
+        ZkError::OtError(e)
+    }
+}
+
+impl From<FancyError> for ZkError {
+    fn from(e: FancyError) -> ZkError {
+        ZkError::FancyError(e)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// neural network model errors
+
+/// Errors produced when loading a neural network model.
+#[derive(Debug)]
+pub enum ModelError {
+    /// An I/O error has occurred.
+    IoError(std::io::Error),
+    /// The model description is invalid at the given line.
+    ParseError(usize, String),
+}
+
+impl std::error::Error for ModelError {}
+
+impl Display for ModelError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ModelError::IoError(e) => write!(f, "IO error: {}", e),
+            ModelError::ParseError(line, s) => write!(f, "line {}: {}", line, s),
+        }
+    }
+}
+
+impl From<std::io::Error> for ModelError {
+    fn from(e: std::io::Error) -> ModelError {
+        ModelError::IoError(e)
+    }
+}
@@ -12,16 +12,33 @@
 use crate::errors::FancyError;
 use itertools::Itertools;
 
+mod activation;
+mod aes;
+mod array;
 mod binary;
 mod bundle;
 mod crt;
+mod fixed;
+mod float;
 mod input;
+mod neural;
 mod reveal;
+mod sha256;
+mod sort;
+pub use activation::{ActivationGadgets, Approximation};
+pub use aes::AesGadgets;
+pub use array::ArrayGadgets;
 pub use binary::{BinaryBundle, BinaryGadgets};
 pub use bundle::{Bundle, BundleGadgets};
 pub use crt::{CrtBundle, CrtGadgets};
+pub use fixed::FixedGadgets;
+pub use float::FloatGadgets;
 pub use input::FancyInput;
+pub(crate) use neural::check_window;
+pub use neural::{Conv2d, Dense, NeuralGadgets, Pool, Shape};
 pub use reveal::FancyReveal;
+pub use sha256::Sha256Gadgets;
+pub use sort::SortGadgets;
 
 /// An object that has some modulus. Basic object of `Fancy` computations.
 pub trait HasModulus {
@@ -62,6 +79,43 @@ pub trait Fancy {
         tt: Option<Vec<u16>>,
     ) -> Result<Self::Item, Self::Error>;
 
+    /// Project `x` and `y` according to the two-input truth table `tt`, where
+    /// the output for `x = a` and `y = b` is `tt[a * y.modulus() + b]`.
+    /// Resulting wire has modulus `q`.
+    ///
+    /// Optional `tt` is useful for hiding the gate from the evaluator. The
+    /// default implementation uses a projection of `x` and of `y` and a
+    /// multiplication for each value of `x`.
+    fn proj2(
+        &mut self,
+        x: &Self::Item,
+        y: &Self::Item,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        let px = x.modulus() as usize;
+        let py = y.modulus() as usize;
+        if let Some(ref tt) = tt {
+            if tt.len() < px * py || tt.iter().any(|&v| v >= q) {
+                return Err(Self::Error::from(FancyError::InvalidTruthTable));
+            }
+        }
+        let mut z: Option<Self::Item> = None;
+        for a in 0..px {
+            // the row of `tt` for `x = a`, selected when `x == a`
+            let select = (0..px).map(|v| (v == a) as u16).collect_vec();
+            let select = self.proj(x, q, Some(select))?;
+            let row = tt.as_ref().map(|tt| tt[a * py..(a + 1) * py].to_vec());
+            let row = self.proj(y, q, row)?;
+            let term = self.mul(&select, &row)?;
+            z = Some(match z {
+                Some(z) => self.add(&z, &term)?,
+                None => term,
+            });
+        }
+        Ok(z.expect("moduli are at least 2"))
+    }
+
     /// Process this wire as output. Some `Fancy` implementors dont actually *return*
     /// output, but they need to be involved in the process, so they can return `None`.
     fn output(&mut self, x: &Self::Item) -> Result<Option<u16>, Self::Error>;
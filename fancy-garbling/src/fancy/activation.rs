@@ -0,0 +1,473 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `ActivationGadgets`, which approximate activation
+//! functions on the fixed-point CRT bundles of `FixedGadgets`.
+//!
+//! Each function is approximated on an interval `[lo, hi]` and held constant
+//! outside of it, using one of two methods given by an `Approximation`:
+//!
+//! * `PiecewiseLinear { segments }` interpolates linearly between
+//!   `segments + 1` evenly spaced points of `[lo, hi]`. It uses a `crt_relu`
+//!   for each point and a single rescaling.
+//! * `Polynomial { degree }` clamps its input to `[lo, hi]` using two
+//!   `crt_relu`s and evaluates the polynomial of degree `degree`
+//!   interpolating the function at the Chebyshev nodes of `[lo, hi]`. It uses
+//!   a multiplication and a rescaling for each degree.
+//!
+//! The comparisons use the fractional mixed radix trick of `crt_relu`, so the
+//! `accuracy` argument and the supported numbers of primes are those of
+//! `crt_relu`.
+//!
+//! # Error bounds
+//!
+//! With accuracy "100%", the output differs from the function applied to the
+//! fixed-point value of `x` by at most `E + 3 * 2^-frac_bits`, where `E` is
+//! the largest error of the real-valued approximation on `[lo, hi]`. The
+//! intervals of `crt_sigmoid` and `crt_tanh` are chosen so that the functions
+//! move by less than `2^-frac_bits` outside of them.
+//!
+//! For `PiecewiseLinear { segments }`, `E <= h^2 / 8 * max |f''|` where
+//! `h = (hi - lo) / segments`, and `max |f''|` is less than 0.1 for the
+//! sigmoid, 0.77 for `tanh` and `e^hi` for `exp`. For `Polynomial { degree }`,
+//! `E` decreases geometrically with the degree. For instance, with
+//! `frac_bits = 8`:
+//!
+//! | Function               | `E`, degree 8 | `E`, degree 12 |
+//! |------------------------|---------------|----------------|
+//! | `crt_sigmoid`          | 9.1e-3        | 1.1e-3         |
+//! | `crt_tanh`             | 3.0e-2        | 4.5e-3         |
+//! | `crt_exp`, `max = 1.0` | 8.6e-5        | 3.1e-8         |
+//!
+//! The intermediate values of the approximations need more bits with more
+//! fractional bits and higher degrees, and the gadgets return an error when
+//! they do not fit in the composite modulus of `x`. Since exact comparisons
+//! support at most 11 primes, the degree of `Polynomial` is limited to about
+//! 12 for `frac_bits = 8`.
+//!
+//! Lower accuracies make each comparison with a breakpoint of the
+//! approximation wrong for some inputs, in which case these bounds do not
+//! hold.
+
+use crate::{
+    errors::FancyError,
+    fancy::{crt::CrtBundle, fixed::FixedGadgets, Fancy},
+};
+use itertools::Itertools;
+use std::f64::consts::{LN_2, PI};
+
+/// How `ActivationGadgets` approximate a function on an interval.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Approximation {
+    /// Linear interpolation between evenly spaced points.
+    PiecewiseLinear {
+        /// The number of linear pieces.
+        segments: usize,
+    },
+    /// The polynomial interpolating the function at the Chebyshev nodes.
+    Polynomial {
+        /// The degree of the polynomial.
+        degree: usize,
+    },
+}
+
+impl<F: Fancy> ActivationGadgets for F {}
+
+/// Extension trait for `Fancy` providing approximate activation functions on
+/// fixed-point CRT bundles.
+pub trait ActivationGadgets: Fancy + FixedGadgets {
+    /// Compute the sigmoid `1 / (1 + e^-x)` of the fixed-point number `x` with
+    /// `frac_bits` fractional bits, approximated on the interval
+    /// `[-frac_bits * ln 2, frac_bits * ln 2]`.
+    fn crt_sigmoid(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+        approximation: Approximation,
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let r = frac_bits as f64 * LN_2;
+        approximate(
+            self,
+            x,
+            frac_bits,
+            sigmoid,
+            (-r, r),
+            approximation,
+            accuracy,
+        )
+    }
+
+    /// Compute `tanh(x)` of the fixed-point number `x` with `frac_bits`
+    /// fractional bits, approximated on the interval `[-r, r]` where
+    /// `r = (frac_bits + 1) * ln 2 / 2`.
+    fn crt_tanh(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+        approximation: Approximation,
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let r = (frac_bits + 1) as f64 * LN_2 / 2.0;
+        approximate(
+            self,
+            x,
+            frac_bits,
+            f64::tanh,
+            (-r, r),
+            approximation,
+            accuracy,
+        )
+    }
+
+    /// Compute `e^x` of the fixed-point number `x` with `frac_bits`
+    /// fractional bits, approximated on the interval
+    /// `[-frac_bits * ln 2, max]`. Inputs larger than `max` result in `e^max`.
+    fn crt_exp(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+        max: f64,
+        approximation: Approximation,
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let lo = -(frac_bits as f64) * LN_2;
+        approximate(
+            self,
+            x,
+            frac_bits,
+            f64::exp,
+            (lo, max),
+            approximation,
+            accuracy,
+        )
+    }
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Approximate `func` of the fixed-point number `x` on the interval
+/// `[lo, hi]`, which is widened to the nearest fixed-point numbers.
+fn approximate<F: FixedGadgets + ?Sized>(
+    f: &mut F,
+    x: &CrtBundle<F::Item>,
+    frac_bits: usize,
+    func: fn(f64) -> f64,
+    (lo, hi): (f64, f64),
+    approximation: Approximation,
+    accuracy: &str,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    let scale = 2f64.powi(frac_bits as i32);
+    let lo = (lo * scale).floor() as i128;
+    let hi = (hi * scale).ceil() as i128;
+    if lo >= hi {
+        return Err(F::Error::from(FancyError::InvalidArg(format!(
+            "empty interval with {} fractional bits",
+            frac_bits
+        ))));
+    }
+    match approximation {
+        Approximation::PiecewiseLinear { segments } => {
+            piecewise_linear(f, x, frac_bits, func, (lo, hi), segments, accuracy)
+        }
+        Approximation::Polynomial { degree } => {
+            polynomial(f, x, frac_bits, func, (lo, hi), degree, accuracy)
+        }
+    }
+}
+
+/// Linearly interpolate `func` between `segments + 1` evenly spaced points of
+/// `[lo, hi]`, given as fixed-point numbers.
+fn piecewise_linear<F: FixedGadgets + ?Sized>(
+    f: &mut F,
+    x: &CrtBundle<F::Item>,
+    frac_bits: usize,
+    func: fn(f64) -> f64,
+    (lo, hi): (i128, i128),
+    segments: usize,
+    accuracy: &str,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    if segments == 0 || segments as i128 > hi - lo {
+        return Err(F::Error::from(FancyError::InvalidArg(format!(
+            "cannot split an interval of {} fixed-point numbers into {} segments",
+            hi - lo,
+            segments
+        ))));
+    }
+    let q = x.composite_modulus();
+    let scale = 2f64.powi(frac_bits as i32);
+    let ts = (0..=segments)
+        .map(|i| lo + (hi - lo) * i as i128 / segments as i128)
+        .collect_vec();
+    let vs = ts.iter().map(|&t| func(t as f64 / scale)).collect_vec();
+
+    // The slopes get enough extra fractional bits that their rounding errors,
+    // accumulated over the interval, stay below 2^-(frac_bits + 1).
+    let width = (hi - lo) as f64 / scale;
+    let extra = width.log2().ceil().max(0.0) as usize + 1;
+    let bound = vs.iter().fold(0.0, |m: f64, v| m.max(v.abs())) + 1.0;
+    check_fits(bound, 2 * frac_bits + extra, q)?;
+    let slopes = ts
+        .windows(2)
+        .zip(vs.windows(2))
+        .map(|(t, v)| {
+            let slope = (v[1] - v[0]) * scale / (t[1] - t[0]) as f64;
+            (slope * 2f64.powi((frac_bits + extra) as i32)).round() as i128
+        })
+        .collect_vec();
+
+    // z = v_0 + sum_i (slope_i - slope_{i-1}) * relu(x - t_i), where the
+    // slopes before t_0 and after t_n are zero
+    let v0 = (vs[0] * 2f64.powi((2 * frac_bits + extra) as i32)).round() as i128;
+    let mut z = constant(f, v0, q)?;
+    let mut prev = 0;
+    for (i, &t) in ts.iter().enumerate() {
+        let slope = slopes.get(i).cloned().unwrap_or(0);
+        if slope != prev {
+            let t = constant(f, t, q)?;
+            let y = f.crt_sub(x, &t)?;
+            let y = f.crt_relu(&y, accuracy, None)?;
+            let y = f.crt_cmul(&y, modulo(slope - prev, q))?;
+            z = f.crt_add(&z, &y)?;
+        }
+        prev = slope;
+    }
+    f.fixed_rescale(&z, frac_bits + extra)
+}
+
+/// Evaluate the polynomial of degree `degree` interpolating `func` at the
+/// Chebyshev nodes of `[lo, hi]`, given as fixed-point numbers, on `x`
+/// clamped to that interval.
+fn polynomial<F: FixedGadgets + ?Sized>(
+    f: &mut F,
+    x: &CrtBundle<F::Item>,
+    frac_bits: usize,
+    func: fn(f64) -> f64,
+    (lo, hi): (i128, i128),
+    degree: usize,
+    accuracy: &str,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    let q = x.composite_modulus();
+    let scale = 2f64.powi(frac_bits as i32);
+    let cs = chebyshev(
+        |v| func(((lo + hi) as f64 + v * (hi - lo) as f64) / 2.0 / scale),
+        degree,
+    );
+
+    // Clenshaw's algorithm uses enough extra fractional bits that its
+    // rounding errors, each amplified at most `degree + 1` times, stay below
+    // 2^-(frac_bits + 1).
+    let extra = (1.5 * ((degree + 1) * (degree + 2)) as f64).log2().ceil() as usize;
+    let bits = frac_bits + extra;
+    let bound = cs
+        .iter()
+        .enumerate()
+        .map(|(k, c)| (k + 1) as f64 * c.abs())
+        .sum::<f64>();
+
+    // The polynomial is a function of v = (2x - lo - hi) / (hi - lo) in
+    // [-1, 1], whose rounding error of at most 1.25 * 2^-v_bits is amplified
+    // at most sum_k k^2 |c_k| times, which must stay below 2^-(frac_bits + 2).
+    let slope = cs
+        .iter()
+        .enumerate()
+        .map(|(k, c)| (k * k) as f64 * c.abs())
+        .sum::<f64>();
+    let v_bits = frac_bits + 2 + (1.25 * slope).log2().ceil().max(0.0) as usize;
+    let m = 128 - (hi - lo).leading_zeros() as usize + 1;
+    check_fits(1.0, v_bits + m, q)?;
+    check_fits(2.0 * bound + 1.0, bits + v_bits, q)?;
+
+    // clamp x to [lo, hi] as lo + relu(x - lo) - relu(x - hi), so that
+    // 2x - lo - hi = 2 relu(x - lo) - 2 relu(x - hi) + lo - hi
+    let lo_c = constant(f, lo, q)?;
+    let a = f.crt_sub(x, &lo_c)?;
+    let a = f.crt_relu(&a, accuracy, None)?;
+    let hi_c = constant(f, hi, q)?;
+    let b = f.crt_sub(x, &hi_c)?;
+    let b = f.crt_relu(&b, accuracy, None)?;
+    let v = f.crt_sub(&a, &b)?;
+    let v = f.crt_cmul(&v, 2)?;
+    let c = constant(f, lo - hi, q)?;
+    let v = f.crt_add(&v, &c)?;
+    let c = (2f64.powi((v_bits + m) as i32) / (hi - lo) as f64).round() as u128;
+    let v = f.crt_cmul(&v, c)?;
+    let v = f.fixed_rescale(&v, m)?;
+
+    // b_k = c_k + 2v b_{k+1} - b_{k+2}, and the result is c_0 + v b_1 - b_2
+    let coef = |f: &mut F, k: usize| {
+        let c = (cs[k] * 2f64.powi(bits as i32)).round() as i128;
+        constant(f, c, q)
+    };
+    let mut b1 = coef(f, degree)?;
+    let mut b2 = None;
+    for k in (0..degree).rev() {
+        let z = f.crt_mul(&b1, &v)?;
+        let z = if k > 0 { f.crt_cmul(&z, 2)? } else { z };
+        let z = f.fixed_rescale(&z, v_bits)?;
+        let c = coef(f, k)?;
+        let mut z = f.crt_add(&z, &c)?;
+        if let Some(b2) = b2 {
+            z = f.crt_sub(&z, &b2)?;
+        }
+        b2 = Some(b1);
+        b1 = z;
+    }
+    f.fixed_rescale(&b1, extra)
+}
+
+/// The coefficients `c_k` of the polynomial `sum_k c_k T_k(v)` of degree
+/// `degree` interpolating `func` at the Chebyshev nodes of `[-1, 1]`, where
+/// `T_k` is the `k`-th Chebyshev polynomial.
+fn chebyshev<G: Fn(f64) -> f64>(func: G, degree: usize) -> Vec<f64> {
+    let n = degree + 1;
+    let angle = |k: usize, j: usize| PI * k as f64 * (j as f64 + 0.5) / n as f64;
+    let ys = (0..n).map(|j| func(angle(1, j).cos())).collect_vec();
+    (0..n)
+        .map(|k| {
+            let weight = if k == 0 { 1.0 } else { 2.0 } / n as f64;
+            weight * (0..n).map(|j| ys[j] * angle(k, j).cos()).sum::<f64>()
+        })
+        .collect()
+}
+
+/// Check that numbers less than `bound` in absolute value with `bits`
+/// fractional bits fit in the signed range of `q`.
+fn check_fits<E: From<FancyError>>(bound: f64, bits: usize, q: u128) -> Result<(), E> {
+    if bound * 2f64.powi(bits as i32) >= (q / 2) as f64 {
+        return Err(E::from(FancyError::InvalidArg(format!(
+            "modulus {} is too small for {} bits of precision",
+            q, bits
+        ))));
+    }
+    Ok(())
+}
+
+fn modulo(x: i128, q: u128) -> u128 {
+    x.rem_euclid(q as i128) as u128
+}
+
+fn constant<F: FixedGadgets + ?Sized>(
+    f: &mut F,
+    x: i128,
+    q: u128,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    f.crt_constant_bundle(modulo(x, q), q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::{Dummy, DummyVal},
+        fancy::FancyInput,
+        util,
+    };
+    use rand::{thread_rng, Rng};
+
+    const FRAC_BITS: usize = 8;
+
+    /// The largest error of `gadget` against `func` on random fixed-point
+    /// inputs in `[lo, hi]` and on its ends.
+    fn max_error<G>(func: fn(f64) -> f64, (lo, hi): (f64, f64), gadget: G) -> f64
+    where
+        G: Fn(&mut Dummy, &CrtBundle<DummyVal>) -> CrtBundle<DummyVal>,
+    {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(11);
+        let scale = 2f64.powi(FRAC_BITS as i32);
+        let mut xs = (0..64).map(|_| rng.gen_range(lo, hi)).collect_vec();
+        xs.extend(&[lo, hi, 0.0]);
+        xs.into_iter()
+            .map(|x| {
+                let x = (x * scale).round() / scale;
+                let mut d = Dummy::new();
+                let xs = d.fixed_encode(x, FRAC_BITS, q).unwrap();
+                let z = gadget(&mut d, &xs);
+                let out = d.fixed_output(&z, FRAC_BITS).unwrap().unwrap();
+                (out - func(x)).abs()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    /// The documented bound for the real-valued approximation error `e`.
+    fn bound(e: f64) -> f64 {
+        e + 3.0 * 2f64.powi(-(FRAC_BITS as i32))
+    }
+
+    #[test]
+    fn sigmoid_piecewise_linear() {
+        let width = 2.0 * FRAC_BITS as f64 * LN_2;
+        for &segments in [4, 16, 64].iter() {
+            let a = Approximation::PiecewiseLinear { segments };
+            let e = max_error(sigmoid, (-12.0, 12.0), |d, x| {
+                d.crt_sigmoid(x, FRAC_BITS, a, "100%").unwrap()
+            });
+            let h = width / segments as f64;
+            assert!(
+                e <= bound(h * h / 8.0 * 0.1),
+                "segments={} e={}",
+                segments,
+                e
+            );
+        }
+    }
+
+    #[test]
+    fn tanh_and_exp_piecewise_linear() {
+        let segments = 32;
+        let a = Approximation::PiecewiseLinear { segments };
+        let e = max_error(f64::tanh, (-8.0, 8.0), |d, x| {
+            d.crt_tanh(x, FRAC_BITS, a, "100%").unwrap()
+        });
+        let h = (FRAC_BITS + 1) as f64 * LN_2 / segments as f64;
+        assert!(e <= bound(h * h / 8.0 * 0.77), "tanh e={}", e);
+
+        let max = 2.0;
+        let e = max_error(f64::exp, (-8.0, max), |d, x| {
+            d.crt_exp(x, FRAC_BITS, max, a, "100%").unwrap()
+        });
+        let h = (max + FRAC_BITS as f64 * LN_2) / segments as f64;
+        assert!(e <= bound(h * h / 8.0 * max.exp()), "exp e={}", e);
+    }
+
+    #[test]
+    fn polynomial_error_bounds() {
+        // the bounds on `E` documented for `frac_bits = 8`
+        for &(degree, e_sigmoid, e_tanh, e_exp) in
+            [(8, 9.1e-3, 3.0e-2, 8.6e-5), (12, 1.1e-3, 4.5e-3, 3.1e-8)].iter()
+        {
+            let a = Approximation::Polynomial { degree };
+            let e = max_error(sigmoid, (-12.0, 12.0), |d, x| {
+                d.crt_sigmoid(x, FRAC_BITS, a, "100%").unwrap()
+            });
+            assert!(e <= bound(e_sigmoid), "sigmoid degree={} e={}", degree, e);
+            let e = max_error(f64::tanh, (-8.0, 8.0), |d, x| {
+                d.crt_tanh(x, FRAC_BITS, a, "100%").unwrap()
+            });
+            assert!(e <= bound(e_tanh), "tanh degree={} e={}", degree, e);
+            let e = max_error(f64::exp, (-8.0, 1.0), |d, x| {
+                d.crt_exp(x, FRAC_BITS, 1.0, a, "100%").unwrap()
+            });
+            assert!(e <= bound(e_exp), "exp degree={} e={}", degree, e);
+        }
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        let q = util::modulus_with_nprimes(5);
+        let mut d = Dummy::new();
+        let x = d.fixed_encode(0.5, FRAC_BITS, q).unwrap();
+        let a = Approximation::Polynomial { degree: 12 };
+        assert!(d.crt_sigmoid(&x, FRAC_BITS, a, "100%").is_err());
+        let a = Approximation::PiecewiseLinear { segments: 0 };
+        assert!(d.crt_tanh(&x, FRAC_BITS, a, "100%").is_err());
+        let a = Approximation::PiecewiseLinear { segments: 4 };
+        assert!(d.crt_exp(&x, FRAC_BITS, -8.0, a, "100%").is_err());
+    }
+}
@@ -0,0 +1,469 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `AesGadgets`, which implement the AES-128 block cipher
+//! over binary bundles.
+//!
+//! Blocks and keys are `BinaryBundle`s of 128 bits holding 16 bytes in order,
+//! each byte least significant bit first, so that the bytes `b` are encoded as
+//! `bin_encode(u128::from_le_bytes(b), 128)`. The S-box is the depth-16 circuit
+//! of Boyar and Peralta, which uses 34 AND gates per byte.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        binary::{BinaryBundle, BinaryGadgets},
+        Fancy,
+        HasModulus,
+    },
+};
+
+impl<F: Fancy> AesGadgets for F {}
+
+/// Extension trait for `Fancy` providing the AES-128 block cipher.
+pub trait AesGadgets: Fancy + BinaryGadgets {
+    /// Expand the 128-bit `key` into the 11 round keys of AES-128.
+    fn aes128_key_schedule(
+        &mut self,
+        key: &BinaryBundle<Self::Item>,
+    ) -> Result<Vec<BinaryBundle<Self::Item>>, Self::Error> {
+        check_block(key)?;
+        let mut words: Vec<Vec<Self::Item>> = key.wires().chunks(32).map(<[_]>::to_vec).collect();
+        for i in 4..4 * ROUNDS + 4 {
+            let mut w = words[i - 1].clone();
+            if i % 4 == 0 {
+                w.rotate_left(8);
+                let mut subbed = Vec::with_capacity(32);
+                for byte in w.chunks(8) {
+                    subbed.extend(sbox(self, byte)?);
+                }
+                w = xor_constant(self, &subbed, RCON[i / 4 - 1] as u32)?;
+            }
+            words.push(xor_bits(self, &words[i - 4], &w)?);
+        }
+        Ok(words
+            .chunks(4)
+            .map(|ws| BinaryBundle::new(ws.concat()))
+            .collect())
+    }
+
+    /// Encrypt the 128-bit `block` under the `round_keys` produced by
+    /// `aes128_key_schedule`.
+    fn aes128_encrypt(
+        &mut self,
+        block: &BinaryBundle<Self::Item>,
+        round_keys: &[BinaryBundle<Self::Item>],
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        check_block(block)?;
+        if round_keys.len() != ROUNDS + 1 {
+            return Err(Self::Error::from(FancyError::InvalidArgNum {
+                got: round_keys.len(),
+                needed: ROUNDS + 1,
+            }));
+        }
+        for key in round_keys {
+            check_block(key)?;
+        }
+        let mut state = xor_bits(self, block.wires(), round_keys[0].wires())?;
+        for (round, key) in round_keys.iter().enumerate().skip(1) {
+            let mut subbed = Vec::with_capacity(16);
+            for byte in state.chunks(8) {
+                subbed.push(sbox(self, byte)?);
+            }
+            let mut bytes = shift_rows(subbed);
+            if round < ROUNDS {
+                bytes = mix_columns(self, &bytes)?;
+            }
+            state = xor_bits(self, &bytes.concat(), key.wires())?;
+        }
+        Ok(BinaryBundle::new(state))
+    }
+
+    /// Encrypt the 128-bit `block` under the secret 128-bit `key`.
+    fn aes128(
+        &mut self,
+        block: &BinaryBundle<Self::Item>,
+        key: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let round_keys = self.aes128_key_schedule(key)?;
+        self.aes128_encrypt(block, &round_keys)
+    }
+
+    /// Encrypt the 128-bit `block` under the public `key`.
+    ///
+    /// The key schedule is computed in the clear, so only the rounds cost gates.
+    fn aes128_public_key(
+        &mut self,
+        block: &BinaryBundle<Self::Item>,
+        key: &[u8; 16],
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let round_keys = key_schedule(key)
+            .iter()
+            .map(|k| self.bin_constant_bundle(u128::from_le_bytes(*k), 128))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.aes128_encrypt(block, &round_keys)
+    }
+}
+
+/// Number of rounds of AES-128.
+const ROUNDS: usize = 10;
+
+/// Round constants of the AES-128 key schedule.
+const RCON: [u8; ROUNDS] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+fn check_block<W: Clone + HasModulus>(x: &BinaryBundle<W>) -> Result<(), FancyError> {
+    if x.size() != 128 {
+        return Err(FancyError::InvalidArg(format!(
+            "AES blocks and keys have 128 bits, got {}",
+            x.size()
+        )));
+    }
+    Ok(())
+}
+
+/// Xor two equal-length vectors of bits.
+pub(super) fn xor_bits<F: Fancy + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    xs.iter().zip(ys).map(|(x, y)| f.xor(x, y)).collect()
+}
+
+/// Xor the bits of the public `c` into `xs`, least significant bit first.
+pub(super) fn xor_constant<F: Fancy + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    c: u32,
+) -> Result<Vec<F::Item>, F::Error> {
+    xs.iter()
+        .enumerate()
+        .map(|(i, x)| {
+            if i < 32 && (c >> i) & 1 == 1 {
+                f.negate(x)
+            } else {
+                Ok(x.clone())
+            }
+        })
+        .collect()
+}
+
+/// Move byte `r + 4c` of the state, in row `r` and column `c`, to column
+/// `c - r`.
+fn shift_rows<W: Clone>(bytes: Vec<Vec<W>>) -> Vec<Vec<W>> {
+    (0..16)
+        .map(|i| {
+            let (r, c) = (i % 4, i / 4);
+            bytes[r + 4 * ((c + r) % 4)].clone()
+        })
+        .collect()
+}
+
+/// Multiply each column of the state by the MDS matrix of AES.
+fn mix_columns<F: Fancy + ?Sized>(
+    f: &mut F,
+    bytes: &[Vec<F::Item>],
+) -> Result<Vec<Vec<F::Item>>, F::Error> {
+    let mut out = Vec::with_capacity(16);
+    for col in bytes.chunks(4) {
+        // 2 a_i + 3 a_{i+1} + a_{i+2} + a_{i+3}
+        //   = xtime(a_i + a_{i+1}) + a_{i+1} + a_{i+2} + a_{i+3}
+        for i in 0..4 {
+            let sum = xor_bits(f, &col[i], &col[(i + 1) % 4])?;
+            let doubled = xtime(f, &sum)?;
+            let rest = xor_bits(f, &col[(i + 2) % 4], &col[(i + 3) % 4])?;
+            let rest = xor_bits(f, &rest, &col[(i + 1) % 4])?;
+            out.push(xor_bits(f, &doubled, &rest)?);
+        }
+    }
+    Ok(out)
+}
+
+/// Multiply a byte by `x` in GF(2^8), reducing by `x^8 + x^4 + x^3 + x + 1`.
+fn xtime<F: Fancy + ?Sized>(f: &mut F, b: &[F::Item]) -> Result<Vec<F::Item>, F::Error> {
+    Ok(vec![
+        b[7].clone(),
+        f.xor(&b[0], &b[7])?,
+        b[1].clone(),
+        f.xor(&b[2], &b[7])?,
+        f.xor(&b[3], &b[7])?,
+        b[4].clone(),
+        b[5].clone(),
+        b[6].clone(),
+    ])
+}
+
+/// The AES S-box on one byte, least significant bit first.
+fn sbox<F: Fancy + ?Sized>(f: &mut F, byte: &[F::Item]) -> Result<Vec<F::Item>, F::Error> {
+    // The circuit numbers bits from the most significant one.
+    let u7 = byte[0].clone();
+    let u6 = byte[1].clone();
+    let u5 = byte[2].clone();
+    let u4 = byte[3].clone();
+    let u3 = byte[4].clone();
+    let u2 = byte[5].clone();
+    let u1 = byte[6].clone();
+    let u0 = byte[7].clone();
+    let t1 = f.xor(&u0, &u3)?;
+    let t2 = f.xor(&u0, &u5)?;
+    let t3 = f.xor(&u0, &u6)?;
+    let t4 = f.xor(&u3, &u5)?;
+    let t5 = f.xor(&u4, &u6)?;
+    let t6 = f.xor(&t1, &t5)?;
+    let t7 = f.xor(&u1, &u2)?;
+    let t8 = f.xor(&u7, &t6)?;
+    let t9 = f.xor(&u7, &t7)?;
+    let t10 = f.xor(&t6, &t7)?;
+    let t11 = f.xor(&u1, &u5)?;
+    let t12 = f.xor(&u2, &u5)?;
+    let t13 = f.xor(&t3, &t4)?;
+    let t14 = f.xor(&t6, &t11)?;
+    let t15 = f.xor(&t5, &t11)?;
+    let t16 = f.xor(&t5, &t12)?;
+    let t17 = f.xor(&t9, &t16)?;
+    let t18 = f.xor(&u3, &u7)?;
+    let t19 = f.xor(&t7, &t18)?;
+    let t20 = f.xor(&t1, &t19)?;
+    let t21 = f.xor(&u6, &u7)?;
+    let t22 = f.xor(&t7, &t21)?;
+    let t23 = f.xor(&t2, &t22)?;
+    let t24 = f.xor(&t2, &t10)?;
+    let t25 = f.xor(&t20, &t17)?;
+    let t26 = f.xor(&t3, &t16)?;
+    let t27 = f.xor(&t1, &t12)?;
+    let m1 = f.and(&t13, &t6)?;
+    let m2 = f.and(&t23, &t8)?;
+    let m3 = f.xor(&t14, &m1)?;
+    let m4 = f.and(&t19, &u7)?;
+    let m5 = f.xor(&m4, &m1)?;
+    let m6 = f.and(&t3, &t16)?;
+    let m7 = f.and(&t22, &t9)?;
+    let m8 = f.xor(&t26, &m6)?;
+    let m9 = f.and(&t20, &t17)?;
+    let m10 = f.xor(&m9, &m6)?;
+    let m11 = f.and(&t1, &t15)?;
+    let m12 = f.and(&t4, &t27)?;
+    let m13 = f.xor(&m12, &m11)?;
+    let m14 = f.and(&t2, &t10)?;
+    let m15 = f.xor(&m14, &m11)?;
+    let m16 = f.xor(&m3, &m2)?;
+    let m17 = f.xor(&m5, &t24)?;
+    let m18 = f.xor(&m8, &m7)?;
+    let m19 = f.xor(&m10, &m15)?;
+    let m20 = f.xor(&m16, &m13)?;
+    let m21 = f.xor(&m17, &m15)?;
+    let m22 = f.xor(&m18, &m13)?;
+    let m23 = f.xor(&m19, &t25)?;
+    let m24 = f.xor(&m22, &m23)?;
+    let m25 = f.and(&m22, &m20)?;
+    let m26 = f.xor(&m21, &m25)?;
+    let m27 = f.xor(&m20, &m21)?;
+    let m28 = f.xor(&m23, &m25)?;
+    let m29 = f.and(&m28, &m27)?;
+    let m30 = f.and(&m26, &m24)?;
+    let m31 = f.and(&m20, &m23)?;
+    let m32 = f.and(&m27, &m31)?;
+    let m33 = f.xor(&m27, &m25)?;
+    let m34 = f.and(&m21, &m22)?;
+    let m35 = f.and(&m24, &m34)?;
+    let m36 = f.xor(&m24, &m25)?;
+    let m37 = f.xor(&m21, &m29)?;
+    let m38 = f.xor(&m32, &m33)?;
+    let m39 = f.xor(&m23, &m30)?;
+    let m40 = f.xor(&m35, &m36)?;
+    let m41 = f.xor(&m38, &m40)?;
+    let m42 = f.xor(&m37, &m39)?;
+    let m43 = f.xor(&m37, &m38)?;
+    let m44 = f.xor(&m39, &m40)?;
+    let m45 = f.xor(&m42, &m41)?;
+    let m46 = f.and(&m44, &t6)?;
+    let m47 = f.and(&m40, &t8)?;
+    let m48 = f.and(&m39, &u7)?;
+    let m49 = f.and(&m43, &t16)?;
+    let m50 = f.and(&m38, &t9)?;
+    let m51 = f.and(&m37, &t17)?;
+    let m52 = f.and(&m42, &t15)?;
+    let m53 = f.and(&m45, &t27)?;
+    let m54 = f.and(&m41, &t10)?;
+    let m55 = f.and(&m44, &t13)?;
+    let m56 = f.and(&m40, &t23)?;
+    let m57 = f.and(&m39, &t19)?;
+    let m58 = f.and(&m43, &t3)?;
+    let m59 = f.and(&m38, &t22)?;
+    let m60 = f.and(&m37, &t20)?;
+    let m61 = f.and(&m42, &t1)?;
+    let m62 = f.and(&m45, &t4)?;
+    let m63 = f.and(&m41, &t2)?;
+    let l0 = f.xor(&m61, &m62)?;
+    let l1 = f.xor(&m50, &m56)?;
+    let l2 = f.xor(&m46, &m48)?;
+    let l3 = f.xor(&m47, &m55)?;
+    let l4 = f.xor(&m54, &m58)?;
+    let l5 = f.xor(&m49, &m61)?;
+    let l6 = f.xor(&m62, &l5)?;
+    let l7 = f.xor(&m46, &l3)?;
+    let l8 = f.xor(&m51, &m59)?;
+    let l9 = f.xor(&m52, &m53)?;
+    let l10 = f.xor(&m53, &l4)?;
+    let l11 = f.xor(&m60, &l2)?;
+    let l12 = f.xor(&m48, &m51)?;
+    let l13 = f.xor(&m50, &l0)?;
+    let l14 = f.xor(&m52, &m61)?;
+    let l15 = f.xor(&m55, &l1)?;
+    let l16 = f.xor(&m56, &l0)?;
+    let l17 = f.xor(&m57, &l1)?;
+    let l18 = f.xor(&m58, &l8)?;
+    let l19 = f.xor(&m63, &l4)?;
+    let l20 = f.xor(&l0, &l1)?;
+    let l21 = f.xor(&l1, &l7)?;
+    let l22 = f.xor(&l3, &l12)?;
+    let l23 = f.xor(&l18, &l2)?;
+    let l24 = f.xor(&l15, &l9)?;
+    let l25 = f.xor(&l6, &l10)?;
+    let l26 = f.xor(&l7, &l9)?;
+    let l27 = f.xor(&l8, &l10)?;
+    let l28 = f.xor(&l11, &l14)?;
+    let l29 = f.xor(&l11, &l17)?;
+    let s0 = f.xor(&l6, &l24)?;
+    let s1 = f.xor(&l16, &l26)?;
+    let s1 = f.negate(&s1)?;
+    let s2 = f.xor(&l19, &l28)?;
+    let s2 = f.negate(&s2)?;
+    let s3 = f.xor(&l6, &l21)?;
+    let s4 = f.xor(&l20, &l22)?;
+    let s5 = f.xor(&l25, &l29)?;
+    let s6 = f.xor(&l13, &l27)?;
+    let s6 = f.negate(&s6)?;
+    let s7 = f.xor(&l6, &l23)?;
+    let s7 = f.negate(&s7)?;
+    Ok(vec![s7, s6, s5, s4, s3, s2, s1, s0])
+}
+
+/// Multiply in GF(2^8), modulo `x^8 + x^4 + x^3 + x + 1`.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut p = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            p ^= a;
+        }
+        a = (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 };
+        b >>= 1;
+    }
+    p
+}
+
+/// The AES S-box in the clear: inversion in GF(2^8) followed by an affine map.
+fn sbox_plain(x: u8) -> u8 {
+    // x^254 is the inverse of x, and maps 0 to 0.
+    let mut inv = 1;
+    for _ in 0..254 {
+        inv = gf_mul(inv, x);
+    }
+    inv ^ inv.rotate_left(1) ^ inv.rotate_left(2) ^ inv.rotate_left(3) ^ inv.rotate_left(4) ^ 0x63
+}
+
+/// The AES-128 key schedule in the clear.
+fn key_schedule(key: &[u8; 16]) -> Vec<[u8; 16]> {
+    let mut words: Vec<[u8; 4]> = key.chunks(4).map(|w| [w[0], w[1], w[2], w[3]]).collect();
+    for i in 4..4 * ROUNDS + 4 {
+        let mut w = words[i - 1];
+        if i % 4 == 0 {
+            w.rotate_left(1);
+            for b in w.iter_mut() {
+                *b = sbox_plain(*b);
+            }
+            w[0] ^= RCON[i / 4 - 1];
+        }
+        for (j, b) in w.iter_mut().enumerate() {
+            *b ^= words[i - 4][j];
+        }
+        words.push(w);
+    }
+    words
+        .chunks(4)
+        .map(|ws| {
+            let mut k = [0; 16];
+            for (j, w) in ws.iter().enumerate() {
+                k[4 * j..4 * j + 4].copy_from_slice(w);
+            }
+            k
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        dummy::Dummy,
+        fancy::{BinaryBundle, FancyInput},
+        util,
+    };
+    use rand::{thread_rng, Rng};
+    use scuttlebutt::{Aes128, Block};
+
+    const KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAINTEXT: [u8; 16] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+    const CIPHERTEXT: [u8; 16] = [
+        0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5,
+        0x5a,
+    ];
+
+    #[test]
+    fn sbox_table() {
+        // Entries from FIPS-197, Figure 7.
+        assert_eq!(sbox_plain(0x00), 0x63);
+        assert_eq!(sbox_plain(0x53), 0xed);
+        assert_eq!(sbox_plain(0xff), 0x16);
+        let mut d = Dummy::new();
+        for x in 0..=255u8 {
+            let byte = d.bin_encode(x as u128, 8).unwrap();
+            let out = BinaryBundle::new(sbox(&mut d, byte.wires()).unwrap());
+            assert_eq!(d.bin_output(&out).unwrap().unwrap(), sbox_plain(x) as u128);
+        }
+    }
+
+    #[test]
+    fn fips197() {
+        let mut d = Dummy::new();
+        let block = d.bin_encode(u128::from_le_bytes(PLAINTEXT), 128).unwrap();
+        let key = d.bin_encode(u128::from_le_bytes(KEY), 128).unwrap();
+        let expected = u128::from_le_bytes(CIPHERTEXT);
+        let secret = d.aes128(&block, &key).unwrap();
+        assert_eq!(d.bin_output(&secret).unwrap().unwrap(), expected);
+        let public = d.aes128_public_key(&block, &KEY).unwrap();
+        assert_eq!(d.bin_output(&public).unwrap().unwrap(), expected);
+    }
+
+    #[test]
+    fn circuit_matches_scuttlebutt() {
+        let mut rng = thread_rng();
+        let mut b = CircuitBuilder::new();
+        let block = b.bin_garbler_input(128);
+        let key = b.bin_evaluator_input(128);
+        let out = b.aes128(&block, &key).unwrap();
+        b.bin_output(&out).unwrap();
+        let circ = b.finish();
+        for _ in 0..4 {
+            let m = rng.gen::<u128>();
+            let k = rng.gen::<u128>();
+            let expected =
+                Aes128::new(Block::from(k.to_le_bytes())).encrypt(Block::from(m.to_le_bytes()));
+            let expected = u128::from_le_bytes(<[u8; 16]>::from(expected));
+            let out = circ
+                .eval_plain(&util::u128_to_bits(m, 128), &util::u128_to_bits(k, 128))
+                .unwrap();
+            assert_eq!(util::u128_from_bits(&out), expected);
+        }
+    }
+}
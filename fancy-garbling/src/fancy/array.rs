@@ -0,0 +1,408 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `ArrayGadgets`, which read and write arrays of bundles
+//! at secret indices.
+//!
+//! Every access touches each element of the array, so the gates computed only
+//! depend on the length of the array and the bitlength of the index.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        binary::{BinaryBundle, BinaryGadgets},
+        bundle::{Bundle, BundleGadgets},
+        Fancy,
+        HasModulus,
+    },
+};
+use std::ops::Deref;
+
+impl<F: Fancy> ArrayGadgets for F {}
+
+/// Extension trait for `Fancy` providing access to arrays of bundles at
+/// secret indices.
+///
+/// The bundles of an array must all have the same moduli. Indices are
+/// unsigned binary bundles, and reading at an index past the end of the array
+/// returns zero while writing there leaves the array unchanged.
+pub trait ArrayGadgets: Fancy + BundleGadgets + BinaryGadgets {
+    /// Return `array[idx]`.
+    ///
+    /// Uses a tree of multiplexers on the bits of `idx`, costing one
+    /// multiplication per wire for each element of `array`.
+    fn oblivious_read<B>(
+        &mut self,
+        array: &[B],
+        idx: &BinaryBundle<Self::Item>,
+    ) -> Result<B, Self::Error>
+    where
+        B: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+    {
+        check_array(array)?;
+        let bits = idx.wires();
+        let nbits = index_bits(array.len(), bits.len());
+        let mut xs = array.iter().map(|x| x.wires().to_vec()).collect::<Vec<_>>();
+        for b in bits[..nbits].iter() {
+            // x' = x0 + b(x1 - x0), where an unpaired last element is paired
+            // with zero
+            let mut next = Vec::with_capacity(xs.len().div_ceil(2));
+            for pair in xs.chunks(2) {
+                let mut zs = Vec::with_capacity(pair[0].len());
+                for (i, x0) in pair[0].iter().enumerate() {
+                    let z = match pair.get(1) {
+                        Some(x1) => {
+                            let d = self.sub(&x1[i], x0)?;
+                            let d = self.mul(&d, b)?;
+                            self.add(x0, &d)?
+                        }
+                        None => {
+                            let d = self.mul(x0, b)?;
+                            self.sub(x0, &d)?
+                        }
+                    };
+                    zs.push(z);
+                }
+                next.push(zs);
+            }
+            xs = next;
+        }
+        // an index too narrow to address the whole array leaves several
+        // groups, of which the first holds the elements it can address
+        let z = Bundle::new(xs.swap_remove(0));
+        match in_range(self, &bits[nbits..])? {
+            Some(b) => Ok(B::from(self.mask(&b, &z)?)),
+            None => Ok(B::from(z)),
+        }
+    }
+
+    /// Return a copy of `array` with `array[idx]` replaced by `value`.
+    ///
+    /// Costs one multiplication per wire for each element of `array`, plus
+    /// about two AND gates per element to compare `idx` with each position.
+    fn oblivious_write<B>(
+        &mut self,
+        array: &[B],
+        idx: &BinaryBundle<Self::Item>,
+        value: &B,
+    ) -> Result<Vec<B>, Self::Error>
+    where
+        B: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+    {
+        self.oblivious_write_many(
+            array,
+            std::slice::from_ref(idx),
+            std::slice::from_ref(value),
+        )
+    }
+
+    /// Return `array[idx]` for each index in `idxs`.
+    ///
+    /// Each read is a linear scan which compares the index with every
+    /// position and sums the selected element. This costs about two AND
+    /// gates per element more than `oblivious_read`.
+    fn oblivious_read_many<B>(
+        &mut self,
+        array: &[B],
+        idxs: &[BinaryBundle<Self::Item>],
+    ) -> Result<Vec<B>, Self::Error>
+    where
+        B: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+    {
+        check_array(array)?;
+        let mut zs = Vec::with_capacity(idxs.len());
+        for idx in idxs.iter() {
+            let sels = selectors(self, idx.wires(), array.len())?;
+            let mut z = self.mask(&sels[0], &array[0])?;
+            for (s, x) in sels.iter().zip(array.iter()).skip(1) {
+                let y = self.mask(s, x)?;
+                z = self.add_bundles(&z, &y)?;
+            }
+            zs.push(B::from(z));
+        }
+        Ok(zs)
+    }
+
+    /// Return a copy of `array` where `array[idxs[i]]` is replaced by
+    /// `values[i]` for each `i` in turn, so that later writes to the same
+    /// index take precedence.
+    ///
+    /// Each write is a linear scan which compares the index with every
+    /// position and conditionally replaces the element.
+    fn oblivious_write_many<B>(
+        &mut self,
+        array: &[B],
+        idxs: &[BinaryBundle<Self::Item>],
+        values: &[B],
+    ) -> Result<Vec<B>, Self::Error>
+    where
+        B: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+    {
+        check_array(array)?;
+        if idxs.len() != values.len() {
+            return Err(Self::Error::from(FancyError::InvalidArgNum {
+                got: values.len(),
+                needed: idxs.len(),
+            }));
+        }
+        if values.iter().any(|v| v.moduli() != array[0].moduli()) {
+            return Err(Self::Error::from(FancyError::UnequalModuli));
+        }
+        let mut array = array.to_vec();
+        for (idx, value) in idxs.iter().zip(values.iter()) {
+            let sels = selectors(self, idx.wires(), array.len())?;
+            for (s, x) in sels.iter().zip(array.iter_mut()) {
+                // x' = x + s(v - x)
+                let mut zs = Vec::with_capacity(x.size());
+                for (xw, vw) in x.wires().iter().zip(value.wires().iter()) {
+                    let d = self.sub(vw, xw)?;
+                    let d = self.mul(&d, s)?;
+                    zs.push(self.add(xw, &d)?);
+                }
+                *x = B::from(Bundle::new(zs));
+            }
+        }
+        Ok(array)
+    }
+}
+
+/// Check that `array` is nonempty and that its bundles have the same moduli.
+fn check_array<B, W, E>(array: &[B]) -> Result<(), E>
+where
+    B: Deref<Target = Bundle<W>>,
+    W: Clone + HasModulus,
+    E: From<FancyError>,
+{
+    if array.is_empty() {
+        return Err(E::from(FancyError::InvalidArg("empty array".to_string())));
+    }
+    let moduli = array[0].moduli();
+    if array.iter().any(|x| x.moduli() != moduli) {
+        return Err(E::from(FancyError::UnequalModuli));
+    }
+    Ok(())
+}
+
+/// The number of low bits of an index with `nbits` bits needed to address an
+/// array of length `n`.
+fn index_bits(n: usize, nbits: usize) -> usize {
+    let mut m = 0;
+    while m < nbits && 1 << m < n {
+        m += 1;
+    }
+    m
+}
+
+/// Returns a wire which is 1 if all of `high` are 0, or `None` if `high` is
+/// empty.
+fn in_range<F: Fancy + ?Sized>(f: &mut F, high: &[F::Item]) -> Result<Option<F::Item>, F::Error> {
+    let mut any = match high.first() {
+        Some(b) => b.clone(),
+        None => return Ok(None),
+    };
+    for b in high.iter().skip(1) {
+        any = f.or(&any, b)?;
+    }
+    f.negate(&any).map(Some)
+}
+
+/// Returns the wires `idx == i` for each position `i < n`, where `bits` are
+/// the bits of `idx`.
+///
+/// The comparisons share their prefixes, so this costs about `2n` AND gates.
+fn selectors<F: Fancy + ?Sized>(
+    f: &mut F,
+    bits: &[F::Item],
+    n: usize,
+) -> Result<Vec<F::Item>, F::Error> {
+    let nbits = index_bits(n, bits.len());
+    // the selectors of the positions below `2^k` using the low `k` bits, or
+    // `None` if every position is selected
+    let mut sels = in_range(f, &bits[nbits..])?.map(|b| vec![b]);
+    for (k, b) in bits[..nbits].iter().enumerate() {
+        sels = Some(match sels {
+            None => vec![f.negate(b)?, b.clone()],
+            Some(sels) => {
+                let mut lo = Vec::with_capacity(sels.len());
+                let mut hi = Vec::with_capacity(sels.len());
+                for (i, s) in sels.iter().enumerate() {
+                    if i + (1 << k) < n {
+                        // s(1 - b) = s - sb
+                        let h = f.and(s, b)?;
+                        lo.push(f.sub(s, &h)?);
+                        hi.push(h);
+                    } else {
+                        let not_b = f.negate(b)?;
+                        lo.push(f.and(s, &not_b)?);
+                    }
+                }
+                lo.extend(hi);
+                lo
+            }
+        });
+    }
+    match sels {
+        Some(mut sels) => {
+            sels.truncate(n);
+            Ok(sels)
+        }
+        None => Ok(vec![f.constant(1, 2)?]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::Dummy,
+        fancy::{CrtBundle, CrtGadgets, FancyInput},
+        util::{self, RngExt},
+    };
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    #[test]
+    fn read() {
+        let mut rng = thread_rng();
+        for n in 1..20 {
+            let xs = (0..n).map(|_| rng.gen_u128() % (1 << 8)).collect_vec();
+            for idx in 0..32 {
+                let mut d = Dummy::new();
+                let array = xs
+                    .iter()
+                    .map(|&x| d.bin_encode(x, 8).unwrap())
+                    .collect_vec();
+                let i = d.bin_encode(idx, 5).unwrap();
+                let z = d.oblivious_read(&array, &i).unwrap();
+                let out = d.bin_output(&z).unwrap().unwrap();
+                assert_eq!(
+                    out,
+                    *xs.get(idx as usize).unwrap_or(&0),
+                    "n={} idx={}",
+                    n,
+                    idx
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn write() {
+        let mut rng = thread_rng();
+        for n in 1..20 {
+            let xs = (0..n).map(|_| rng.gen_u128() % (1 << 8)).collect_vec();
+            for idx in 0..32 {
+                let v = rng.gen_u128() % (1 << 8);
+                let mut d = Dummy::new();
+                let array = xs
+                    .iter()
+                    .map(|&x| d.bin_encode(x, 8).unwrap())
+                    .collect_vec();
+                let i = d.bin_encode(idx, 5).unwrap();
+                let value = d.bin_encode(v, 8).unwrap();
+                let zs = d.oblivious_write(&array, &i, &value).unwrap();
+                let out = d.bin_outputs(&zs).unwrap().unwrap();
+                let mut should_be = xs.clone();
+                if let Some(x) = should_be.get_mut(idx as usize) {
+                    *x = v;
+                }
+                assert_eq!(out, should_be, "n={} idx={}", n, idx);
+            }
+        }
+    }
+
+    #[test]
+    fn narrow_index() {
+        let mut rng = thread_rng();
+        let n = 20;
+        let xs = (0..n).map(|_| rng.gen_u128() % (1 << 8)).collect_vec();
+        for idx in 0..8 {
+            let v = rng.gen_u128() % (1 << 8);
+            let mut d = Dummy::new();
+            let array = xs
+                .iter()
+                .map(|&x| d.bin_encode(x, 8).unwrap())
+                .collect_vec();
+            let i = d.bin_encode(idx, 3).unwrap();
+            let value = d.bin_encode(v, 8).unwrap();
+            let z = d.oblivious_read(&array, &i).unwrap();
+            assert_eq!(d.bin_output(&z).unwrap().unwrap(), xs[idx as usize]);
+            let zs = d.oblivious_read_many(&array, &[i.clone()]).unwrap();
+            assert_eq!(d.bin_output(&zs[0]).unwrap().unwrap(), xs[idx as usize]);
+            let zs = d.oblivious_write(&array, &i, &value).unwrap();
+            let mut should_be = xs.clone();
+            should_be[idx as usize] = v;
+            assert_eq!(d.bin_outputs(&zs).unwrap().unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn read_many_crt() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(10);
+        let n = 11;
+        let xs = (0..n).map(|_| rng.gen_u128() % q).collect_vec();
+        let idxs = (0..16).collect_vec();
+        let mut d = Dummy::new();
+        let array = xs
+            .iter()
+            .map(|&x| d.crt_encode(x, q).unwrap())
+            .collect_vec();
+        let is = idxs
+            .iter()
+            .map(|&i| d.bin_encode(i, 4).unwrap())
+            .collect_vec();
+        let zs = d.oblivious_read_many(&array, &is).unwrap();
+        let out = d.crt_outputs(&zs).unwrap().unwrap();
+        for (idx, z) in idxs.iter().zip(out.iter()) {
+            assert_eq!(*z, *xs.get(*idx as usize).unwrap_or(&0));
+        }
+    }
+
+    #[test]
+    fn write_many() {
+        let mut rng = thread_rng();
+        let n = 9;
+        let xs = (0..n).map(|_| rng.gen_u128() % (1 << 6)).collect_vec();
+        // repeated indices, where the last write wins
+        let idxs = (0..20).map(|_| rng.gen_u128() % 12).collect_vec();
+        let vs = (0..20).map(|_| rng.gen_u128() % (1 << 6)).collect_vec();
+        let mut d = Dummy::new();
+        let array = xs
+            .iter()
+            .map(|&x| d.bin_encode(x, 6).unwrap())
+            .collect_vec();
+        let is = idxs
+            .iter()
+            .map(|&i| d.bin_encode(i, 4).unwrap())
+            .collect_vec();
+        let values = vs
+            .iter()
+            .map(|&v| d.bin_encode(v, 6).unwrap())
+            .collect_vec();
+        let zs = d.oblivious_write_many(&array, &is, &values).unwrap();
+        let out = d.bin_outputs(&zs).unwrap().unwrap();
+        let mut should_be = xs;
+        for (&i, &v) in idxs.iter().zip(vs.iter()) {
+            if let Some(x) = should_be.get_mut(i as usize) {
+                *x = v;
+            }
+        }
+        assert_eq!(out, should_be);
+    }
+
+    #[test]
+    fn unequal_moduli() {
+        let mut d = Dummy::new();
+        let x = d.crt_encode(3, util::modulus_with_width(8)).unwrap();
+        let y = d.crt_encode(3, util::modulus_with_width(16)).unwrap();
+        let i = d.bin_encode(1, 1).unwrap();
+        let array: Vec<CrtBundle<_>> = vec![x, y];
+        assert!(d.oblivious_read(&array, &i).is_err());
+        assert!(d.oblivious_read(&array[..0], &i).is_err());
+        assert!(d.oblivious_write(&array[..1], &i, &array[1]).is_err());
+    }
+}
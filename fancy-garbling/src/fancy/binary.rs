@@ -144,6 +144,9 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         }
         let xwires = xs.wires();
         let ywires = ys.wires();
+        if xwires.len() == 1 {
+            return Ok(BinaryBundle::new(vec![self.xor(&xwires[0], &ywires[0])?]));
+        }
         let (mut z, mut c) = self.adder(&xwires[0], &ywires[0], None)?;
         let mut bs = vec![z];
         for i in 1..xwires.len() - 1 {
@@ -196,6 +199,45 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         Ok(sum)
     }
 
+    /// Full-width unsigned binary multiplication, returning a product with
+    /// twice as many bits as the inputs.
+    ///
+    /// Inputs of 16 bits or more are multiplied using Karatsuba
+    /// multiplication, which needs fewer AND gates than schoolbook
+    /// multiplication for wide inputs.
+    fn bin_mul(
+        &mut self,
+        xs: &BinaryBundle<Self::Item>,
+        ys: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        if xs.moduli() != ys.moduli() {
+            return Err(Self::Error::from(FancyError::UnequalModuli));
+        }
+        mul_karatsuba(self, xs.wires(), ys.wires()).map(BinaryBundle::new)
+    }
+
+    /// Full-width signed binary multiplication of twos complement bundles,
+    /// returning a product with twice as many bits as the inputs.
+    fn bin_mul_signed(
+        &mut self,
+        xs: &BinaryBundle<Self::Item>,
+        ys: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let n = xs.size();
+        let z = self.bin_mul(xs, ys)?;
+        // As signed numbers, x * y = X * Y - 2^n (x_sign * Y + y_sign * X)
+        // where X and Y are the unsigned interpretations of x and y.
+        let x_sign = xs.wires().last().unwrap();
+        let y_sign = ys.wires().last().unwrap();
+        let x_masked = self.mask(y_sign, xs)?;
+        let y_masked = self.mask(x_sign, ys)?;
+        let (hi, _) = sub_geq(self, &z.wires()[n..], x_masked.wires())?;
+        let (hi, _) = sub_geq(self, &hi, y_masked.wires())?;
+        let mut ws = z.wires()[..n].to_vec();
+        ws.extend(hi);
+        Ok(BinaryBundle::new(ws))
+    }
+
     /// Compute the twos complement of the input bundle (which must be base 2).
     fn bin_twos_complement(
         &mut self,
@@ -315,6 +357,199 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         self.negate(&z)
     }
 
+    /// Unsigned binary division using restoring division. Returns the quotient
+    /// and the remainder, both with the same number of bits as the inputs.
+    ///
+    /// If `y = 0`, the quotient has every bit set and the remainder is `x`.
+    fn bin_divrem(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<(BinaryBundle<Self::Item>, BinaryBundle<Self::Item>), Self::Error> {
+        if x.moduli() != y.moduli() {
+            return Err(Self::Error::from(FancyError::UnequalModuli));
+        }
+        let n = x.size();
+        let zero = self.constant(0, 2)?;
+        let mut ys = y.wires().to_vec();
+        ys.push(zero.clone());
+
+        let mut rem = vec![zero; n];
+        let mut quot = Vec::with_capacity(n);
+        for xi in x.wires().iter().rev() {
+            // shift the next bit of `x` into the remainder, which needs an
+            // extra bit to hold the result
+            let mut shifted = Vec::with_capacity(n + 1);
+            shifted.push(xi.clone());
+            shifted.extend(rem.iter().cloned());
+            let (diff, geq) = sub_geq(self, &shifted, &ys)?;
+            shifted.pop();
+            rem = self
+                .multiplex(
+                    &geq,
+                    &Bundle::new(shifted),
+                    &Bundle::new(diff[..n].to_vec()),
+                )?
+                .wires()
+                .to_vec();
+            quot.push(geq);
+        }
+        quot.reverse();
+        Ok((BinaryBundle::new(quot), BinaryBundle::new(rem)))
+    }
+
+    /// Unsigned binary division. See `bin_divrem`.
+    fn bin_div(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.bin_divrem(x, y).map(|(q, _)| q)
+    }
+
+    /// Unsigned binary remainder. See `bin_divrem`.
+    fn bin_rem(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.bin_divrem(x, y).map(|(_, r)| r)
+    }
+
+    /// Signed binary division of twos complement bundles, rounding towards
+    /// zero. Returns the quotient and the remainder, which has the sign of `x`.
+    ///
+    /// If `y = 0`, the quotient is `-1` for nonnegative `x` and `1` otherwise,
+    /// and the remainder is `x`. Dividing the most negative number by `-1`
+    /// wraps around to the most negative number.
+    fn bin_divrem_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<(BinaryBundle<Self::Item>, BinaryBundle<Self::Item>), Self::Error> {
+        if x.moduli() != y.moduli() {
+            return Err(Self::Error::from(FancyError::UnequalModuli));
+        }
+        let x_sign = x.wires().last().unwrap();
+        let y_sign = y.wires().last().unwrap();
+        let x_abs = self.bin_abs(x)?;
+        let y_abs = self.bin_abs(y)?;
+        let (q, r) = self.bin_divrem(&x_abs, &y_abs)?;
+
+        let q_sign = self.xor(x_sign, y_sign)?;
+        let q_neg = self.bin_twos_complement(&q)?;
+        let q = self.multiplex(&q_sign, &q, &q_neg).map(BinaryBundle)?;
+        let r_neg = self.bin_twos_complement(&r)?;
+        let r = self.multiplex(x_sign, &r, &r_neg).map(BinaryBundle)?;
+        Ok((q, r))
+    }
+
+    /// Signed binary division. See `bin_divrem_signed`.
+    fn bin_div_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.bin_divrem_signed(x, y).map(|(q, _)| q)
+    }
+
+    /// Signed binary remainder. See `bin_divrem_signed`.
+    fn bin_rem_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        self.bin_divrem_signed(x, y).map(|(_, r)| r)
+    }
+
+    /// Unsigned integer square root, rounding down, using the restoring
+    /// digit-by-digit method. The result has the same number of bits as `x`.
+    fn bin_isqrt(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let n = x.size();
+        let zero = self.constant(0, 2)?;
+        let one = self.constant(1, 2)?;
+        let mut xs = x.wires().to_vec();
+        if n % 2 == 1 {
+            xs.push(zero.clone());
+        }
+        // the root has `k` bits, and the remainder never exceeds `k + 2` bits
+        let k = xs.len() / 2;
+        let w = k + 2;
+
+        let mut rem = vec![zero.clone(); w];
+        let mut root: Vec<Self::Item> = Vec::with_capacity(n);
+        for i in (0..k).rev() {
+            // shift the next two bits of `x` into the remainder
+            let mut shifted = vec![xs[2 * i].clone(), xs[2 * i + 1].clone()];
+            shifted.extend(rem[..w - 2].iter().cloned());
+            // try subtracting `4 * root + 1`
+            let mut trial = vec![one.clone(), zero.clone()];
+            trial.extend(root.iter().cloned());
+            trial.resize(w, zero.clone());
+            let (diff, geq) = sub_geq(self, &shifted, &trial)?;
+            rem = self
+                .multiplex(&geq, &Bundle::new(shifted), &Bundle::new(diff))?
+                .wires()
+                .to_vec();
+            root.insert(0, geq);
+        }
+        root.resize(n, zero);
+        Ok(BinaryBundle::new(root))
+    }
+
+    /// Shift `x` left by the secret amount `s`, filling in zeros. Uses a
+    /// barrel shifter with one layer of `mux` gates per bit of `s`.
+    fn bin_shl(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let zero = self.constant(0, 2)?;
+        barrel_shift(self, x.wires(), s.wires(), Shift::Left(zero)).map(BinaryBundle::new)
+    }
+
+    /// Logical right shift of `x` by the secret amount `s`, filling in zeros.
+    fn bin_shr(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let zero = self.constant(0, 2)?;
+        barrel_shift(self, x.wires(), s.wires(), Shift::Right(zero)).map(BinaryBundle::new)
+    }
+
+    /// Arithmetic right shift of the twos complement bundle `x` by the secret
+    /// amount `s`, filling in copies of the sign bit.
+    fn bin_shr_arithmetic(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let sign = x.wires().last().unwrap().clone();
+        barrel_shift(self, x.wires(), s.wires(), Shift::Right(sign)).map(BinaryBundle::new)
+    }
+
+    /// Rotate `x` left by the secret amount `s`, modulo the size of `x`.
+    fn bin_rotl(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        barrel_shift(self, x.wires(), s.wires(), Shift::RotateLeft).map(BinaryBundle::new)
+    }
+
+    /// Rotate `x` right by the secret amount `s`, modulo the size of `x`.
+    fn bin_rotr(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        s: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        barrel_shift(self, x.wires(), s.wires(), Shift::RotateRight).map(BinaryBundle::new)
+    }
+
     /// Compute the maximum bundle in `xs`.
     fn bin_max(
         &mut self,
@@ -375,3 +610,176 @@ pub trait BinaryGadgets: Fancy + BundleGadgets {
         Ok(outs)
     }
 }
+
+/// Subtract the bits `ys` from the bits `xs`, which have the same length,
+/// returning the difference and whether `xs >= ys`.
+pub(super) fn sub_geq<F: Fancy + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<(Vec<F::Item>, F::Item), F::Error> {
+    // x - y = x + !y + 1, which carries out exactly when x >= y
+    let mut carry = f.constant(1, 2)?;
+    let mut diff = Vec::with_capacity(xs.len());
+    for (x, y) in xs.iter().zip(ys.iter()) {
+        let not_y = f.negate(y)?;
+        let (z, c) = f.adder(x, &not_y, Some(&carry))?;
+        diff.push(z);
+        carry = c;
+    }
+    Ok((diff, carry))
+}
+
+/// The number of bits from which `BinaryGadgets::bin_mul` uses Karatsuba
+/// multiplication. Below this, the additions needed to combine the partial
+/// products cost more than the AND gates saved.
+const KARATSUBA_THRESHOLD: usize = 16;
+
+/// Multiply the bits `xs` and `ys`, which have the same length, returning all
+/// bits of the product.
+fn mul_schoolbook<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let n = xs.len();
+    let partial = |f: &mut F, y: &F::Item| {
+        xs.iter()
+            .map(|x| f.and(x, y))
+            .collect::<Result<Vec<F::Item>, F::Error>>()
+    };
+    // Add the partial products one at a time, moving the low bit of the
+    // running sum to the output each time, so that every addition is only
+    // `n` bits wide.
+    let mut out = Vec::with_capacity(2 * n);
+    let mut acc = partial(f, &ys[0])?;
+    let mut carry = f.constant(0, 2)?;
+    for y in ys.iter().skip(1) {
+        let mut hi = acc.split_off(1);
+        out.append(&mut acc);
+        hi.push(carry);
+        let p = partial(f, y)?;
+        let (sum, c) = f.bin_addition(&BinaryBundle::new(hi), &BinaryBundle::new(p))?;
+        acc = sum.wires().to_vec();
+        carry = c;
+    }
+    out.append(&mut acc);
+    out.push(carry);
+    Ok(out)
+}
+
+/// Multiply the bits `xs` and `ys`, which have the same length, returning all
+/// bits of the product. Uses Karatsuba multiplication for inputs of at least
+/// `KARATSUBA_THRESHOLD` bits.
+fn mul_karatsuba<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let n = xs.len();
+    if n < KARATSUBA_THRESHOLD {
+        return mul_schoolbook(f, xs, ys);
+    }
+    let h = n / 2;
+    let m = n - h;
+    let zero = f.constant(0, 2)?;
+    let pad = |ws: &[F::Item], len: usize| {
+        let mut ws = ws.to_vec();
+        ws.resize(len, zero.clone());
+        BinaryBundle::new(ws)
+    };
+
+    // x = x1 2^h + x0 and y = y1 2^h + y0
+    let (x0, x1) = xs.split_at(h);
+    let (y0, y1) = ys.split_at(h);
+    let z0 = mul_karatsuba(f, x0, y0)?;
+    let z2 = mul_karatsuba(f, x1, y1)?;
+
+    // z1 = (x0 + x1)(y0 + y1) - z0 - z2 = x0 y1 + x1 y0
+    let (sx, cx) = f.bin_addition(&pad(x0, m), &BinaryBundle::new(x1.to_vec()))?;
+    let (sy, cy) = f.bin_addition(&pad(y0, m), &BinaryBundle::new(y1.to_vec()))?;
+    let mut sx = sx.wires().to_vec();
+    sx.push(cx);
+    let mut sy = sy.wires().to_vec();
+    sy.push(cy);
+    let z1 = mul_karatsuba(f, &sx, &sy)?;
+    let (z1, _) = sub_geq(f, &z1, pad(&z0, z1.len()).wires())?;
+    let (z1, _) = sub_geq(f, &z1, pad(&z2, z1.len()).wires())?;
+
+    // z0 and z2 do not overlap, so only z1 needs to be added
+    let mut z = z0;
+    z.extend(z2);
+    let hi = f.bin_addition_no_carry(&BinaryBundle::new(z.split_off(h)), &pad(&z1, 2 * n - h))?;
+    z.extend(hi.wires().iter().cloned());
+    Ok(z)
+}
+
+/// The kinds of barrel shifts, holding the wire shifted in if any.
+enum Shift<W> {
+    Left(W),
+    Right(W),
+    RotateLeft,
+    RotateRight,
+}
+
+/// Shift or rotate the bits `xs` by the amount given by the bits `ss`.
+fn barrel_shift<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ss: &[F::Item],
+    shift: Shift<F::Item>,
+) -> Result<Vec<F::Item>, F::Error> {
+    let n = xs.len();
+    if n == 0 {
+        return Ok(Vec::new());
+    }
+    let mut xs = Bundle::new(xs.to_vec());
+    // Bits of the shift amount worth at least `n` shift every bit out, so
+    // these are handled by a single layer at the end.
+    let mut overflow = Vec::new();
+    // For bit `j` of the shift amount, `exact` is `2^j` while this is less
+    // than `n`, and `rem` is `2^j mod n`.
+    let mut exact = Some(1).filter(|&e| e < n);
+    let mut rem = 1 % n;
+    for s in ss.iter() {
+        let k = match shift {
+            Shift::Left(_) | Shift::Right(_) => exact,
+            Shift::RotateLeft | Shift::RotateRight => Some(rem),
+        };
+        exact = exact.map(|e| 2 * e).filter(|&e| e < n);
+        rem = 2 * rem % n;
+        let k = match k {
+            Some(k) => k,
+            None => {
+                overflow.push(s.clone());
+                continue;
+            }
+        };
+        if k == 0 {
+            continue;
+        }
+        let ws = xs.wires();
+        let shifted = (0..n)
+            .map(|i| match shift {
+                Shift::Left(ref fill) if i < k => fill.clone(),
+                Shift::Left(_) => ws[i - k].clone(),
+                Shift::Right(ref fill) if i + k >= n => fill.clone(),
+                Shift::Right(_) => ws[i + k].clone(),
+                Shift::RotateLeft => ws[(i + n - k) % n].clone(),
+                Shift::RotateRight => ws[(i + k) % n].clone(),
+            })
+            .collect::<Vec<F::Item>>();
+        xs = f.multiplex(s, &xs, &Bundle::new(shifted))?;
+    }
+    let fill = match shift {
+        Shift::Left(fill) | Shift::Right(fill) => fill,
+        Shift::RotateLeft | Shift::RotateRight => return Ok(xs.wires().to_vec()),
+    };
+    let s = match overflow.len() {
+        0 => return Ok(xs.wires().to_vec()),
+        1 => overflow[0].clone(),
+        _ => f.or_many(&overflow)?,
+    };
+    f.multiplex(&s, &xs, &Bundle::new(vec![fill; n]))
+        .map(|xs| xs.wires().to_vec())
+}
@@ -9,7 +9,10 @@
 use super::{Fancy, HasModulus};
 use crate::{
     errors::FancyError,
-    fancy::bundle::{Bundle, BundleGadgets},
+    fancy::{
+        binary::{sub_geq, BinaryBundle, BinaryGadgets},
+        bundle::{Bundle, BundleGadgets},
+    },
     util,
 };
 use itertools::Itertools;
@@ -53,7 +56,7 @@ impl<W: Clone + HasModulus> From<Bundle<W>> for CrtBundle<W> {
 impl<F: Fancy> CrtGadgets for F {}
 
 /// Extension trait for `Fancy` providing advanced CRT gadgets based on bundles of wires.
-pub trait CrtGadgets: Fancy + BundleGadgets {
+pub trait CrtGadgets: Fancy + BundleGadgets + BinaryGadgets {
     /// Creates a bundle of constant wires for the CRT representation of `x` under
     /// composite modulus `q`.
     fn crt_constant_bundle(
@@ -132,6 +135,27 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
         self.mul_bundles(x, y).map(CrtBundle)
     }
 
+    /// If `b = 0` then return `x`, else return `y`, where `b` is a wire of
+    /// modulus 2.
+    fn crt_multiplex(
+        &mut self,
+        b: &Self::Item,
+        x: &CrtBundle<Self::Item>,
+        y: &CrtBundle<Self::Item>,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let notb = self.negate(b)?;
+        x.wires()
+            .iter()
+            .zip(y.wires().iter())
+            .map(|(x, y)| {
+                let xp = self.mul(x, &notb)?;
+                let yp = self.mul(y, b)?;
+                self.add(&xp, &yp)
+            })
+            .collect::<Result<Vec<Self::Item>, Self::Error>>()
+            .map(CrtBundle::new)
+    }
+
     /// Exponentiate `x` by the constant `c`.
     fn crt_cexp(
         &mut self,
@@ -170,6 +194,93 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
             .map(CrtBundle::new)
     }
 
+    ////////////////////////////////////////////////////////////////////////////////
+    // Conversions between CRT and binary representations.
+
+    /// Compute the mixed radix digits `d_i` of `x` with respect to its own
+    /// moduli `p_i`, such that `x = d_0 + p_0 * (d_1 + p_1 * (d_2 + ...))`, using
+    /// Garner's algorithm. Digit `d_i` has modulus `p_i`.
+    fn crt_mixed_radix_digits(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+    ) -> Result<Vec<Self::Item>, Self::Error> {
+        let ps = x.moduli();
+        let mut rs = x.wires().to_vec();
+        for i in 0..ps.len() {
+            // the remaining residues become those of `(x - d_i) / p_i`
+            for j in i + 1..ps.len() {
+                let d = self.mod_change(&rs[i], ps[j])?;
+                let r = self.sub(&rs[j], &d)?;
+                let c = util::inv((ps[i] % ps[j]) as i128, ps[j] as i128) as u16;
+                rs[j] = self.cmul(&r, c)?;
+            }
+        }
+        Ok(rs)
+    }
+
+    /// Convert `x` into a binary bundle with `nbits` bits holding `x` modulo
+    /// `2^nbits`, where `x` is between 0 and `Q-1`.
+    fn crt_to_bin(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        nbits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        if nbits == 0 {
+            return Err(Self::Error::from(FancyError::InvalidArg(
+                "cannot convert to 0 bits".to_string(),
+            )));
+        }
+        let width = std::cmp::min(nbits, nbits_of(x.composite_modulus()));
+        let mut zs = mixed_radix_to_bin(self, x, width)?;
+        zs.resize(nbits, self.constant(0, 2)?);
+        Ok(BinaryBundle::new(zs))
+    }
+
+    /// Convert `x` into a twos complement binary bundle with `nbits` bits,
+    /// where values of `x` greater than `Q/2` are negative. The result is
+    /// reduced modulo `2^nbits`.
+    fn crt_to_bin_signed(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        nbits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        if nbits == 0 {
+            return Err(Self::Error::from(FancyError::InvalidArg(
+                "cannot convert to 0 bits".to_string(),
+            )));
+        }
+        let q = x.composite_modulus();
+        // `x - Q` needs one more bit than `Q`
+        let width = std::cmp::max(nbits, nbits_of(q) + 1);
+        let zs = mixed_radix_to_bin(self, x, width)?;
+        let half = self.bin_constant_bundle(q / 2 + 1, width)?;
+        let (_, neg) = sub_geq(self, &zs, half.wires())?;
+        let qs = self.bin_multiplex_constant_bits(&neg, 0, q, width)?;
+        let (mut zs, _) = sub_geq(self, &zs, qs.wires())?;
+        zs.truncate(nbits);
+        Ok(BinaryBundle::new(zs))
+    }
+
+    /// Convert the unsigned binary bundle `x` into a CRT bundle with composite
+    /// modulus `q`, holding `x` modulo `q`.
+    fn bin_to_crt(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        q: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        bin_to_crt(self, x, q, false)
+    }
+
+    /// Convert the twos complement binary bundle `x` into a CRT bundle with
+    /// composite modulus `q`, holding `x` modulo `q`.
+    fn bin_to_crt_signed(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        q: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        bin_to_crt(self, x, q, true)
+    }
+
     ////////////////////////////////////////////////////////////////////////////////
     // Fancy functions based on Mike's fractional mixed radix trick.
 
@@ -329,6 +440,80 @@ pub trait CrtGadgets: Fancy + BundleGadgets {
     }
 }
 
+/// The number of bits needed to write `q`.
+fn nbits_of(q: u128) -> usize {
+    128 - q.leading_zeros() as usize
+}
+
+/// Convert `x` into `nbits` bits holding `x` modulo `2^nbits`, by computing its
+/// mixed radix digits and evaluating them in binary using Horner's rule.
+fn mixed_radix_to_bin<F: CrtGadgets + ?Sized>(
+    f: &mut F,
+    x: &CrtBundle<F::Item>,
+    nbits: usize,
+) -> Result<Vec<F::Item>, F::Error> {
+    let ps = x.moduli();
+    let ds = f.crt_mixed_radix_digits(x)?;
+    let mut z = digit_to_bin(f, ds.last().unwrap(), nbits)?;
+    for (d, &p) in ds.iter().zip(ps.iter()).rev().skip(1) {
+        let zp = f.bin_cmul(&z, p as u128, nbits)?;
+        let d = digit_to_bin(f, d, nbits)?;
+        z = f.bin_addition_no_carry(&zp, &d)?;
+    }
+    Ok(z.wires().to_vec())
+}
+
+/// Project the digit `d` into `nbits` bits.
+fn digit_to_bin<F: CrtGadgets + ?Sized>(
+    f: &mut F,
+    d: &F::Item,
+    nbits: usize,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    let p = d.modulus();
+    (0..nbits)
+        .map(|i| {
+            if i >= 16 || (p - 1) >> i == 0 {
+                f.constant(0, 2)
+            } else {
+                let tt = (0..p).map(|x| (x >> i) & 1).collect_vec();
+                f.proj(d, 2, Some(tt))
+            }
+        })
+        .collect::<Result<Vec<F::Item>, F::Error>>()
+        .map(BinaryBundle::new)
+}
+
+/// Convert the bits `x` into a CRT bundle with composite modulus `q`. If
+/// `signed`, the most significant bit has negative weight.
+fn bin_to_crt<F: CrtGadgets + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    q: u128,
+    signed: bool,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    let n = x.size();
+    util::factor(q)
+        .into_iter()
+        .map(|p| {
+            // each bit is projected to its weight mod p, and these are summed
+            let mut z = f.constant(0, p)?;
+            let mut weight = 1 % p as u32;
+            for (i, b) in x.wires().iter().enumerate() {
+                let w = if signed && i == n - 1 {
+                    (p as u32 - weight) % p as u32
+                } else {
+                    weight
+                };
+                let t = f.proj(b, p, Some(vec![0, w as u16]))?;
+                z = f.add(&z, &t)?;
+                weight = 2 * weight % p as u32;
+            }
+            Ok(z)
+        })
+        .collect::<Result<Vec<F::Item>, F::Error>>()
+        .map(CrtBundle::new)
+}
+
 /// Compute the `ms` needed for the number of CRT primes in `x`, with accuracy
 /// `accuracy`.
 ///
@@ -0,0 +1,180 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `FixedGadgets`, which provide fixed-point arithmetic over
+//! CRT bundles.
+//!
+//! A fixed-point number `x` with `frac_bits` fractional bits is represented by
+//! the CRT bundle holding `x * 2^frac_bits` modulo the composite modulus `Q`,
+//! where values greater than `Q/2` are negative. Use `FancyInput::fixed_encode`
+//! and `FancyReveal::fixed_reveal` to convert these to and from `f64`.
+//!
+//! Fixed-point numbers with the same number of fractional bits are added and
+//! subtracted using `crt_add` and `crt_sub`. Multiplication doubles the number
+//! of fractional bits, so products are rescaled by converting them to binary,
+//! shifting, and converting back.
+
+use crate::{
+    fancy::{
+        binary::BinaryBundle,
+        crt::{CrtBundle, CrtGadgets},
+        Fancy,
+    },
+    util,
+};
+
+impl<F: Fancy> FixedGadgets for F {}
+
+/// Extension trait for `Fancy` providing fixed-point gadgets over CRT bundles.
+pub trait FixedGadgets: Fancy + CrtGadgets {
+    /// Create a constant bundle holding the fixed-point number `x` with
+    /// `frac_bits` fractional bits modulo `q`. Returns an error if `x` does
+    /// not fit.
+    fn fixed_constant(
+        &mut self,
+        x: f64,
+        frac_bits: usize,
+        q: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        self.crt_constant_bundle(util::try_to_fixed(x, frac_bits, q)?, q)
+    }
+
+    /// Output a fixed-point number with `frac_bits` fractional bits.
+    fn fixed_output(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+    ) -> Result<Option<f64>, Self::Error> {
+        let q = x.composite_modulus();
+        match self.crt_output(x)? {
+            Some(z) => Ok(Some(util::try_from_fixed(z, frac_bits, q)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Divide the signed value of `x` by `2^frac_bits`, rounding down.
+    fn fixed_rescale(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let q = x.composite_modulus();
+        // the signed value of `x` fits in one more bit than `q`
+        let nbits = 129 - q.leading_zeros() as usize;
+        let bits = self.crt_to_bin_signed(x, nbits)?;
+        let shift = std::cmp::min(frac_bits, nbits - 1);
+        let bits = BinaryBundle::new(bits.wires()[shift..].to_vec());
+        self.bin_to_crt_signed(&bits, q)
+    }
+
+    /// Multiply the fixed-point numbers `x` and `y` with `frac_bits`
+    /// fractional bits, rounding the product down.
+    ///
+    /// The product before rescaling is `x * y * 2^(2 * frac_bits)`, which must
+    /// be less than `Q/2` in absolute value.
+    fn fixed_mul(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        y: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let z = self.crt_mul(x, y)?;
+        self.fixed_rescale(&z, frac_bits)
+    }
+
+    /// Multiply the fixed-point number `x` with `frac_bits` fractional bits by
+    /// the constant `c`, rounding the product down. Like `fixed_mul`, the
+    /// product before rescaling must be less than `Q/2` in absolute value.
+    fn fixed_cmul(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        c: f64,
+        frac_bits: usize,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let c = util::try_to_fixed(c, frac_bits, x.composite_modulus())?;
+        let z = self.crt_cmul(x, c)?;
+        self.fixed_rescale(&z, frac_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::Dummy,
+        fancy::{FancyInput, FancyReveal},
+    };
+    use rand::{thread_rng, Rng};
+
+    const FRAC_BITS: usize = 12;
+
+    /// The exact result of `fixed_mul` on the fixed-point encodings of `x`
+    /// and `y`.
+    fn mul_floor(x: f64, y: f64) -> f64 {
+        let scale = 2f64.powi(FRAC_BITS as i32);
+        let x = (x * scale).round() as i128;
+        let y = (y * scale).round() as i128;
+        (x * y).div_euclid(1 << FRAC_BITS) as f64 / scale
+    }
+
+    #[test]
+    fn mul() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(64);
+        for _ in 0..128 {
+            let x = rng.gen_range(-1000.0, 1000.0);
+            let y = rng.gen_range(-1000.0, 1000.0);
+            let mut d = Dummy::new();
+            let xs = d.fixed_encode(x, FRAC_BITS, q).unwrap();
+            let ys = d.fixed_encode(y, FRAC_BITS, q).unwrap();
+            let z = d.fixed_mul(&xs, &ys, FRAC_BITS).unwrap();
+            let out = d.fixed_output(&z, FRAC_BITS).unwrap().unwrap();
+            assert_eq!(out, mul_floor(x, y), "x={} y={}", x, y);
+        }
+    }
+
+    #[test]
+    fn cmul_and_constants() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(48);
+        for _ in 0..128 {
+            let x = rng.gen_range(-100.0, 100.0);
+            let c = rng.gen_range(-100.0, 100.0);
+            let mut d = Dummy::new();
+            let xs = d.fixed_encode(x, FRAC_BITS, q).unwrap();
+            let z = d.fixed_cmul(&xs, c, FRAC_BITS).unwrap();
+            assert_eq!(d.fixed_reveal(&z, FRAC_BITS).unwrap(), mul_floor(x, c));
+
+            // a product with a constant bundle, accumulated with `crt_add`
+            let cs = d.fixed_constant(c, FRAC_BITS, q).unwrap();
+            let z = d.fixed_mul(&xs, &cs, FRAC_BITS).unwrap();
+            let z = d.crt_add(&z, &cs).unwrap();
+            let out = d.fixed_reveal(&z, FRAC_BITS).unwrap();
+            let c = util::from_fixed(util::to_fixed(c, FRAC_BITS, q), FRAC_BITS, q);
+            assert_eq!(out, mul_floor(x, c) + c);
+        }
+    }
+
+    #[test]
+    fn rescale_rounds_down() {
+        let q = util::modulus_with_width(20);
+        for &(x, should_be) in [(7, 1), (-7, -2), (-8, -2), (8, 2), (0, 0), (-1, -1)].iter() {
+            let mut d = Dummy::new();
+            let xs = d.fixed_encode(x as f64, 0, q).unwrap();
+            let z = d.fixed_rescale(&xs, 2).unwrap();
+            assert_eq!(d.fixed_reveal(&z, 0).unwrap(), should_be as f64);
+        }
+    }
+    #[test]
+    fn out_of_range_values_are_errors() {
+        let q = util::modulus_with_width(32);
+        let mut d = Dummy::new();
+        assert!(d.fixed_encode(f64::NAN, FRAC_BITS, q).is_err());
+        assert!(d.fixed_constant(1e300, FRAC_BITS, q).is_err());
+        let xs = d.fixed_encode(1.0, FRAC_BITS, q).unwrap();
+        assert!(d.fixed_cmul(&xs, f64::INFINITY, FRAC_BITS).is_err());
+    }
+}
@@ -0,0 +1,999 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `FloatGadgets`, which implement IEEE-754 binary32 and
+//! binary64 arithmetic over binary bundles.
+//!
+//! A float is a `BinaryBundle` holding the bits of its IEEE-754 encoding,
+//! least significant bit first, such as `bin_encode(x.to_bits() as u128, 32)`
+//! for an `f32`. Bundles of 32 bits are binary32 numbers and bundles of 64
+//! bits are binary64 numbers.
+//!
+//! Results are rounded to nearest, ties to even, and agree bit for bit with
+//! Rust's `f32` and `f64` operations, except that every NaN result is the quiet
+//! NaN with a clear sign bit and an empty payload.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        binary::{sub_geq, BinaryBundle, BinaryGadgets},
+        Fancy,
+        HasModulus,
+    },
+};
+
+impl<F: Fancy> FloatGadgets for F {}
+
+/// Extension trait for `Fancy` providing IEEE-754 floating-point gadgets.
+pub trait FloatGadgets: Fancy + BinaryGadgets {
+    /// Compute `x + y`.
+    fn float_add(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        add(self, fmt, x.wires(), y.wires()).map(BinaryBundle::new)
+    }
+
+    /// Compute `x - y`.
+    fn float_sub(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        let mut ys = y.wires().to_vec();
+        let sign = ys.pop().unwrap();
+        ys.push(self.negate(&sign)?);
+        add(self, fmt, x.wires(), &ys).map(BinaryBundle::new)
+    }
+
+    /// Compute `x * y`.
+    fn float_mul(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        mul(self, fmt, x.wires(), y.wires()).map(BinaryBundle::new)
+    }
+
+    /// Compute `x / y`.
+    fn float_div(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        div(self, fmt, x.wires(), y.wires()).map(BinaryBundle::new)
+    }
+
+    /// Returns 1 if `x < y`. Comparisons with NaN are false.
+    fn float_lt(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<Self::Item, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        compare(self, fmt, x.wires(), y.wires()).map(|(lt, _)| lt)
+    }
+
+    /// Returns 1 if `x <= y`. Comparisons with NaN are false.
+    fn float_le(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<Self::Item, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        let (lt, eq) = compare(self, fmt, x.wires(), y.wires())?;
+        self.or(&lt, &eq)
+    }
+
+    /// Returns 1 if `x == y`, so that `0 == -0` and NaN is not equal to
+    /// anything.
+    fn float_eq(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        y: &BinaryBundle<Self::Item>,
+    ) -> Result<Self::Item, Self::Error> {
+        let fmt = Format::of_pair(x, y)?;
+        compare(self, fmt, x.wires(), y.wires()).map(|(_, eq)| eq)
+    }
+
+    /// Convert the twos complement integer `x` into a float with `nbits`
+    /// bits, like `x as f32` or `x as f64` in Rust.
+    fn float_from_int(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        nbits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::new(nbits)?;
+        if x.size() == 0 {
+            return Err(Self::Error::from(FancyError::InvalidArg(
+                "cannot convert an empty integer".to_string(),
+            )));
+        }
+        from_int(self, fmt, x.wires()).map(BinaryBundle::new)
+    }
+
+    /// Convert the float `x` into a twos complement integer with `nbits`
+    /// bits, like `x as i32` or `x as i64` in Rust. The result is rounded
+    /// towards zero, saturates at the bounds of the integer type, and NaN is
+    /// converted to zero.
+    fn float_to_int(
+        &mut self,
+        x: &BinaryBundle<Self::Item>,
+        nbits: usize,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        let fmt = Format::new(x.size())?;
+        if nbits == 0 || nbits > 128 {
+            return Err(Self::Error::from(FancyError::InvalidArg(format!(
+                "cannot convert to {} bit integers",
+                nbits
+            ))));
+        }
+        to_int(self, fmt, x.wires(), nbits).map(BinaryBundle::new)
+    }
+}
+
+/// The widths of the fields of a floating-point format.
+#[derive(Clone, Copy)]
+struct Format {
+    exp_bits: usize,
+    frac_bits: usize,
+}
+
+impl Format {
+    /// The format of floats with `nbits` bits.
+    fn new<E: From<FancyError>>(nbits: usize) -> Result<Format, E> {
+        match nbits {
+            32 => Ok(Format {
+                exp_bits: 8,
+                frac_bits: 23,
+            }),
+            64 => Ok(Format {
+                exp_bits: 11,
+                frac_bits: 52,
+            }),
+            _ => Err(E::from(FancyError::InvalidArg(format!(
+                "{} bit floats are not supported",
+                nbits
+            )))),
+        }
+    }
+
+    /// The format of the floats `x` and `y`, which must have the same size.
+    fn of_pair<W: Clone + HasModulus, E: From<FancyError>>(
+        x: &BinaryBundle<W>,
+        y: &BinaryBundle<W>,
+    ) -> Result<Format, E> {
+        if x.size() != y.size() {
+            return Err(E::from(FancyError::InvalidArg(
+                "floats have different sizes".to_string(),
+            )));
+        }
+        Format::new(x.size())
+    }
+
+    fn nbits(self) -> usize {
+        1 + self.exp_bits + self.frac_bits
+    }
+
+    fn bias(self) -> i128 {
+        (1 << (self.exp_bits - 1)) - 1
+    }
+
+    /// The number of bits of the signed exponents of intermediate results,
+    /// which must hold the exponents of products and quotients, as well as
+    /// those of normalized subnormals.
+    fn exp_width(self) -> usize {
+        self.exp_bits + 3
+    }
+}
+
+/// The fields of a float, along with its class.
+struct Unpacked<W> {
+    sign: W,
+    /// The signed exponent, which is 1 for subnormals.
+    exp: Vec<W>,
+    /// The significand including the hidden bit.
+    sig: Vec<W>,
+    nan: W,
+    inf: W,
+    zero: W,
+}
+
+fn unpack<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+) -> Result<Unpacked<F::Item>, F::Error> {
+    let m = fmt.frac_bits;
+    let frac = &xs[..m];
+    let exp = &xs[m..m + fmt.exp_bits];
+    let exp_any = or_all(f, exp)?;
+    let exp_all = and_all(f, exp)?;
+    let frac_any = or_all(f, frac)?;
+    let exp_zero = f.negate(&exp_any)?;
+    let frac_zero = f.negate(&frac_any)?;
+
+    let mut sig = frac.to_vec();
+    sig.push(exp_any);
+    let mut exp = exp.to_vec();
+    exp[0] = f.or(&exp[0], &exp_zero)?;
+    exp.resize(fmt.exp_width(), f.constant(0, 2)?);
+    Ok(Unpacked {
+        sign: xs[fmt.nbits() - 1].clone(),
+        exp,
+        sig,
+        nan: f.and(&exp_all, &frac_any)?,
+        inf: f.and(&exp_all, &frac_zero)?,
+        zero: f.and(&exp_zero, &frac_zero)?,
+    })
+}
+
+/// Round the value `sig * 2^(exp - bias - (k - 1))` to the nearest float
+/// with sign `sign`, where `sig` has `k` bits and `exp` is a signed exponent.
+///
+/// `sig` must have at least two bits below the last bit of the rounded
+/// significand, and any bits of the exact value which do not fit in `sig` must
+/// be jammed into its least significant bit.
+fn round_pack<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    sign: &F::Item,
+    exp: &[F::Item],
+    sig: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let m = fmt.frac_bits;
+    let k = sig.len();
+    let w = exp.len();
+    debug_assert!(k >= m + 3);
+    let (sig, exp) = normalize(f, sig, exp)?;
+
+    // Results with exponent below 1 are subnormal, and are shifted right by
+    // `1 - exp` so that their exponent is 1. Zero is treated as subnormal.
+    let one = constant(f, 1, w)?;
+    let exp_m1 = sub_bits(f, &exp, &one)?;
+    let no_top = f.negate(&sig[k - 1])?;
+    let tiny = f.or(&exp_m1[w - 1], &no_top)?;
+    let d = f.bin_twos_complement(&BinaryBundle::new(exp_m1.clone()))?;
+    let amt = mask(f, &tiny, d.wires())?;
+    let sig = shift_right_jam(f, &sig, &amt)?;
+    let not_tiny = f.negate(&tiny)?;
+    let exp_m1 = mask(f, &not_tiny, &exp_m1)?;
+
+    // round to nearest, ties to even
+    let top = &sig[k - 1 - m..];
+    let guard = &sig[k - 2 - m];
+    let sticky = or_all(f, &sig[..k - 2 - m])?;
+    let odd = f.or(&sticky, &top[0])?;
+    let up = f.and(guard, &odd)?;
+    let rounded = increment(f, top, &up)?;
+
+    // The bits of the rounded significand from the hidden bit up are added to
+    // the exponent, which takes care of subnormals rounding up to normals and
+    // of significands rounding up to the next power of two.
+    let zero = f.constant(0, 2)?;
+    let mut carry = rounded[m..].to_vec();
+    carry.resize(w, zero);
+    let exp = add_bits(f, &exp_m1, &carry)?;
+    let max = constant(f, (1 << fmt.exp_bits) - 1, w)?;
+    let (_, overflow) = sub_geq(f, &exp, &max)?;
+
+    let not_overflow = f.negate(&overflow)?;
+    let mut zs = mask(f, &not_overflow, &rounded[..m])?;
+    for x in exp[..fmt.exp_bits].iter() {
+        zs.push(f.or(x, &overflow)?);
+    }
+    zs.push(sign.clone());
+    Ok(zs)
+}
+
+/// Replace the packed float `zs` by zero, infinity or NaN according to the
+/// flags, keeping its sign unless it is NaN. NaN takes precedence over
+/// infinity, which takes precedence over zero.
+fn set_special<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    zs: &[F::Item],
+    zero: Option<&F::Item>,
+    inf: &F::Item,
+    nan: &F::Item,
+) -> Result<Vec<F::Item>, F::Error> {
+    let m = fmt.frac_bits;
+    let n = fmt.nbits();
+    let inf_or_nan = f.or(inf, nan)?;
+    let (clear_exp, clear_frac) = match zero {
+        Some(zero) => {
+            let z = f.or(zero, &inf_or_nan)?;
+            (Some(f.negate(zero)?), f.negate(&z)?)
+        }
+        None => (None, f.negate(&inf_or_nan)?),
+    };
+    let mut out = mask(f, &clear_frac, &zs[..m])?;
+    out[m - 1] = f.or(&out[m - 1], nan)?;
+    for x in zs[m..n - 1].iter() {
+        let x = match clear_exp {
+            Some(ref c) => f.and(x, c)?,
+            None => x.clone(),
+        };
+        out.push(f.or(&x, &inf_or_nan)?);
+    }
+    let not_nan = f.negate(nan)?;
+    out.push(f.and(&zs[n - 1], &not_nan)?);
+    Ok(out)
+}
+
+fn add<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let n = fmt.nbits();
+    let w = fmt.exp_width();
+
+    // order the operands so that `|a| >= |b|`
+    let swap = f.bin_lt(
+        &BinaryBundle::new(xs[..n - 1].to_vec()),
+        &BinaryBundle::new(ys[..n - 1].to_vec()),
+    )?;
+    let a = select(f, &swap, xs, ys)?;
+    let b = xs
+        .iter()
+        .zip(ys.iter())
+        .zip(a.iter())
+        .map(|((x, y), a)| {
+            let z = f.xor(x, y)?;
+            f.xor(&z, a)
+        })
+        .collect::<Result<Vec<F::Item>, F::Error>>()?;
+    let a = unpack(f, fmt, &a)?;
+    let b = unpack(f, fmt, &b)?;
+    let sub = f.xor(&a.sign, &b.sign)?;
+
+    // Align the significands, with three extra bits below them so that the
+    // bits of `b` shifted out can be jammed into the last one, and a bit above
+    // for the carry.
+    let zero = f.constant(0, 2)?;
+    let extend = |sig: &[F::Item]| {
+        let mut ws = vec![zero.clone(); 3];
+        ws.extend(sig.iter().cloned());
+        ws.push(zero.clone());
+        ws
+    };
+    let a_sig = extend(&a.sig);
+    let b_sig = extend(&b.sig);
+    let d = sub_bits(f, &a.exp, &b.exp)?;
+    let b_sig = shift_right_jam(f, &b_sig, &d)?;
+
+    // a + b, or a - b = a + !b + 1
+    let mut carry = sub.clone();
+    let mut sum = Vec::with_capacity(a_sig.len());
+    for (x, y) in a_sig.iter().zip(b_sig.iter()) {
+        let y = f.xor(y, &sub)?;
+        let (z, c) = f.adder(x, &y, Some(&carry))?;
+        sum.push(z);
+        carry = c;
+    }
+
+    // exact cancellation results in positive zero
+    let nonzero = or_all(f, &sum)?;
+    let sign = f.and(&a.sign, &nonzero)?;
+    let sign = f.mux(&sub, &a.sign, &sign)?;
+    let one = constant(f, 1, w)?;
+    let exp = add_bits(f, &a.exp, &one)?;
+    let zs = round_pack(f, fmt, &sign, &exp, &sum)?;
+
+    // if `b` is infinite then so is `a`
+    let infs = f.and(&a.inf, &b.inf)?;
+    let inf_diff = f.and(&infs, &sub)?;
+    let nan = or_all(f, &[a.nan, b.nan, inf_diff])?;
+    set_special(f, fmt, &zs, None, &a.inf, &nan)
+}
+
+fn mul<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let w = fmt.exp_width();
+    let a = unpack(f, fmt, xs)?;
+    let b = unpack(f, fmt, ys)?;
+    let sign = f.xor(&a.sign, &b.sign)?;
+
+    let sig = f.bin_mul(
+        &BinaryBundle::new(a.sig.clone()),
+        &BinaryBundle::new(b.sig.clone()),
+    )?;
+    let exp = add_bits(f, &a.exp, &b.exp)?;
+    let bias = constant(f, fmt.bias() - 1, w)?;
+    let exp = sub_bits(f, &exp, &bias)?;
+    let zs = round_pack(f, fmt, &sign, &exp, sig.wires())?;
+
+    let inf = f.or(&a.inf, &b.inf)?;
+    let inf_zero = f.and(&a.inf, &b.zero)?;
+    let zero_inf = f.and(&a.zero, &b.inf)?;
+    let nan = or_all(f, &[a.nan, b.nan, inf_zero, zero_inf])?;
+    set_special(f, fmt, &zs, None, &inf, &nan)
+}
+
+fn div<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let m = fmt.frac_bits;
+    let w = fmt.exp_width();
+    let a = unpack(f, fmt, xs)?;
+    let b = unpack(f, fmt, ys)?;
+    let sign = f.xor(&a.sign, &b.sign)?;
+
+    // With both significands normalized their quotient is between 1/2 and 2,
+    // so `m + 5` bits of it leave at least two bits below the rounded result.
+    let (a_sig, a_exp) = normalize(f, &a.sig, &a.exp)?;
+    let (b_sig, b_exp) = normalize(f, &b.sig, &b.exp)?;
+    let zero = f.constant(0, 2)?;
+    let mut rem = a_sig;
+    rem.push(zero.clone());
+    let mut divisor = b_sig;
+    divisor.push(zero.clone());
+    let mut quot = Vec::with_capacity(m + 5);
+    for _ in 0..m + 5 {
+        let (diff, geq) = sub_geq(f, &rem, &divisor)?;
+        rem = select(f, &geq, &rem, &diff)?;
+        rem.pop();
+        rem.insert(0, zero.clone());
+        quot.push(geq);
+    }
+    quot.reverse();
+    let inexact = or_all(f, &rem)?;
+    quot[0] = f.or(&quot[0], &inexact)?;
+
+    let exp = sub_bits(f, &a_exp, &b_exp)?;
+    let bias = constant(f, fmt.bias(), w)?;
+    let exp = add_bits(f, &exp, &bias)?;
+    let zs = round_pack(f, fmt, &sign, &exp, &quot)?;
+
+    let inf = f.or(&a.inf, &b.zero)?;
+    let zero = f.or(&a.zero, &b.inf)?;
+    let zeros = f.and(&a.zero, &b.zero)?;
+    let infs = f.and(&a.inf, &b.inf)?;
+    let nan = or_all(f, &[a.nan, b.nan, zeros, infs])?;
+    set_special(f, fmt, &zs, Some(&zero), &inf, &nan)
+}
+
+/// Returns whether `x < y` and whether `x == y`.
+fn compare<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<(F::Item, F::Item), F::Error> {
+    let n = fmt.nbits();
+    let a = unpack(f, fmt, xs)?;
+    let b = unpack(f, fmt, ys)?;
+    let nan = f.or(&a.nan, &b.nan)?;
+    let zeros = f.and(&a.zero, &b.zero)?;
+
+    // With equal signs, compare the magnitudes, in reverse for negative
+    // numbers. Otherwise `x < y` if `x` is negative.
+    let p = select(f, &a.sign, &xs[..n - 1], &ys[..n - 1])?;
+    let q = select(f, &a.sign, &ys[..n - 1], &xs[..n - 1])?;
+    let lt = f.bin_lt(&BinaryBundle::new(p), &BinaryBundle::new(q))?;
+    let diff_sign = f.xor(&a.sign, &b.sign)?;
+    let lt = f.mux(&diff_sign, &lt, &a.sign)?;
+    let unordered = f.or(&nan, &zeros)?;
+    let ordered = f.negate(&unordered)?;
+    let lt = f.and(&lt, &ordered)?;
+
+    let diffs = xs
+        .iter()
+        .zip(ys.iter())
+        .map(|(x, y)| f.xor(x, y))
+        .collect::<Result<Vec<F::Item>, F::Error>>()?;
+    let diff = or_all(f, &diffs)?;
+    let same = f.negate(&diff)?;
+    let eq = f.or(&same, &zeros)?;
+    let not_nan = f.negate(&nan)?;
+    let eq = f.and(&eq, &not_nan)?;
+    Ok((lt, eq))
+}
+
+fn from_int<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let n = xs.len();
+    let sign = xs[n - 1].clone();
+    let mag = negate_if(f, &sign, xs)?;
+    // the magnitude needs two bits below the rounded significand
+    let k = std::cmp::max(n, fmt.frac_bits + 3);
+    let mut sig = vec![f.constant(0, 2)?; k - n];
+    sig.extend(mag);
+    let exp = constant(f, fmt.bias() + n as i128 - 1, fmt.exp_width())?;
+    round_pack(f, fmt, &sign, &exp, &sig)
+}
+
+fn to_int<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    fmt: Format,
+    xs: &[F::Item],
+    n: usize,
+) -> Result<Vec<F::Item>, F::Error> {
+    let m = fmt.frac_bits;
+    let w = fmt.exp_width();
+    let a = unpack(f, fmt, xs)?;
+    let bias = constant(f, fmt.bias(), w)?;
+    let s = sub_bits(f, &a.exp, &bias)?;
+    // |x| < 1
+    let small = s[w - 1].clone();
+    // |x| >= 2^(n - 1)
+    let max = constant(f, n as i128 - 1, w)?;
+    let t = sub_bits(f, &s, &max)?;
+    let overflow = f.negate(&t[w - 1])?;
+
+    // Otherwise the magnitude is `sig * 2^(s - m)` rounded down, where
+    // `0 <= s <= n - 2`.
+    let mut nshift = 0;
+    while 1 << nshift < n {
+        nshift += 1;
+    }
+    let mut buf = a.sig.clone();
+    buf.resize(m + n, f.constant(0, 2)?);
+    let buf = f.bin_shl(
+        &BinaryBundle::new(buf),
+        &BinaryBundle::new(s[..nshift].to_vec()),
+    )?;
+    let zs = negate_if(f, &a.sign, &buf.wires()[m..])?;
+
+    let zero = f.or(&small, &a.nan)?;
+    let not_zero = f.negate(&zero)?;
+    let zs = mask(f, &not_zero, &zs)?;
+    let not_nan = f.negate(&a.nan)?;
+    let saturate = f.and(&overflow, &not_nan)?;
+    let not_sign = f.negate(&a.sign)?;
+    let mut bound = vec![not_sign; n - 1];
+    bound.push(a.sign);
+    select(f, &saturate, &zs, &bound)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// helpers for vectors of bits
+
+/// The constant `x` with `n` bits, in twos complement.
+fn constant<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    x: i128,
+    n: usize,
+) -> Result<Vec<F::Item>, F::Error> {
+    f.bin_constant_bundle(x as u128, n)
+        .map(|x| x.wires().to_vec())
+}
+
+fn add_bits<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    f.bin_addition_no_carry(
+        &BinaryBundle::new(xs.to_vec()),
+        &BinaryBundle::new(ys.to_vec()),
+    )
+    .map(|z| z.wires().to_vec())
+}
+
+fn sub_bits<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    sub_geq(f, xs, ys).map(|(z, _)| z)
+}
+
+/// Add the bit `b` to `xs`, returning a result with one more bit.
+fn increment<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    b: &F::Item,
+) -> Result<Vec<F::Item>, F::Error> {
+    let mut carry = b.clone();
+    let mut zs = Vec::with_capacity(xs.len() + 1);
+    for x in xs.iter() {
+        let (z, c) = f.adder(x, &carry, None)?;
+        zs.push(z);
+        carry = c;
+    }
+    zs.push(carry);
+    Ok(zs)
+}
+
+/// Compute `-xs` if `b = 1`, else `xs`.
+fn negate_if<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    b: &F::Item,
+    xs: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let ys = xs
+        .iter()
+        .map(|x| f.xor(x, b))
+        .collect::<Result<Vec<F::Item>, F::Error>>()?;
+    let mut zs = increment(f, &ys, b)?;
+    zs.pop();
+    Ok(zs)
+}
+
+/// If `b = 0` returns `xs` else `ys`, using one AND gate per bit.
+fn select<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    b: &F::Item,
+    xs: &[F::Item],
+    ys: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    xs.iter()
+        .zip(ys.iter())
+        .map(|(x, y)| {
+            let d = f.xor(x, y)?;
+            let d = f.and(&d, b)?;
+            f.xor(x, &d)
+        })
+        .collect()
+}
+
+/// If `b = 0` returns zeros else `xs`.
+fn mask<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    b: &F::Item,
+    xs: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    xs.iter().map(|x| f.and(x, b)).collect()
+}
+
+fn or_all<F: BinaryGadgets + ?Sized>(f: &mut F, xs: &[F::Item]) -> Result<F::Item, F::Error> {
+    match xs.len() {
+        0 => f.constant(0, 2),
+        1 => Ok(xs[0].clone()),
+        _ => f.or_many(xs),
+    }
+}
+
+fn and_all<F: BinaryGadgets + ?Sized>(f: &mut F, xs: &[F::Item]) -> Result<F::Item, F::Error> {
+    match xs.len() {
+        0 => f.constant(1, 2),
+        1 => Ok(xs[0].clone()),
+        _ => f.and_many(xs),
+    }
+}
+
+/// Shift `sig` left until its most significant bit is set, subtracting the
+/// shift amount from `exp`. Zero is shifted by `2^l - 1` where `2^l >= k`.
+fn normalize<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    sig: &[F::Item],
+    exp: &[F::Item],
+) -> Result<(Vec<F::Item>, Vec<F::Item>), F::Error> {
+    let k = sig.len();
+    let zero = f.constant(0, 2)?;
+    let mut nstages = 0;
+    while 1 << nstages < k {
+        nstages += 1;
+    }
+    // find the bits of the leading zero count from the most significant
+    let mut sig = sig.to_vec();
+    let mut lz = Vec::with_capacity(exp.len());
+    for j in (0..nstages).rev() {
+        let amt = 1 << j;
+        let any = or_all(f, &sig[k - amt..])?;
+        let z = f.negate(&any)?;
+        let shifted = (0..k)
+            .map(|i| {
+                if i < amt {
+                    zero.clone()
+                } else {
+                    sig[i - amt].clone()
+                }
+            })
+            .collect::<Vec<F::Item>>();
+        sig = select(f, &z, &sig, &shifted)?;
+        lz.insert(0, z);
+    }
+    lz.resize(exp.len(), zero);
+    let exp = sub_bits(f, exp, &lz)?;
+    Ok((sig, exp))
+}
+
+/// Shift `sig` right by the unsigned amount `amt`, setting the least
+/// significant bit if any set bits are shifted out.
+fn shift_right_jam<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    sig: &[F::Item],
+    amt: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let k = sig.len();
+    let zero = f.constant(0, 2)?;
+    let mut sig = sig.to_vec();
+    // bits of the shift amount worth at least `k` shift every bit out
+    let mut overflow = Vec::new();
+    for (j, s) in amt.iter().enumerate() {
+        let a = if j < 64 { 1u64 << j } else { u64::MAX };
+        if a >= k as u64 {
+            overflow.push(s.clone());
+            continue;
+        }
+        let a = a as usize;
+        let mut shifted = (0..k)
+            .map(|i| {
+                if i + a < k {
+                    sig[i + a].clone()
+                } else {
+                    zero.clone()
+                }
+            })
+            .collect::<Vec<F::Item>>();
+        shifted[0] = or_all(f, &sig[..=a])?;
+        sig = select(f, s, &sig, &shifted)?;
+    }
+    if !overflow.is_empty() {
+        let s = or_all(f, &overflow)?;
+        let mut jammed = vec![zero; k];
+        jammed[0] = or_all(f, &sig)?;
+        sig = select(f, &s, &sig, &jammed)?;
+    }
+    Ok(sig)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::{Dummy, DummyVal},
+        fancy::FancyInput,
+        util::RngExt,
+    };
+    use rand::{thread_rng, Rng};
+
+    type Bits = BinaryBundle<DummyVal>;
+
+    /// Interesting binary32 values, and random ones.
+    fn values32() -> Vec<u128> {
+        let mut rng = thread_rng();
+        let mut xs = vec![
+            0.0,
+            1.0,
+            1.5,
+            3.0,
+            0.1,
+            1.0 + f32::EPSILON,
+            16_777_216.0,
+            16_777_217.0,
+            f32::MAX,
+            f32::MIN_POSITIVE,
+            f32::from_bits(1),
+            f32::from_bits(0x007f_ffff),
+            f32::INFINITY,
+        ];
+        for _ in 0..4 {
+            xs.push(rng.gen_range(-4.0, 4.0));
+            xs.push(f32::from_bits(rng.gen::<u32>()));
+            xs.push(f32::from_bits(rng.gen::<u32>() & 0x807f_ffff));
+        }
+        let mut xs = xs
+            .into_iter()
+            .flat_map(|x| vec![x, -x])
+            .map(|x| x.to_bits() as u128)
+            .collect::<Vec<u128>>();
+        xs.push(f32::NAN.to_bits() as u128);
+        xs
+    }
+
+    /// Interesting binary64 values, and random ones.
+    fn values64() -> Vec<u128> {
+        let mut rng = thread_rng();
+        let mut xs = vec![
+            0.0,
+            1.0,
+            1.5,
+            3.0,
+            0.1,
+            1.0 + f64::EPSILON,
+            9_007_199_254_740_992.0,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+            f64::from_bits(1),
+            f64::from_bits(0x000f_ffff_ffff_ffff),
+            f64::INFINITY,
+        ];
+        for _ in 0..3 {
+            xs.push(rng.gen_range(-4.0, 4.0));
+            xs.push(f64::from_bits(rng.gen_u64()));
+            xs.push(f64::from_bits(rng.gen_u64() & 0x800f_ffff_ffff_ffff));
+        }
+        let mut xs = xs
+            .into_iter()
+            .flat_map(|x| vec![x, -x])
+            .map(|x| x.to_bits() as u128)
+            .collect::<Vec<u128>>();
+        xs.push(f64::NAN.to_bits() as u128);
+        xs
+    }
+
+    fn is_nan(x: u128, nbits: usize) -> bool {
+        if nbits == 32 {
+            f32::from_bits(x as u32).is_nan()
+        } else {
+            f64::from_bits(x as u64).is_nan()
+        }
+    }
+
+    /// Check the binary operation `gadget` against `op` on all pairs of `xs`,
+    /// and on pairs of nearby values.
+    fn check_binop<G, O>(nbits: usize, xs: &[u128], gadget: G, op: O)
+    where
+        G: Fn(&mut Dummy, &Bits, &Bits) -> Bits,
+        O: Fn(u128, u128) -> u128,
+    {
+        let mut rng = thread_rng();
+        let mut pairs = Vec::new();
+        for &x in xs.iter() {
+            for &y in xs.iter() {
+                pairs.push((x, y));
+            }
+        }
+        // values close to each other, for cancellation and rounding
+        for _ in 0..64 {
+            let x = rng.gen_u128() >> (128 - nbits);
+            let y = x ^ (rng.gen_u128() >> (128 - nbits / 2 + rng.gen_range(0, 8)));
+            pairs.push((x, y));
+        }
+        for (x, y) in pairs {
+            let mut d = Dummy::new();
+            let xb = d.bin_encode(x, nbits).unwrap();
+            let yb = d.bin_encode(y, nbits).unwrap();
+            let z = gadget(&mut d, &xb, &yb);
+            let z = d.bin_output(&z).unwrap().unwrap();
+            let should_be = op(x, y);
+            if is_nan(should_be, nbits) {
+                assert!(is_nan(z, nbits), "x={:#x} y={:#x} z={:#x}", x, y, z);
+            } else {
+                assert_eq!(z, should_be, "x={:#x} y={:#x}", x, y);
+            }
+        }
+    }
+
+    fn f32op(op: fn(f32, f32) -> f32) -> impl Fn(u128, u128) -> u128 {
+        move |x, y| op(f32::from_bits(x as u32), f32::from_bits(y as u32)).to_bits() as u128
+    }
+
+    fn f64op(op: fn(f64, f64) -> f64) -> impl Fn(u128, u128) -> u128 {
+        move |x, y| op(f64::from_bits(x as u64), f64::from_bits(y as u64)).to_bits() as u128
+    }
+
+    #[test]
+    fn add() {
+        let add = |d: &mut Dummy, x: &Bits, y: &Bits| d.float_add(x, y).unwrap();
+        check_binop(32, &values32(), add, f32op(|x, y| x + y));
+        check_binop(64, &values64(), add, f64op(|x, y| x + y));
+    }
+
+    #[test]
+    fn sub() {
+        let sub = |d: &mut Dummy, x: &Bits, y: &Bits| d.float_sub(x, y).unwrap();
+        check_binop(32, &values32(), sub, f32op(|x, y| x - y));
+        check_binop(64, &values64(), sub, f64op(|x, y| x - y));
+    }
+
+    #[test]
+    fn mul() {
+        let mul = |d: &mut Dummy, x: &Bits, y: &Bits| d.float_mul(x, y).unwrap();
+        check_binop(32, &values32(), mul, f32op(|x, y| x * y));
+        check_binop(64, &values64(), mul, f64op(|x, y| x * y));
+    }
+
+    #[test]
+    fn div() {
+        let div = |d: &mut Dummy, x: &Bits, y: &Bits| d.float_div(x, y).unwrap();
+        check_binop(32, &values32(), div, f32op(|x, y| x / y));
+        check_binop(64, &values64(), div, f64op(|x, y| x / y));
+    }
+
+    #[test]
+    fn compare() {
+        for &nbits in [32, 64].iter() {
+            let xs = if nbits == 32 { values32() } else { values64() };
+            for &x in xs.iter() {
+                for &y in xs.iter() {
+                    let (lt, le, eq) = if nbits == 32 {
+                        let (x, y) = (f32::from_bits(x as u32), f32::from_bits(y as u32));
+                        (x < y, x <= y, x == y)
+                    } else {
+                        let (x, y) = (f64::from_bits(x as u64), f64::from_bits(y as u64));
+                        (x < y, x <= y, x == y)
+                    };
+                    let mut d = Dummy::new();
+                    let xb = d.bin_encode(x, nbits).unwrap();
+                    let yb = d.bin_encode(y, nbits).unwrap();
+                    let z = d.float_lt(&xb, &yb).unwrap();
+                    assert_eq!(z.val() == 1, lt, "x={:#x} y={:#x}", x, y);
+                    let z = d.float_le(&xb, &yb).unwrap();
+                    assert_eq!(z.val() == 1, le, "x={:#x} y={:#x}", x, y);
+                    let z = d.float_eq(&xb, &yb).unwrap();
+                    assert_eq!(z.val() == 1, eq, "x={:#x} y={:#x}", x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn from_int() {
+        let mut rng = thread_rng();
+        let mut xs: Vec<i64> = vec![0, 1, -1, 16_777_217, -16_777_219, i64::MAX, i64::MIN];
+        for _ in 0..32 {
+            let shift = rng.gen_range(0, 64);
+            xs.push(rng.gen::<i64>() >> shift);
+        }
+        for x in xs {
+            let mut d = Dummy::new();
+            let xb = d.bin_encode(x as u64 as u128, 64).unwrap();
+            let z = d.float_from_int(&xb, 32).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, (x as f32).to_bits() as u128, "x={}", x);
+            let z = d.float_from_int(&xb, 64).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, (x as f64).to_bits() as u128, "x={}", x);
+
+            let x = x as i16;
+            let xb = d.bin_encode(x as u16 as u128, 16).unwrap();
+            let z = d.float_from_int(&xb, 32).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, (x as f32).to_bits() as u128, "x={}", x);
+        }
+    }
+
+    #[test]
+    fn to_int() {
+        let mask = |x: i64, nbits: usize| (x as u128) & ((1 << nbits) - 1);
+        for x in values32() {
+            let mut d = Dummy::new();
+            let xb = d.bin_encode(x, 32).unwrap();
+            let x = f32::from_bits(x as u32);
+            let z = d.float_to_int(&xb, 32).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, mask(x as i32 as i64, 32), "x={}", x);
+            let z = d.float_to_int(&xb, 8).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, mask(x as i8 as i64, 8), "x={}", x);
+        }
+        let mut xs = values64();
+        xs.push(9.223_372_036_854_775e18f64.to_bits() as u128);
+        xs.push((-9.223_372_036_854_775e18f64).to_bits() as u128);
+        xs.push(123_456_789.987f64.to_bits() as u128);
+        for x in xs {
+            let mut d = Dummy::new();
+            let xb = d.bin_encode(x, 64).unwrap();
+            let x = f64::from_bits(x as u64);
+            let z = d.float_to_int(&xb, 64).unwrap();
+            let z = d.bin_output(&z).unwrap().unwrap();
+            assert_eq!(z, mask(x as i64, 64), "x={}", x);
+        }
+    }
+
+    #[test]
+    fn unsupported_sizes() {
+        let mut d = Dummy::new();
+        let x = d.bin_encode(0, 16).unwrap();
+        let y = d.bin_encode(0, 32).unwrap();
+        assert!(d.float_add(&x, &x).is_err());
+        assert!(d.float_mul(&x, &y).is_err());
+        assert!(d.float_from_int(&x, 16).is_err());
+        assert!(d.float_to_int(&y, 0).is_err());
+        assert!(d.float_from_int(&BinaryBundle::new(Vec::new()), 32).is_err());
+    }
+}
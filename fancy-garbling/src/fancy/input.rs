@@ -116,6 +116,18 @@ pub trait FancyInput {
         self.encode_bundle(&xs, &qs).map(CrtBundle::from)
     }
 
+    /// Encode a CRT input bundle holding `value` as a fixed-point number with
+    /// `frac_bits` fractional bits. See `util::to_fixed`. Returns an error if
+    /// `value` does not fit.
+    fn fixed_encode(
+        &mut self,
+        value: f64,
+        frac_bits: usize,
+        modulus: u128,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        self.crt_encode(util::try_to_fixed(value, frac_bits, modulus)?, modulus)
+    }
+
     /// Receive an CRT input bundle.
     fn crt_receive(&mut self, modulus: u128) -> Result<CrtBundle<Self::Item>, Self::Error> {
         let qs = util::factor(modulus);
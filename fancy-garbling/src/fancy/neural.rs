@@ -0,0 +1,577 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `NeuralGadgets`, which provide the layers of neural
+//! networks over fixed-point CRT bundles.
+//!
+//! Images are slices of bundles holding the values of each channel in turn,
+//! each channel in row-major order, with dimensions given by a `Shape`. Every
+//! layer holds the fixed-point numbers of `FixedGadgets` with `frac_bits`
+//! fractional bits, and the linear layers rescale each of their outputs once.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        activation::ActivationGadgets,
+        crt::CrtBundle,
+        fixed::FixedGadgets,
+        Fancy,
+        HasModulus,
+    },
+    util,
+};
+use itertools::Itertools;
+
+/// The dimensions of an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Shape {
+    /// The number of channels.
+    pub channels: usize,
+    /// The number of rows of each channel.
+    pub height: usize,
+    /// The number of columns of each channel.
+    pub width: usize,
+}
+
+impl Shape {
+    /// Create a new shape.
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Shape {
+            channels,
+            height,
+            width,
+        }
+    }
+
+    /// The number of values in an image of this shape.
+    pub fn size(&self) -> usize {
+        self.checked_size().expect("image size overflows a usize")
+    }
+
+    /// The number of values in an image of this shape, or `None` if it
+    /// overflows a `usize`.
+    pub fn checked_size(&self) -> Option<usize> {
+        self.channels
+            .checked_mul(self.height)?
+            .checked_mul(self.width)
+    }
+
+    fn index(&self, c: usize, y: usize, x: usize) -> usize {
+        (c * self.height + y) * self.width + x
+    }
+}
+
+/// A fully connected layer with public weights.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dense {
+    /// The weights, with a row for each output.
+    pub weights: Vec<Vec<f64>>,
+    /// The bias of each output.
+    pub biases: Vec<f64>,
+}
+
+/// A two-dimensional convolution with public kernels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conv2d {
+    /// The number of output channels.
+    pub out_channels: usize,
+    /// The height and width of the kernels.
+    pub kernel: (usize, usize),
+    /// The step between positions of the kernels.
+    pub stride: usize,
+    /// The number of zeros added around each side of the input.
+    pub padding: usize,
+    /// The weights of the kernels, indexed by output channel, input channel,
+    /// row and column, in that order.
+    pub weights: Vec<f64>,
+    /// The bias of each output channel.
+    pub biases: Vec<f64>,
+}
+
+impl Conv2d {
+    /// The shape of the output on an input of shape `input`.
+    pub fn output_shape(&self, input: Shape) -> Shape {
+        let (kh, kw) = self.kernel;
+        Shape::new(
+            self.out_channels,
+            (input.height + 2 * self.padding - kh) / self.stride + 1,
+            (input.width + 2 * self.padding - kw) / self.stride + 1,
+        )
+    }
+}
+
+/// A pooling layer over square windows of each channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pool {
+    /// The height and width of the windows.
+    pub size: usize,
+    /// The step between windows.
+    pub stride: usize,
+}
+
+impl Pool {
+    /// The shape of the output on an input of shape `input`.
+    pub fn output_shape(&self, input: Shape) -> Shape {
+        Shape::new(
+            input.channels,
+            (input.height - self.size) / self.stride + 1,
+            (input.width - self.size) / self.stride + 1,
+        )
+    }
+}
+
+impl<F: Fancy> NeuralGadgets for F {}
+
+/// Extension trait for `Fancy` providing neural network layers over
+/// fixed-point CRT bundles.
+pub trait NeuralGadgets: Fancy + ActivationGadgets {
+    /// Compute the fully connected layer `layer` on `xs`.
+    fn crt_dense(
+        &mut self,
+        layer: &Dense,
+        xs: &[CrtBundle<Self::Item>],
+        frac_bits: usize,
+    ) -> Result<Vec<CrtBundle<Self::Item>>, Self::Error> {
+        let q = modulus(xs)?;
+        check_len(layer.biases.len(), layer.weights.len())?;
+        layer
+            .weights
+            .iter()
+            .zip(layer.biases.iter())
+            .map(|(row, &bias)| {
+                check_len(xs.len(), row.len())?;
+                let terms = row.iter().zip(xs.iter()).map(|(&w, x)| (w, x));
+                linear(self, terms, bias, q, frac_bits)
+            })
+            .collect()
+    }
+
+    /// Compute the fully connected layer with secret `weights`, with a row for
+    /// each output, and secret `biases` on `xs`.
+    fn crt_dense_secret(
+        &mut self,
+        weights: &[Vec<CrtBundle<Self::Item>>],
+        biases: &[CrtBundle<Self::Item>],
+        xs: &[CrtBundle<Self::Item>],
+        frac_bits: usize,
+    ) -> Result<Vec<CrtBundle<Self::Item>>, Self::Error> {
+        modulus(xs)?;
+        check_len(biases.len(), weights.len())?;
+        weights
+            .iter()
+            .zip(biases.iter())
+            .map(|(row, bias)| {
+                check_len(xs.len(), row.len())?;
+                let mut z = self.crt_mul(&row[0], &xs[0])?;
+                for (w, x) in row.iter().zip(xs.iter()).skip(1) {
+                    let y = self.crt_mul(w, x)?;
+                    z = self.crt_add(&z, &y)?;
+                }
+                let z = rescale(self, &z, frac_bits)?;
+                self.crt_add(&z, bias)
+            })
+            .collect()
+    }
+
+    /// Compute the convolution `layer` on the image `xs` of shape `shape`,
+    /// returning the output image and its shape.
+    fn crt_conv2d(
+        &mut self,
+        layer: &Conv2d,
+        xs: &[CrtBundle<Self::Item>],
+        shape: Shape,
+        frac_bits: usize,
+    ) -> Result<(Vec<CrtBundle<Self::Item>>, Shape), Self::Error> {
+        let q = modulus(xs)?;
+        let (kh, kw) = layer.kernel;
+        check_len(xs.len(), shape.size())?;
+        check_len(layer.biases.len(), layer.out_channels)?;
+        check_len(
+            layer.weights.len(),
+            layer.out_channels * shape.channels * kh * kw,
+        )?;
+        check_window(shape, layer.kernel, layer.padding, layer.stride)?;
+
+        let out = layer.output_shape(shape);
+        let mut zs = Vec::with_capacity(out.size());
+        for o in 0..out.channels {
+            for y in 0..out.height {
+                for x in 0..out.width {
+                    let terms = (0..shape.channels)
+                        .cartesian_product(0..kh)
+                        .cartesian_product(0..kw)
+                        .filter_map(|((c, i), j)| {
+                            // the input under the kernel, leaving out the padding
+                            let u = (y * layer.stride + i).checked_sub(layer.padding)?;
+                            let v = (x * layer.stride + j).checked_sub(layer.padding)?;
+                            if u >= shape.height || v >= shape.width {
+                                return None;
+                            }
+                            let w = layer.weights[((o * shape.channels + c) * kh + i) * kw + j];
+                            Some((w, &xs[shape.index(c, u, v)]))
+                        });
+                    zs.push(linear(self, terms, layer.biases[o], q, frac_bits)?);
+                }
+            }
+        }
+        Ok((zs, out))
+    }
+
+    /// Average the windows of `pool` in the image `xs` of shape `shape`,
+    /// returning the output image and its shape. Requires `frac_bits > 0`.
+    fn crt_avg_pool(
+        &mut self,
+        pool: &Pool,
+        xs: &[CrtBundle<Self::Item>],
+        shape: Shape,
+        frac_bits: usize,
+    ) -> Result<(Vec<CrtBundle<Self::Item>>, Shape), Self::Error> {
+        if frac_bits == 0 {
+            return Err(Self::Error::from(FancyError::InvalidArg(
+                "average pooling requires fractional bits".to_string(),
+            )));
+        }
+        let q = modulus(xs)?;
+        let weight = 1.0 / (pool.size * pool.size) as f64;
+        pool_with(self, pool, xs, shape, |f, window| {
+            let terms = window.iter().map(|x| (weight, *x));
+            linear(f, terms, 0.0, q, frac_bits)
+        })
+    }
+
+    /// Take the maximum of the windows of `pool` in the image `xs` of shape
+    /// `shape` using `crt_max` with accuracy `accuracy`, returning the output
+    /// image and its shape.
+    fn crt_max_pool(
+        &mut self,
+        pool: &Pool,
+        xs: &[CrtBundle<Self::Item>],
+        shape: Shape,
+        accuracy: &str,
+    ) -> Result<(Vec<CrtBundle<Self::Item>>, Shape), Self::Error> {
+        pool_with(self, pool, xs, shape, |f, window| {
+            if window.len() == 1 {
+                return Ok(window[0].clone());
+            }
+            let window = window.into_iter().cloned().collect_vec();
+            f.crt_max(&window, accuracy)
+        })
+    }
+
+    /// Return a bundle holding the index of the largest bundle in `xs`, or
+    /// of the first one if several are largest, comparing using `crt_lt` with
+    /// accuracy `accuracy`.
+    fn crt_argmax(
+        &mut self,
+        xs: &[CrtBundle<Self::Item>],
+        accuracy: &str,
+    ) -> Result<CrtBundle<Self::Item>, Self::Error> {
+        let q = modulus(xs)?;
+        let mut max = xs[0].clone();
+        let mut argmax = self.crt_constant_bundle(0, q)?;
+        for (i, x) in xs.iter().enumerate().skip(1) {
+            let larger = self.crt_lt(&max, x, accuracy)?;
+            max = self.crt_multiplex(&larger, &max, x)?;
+            let i = self.crt_constant_bundle(i as u128, q)?;
+            argmax = self.crt_multiplex(&larger, &argmax, &i)?;
+        }
+        Ok(argmax)
+    }
+}
+
+/// Compute `bias + sum_i w_i x_i` for public weights `w_i`, rescaling once.
+fn linear<'a, F, I>(
+    f: &mut F,
+    terms: I,
+    bias: f64,
+    q: u128,
+    frac_bits: usize,
+) -> Result<CrtBundle<F::Item>, F::Error>
+where
+    F: FixedGadgets + ?Sized,
+    F::Item: 'a,
+    I: IntoIterator<Item = (f64, &'a CrtBundle<F::Item>)>,
+{
+    let mut z = f.crt_constant_bundle(util::try_to_fixed(bias, 2 * frac_bits, q)?, q)?;
+    for (w, x) in terms {
+        let w = util::try_to_fixed(w, frac_bits, q)?;
+        if w != 0 {
+            let y = f.crt_cmul(x, w)?;
+            z = f.crt_add(&z, &y)?;
+        }
+    }
+    rescale(f, &z, frac_bits)
+}
+
+/// Apply `reduce` to each window of `pool` in the image `xs` of shape `shape`.
+fn pool_with<F, R>(
+    f: &mut F,
+    pool: &Pool,
+    xs: &[CrtBundle<F::Item>],
+    shape: Shape,
+    mut reduce: R,
+) -> Result<(Vec<CrtBundle<F::Item>>, Shape), F::Error>
+where
+    F: Fancy + ?Sized,
+    R: FnMut(&mut F, Vec<&CrtBundle<F::Item>>) -> Result<CrtBundle<F::Item>, F::Error>,
+{
+    check_len(xs.len(), shape.size())?;
+    check_window(shape, (pool.size, pool.size), 0, pool.stride)?;
+    let out = pool.output_shape(shape);
+    let mut zs = Vec::with_capacity(out.size());
+    for c in 0..out.channels {
+        for y in 0..out.height {
+            for x in 0..out.width {
+                let window = (0..pool.size)
+                    .cartesian_product(0..pool.size)
+                    .map(|(i, j)| &xs[shape.index(c, y * pool.stride + i, x * pool.stride + j)])
+                    .collect_vec();
+                zs.push(reduce(f, window)?);
+            }
+        }
+    }
+    Ok((zs, out))
+}
+
+/// Divide `x` by `2^frac_bits` using `fixed_rescale`, skipping it when there
+/// is nothing to do.
+fn rescale<F: FixedGadgets + ?Sized>(
+    f: &mut F,
+    x: &CrtBundle<F::Item>,
+    frac_bits: usize,
+) -> Result<CrtBundle<F::Item>, F::Error> {
+    if frac_bits == 0 {
+        Ok(x.clone())
+    } else {
+        f.fixed_rescale(x, frac_bits)
+    }
+}
+
+/// Check that windows of size `window` with step `stride` fit in images of
+/// shape `shape` with `padding` zeros around each side.
+pub(crate) fn check_window<E: From<FancyError>>(
+    shape: Shape,
+    (height, width): (usize, usize),
+    padding: usize,
+    stride: usize,
+) -> Result<(), E> {
+    // Whether the window fits in `n` values with padding on both sides.
+    let fits = |n: usize, window: usize| {
+        let padded = padding.checked_mul(2).and_then(|p| p.checked_add(n));
+        matches!(padded, Some(m) if m >= window)
+    };
+    if stride == 0
+        || height == 0
+        || width == 0
+        || !fits(shape.height, height)
+        || !fits(shape.width, width)
+    {
+        return Err(E::from(FancyError::InvalidArg(format!(
+            "invalid {}x{} window with stride {} and padding {} on a {}x{} image",
+            height, width, stride, padding, shape.height, shape.width
+        ))));
+    }
+    Ok(())
+}
+
+fn modulus<W: Clone + HasModulus, E: From<FancyError>>(xs: &[CrtBundle<W>]) -> Result<u128, E> {
+    xs.first()
+        .map(|x| x.composite_modulus())
+        .ok_or_else(|| E::from(FancyError::InvalidArgNum { got: 0, needed: 1 }))
+}
+
+fn check_len<E: From<FancyError>>(got: usize, needed: usize) -> Result<(), E> {
+    if got != needed {
+        return Err(E::from(FancyError::InvalidArgNum { got, needed }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::Dummy,
+        fancy::{FancyInput, FancyReveal},
+    };
+    use rand::{thread_rng, Rng};
+
+    const FRAC_BITS: usize = 6;
+
+    /// A random fixed-point number with `FRAC_BITS` fractional bits.
+    fn random_fixed(rng: &mut impl Rng, bound: f64) -> f64 {
+        let scale = (1 << FRAC_BITS) as f64;
+        (rng.gen_range(-bound, bound) * scale).round() / scale
+    }
+
+    /// `bias + sum_i w_i x_i` rounded down as computed by `linear`.
+    fn linear_floor(ws: &[f64], xs: &[f64], bias: f64) -> f64 {
+        let scale = (1 << FRAC_BITS) as f64;
+        let z = ws
+            .iter()
+            .zip(xs.iter())
+            .map(|(w, x)| (w * scale).round() * x * scale)
+            .sum::<f64>()
+            + (bias * scale * scale).round();
+        (z / scale).floor() / scale
+    }
+
+    #[test]
+    fn dense() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let xs = (0..5).map(|_| random_fixed(&mut rng, 4.0)).collect_vec();
+        let layer = Dense {
+            weights: (0..3)
+                .map(|_| (0..5).map(|_| rng.gen_range(-2.0, 2.0)).collect())
+                .collect(),
+            biases: (0..3).map(|_| rng.gen_range(-2.0, 2.0)).collect(),
+        };
+        let mut d = Dummy::new();
+        let inputs = xs
+            .iter()
+            .map(|&x| d.fixed_encode(x, FRAC_BITS, q).unwrap())
+            .collect_vec();
+        let zs = d.crt_dense(&layer, &inputs, FRAC_BITS).unwrap();
+        for (i, z) in zs.iter().enumerate() {
+            let should_be = linear_floor(&layer.weights[i], &xs, layer.biases[i]);
+            assert_eq!(d.fixed_reveal(z, FRAC_BITS).unwrap(), should_be);
+        }
+
+        // the same layer with secret weights, with the bias added after
+        // rescaling
+        let ws = layer
+            .weights
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&w| d.fixed_encode(w, FRAC_BITS, q).unwrap())
+                    .collect_vec()
+            })
+            .collect_vec();
+        let bs = layer
+            .biases
+            .iter()
+            .map(|&b| d.fixed_encode(b, FRAC_BITS, q).unwrap())
+            .collect_vec();
+        let zs = d.crt_dense_secret(&ws, &bs, &inputs, FRAC_BITS).unwrap();
+        for (i, z) in zs.iter().enumerate() {
+            let b = util::from_fixed(util::to_fixed(layer.biases[i], FRAC_BITS, q), FRAC_BITS, q);
+            let should_be = linear_floor(&layer.weights[i], &xs, 0.0) + b;
+            assert_eq!(d.fixed_reveal(z, FRAC_BITS).unwrap(), should_be);
+        }
+    }
+
+    #[test]
+    fn conv2d() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(6);
+        let shape = Shape::new(2, 5, 4);
+        let xs = (0..shape.size())
+            .map(|_| rng.gen_range(-9, 10))
+            .collect_vec();
+        for &(stride, padding) in [(1, 0), (2, 1)].iter() {
+            let layer = Conv2d {
+                out_channels: 3,
+                kernel: (3, 2),
+                stride,
+                padding,
+                weights: (0..3 * 2 * 3 * 2)
+                    .map(|_| rng.gen_range(-3, 4) as f64)
+                    .collect(),
+                biases: (0..3).map(|_| rng.gen_range(-3, 4) as f64).collect(),
+            };
+            let mut d = Dummy::new();
+            let inputs = xs
+                .iter()
+                .map(|&x| d.fixed_encode(x as f64, 0, q).unwrap())
+                .collect_vec();
+            let (zs, out) = d.crt_conv2d(&layer, &inputs, shape, 0).unwrap();
+            assert_eq!(out, layer.output_shape(shape));
+            assert_eq!(zs.len(), out.size());
+            for o in 0..out.channels {
+                for y in 0..out.height {
+                    for x in 0..out.width {
+                        let mut should_be = layer.biases[o];
+                        for c in 0..shape.channels {
+                            for i in 0..3 {
+                                for j in 0..2 {
+                                    let u = (y * stride + i) as isize - padding as isize;
+                                    let v = (x * stride + j) as isize - padding as isize;
+                                    if u >= 0 && v >= 0 && u < 5 && v < 4 {
+                                        let w = layer.weights[((o * 2 + c) * 3 + i) * 2 + j];
+                                        should_be +=
+                                            w * xs[shape.index(c, u as usize, v as usize)] as f64;
+                                    }
+                                }
+                            }
+                        }
+                        let z = &zs[out.index(o, y, x)];
+                        assert_eq!(d.fixed_reveal(z, 0).unwrap(), should_be);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pooling() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(8);
+        let shape = Shape::new(2, 4, 6);
+        let xs = (0..shape.size())
+            .map(|_| random_fixed(&mut rng, 8.0))
+            .collect_vec();
+        let pool = Pool { size: 2, stride: 2 };
+        let mut d = Dummy::new();
+        let inputs = xs
+            .iter()
+            .map(|&x| d.fixed_encode(x, FRAC_BITS, q).unwrap())
+            .collect_vec();
+        let (avgs, out) = d.crt_avg_pool(&pool, &inputs, shape, FRAC_BITS).unwrap();
+        let (maxs, _) = d.crt_max_pool(&pool, &inputs, shape, "100%").unwrap();
+        assert_eq!(out, Shape::new(2, 2, 3));
+        for c in 0..2 {
+            for y in 0..2 {
+                for x in 0..3 {
+                    let window = [(0, 0), (0, 1), (1, 0), (1, 1)]
+                        .iter()
+                        .map(|&(i, j)| xs[shape.index(c, 2 * y + i, 2 * x + j)])
+                        .collect_vec();
+                    let i = out.index(c, y, x);
+                    assert_eq!(
+                        d.fixed_reveal(&avgs[i], FRAC_BITS).unwrap(),
+                        linear_floor(&[0.25; 4], &window, 0.0)
+                    );
+                    assert_eq!(
+                        d.fixed_reveal(&maxs[i], FRAC_BITS).unwrap(),
+                        window.iter().cloned().fold(f64::MIN, f64::max)
+                    );
+                }
+            }
+        }
+        assert!(d.crt_avg_pool(&pool, &inputs, shape, 0).is_err());
+        let pool = Pool { size: 5, stride: 1 };
+        assert!(d.crt_max_pool(&pool, &inputs, shape, "100%").is_err());
+    }
+
+    #[test]
+    fn argmax() {
+        let q = util::modulus_with_nprimes(6);
+        for xs in [vec![3, -2, 7, 7, 1], vec![-5], vec![-1, -4, -1], vec![0, 2]].iter() {
+            let mut d = Dummy::new();
+            let inputs = xs
+                .iter()
+                .map(|&x| d.fixed_encode(x as f64, 0, q).unwrap())
+                .collect_vec();
+            let z = d.crt_argmax(&inputs, "100%").unwrap();
+            let max = xs.iter().max().unwrap();
+            let should_be = xs.iter().position(|x| x == max).unwrap();
+            assert_eq!(d.crt_reveal(&z).unwrap(), should_be as u128);
+        }
+        assert!(Dummy::new().crt_argmax(&[], "100%").is_err());
+    }
+}
@@ -57,6 +57,18 @@ pub trait FancyReveal: Fancy {
         Ok(zs)
     }
 
+    /// Reveal a CRT bundle holding a fixed-point number with `frac_bits`
+    /// fractional bits to all parties. See `util::from_fixed`.
+    fn fixed_reveal(
+        &mut self,
+        x: &CrtBundle<Self::Item>,
+        frac_bits: usize,
+    ) -> Result<f64, Self::Error> {
+        let q = x.composite_modulus();
+        let z = self.crt_reveal(x)?;
+        Ok(util::try_from_fixed(z, frac_bits, q)?)
+    }
+
     /// Reveal a binary bundle to all parties.
     fn bin_reveal(&mut self, x: &BinaryBundle<Self::Item>) -> Result<u128, Self::Error> {
         let bits = self.reveal_many(x.wires())?;
@@ -0,0 +1,335 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `Sha256Gadgets`, which implement the SHA-256 hash function
+//! and HMAC-SHA-256 over binary bundles.
+//!
+//! Messages, keys and digests are `BinaryBundle`s holding bytes in order, each
+//! byte least significant bit first, as for `AesGadgets`. The length of a
+//! message is public, so its padding costs no gates.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        aes::{xor_bits, xor_constant},
+        binary::{BinaryBundle, BinaryGadgets},
+        Fancy,
+        HasModulus,
+    },
+};
+
+impl<F: Fancy> Sha256Gadgets for F {}
+
+/// Extension trait for `Fancy` providing SHA-256 and HMAC-SHA-256.
+pub trait Sha256Gadgets: Fancy + BinaryGadgets {
+    /// Compute the 256-bit SHA-256 digest of `msg`, whose number of bits must
+    /// be a multiple of 8.
+    fn sha256(
+        &mut self,
+        msg: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        check_bytes(msg)?;
+        sha256(self, msg.wires().to_vec()).map(BinaryBundle::new)
+    }
+
+    /// Compute the 256-bit HMAC-SHA-256 of `msg` under `key`, as defined in
+    /// RFC 2104. Keys longer than 64 bytes are hashed first.
+    fn hmac_sha256(
+        &mut self,
+        key: &BinaryBundle<Self::Item>,
+        msg: &BinaryBundle<Self::Item>,
+    ) -> Result<BinaryBundle<Self::Item>, Self::Error> {
+        check_bytes(key)?;
+        check_bytes(msg)?;
+        let mut key = key.wires().to_vec();
+        if key.len() > BLOCK_BITS {
+            key = sha256(self, key)?;
+        }
+        let zero = self.constant(0, 2)?;
+        key.resize(BLOCK_BITS, zero);
+        let mut inner = xor_bytes(self, &key, 0x36)?;
+        inner.extend(msg.wires().iter().cloned());
+        let digest = sha256(self, inner)?;
+        let mut outer = xor_bytes(self, &key, 0x5c)?;
+        outer.extend(digest);
+        sha256(self, outer).map(BinaryBundle::new)
+    }
+}
+
+/// Number of bits in a SHA-256 message block.
+const BLOCK_BITS: usize = 512;
+
+/// Initial hash value of SHA-256.
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn check_bytes<W: Clone + HasModulus>(x: &BinaryBundle<W>) -> Result<(), FancyError> {
+    if !x.size().is_multiple_of(8) {
+        return Err(FancyError::InvalidArg(format!(
+            "expected a whole number of bytes, got {} bits",
+            x.size()
+        )));
+    }
+    Ok(())
+}
+
+/// Hash the bytes in `msg`, returning the digest as bytes.
+fn sha256<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    mut msg: Vec<F::Item>,
+) -> Result<Vec<F::Item>, F::Error> {
+    let zero = f.constant(0, 2)?;
+    let one = f.constant(1, 2)?;
+    let len = msg.len() as u64;
+    msg.extend(constant_bits(0x80, 8, &zero, &one));
+    while msg.len() % BLOCK_BITS != BLOCK_BITS - 64 {
+        msg.push(zero.clone());
+    }
+    msg.extend(swap_bytes(&constant_bits(len, 64, &zero, &one)));
+    let mut state = H0
+        .iter()
+        .map(|&h| constant_bits(h as u64, 32, &zero, &one))
+        .collect::<Vec<_>>();
+    for block in msg.chunks(BLOCK_BITS) {
+        state = compress(f, &state, block, &zero, &one)?;
+    }
+    Ok(state.iter().flat_map(|w| swap_bytes(w)).collect())
+}
+
+/// Run the compression function on one block of 512 bits.
+fn compress<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    state: &[Vec<F::Item>],
+    block: &[F::Item],
+    zero: &F::Item,
+    one: &F::Item,
+) -> Result<Vec<Vec<F::Item>>, F::Error> {
+    let mut w = block.chunks(32).map(swap_bytes).collect::<Vec<_>>();
+    for t in 16..64 {
+        let (x, y) = (&w[t - 15], &w[t - 2]);
+        let s0 = xor3(f, &rotr(x, 7), &rotr(x, 18), &shr(x, 3, zero))?;
+        let s1 = xor3(f, &rotr(y, 17), &rotr(y, 19), &shr(y, 10, zero))?;
+        let sum = add(f, &w[t - 16], &s0)?;
+        let sum = add(f, &sum, &w[t - 7])?;
+        w.push(add(f, &sum, &s1)?);
+    }
+    // The working variables a, b, c, d, e, f, g and h.
+    let mut v = state.to_vec();
+    for t in 0..64 {
+        let s1 = xor3(f, &rotr(&v[4], 6), &rotr(&v[4], 11), &rotr(&v[4], 25))?;
+        let ch = choose(f, &v[4], &v[5], &v[6])?;
+        let t1 = add(f, &v[7], &s1)?;
+        let t1 = add(f, &t1, &ch)?;
+        let t1 = add(f, &t1, &constant_bits(K[t] as u64, 32, zero, one))?;
+        let t1 = add(f, &t1, &w[t])?;
+        let s0 = xor3(f, &rotr(&v[0], 2), &rotr(&v[0], 13), &rotr(&v[0], 22))?;
+        let maj = majority(f, &v[0], &v[1], &v[2])?;
+        let t2 = add(f, &s0, &maj)?;
+        v.rotate_right(1);
+        v[4] = add(f, &v[4], &t1)?;
+        v[0] = add(f, &t1, &t2)?;
+    }
+    state
+        .iter()
+        .zip(v.iter())
+        .map(|(h, x)| add(f, h, x))
+        .collect()
+}
+
+/// Add two 32-bit words modulo 2^32.
+fn add<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    x: &[F::Item],
+    y: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let z = f.bin_addition_no_carry(
+        &BinaryBundle::new(x.to_vec()),
+        &BinaryBundle::new(y.to_vec()),
+    )?;
+    Ok(z.wires().to_vec())
+}
+
+fn xor3<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &[F::Item],
+    y: &[F::Item],
+    z: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    let xy = xor_bits(f, x, y)?;
+    xor_bits(f, &xy, z)
+}
+
+/// For each bit, `y` if `x` is set and `z` otherwise.
+fn choose<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &[F::Item],
+    y: &[F::Item],
+    z: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    (0..32)
+        .map(|i| {
+            let yz = f.xor(&y[i], &z[i])?;
+            let t = f.and(&x[i], &yz)?;
+            f.xor(&t, &z[i])
+        })
+        .collect()
+}
+
+/// For each bit, the majority of `x`, `y` and `z`.
+fn majority<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &[F::Item],
+    y: &[F::Item],
+    z: &[F::Item],
+) -> Result<Vec<F::Item>, F::Error> {
+    (0..32)
+        .map(|i| {
+            let xy = f.xor(&x[i], &y[i])?;
+            let yz = f.xor(&y[i], &z[i])?;
+            let t = f.and(&xy, &yz)?;
+            f.xor(&t, &y[i])
+        })
+        .collect()
+}
+
+fn rotr<W: Clone>(x: &[W], n: usize) -> Vec<W> {
+    (0..32).map(|i| x[(i + n) % 32].clone()).collect()
+}
+
+fn shr<W: Clone>(x: &[W], n: usize, zero: &W) -> Vec<W> {
+    (0..32)
+        .map(|i| x.get(i + n).unwrap_or(zero).clone())
+        .collect()
+}
+
+/// Xor the public byte `c` into each byte of `xs`.
+fn xor_bytes<F: Fancy + ?Sized>(
+    f: &mut F,
+    xs: &[F::Item],
+    c: u8,
+) -> Result<Vec<F::Item>, F::Error> {
+    let mut out = Vec::with_capacity(xs.len());
+    for byte in xs.chunks(8) {
+        out.extend(xor_constant(f, byte, u32::from(c))?);
+    }
+    Ok(out)
+}
+
+/// The `n` bits of `x` as constant wires, least significant first.
+fn constant_bits<W: Clone>(x: u64, n: usize, zero: &W, one: &W) -> Vec<W> {
+    (0..n)
+        .map(|i| if (x >> i) & 1 == 1 { one } else { zero }.clone())
+        .collect()
+}
+
+/// Convert between bytes in order and a big-endian number, both least
+/// significant bit first.
+fn swap_bytes<W: Clone>(x: &[W]) -> Vec<W> {
+    let n = x.len() / 8;
+    (0..x.len())
+        .map(|i| x[8 * (n - 1 - i / 8) + i % 8].clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::CircuitBuilder,
+        dummy::Dummy,
+        fancy::{BundleGadgets, FancyInput},
+        util,
+    };
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn encode(d: &mut Dummy, bytes: &[u8]) -> BinaryBundle<crate::dummy::DummyVal> {
+        let bits = util::bytes_to_bits(bytes);
+        BinaryBundle::new(d.encode_many(&bits, &vec![2; bits.len()]).unwrap())
+    }
+
+    fn sha256_dummy(msg: &[u8]) -> Vec<u8> {
+        let mut d = Dummy::new();
+        let x = encode(&mut d, msg);
+        let z = d.sha256(&x).unwrap();
+        util::bytes_from_bits(&d.output_bundle(&z).unwrap().unwrap())
+    }
+
+    fn hmac_dummy(key: &[u8], msg: &[u8]) -> Vec<u8> {
+        let mut d = Dummy::new();
+        let k = encode(&mut d, key);
+        let x = encode(&mut d, msg);
+        let z = d.hmac_sha256(&k, &x).unwrap();
+        util::bytes_from_bits(&d.output_bundle(&z).unwrap().unwrap())
+    }
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            sha256_dummy(b""),
+            hex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+        assert_eq!(
+            sha256_dummy(b"abc"),
+            hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+        assert_eq!(
+            sha256_dummy(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hex("248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1")
+        );
+    }
+
+    #[test]
+    fn hmac_rfc4231() {
+        assert_eq!(
+            hmac_dummy(&[0x0b; 20], b"Hi There"),
+            hex("b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7")
+        );
+        assert_eq!(
+            hmac_dummy(b"Jefe", b"what do ya want for nothing?"),
+            hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            hmac_dummy(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            hex("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54")
+        );
+    }
+
+    #[test]
+    fn sha256_circuit() {
+        let mut b = CircuitBuilder::new();
+        let x = b.bin_garbler_input(24);
+        let z = b.sha256(&x).unwrap();
+        b.output_bundle(&z).unwrap();
+        let circ = b.finish();
+        let out = circ.eval_plain(&util::bytes_to_bits(b"abc"), &[]).unwrap();
+        assert_eq!(
+            util::bytes_from_bits(&out),
+            hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+        );
+    }
+}
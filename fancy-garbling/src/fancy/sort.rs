@@ -0,0 +1,252 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Module containing `SortGadgets`, which obliviously sort bundles using
+//! Batcher's odd-even merge sorting network.
+//!
+//! The sequence of comparisons only depends on the number of bundles, so the
+//! sorting network reveals nothing about the order of the inputs.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        binary::{BinaryBundle, BinaryGadgets},
+        bundle::{Bundle, BundleGadgets},
+        crt::{CrtBundle, CrtGadgets},
+        Fancy,
+    },
+};
+use std::ops::Deref;
+
+impl<F: Fancy> SortGadgets for F {}
+
+/// Extension trait for `Fancy` providing oblivious sorting of bundles.
+pub trait SortGadgets: Fancy + BundleGadgets + BinaryGadgets + CrtGadgets {
+    /// Sort `xs` in ascending order, where `lt(self, x, y)` returns a mod 2
+    /// wire which is 1 if `x` must come before `y`. Bundles which are equal
+    /// according to `lt` may come out in any order.
+    fn sort_by<B, C>(&mut self, xs: &[B], mut lt: C) -> Result<Vec<B>, Self::Error>
+    where
+        B: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+        C: FnMut(&mut Self, &B, &B) -> Result<Self::Item, Self::Error>,
+    {
+        let mut xs = xs.to_vec();
+        for (i, j) in odd_even_merge_sort(xs.len()) {
+            let swap = lt(self, &xs[j], &xs[i])?;
+            let (x, y) = self.cond_swap(&swap, &xs[i], &xs[j])?;
+            xs[i] = x;
+            xs[j] = y;
+        }
+        Ok(xs)
+    }
+
+    /// Sort `keys` in ascending order according to `lt` as in `sort_by`,
+    /// moving each payload along with its key.
+    fn sort_pairs_by<K, P, C>(
+        &mut self,
+        keys: &[K],
+        payloads: &[P],
+        mut lt: C,
+    ) -> Result<(Vec<K>, Vec<P>), Self::Error>
+    where
+        K: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+        P: Clone + Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+        C: FnMut(&mut Self, &K, &K) -> Result<Self::Item, Self::Error>,
+    {
+        if keys.len() != payloads.len() {
+            return Err(Self::Error::from(FancyError::InvalidArgNum {
+                got: payloads.len(),
+                needed: keys.len(),
+            }));
+        }
+        let mut keys = keys.to_vec();
+        let mut payloads = payloads.to_vec();
+        for (i, j) in odd_even_merge_sort(keys.len()) {
+            let swap = lt(self, &keys[j], &keys[i])?;
+            let (x, y) = self.cond_swap(&swap, &keys[i], &keys[j])?;
+            keys[i] = x;
+            keys[j] = y;
+            let (x, y) = self.cond_swap(&swap, &payloads[i], &payloads[j])?;
+            payloads[i] = x;
+            payloads[j] = y;
+        }
+        Ok((keys, payloads))
+    }
+
+    /// Swap `x` and `y` if `b = 1`, where `b` is mod 2.
+    fn cond_swap<B>(&mut self, b: &Self::Item, x: &B, y: &B) -> Result<(B, B), Self::Error>
+    where
+        B: Deref<Target = Bundle<Self::Item>> + From<Bundle<Self::Item>>,
+    {
+        // x' = x + b(y - x) and y' = y - b(y - x), using one multiplication
+        // per wire.
+        let mut xs = Vec::with_capacity(x.size());
+        let mut ys = Vec::with_capacity(y.size());
+        for (x, y) in x.wires().iter().zip(y.wires().iter()) {
+            let d = self.sub(y, x)?;
+            let d = self.mul(&d, b)?;
+            xs.push(self.add(x, &d)?);
+            ys.push(self.sub(y, &d)?);
+        }
+        Ok((B::from(Bundle::new(xs)), B::from(Bundle::new(ys))))
+    }
+
+    /// Sort binary bundles in ascending order, as unsigned numbers.
+    fn bin_sort(
+        &mut self,
+        xs: &[BinaryBundle<Self::Item>],
+    ) -> Result<Vec<BinaryBundle<Self::Item>>, Self::Error> {
+        self.sort_by(xs, |f, x, y| f.bin_lt(x, y))
+    }
+
+    /// Sort CRT bundles in ascending order, comparing using `crt_lt` with
+    /// accuracy `accuracy`.
+    fn crt_sort(
+        &mut self,
+        xs: &[CrtBundle<Self::Item>],
+        accuracy: &str,
+    ) -> Result<Vec<CrtBundle<Self::Item>>, Self::Error> {
+        self.sort_by(xs, |f, x, y| f.crt_lt(x, y, accuracy))
+    }
+}
+
+/// The comparators `(i, j)` with `i < j` of Batcher's odd-even merge sorting
+/// network on `n` inputs, in order. Each comparator puts the smaller of the
+/// inputs at `i`.
+///
+/// When `n` is not a power of two, comparators involving inputs beyond `n` are
+/// dropped, which is equivalent to padding with inputs larger than any other.
+fn odd_even_merge_sort(n: usize) -> Vec<(usize, usize)> {
+    let mut comparators = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k >= 1 {
+            let mut j = k % p;
+            while j + k < n {
+                for i in 0..std::cmp::min(k, n - j - k) {
+                    if (i + j) / (2 * p) == (i + j + k) / (2 * p) {
+                        comparators.push((i + j, i + j + k));
+                    }
+                }
+                j += 2 * k;
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    comparators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dummy::Dummy,
+        fancy::FancyInput,
+        util::{self, RngExt},
+    };
+    use itertools::Itertools;
+    use rand::thread_rng;
+
+    #[test]
+    fn zero_one_principle() {
+        // a comparator network sorts every input if it sorts every 0-1 input
+        for n in 0..=10 {
+            let network = odd_even_merge_sort(n);
+            for x in 0..1u32 << n {
+                let mut bits = (0..n).map(|i| x >> i & 1).collect_vec();
+                for &(i, j) in network.iter() {
+                    if bits[i] > bits[j] {
+                        bits.swap(i, j);
+                    }
+                }
+                assert!(bits.windows(2).all(|w| w[0] <= w[1]), "n={} x={}", n, x);
+            }
+        }
+    }
+
+    #[test]
+    fn bin_sort() {
+        let mut rng = thread_rng();
+        let nbits = 10;
+        for n in 1..20 {
+            let xs = (0..n).map(|_| rng.gen_u128() % (1 << nbits)).collect_vec();
+            let mut d = Dummy::new();
+            let bs = xs
+                .iter()
+                .map(|&x| d.bin_encode(x, nbits).unwrap())
+                .collect_vec();
+            let zs = d.bin_sort(&bs).unwrap();
+            let out = d.bin_outputs(&zs).unwrap().unwrap();
+            assert_eq!(out, xs.iter().cloned().sorted().collect_vec());
+        }
+    }
+
+    #[test]
+    fn crt_sort() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_width(10);
+        for n in 1..12 {
+            // crt_lt compares correctly when the difference is less than q/2
+            let xs = (0..n).map(|_| rng.gen_u128() % (q / 2)).collect_vec();
+            let mut d = Dummy::new();
+            let bs = xs
+                .iter()
+                .map(|&x| d.crt_encode(x, q).unwrap())
+                .collect_vec();
+            let zs = d.crt_sort(&bs, "100%").unwrap();
+            let out = d.crt_outputs(&zs).unwrap().unwrap();
+            assert_eq!(out, xs.iter().cloned().sorted().collect_vec());
+        }
+    }
+
+    #[test]
+    fn sort_pairs_descending() {
+        let mut rng = thread_rng();
+        let nbits = 8;
+        let n = 13;
+        let keys = (0..n).map(|_| rng.gen_u128() % (1 << nbits)).collect_vec();
+        let mut d = Dummy::new();
+        let ks = keys
+            .iter()
+            .map(|&x| d.bin_encode(x, nbits).unwrap())
+            .collect_vec();
+        // the payload of each key is its original position
+        let ps = (0..n)
+            .map(|i| d.bin_encode(i as u128, 4).unwrap())
+            .collect_vec();
+        let (ks, ps) = d.sort_pairs_by(&ks, &ps, |f, x, y| f.bin_lt(y, x)).unwrap();
+        let ks = d.bin_outputs(&ks).unwrap().unwrap();
+        let ps = d.bin_outputs(&ps).unwrap().unwrap();
+        assert!(ks.windows(2).all(|w| w[0] >= w[1]));
+        for (k, p) in ks.iter().zip(ps.iter()) {
+            assert_eq!(*k, keys[*p as usize]);
+        }
+        assert_eq!(
+            ps.iter().cloned().sorted().collect_vec(),
+            (0..n as u128).collect_vec()
+        );
+    }
+
+    #[test]
+    fn cond_swap_mixed_moduli() {
+        let mut d = Dummy::new();
+        let x = d.encode_many(&[1, 4, 10], &[2, 5, 11]).unwrap();
+        let y = d.encode_many(&[0, 2, 3], &[2, 5, 11]).unwrap();
+        let (x, y) = (CrtBundle::new(x), CrtBundle::new(y));
+        for b in 0..2 {
+            let b = d.encode(b, 2).unwrap();
+            let (u, v) = d.cond_swap(&b, &x, &y).unwrap();
+            let (u, v) = (d.output_bundle(&u).unwrap(), d.output_bundle(&v).unwrap());
+            if b.val() == 0 {
+                assert_eq!((u, v), (Some(vec![1, 4, 10]), Some(vec![0, 2, 3])));
+            } else {
+                assert_eq!((u, v), (Some(vec![0, 2, 3]), Some(vec![1, 4, 10])));
+            }
+        }
+    }
+}
@@ -8,8 +8,35 @@
 
 mod evaluator;
 mod garbler;
+mod parallel;
+mod three_halves;
 
 pub use crate::garble::{evaluator::Evaluator, garbler::Garbler};
+pub(crate) use garbler::mul_table_size;
+
+/// The scheme used to garble multiplication gates between wires of modulus 2.
+///
+/// The garbler and evaluator must use the same scheme: `Garbler::with_scheme`
+/// sends the scheme to the evaluator, and `Evaluator::with_scheme` fails if it
+/// does not match its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryScheme {
+    /// Half-gates (ZRE15), sending two ciphertexts per AND gate.
+    HalfGates,
+    /// The three halves scheme of Rosulek and Roy (RR21), sending one and a
+    /// half ciphertexts per AND gate.
+    ThreeHalves,
+}
+
+impl BinaryScheme {
+    /// The byte identifying the scheme on the channel.
+    fn tag(self) -> u8 {
+        match self {
+            BinaryScheme::HalfGates => 0,
+            BinaryScheme::ThreeHalves => 1,
+        }
+    }
+}
 
 ////////////////////////////////////////////////////////////////////////////////
 // tests
@@ -147,6 +174,21 @@ mod nonstreaming {
         });
     }
 
+    #[test] // proj2_rand
+    fn proj2_rand() {
+        garble_test_helper(|q| {
+            let mut rng = thread_rng();
+            let p = 2 + rng.gen_u16() % 8;
+            let tab = (0..q * p).map(|_| rng.gen_u16() % p).collect_vec();
+            let mut b = CircuitBuilder::new();
+            let x = b.evaluator_input(q);
+            let y = b.evaluator_input(p);
+            let z = b.proj2(&x, &y, p, Some(tab)).unwrap();
+            b.output(&z).unwrap();
+            b.finish()
+        });
+    }
+
     #[test] // mod_change
     fn mod_change() {
         garble_test_helper(|q| {
@@ -173,8 +215,8 @@ mod nonstreaming {
     #[test] // half_gate_unequal_mods
     fn half_gate_unequal_mods() {
         let mut rng = AesRng::from_seed(Block::from(0 as u128));
-        for q in 3..16 {
-            let ymod = 2 + rng.gen_u16() % 6; // lower mod is capped at 8 for now
+        for q in 3..24 {
+            let ymod = 2 + rng.gen_u16() % (q - 2);
             println!("\nTESTING MOD q={} ymod={}", q, ymod);
 
             let mut b = CircuitBuilder::new();
@@ -287,7 +329,9 @@ mod nonstreaming {
 mod streaming {
     use crate::{
         dummy::{Dummy, DummyVal},
+        errors::EvaluatorError,
         util::RngExt,
+        BinaryScheme,
         Evaluator,
         Fancy,
         FancyInput,
@@ -418,6 +462,19 @@ mod streaming {
         }
     }
 
+    #[test]
+    fn scheme_mismatch() {
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            Garbler::with_scheme(sender, AesRng::new(), BinaryScheme::HalfGates).unwrap();
+        });
+        match Evaluator::with_scheme(receiver, BinaryScheme::ThreeHalves) {
+            Err(EvaluatorError::SchemeMismatch) => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        handle.join().unwrap();
+    }
+
     #[test]
     fn proj() {
         fn fancy_projection<F: Fancy>(b: &mut F, xs: &[F::Item], q: u16) -> Option<u16> {
@@ -437,6 +494,34 @@ mod streaming {
             );
         }
     }
+
+    #[test]
+    fn proj2() {
+        fn fancy_projection<F: Fancy>(
+            b: &mut F,
+            xs: &[F::Item],
+            q: u16,
+            tt: &[u16],
+        ) -> Option<u16> {
+            let z = b.proj2(&xs[0], &xs[1], q, Some(tt.to_vec())).unwrap();
+            b.output(&z).unwrap()
+        }
+
+        let mut rng = thread_rng();
+        for _ in 0..16 {
+            let p = 2 + rng.gen_u16() % 20;
+            let r = 2 + rng.gen_u16() % 20;
+            let q = rng.gen_modulus();
+            let tt = (0..p * r).map(|_| rng.gen_u16() % q).collect_vec();
+            let tt = &tt;
+            streaming_test(
+                move |b, xs| fancy_projection(b, xs, q, tt),
+                move |b, xs| fancy_projection(b, xs, q, tt),
+                move |b, xs| fancy_projection(b, xs, q, tt),
+                &[p, r],
+            );
+        }
+    }
 }
 
 #[cfg(test)]
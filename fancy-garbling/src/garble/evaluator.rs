@@ -4,13 +4,20 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+use super::{
+    garbler::{mul_table_size, MINITABLE_ENTRIES_PER_BLOCK},
+    parallel::{par_map, Schedule, Table},
+    three_halves::{self, eval_and},
+    BinaryScheme,
+};
 use crate::{
+    circuit::{Circuit, Gate},
     errors::{EvaluatorError, FancyError},
     fancy::{Fancy, FancyReveal, HasModulus},
     util::{output_tweak, tweak, tweak2},
     wire::Wire,
 };
-use scuttlebutt::AbstractChannel;
+use scuttlebutt::{AbstractChannel, Block};
 
 /// Streaming evaluator using a callback to receive ciphertexts as needed.
 ///
@@ -20,16 +27,35 @@ pub struct Evaluator<C> {
     channel: C,
     current_gate: usize,
     current_output: usize,
+    scheme: BinaryScheme,
 }
 
 impl<C: AbstractChannel> Evaluator<C> {
-    /// Create a new `Evaluator`.
+    /// Create a new `Evaluator`, using half-gates for all multiplications.
     pub fn new(channel: C) -> Self {
         Evaluator {
             channel,
             current_gate: 0,
             current_output: 0,
+            scheme: BinaryScheme::HalfGates,
+        }
+    }
+
+    /// Create a new `Evaluator` using `scheme` for multiplications between
+    /// wires of modulus 2. Fails with `EvaluatorError::SchemeMismatch` if the
+    /// garbler, created using `Garbler::with_scheme`, uses another scheme.
+    pub fn with_scheme(channel: C, scheme: BinaryScheme) -> Result<Self, EvaluatorError> {
+        let mut ev = Self::new(channel);
+        ev.scheme = scheme;
+        if ev.channel.read_u8()? != scheme.tag() {
+            return Err(EvaluatorError::SchemeMismatch);
         }
+        Ok(ev)
+    }
+
+    /// The scheme used for multiplications between wires of modulus 2.
+    pub fn scheme(&self) -> BinaryScheme {
+        self.scheme
     }
 
     /// The current non-free gate index of the garbling computation.
@@ -51,6 +77,96 @@ impl<C: AbstractChannel> Evaluator<C> {
         let block = self.channel.read_block()?;
         Ok(Wire::from_block(block, modulus))
     }
+
+    /// Evaluate `circuit` using up to `nthreads` threads, where
+    /// `garbler_inputs` and `evaluator_inputs` are the encoded inputs.
+    ///
+    /// Non-free gates are evaluated in parallel one multiplicative level at a
+    /// time. Garbled tables are read in the same order as `Circuit::eval`
+    /// would read them, so the garbler may use either `Circuit::eval` or
+    /// `Garbler::eval_circuit_parallel`.
+    pub fn eval_circuit_parallel(
+        &mut self,
+        circuit: &Circuit,
+        garbler_inputs: &[Wire],
+        evaluator_inputs: &[Wire],
+        nthreads: usize,
+    ) -> Result<Option<Vec<u16>>, EvaluatorError> {
+        let sched = Schedule::new(circuit)?;
+        let gate_offset = self.current_gate;
+        self.current_gate += sched.num_nonfree_gates();
+
+        let mut wires: Vec<Option<Wire>> = vec![None; circuit.gates.len()];
+        let mut tables: Vec<Option<Table>> = vec![None; circuit.gates.len()];
+        let mut nread = 0;
+
+        for level in sched.levels.iter() {
+            // Read everything the garbler sends up to this level, in gate order.
+            while nread < level.stream_end {
+                let i = sched.stream[nread];
+                let table = if sched.three_halves(circuit, i, self.scheme) {
+                    let mut table = [0; three_halves::TABLE_SIZE];
+                    self.channel.read_bytes(&mut table)?;
+                    Table::ThreeHalves(table)
+                } else {
+                    Table::Blocks(self.channel.read_blocks(sched.table_size(circuit, i))?)
+                };
+                tables[i] = Some(table);
+                nread += 1;
+            }
+
+            let wires_ = &wires;
+            let tables_ = &tables;
+            let results = par_map(&level.nonfree, nthreads, |&i| {
+                let gate_num = gate_offset + sched.rank(i);
+                match (&circuit.gates[i], &tables_[i]) {
+                    (Gate::Mul { .. }, Some(Table::ThreeHalves(table))) => {
+                        let (A, B) = sched.mul_args(wires_, i);
+                        eval_and(A, B, table, gate_num)
+                    }
+                    (Gate::Mul { .. }, Some(Table::Blocks(table))) => {
+                        let (A, B) = sched.mul_args(wires_, i);
+                        eval_mul(A, B, table, gate_num)
+                    }
+                    (Gate::Proj { .. }, Some(Table::Blocks(table))) => {
+                        let x = sched.arg(wires_, i);
+                        eval_proj(x, circuit.modulus(i), table, gate_num)
+                    }
+                    (Gate::Proj2 { .. }, Some(Table::Blocks(table))) => {
+                        let (x, y) = sched.proj2_args(wires_, i);
+                        eval_proj2(x, y, circuit.modulus(i), table, gate_num)
+                    }
+                    _ => unreachable!("not a non-free gate"),
+                }
+            });
+            for (&i, wire) in level.nonfree.iter().zip(results) {
+                wires[i] = Some(wire);
+                tables[i] = None;
+            }
+
+            for &i in level.free.iter() {
+                let wire = match circuit.gates[i] {
+                    Gate::GarblerInput { id } => garbler_inputs[id].clone(),
+                    Gate::EvaluatorInput { id } => evaluator_inputs[id].clone(),
+                    Gate::Constant { .. } => match tables[i].take() {
+                        Some(Table::Blocks(table)) => {
+                            Wire::from_block(table[0], circuit.modulus(i))
+                        }
+                        _ => unreachable!("constants are read as blocks"),
+                    },
+                    _ => sched.eval_free(circuit, i, &wires)?,
+                };
+                wires[i] = Some(wire);
+            }
+        }
+
+        let mut outputs = Vec::with_capacity(sched.outputs.len());
+        for &i in sched.outputs.iter() {
+            let wire = wires[i].as_ref().ok_or(FancyError::UninitializedValue)?;
+            outputs.push(self.output(wire)?);
+        }
+        Ok(outputs.into_iter().collect())
+    }
 }
 
 impl<C: AbstractChannel> FancyReveal for Evaluator<C> {
@@ -94,8 +210,13 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         }
         let q = A.modulus();
         let qb = B.modulus();
-        let unequal = q != qb;
-        let ngates = q as usize + qb as usize - 2 + unequal as usize;
+        if self.scheme == BinaryScheme::ThreeHalves && q == 2 && qb == 2 {
+            let mut table = [0; three_halves::TABLE_SIZE];
+            self.channel.read_bytes(&mut table)?;
+            let gate_num = self.current_gate();
+            return Ok(eval_and(A, B, &table, gate_num));
+        }
+        let ngates = mul_table_size(q, qb);
         let mut gate = Vec::with_capacity(ngates);
         {
             for _ in 0..ngates {
@@ -104,36 +225,7 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
             }
         }
         let gate_num = self.current_gate();
-        let g = tweak2(gate_num as u64, 0);
-
-        // garbler's half gate
-        let L = if A.color() == 0 {
-            A.hashback(g, q)
-        } else {
-            let ct_left = gate[A.color() as usize - 1];
-            Wire::from_block(ct_left ^ A.hash(g), q)
-        };
-
-        // evaluator's half gate
-        let R = if B.color() == 0 {
-            B.hashback(g, q)
-        } else {
-            let ct_right = gate[(q + B.color()) as usize - 2];
-            Wire::from_block(ct_right ^ B.hash(g), q)
-        };
-
-        // hack for unequal mods
-        let new_b_color = if unequal {
-            let minitable = *gate.last().unwrap();
-            let ct = u128::from(minitable) >> (B.color() * 16);
-            let pt = u128::from(B.hash(tweak2(gate_num as u64, 1))) ^ ct;
-            pt as u16
-        } else {
-            B.color()
-        };
-
-        let res = L.plus_mov(&R.plus_mov(&A.cmul(new_b_color)));
-        Ok(res)
+        Ok(eval_mul(A, B, &gate, gate_num))
     }
 
     fn proj(&mut self, x: &Wire, q: u16, _: Option<Vec<u16>>) -> Result<Wire, EvaluatorError> {
@@ -143,13 +235,21 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
             let block = self.channel.read_block()?;
             gate.push(block);
         }
-        let t = tweak(self.current_gate());
-        if x.color() == 0 {
-            Ok(x.hashback(t, q))
-        } else {
-            let ct = gate[x.color() as usize - 1];
-            Ok(Wire::from_block(ct ^ x.hash(t), q))
-        }
+        let gate_num = self.current_gate();
+        Ok(eval_proj(x, q, &gate, gate_num))
+    }
+
+    fn proj2(
+        &mut self,
+        x: &Wire,
+        y: &Wire,
+        q: u16,
+        _: Option<Vec<u16>>,
+    ) -> Result<Wire, EvaluatorError> {
+        let ngates = x.modulus() as usize * y.modulus() as usize - 1;
+        let gate = self.channel.read_blocks(ngates)?;
+        let gate_num = self.current_gate();
+        Ok(eval_proj2(x, y, q, &gate, gate_num))
     }
 
     fn output(&mut self, x: &Wire) -> Result<Option<u16>, EvaluatorError> {
@@ -176,3 +276,62 @@ impl<C: AbstractChannel> Fancy for Evaluator<C> {
         }
     }
 }
+
+/// Evaluate a multiplication gate with `A.modulus() >= B.modulus()` using its
+/// garbled table.
+pub(super) fn eval_mul(A: &Wire, B: &Wire, gate: &[Block], gate_num: usize) -> Wire {
+    let q = A.modulus();
+    let unequal = q != B.modulus();
+    let g = tweak2(gate_num as u64, 0);
+
+    // garbler's half gate
+    let L = if A.color() == 0 {
+        A.hashback(g, q)
+    } else {
+        let ct_left = gate[A.color() as usize - 1];
+        Wire::from_block(ct_left ^ A.hash(g), q)
+    };
+
+    // evaluator's half gate
+    let R = if B.color() == 0 {
+        B.hashback(g, q)
+    } else {
+        let ct_right = gate[(q + B.color()) as usize - 2];
+        Wire::from_block(ct_right ^ B.hash(g), q)
+    };
+
+    // hack for unequal mods
+    let new_b_color = if unequal {
+        let i = B.color() as usize;
+        let minitable = gate[(q + B.modulus()) as usize - 2 + i / MINITABLE_ENTRIES_PER_BLOCK];
+        let ct = u128::from(minitable) >> (i % MINITABLE_ENTRIES_PER_BLOCK * 16);
+        let pt = u128::from(B.hash(tweak2(gate_num as u64, 1))) ^ ct;
+        pt as u16
+    } else {
+        B.color()
+    };
+
+    L.plus_mov(&R.plus_mov(&A.cmul(new_b_color)))
+}
+
+/// Evaluate a projection gate using its garbled table.
+pub(super) fn eval_proj(x: &Wire, q: u16, gate: &[Block], gate_num: usize) -> Wire {
+    let t = tweak(gate_num);
+    if x.color() == 0 {
+        x.hashback(t, q)
+    } else {
+        let ct = gate[x.color() as usize - 1];
+        Wire::from_block(ct ^ x.hash(t), q)
+    }
+}
+
+/// Evaluate a two-input projection gate using its garbled table.
+pub(super) fn eval_proj2(x: &Wire, y: &Wire, q: u16, gate: &[Block], gate_num: usize) -> Wire {
+    let t = tweak(gate_num);
+    let ix = x.color() as usize * y.modulus() as usize + y.color() as usize;
+    if ix == 0 {
+        x.hashback2(y, t, q)
+    } else {
+        Wire::from_block(gate[ix - 1] ^ x.hash2(y, t), q)
+    }
+}
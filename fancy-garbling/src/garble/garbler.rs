@@ -4,7 +4,13 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
+use super::{
+    parallel::{par_map, Schedule, Table},
+    three_halves::garble_and,
+    BinaryScheme,
+};
 use crate::{
+    circuit::{Circuit, Gate},
     errors::{FancyError, GarblerError},
     fancy::{BinaryBundle, CrtBundle, Fancy, FancyReveal, HasModulus},
     util::{output_tweak, tweak, tweak2, RngExt},
@@ -21,10 +27,11 @@ pub struct Garbler<C, RNG> {
     current_output: usize,
     current_gate: usize,
     rng: RNG,
+    scheme: BinaryScheme,
 }
 
 impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
-    /// Create a new garbler.
+    /// Create a new garbler, using half-gates for all multiplications.
     pub fn new(channel: C, rng: RNG) -> Self {
         Garbler {
             channel,
@@ -32,9 +39,32 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
             current_gate: 0,
             current_output: 0,
             rng,
+            scheme: BinaryScheme::HalfGates,
         }
     }
 
+    /// Create a new garbler using `scheme` for multiplications between wires
+    /// of modulus 2, and send the scheme to the evaluator. The evaluator must
+    /// be created using `Evaluator::with_scheme`.
+    pub fn with_scheme(channel: C, rng: RNG, scheme: BinaryScheme) -> Result<Self, GarblerError> {
+        let mut gb = Self::new(channel, rng);
+        gb.scheme = scheme;
+        gb.channel.write_u8(scheme.tag())?;
+        gb.channel.flush()?;
+        Ok(gb)
+    }
+
+    /// The scheme used for multiplications between wires of modulus 2.
+    pub fn scheme(&self) -> BinaryScheme {
+        self.scheme
+    }
+
+    /// Whether a multiplication between wires of moduli `q` and `qb` uses the
+    /// three halves scheme.
+    fn three_halves(&self, q: u16, qb: u16) -> bool {
+        self.scheme == BinaryScheme::ThreeHalves && q == 2 && qb == 2
+    }
+
     /// The current non-free gate index of the garbling computation
     fn current_gate(&mut self) -> usize {
         let current = self.current_gate;
@@ -124,6 +154,132 @@ impl<C: AbstractChannel, RNG: CryptoRng + RngCore> Garbler<C, RNG> {
         let (gbs, evs) = self.encode_many_wires(&xs, &ms)?;
         Ok((BinaryBundle::new(gbs), BinaryBundle::new(evs)))
     }
+
+    /// Garble `circuit` using up to `nthreads` threads, where `garbler_inputs`
+    /// and `evaluator_inputs` are the zero-wires of the inputs.
+    ///
+    /// Non-free gates are garbled in parallel one multiplicative level at a
+    /// time. Garbled tables are sent in the same order as `Circuit::eval`
+    /// would send them, so the evaluator may use either `Circuit::eval` or
+    /// `Evaluator::eval_circuit_parallel`.
+    pub fn eval_circuit_parallel(
+        &mut self,
+        circuit: &Circuit,
+        garbler_inputs: &[Wire],
+        evaluator_inputs: &[Wire],
+        nthreads: usize,
+    ) -> Result<Option<Vec<u16>>, GarblerError> {
+        let sched = Schedule::new(circuit)?;
+        for &q in circuit.gate_moduli.iter() {
+            self.delta(q);
+        }
+        let gate_offset = self.current_gate;
+        self.current_gate += sched.num_nonfree_gates();
+
+        let mut wires: Vec<Option<Wire>> = vec![None; circuit.gates.len()];
+        let mut tables: Vec<Option<Table>> = vec![None; circuit.gates.len()];
+        let mut nsent = 0;
+
+        for level in sched.levels.iter() {
+            // Draw the randomness for unequal moduli multiplications here, so
+            // that the worker threads do not need the rng.
+            let mut jobs = Vec::with_capacity(level.nonfree.len());
+            for &i in level.nonfree.iter() {
+                let r = match circuit.gates[i] {
+                    Gate::Mul { .. } => {
+                        let (q, qb) = sched.mul_moduli(circuit, i);
+                        if self.three_halves(q, qb) {
+                            self.rng.gen_u16() & 3
+                        } else if q == qb {
+                            0
+                        } else {
+                            self.rng.gen_u16() % q
+                        }
+                    }
+                    _ => 0,
+                };
+                jobs.push((i, r));
+            }
+
+            let deltas = &self.deltas;
+            let scheme = self.scheme;
+            let wires_ = &wires;
+            let results = par_map(&jobs, nthreads, |&(i, r)| {
+                let gate_num = gate_offset + sched.rank(i);
+                match circuit.gates[i] {
+                    Gate::Mul { .. } => {
+                        let (A, B) = sched.mul_args(wires_, i);
+                        let D = &deltas[&A.modulus()];
+                        let Db = &deltas[&B.modulus()];
+                        if scheme == BinaryScheme::ThreeHalves && A.modulus() == 2 {
+                            let (wire, table) = garble_and(A, B, D, gate_num, r as u8);
+                            (wire, Table::ThreeHalves(table))
+                        } else {
+                            let (wire, table) = garble_mul(A, B, D, Db, gate_num, r);
+                            (wire, Table::Blocks(table))
+                        }
+                    }
+                    Gate::Proj { ref tt, .. } => {
+                        let A = sched.arg(wires_, i);
+                        let q_out = circuit.modulus(i);
+                        let Din = &deltas[&A.modulus()];
+                        let Dout = &deltas[&q_out];
+                        let (wire, table) = garble_proj(A, q_out, tt, Din, Dout, gate_num);
+                        (wire, Table::Blocks(table))
+                    }
+                    Gate::Proj2 { ref tt, .. } => {
+                        let (A, B) = sched.proj2_args(wires_, i);
+                        let q_out = circuit.modulus(i);
+                        let Da = &deltas[&A.modulus()];
+                        let Db = &deltas[&B.modulus()];
+                        let Dout = &deltas[&q_out];
+                        let (wire, table) = garble_proj2(A, B, q_out, tt, Da, Db, Dout, gate_num);
+                        (wire, Table::Blocks(table))
+                    }
+                    _ => unreachable!("not a non-free gate"),
+                }
+            });
+            for (&(i, _), (wire, table)) in jobs.iter().zip(results) {
+                wires[i] = Some(wire);
+                tables[i] = Some(table);
+            }
+
+            for &i in level.free.iter() {
+                let wire = match circuit.gates[i] {
+                    Gate::GarblerInput { id } => garbler_inputs[id].clone(),
+                    Gate::EvaluatorInput { id } => evaluator_inputs[id].clone(),
+                    Gate::Constant { val } => {
+                        let q = circuit.modulus(i);
+                        let zero = Wire::rand(&mut self.rng, q);
+                        let wire = zero.plus(&self.deltas[&q].cmul(val));
+                        tables[i] = Some(Table::Blocks(vec![wire.as_block()]));
+                        zero
+                    }
+                    _ => sched.eval_free(circuit, i, &wires)?,
+                };
+                wires[i] = Some(wire);
+            }
+
+            // Send everything that is ready, in gate order.
+            while let Some(table) = sched.stream.get(nsent).and_then(|&i| tables[i].take()) {
+                match table {
+                    Table::Blocks(blocks) => {
+                        for block in blocks.iter() {
+                            self.channel.write_block(block)?;
+                        }
+                    }
+                    Table::ThreeHalves(bytes) => self.channel.write_bytes(&bytes)?,
+                }
+                nsent += 1;
+            }
+        }
+
+        for &i in sched.outputs.iter() {
+            let wire = wires[i].as_ref().ok_or(FancyError::UninitializedValue)?;
+            self.output(wire)?;
+        }
+        Ok(None)
+    }
 }
 
 impl<C: AbstractChannel, RNG: RngCore + CryptoRng> FancyReveal for Garbler<C, RNG> {
@@ -176,172 +332,310 @@ impl<C: AbstractChannel, RNG: RngCore + CryptoRng> Fancy for Garbler<C, RNG> {
         let gate_num = self.current_gate();
 
         let D = self.delta(q);
+        if self.three_halves(q, qb) {
+            let r = (self.rng.gen_u16() & 3) as u8;
+            let (X, table) = garble_and(A, B, &D, gate_num, r);
+            self.channel.write_bytes(&table)?;
+            return Ok(X);
+        }
         let Db = self.delta(qb);
 
-        let r;
-        let mut gate = vec![Block::default(); q as usize + qb as usize - 2];
-
         // hack for unequal moduli
-        if q != qb {
-            // would need to pack minitable into more than one u128 to support qb > 8
-            if qb > 8 {
-                return Err(GarblerError::AsymmetricHalfGateModuliMax8(qb));
-            }
-
-            r = self.rng.gen_u16() % q;
-            let t = tweak2(gate_num as u64, 1);
-
-            let mut minitable = vec![u128::default(); qb as usize];
-            let mut B_ = B.clone();
-            for b in 0..qb {
-                if b > 0 {
-                    B_.plus_eq(&Db);
-                }
-                let new_color = ((r + b) % q) as u128;
-                let ct = (u128::from(B_.hash(t)) & 0xFFFF) ^ new_color;
-                minitable[B_.color() as usize] = ct;
-            }
+        let r = if q != qb { self.rng.gen_u16() % q } else { 0 };
 
-            let mut packed = 0;
-            for i in 0..qb as usize {
-                packed += minitable[i] << (16 * i);
-            }
-            gate.push(Block::from(packed));
-        } else {
-            r = B.color(); // secret value known only to the garbler (ev knows r+b)
+        let (X, gate) = garble_mul(A, B, &D, &Db, gate_num, r);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
         }
+        Ok(X)
+    }
 
-        let g = tweak2(gate_num as u64, 0);
-
-        // X = H(A+aD) + arD such that a + A.color == 0
-        let alpha = (q - A.color()) % q; // alpha = -A.color
-        let X = A
-            .plus(&D.cmul(alpha))
-            .hashback(g, q)
-            .plus_mov(&D.cmul(alpha * r % q));
+    fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
+        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
+        let gate_num = self.current_gate();
+        let Din = self.delta(A.modulus());
+        let Dout = self.delta(q_out);
 
-        // Y = H(B + bD) + (b + r)A such that b + B.color == 0
-        let beta = (qb - B.color()) % qb;
-        let Y = B
-            .plus(&Db.cmul(beta))
-            .hashback(g, q)
-            .plus_mov(&A.cmul((beta + r) % q));
+        let (C, gate) = garble_proj(A, q_out, &tt, &Din, &Dout, gate_num);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(C)
+    }
 
-        let mut precomp = Vec::with_capacity(q as usize);
+    fn proj2(
+        &mut self,
+        A: &Wire,
+        B: &Wire,
+        q_out: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Wire, GarblerError> {
+        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
+        let gate_num = self.current_gate();
+        let Da = self.delta(A.modulus());
+        let Db = self.delta(B.modulus());
+        let Dout = self.delta(q_out);
 
-        // precompute a lookup table of X.minus(&D_cmul[(a * r % q)])
-        //                            = X.plus(&D_cmul[((q - (a * r % q)) % q)])
-        let mut X_ = X.clone();
-        precomp.push(X_.as_block());
-        for _ in 1..q {
-            X_.plus_eq(&D);
-            precomp.push(X_.as_block());
+        let (C, gate) = garble_proj2(A, B, q_out, &tt, &Da, &Db, &Dout, gate_num);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
         }
+        Ok(C)
+    }
 
-        let mut A_ = A.clone();
-        for a in 0..q {
-            if a > 0 {
-                A_.plus_eq(&D);
-            }
-            // garbler's half-gate: outputs X-arD
-            // G = H(A+aD) ^ X+a(-r)D = H(A+aD) ^ X-arD
-            if A_.color() != 0 {
-                gate[A_.color() as usize - 1] =
-                    A_.hash(g) ^ precomp[((q - (a * r % q)) % q) as usize];
-            }
+    fn output(&mut self, X: &Wire) -> Result<Option<u16>, GarblerError> {
+        let q = X.modulus();
+        let i = self.current_output();
+        let D = self.delta(q);
+        for k in 0..q {
+            let block = X.plus(&D.cmul(k)).hash(output_tweak(i, k));
+            self.channel.write_block(&block)?;
         }
+        Ok(None)
+    }
+}
 
-        precomp.clear();
-
-        // precompute a lookup table of Y.minus(&A_cmul[((b+r) % q)])
-        //                            = Y.plus(&A_cmul[((q - ((b+r) % q)) % q)])
-        let mut Y_ = Y.clone();
-        precomp.push(Y_.as_block());
-        for _ in 1..q {
-            Y_.plus_eq(&A);
-            precomp.push(Y_.as_block());
-        }
+/// The number of entries of the minitable of a multiplication with unequal
+/// moduli packed into each block.
+pub(super) const MINITABLE_ENTRIES_PER_BLOCK: usize = 8;
+
+/// The number of blocks in the garbled table of a multiplication gate with
+/// moduli `q >= qb`.
+pub(crate) fn mul_table_size(q: u16, qb: u16) -> usize {
+    let minitable = if q != qb {
+        (qb as usize).div_ceil(MINITABLE_ENTRIES_PER_BLOCK)
+    } else {
+        0
+    };
+    q as usize + qb as usize - 2 + minitable
+}
 
+/// Garble a multiplication gate with `A.modulus() >= B.modulus()`, returning
+/// the output zero-wire and the ciphertexts to send to the evaluator.
+///
+/// `r` must be uniformly random mod `A.modulus()` when the moduli differ, and
+/// is ignored otherwise.
+pub(super) fn garble_mul(
+    A: &Wire,
+    B: &Wire,
+    D: &Wire,
+    Db: &Wire,
+    gate_num: usize,
+    r: u16,
+) -> (Wire, Vec<Block>) {
+    let q = A.modulus();
+    let qb = B.modulus();
+
+    let mut gate = vec![Block::default(); q as usize + qb as usize - 2];
+
+    // hack for unequal moduli
+    let r = if q != qb {
+        let t = tweak2(gate_num as u64, 1);
+
+        let mut minitable = vec![u128::default(); qb as usize];
         let mut B_ = B.clone();
         for b in 0..qb {
             if b > 0 {
-                B_.plus_eq(&Db);
-            }
-            // evaluator's half-gate: outputs Y-(b+r)D
-            // G = H(B+bD) + Y-(b+r)A
-            if B_.color() != 0 {
-                gate[q as usize - 1 + B_.color() as usize - 1] =
-                    B_.hash(g) ^ precomp[((q - ((b + r) % q)) % q) as usize];
+                B_.plus_eq(Db);
             }
+            let new_color = ((r + b) % q) as u128;
+            let ct = (u128::from(B_.hash(t)) & 0xFFFF) ^ new_color;
+            minitable[B_.color() as usize] = ct;
         }
 
-        for block in gate.iter() {
-            self.channel.write_block(block)?;
+        // pack eight 16-bit entries into each block
+        for entries in minitable.chunks(MINITABLE_ENTRIES_PER_BLOCK) {
+            let packed = entries
+                .iter()
+                .enumerate()
+                .fold(0, |acc, (i, ct)| acc + (ct << (16 * i)));
+            gate.push(Block::from(packed));
         }
-        Ok(X.plus_mov(&Y))
+        r
+    } else {
+        B.color() // secret value known only to the garbler (ev knows r+b)
+    };
+
+    let g = tweak2(gate_num as u64, 0);
+
+    // X = H(A+aD) + arD such that a + A.color == 0
+    let alpha = (q - A.color()) % q; // alpha = -A.color
+    let X = A
+        .plus(&D.cmul(alpha))
+        .hashback(g, q)
+        .plus_mov(&D.cmul(alpha * r % q));
+
+    // Y = H(B + bD) + (b + r)A such that b + B.color == 0
+    let beta = (qb - B.color()) % qb;
+    let Y = B
+        .plus(&Db.cmul(beta))
+        .hashback(g, q)
+        .plus_mov(&A.cmul((beta + r) % q));
+
+    let mut precomp = Vec::with_capacity(q as usize);
+
+    // precompute a lookup table of X.minus(&D_cmul[(a * r % q)])
+    //                            = X.plus(&D_cmul[((q - (a * r % q)) % q)])
+    let mut X_ = X.clone();
+    precomp.push(X_.as_block());
+    for _ in 1..q {
+        X_.plus_eq(D);
+        precomp.push(X_.as_block());
     }
 
-    fn proj(&mut self, A: &Wire, q_out: u16, tt: Option<Vec<u16>>) -> Result<Wire, GarblerError> {
-        let tt = tt.ok_or(GarblerError::TruthTableRequired)?;
-
-        let q_in = A.modulus();
-        let mut gate = vec![Block::default(); q_in as usize - 1];
-
-        let tao = A.color();
-        let g = tweak(self.current_gate());
+    let mut A_ = A.clone();
+    for a in 0..q {
+        if a > 0 {
+            A_.plus_eq(D);
+        }
+        // garbler's half-gate: outputs X-arD
+        // G = H(A+aD) ^ X+a(-r)D = H(A+aD) ^ X-arD
+        if A_.color() != 0 {
+            gate[A_.color() as usize - 1] = A_.hash(g) ^ precomp[((q - (a * r % q)) % q) as usize];
+        }
+    }
 
-        let Din = self.delta(q_in);
-        let Dout = self.delta(q_out);
+    precomp.clear();
 
-        // output zero-wire
-        // W_g^0 <- -H(g, W_{a_1}^0 - \tao\Delta_m) - \phi(-\tao)\Delta_n
-        let C = A
-            .plus(&Din.cmul((q_in - tao) % q_in))
-            .hashback(g, q_out)
-            .plus_mov(&Dout.cmul((q_out - tt[((q_in - tao) % q_in) as usize]) % q_out));
-
-        // precompute `let C_ = C.plus(&Dout.cmul(tt[x as usize]))`
-        let C_precomputed = {
-            let mut C_ = C.clone();
-            (0..q_out)
-                .map(|x| {
-                    if x > 0 {
-                        C_.plus_eq(&Dout);
-                    }
-                    C_.as_block()
-                })
-                .collect::<Vec<Block>>()
-        };
-
-        let mut A_ = A.clone();
-        for x in 0..q_in {
-            if x > 0 {
-                A_.plus_eq(&Din); // avoiding expensive cmul for `A_ = A.plus(&Din.cmul(x))`
-            }
+    // precompute a lookup table of Y.minus(&A_cmul[((b+r) % q)])
+    //                            = Y.plus(&A_cmul[((q - ((b+r) % q)) % q)])
+    let mut Y_ = Y.clone();
+    precomp.push(Y_.as_block());
+    for _ in 1..q {
+        Y_.plus_eq(A);
+        precomp.push(Y_.as_block());
+    }
 
-            let ix = (tao as usize + x as usize) % q_in as usize;
-            if ix == 0 {
-                continue;
-            }
+    let mut B_ = B.clone();
+    for b in 0..qb {
+        if b > 0 {
+            B_.plus_eq(Db);
+        }
+        // evaluator's half-gate: outputs Y-(b+r)D
+        // G = H(B+bD) + Y-(b+r)A
+        if B_.color() != 0 {
+            gate[q as usize - 1 + B_.color() as usize - 1] =
+                B_.hash(g) ^ precomp[((q - ((b + r) % q)) % q) as usize];
+        }
+    }
+    (X.plus_mov(&Y), gate)
+}
 
-            let ct = A_.hash(g) ^ C_precomputed[tt[x as usize] as usize];
-            gate[ix - 1] = ct;
+/// Garble a projection gate, returning the output zero-wire and the
+/// ciphertexts to send to the evaluator.
+pub(super) fn garble_proj(
+    A: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    Din: &Wire,
+    Dout: &Wire,
+    gate_num: usize,
+) -> (Wire, Vec<Block>) {
+    let q_in = A.modulus();
+    let mut gate = vec![Block::default(); q_in as usize - 1];
+
+    let tao = A.color();
+    let g = tweak(gate_num);
+
+    // output zero-wire
+    // W_g^0 <- -H(g, W_{a_1}^0 - \tao\Delta_m) - \phi(-\tao)\Delta_n
+    let C = A
+        .plus(&Din.cmul((q_in - tao) % q_in))
+        .hashback(g, q_out)
+        .plus_mov(&Dout.cmul((q_out - tt[((q_in - tao) % q_in) as usize]) % q_out));
+
+    // precompute `let C_ = C.plus(&Dout.cmul(tt[x as usize]))`
+    let C_precomputed = {
+        let mut C_ = C.clone();
+        (0..q_out)
+            .map(|x| {
+                if x > 0 {
+                    C_.plus_eq(Dout);
+                }
+                C_.as_block()
+            })
+            .collect::<Vec<Block>>()
+    };
+
+    let mut A_ = A.clone();
+    for x in 0..q_in {
+        if x > 0 {
+            A_.plus_eq(Din); // avoiding expensive cmul for `A_ = A.plus(&Din.cmul(x))`
         }
 
-        for block in gate.iter() {
-            self.channel.write_block(block)?;
+        let ix = (tao as usize + x as usize) % q_in as usize;
+        if ix == 0 {
+            continue;
         }
-        Ok(C)
+
+        let ct = A_.hash(g) ^ C_precomputed[tt[x as usize] as usize];
+        gate[ix - 1] = ct;
     }
+    (C, gate)
+}
 
-    fn output(&mut self, X: &Wire) -> Result<Option<u16>, GarblerError> {
-        let q = X.modulus();
-        let i = self.current_output();
-        let D = self.delta(q);
-        for k in 0..q {
-            let block = X.plus(&D.cmul(k)).hash(output_tweak(i, k));
-            self.channel.write_block(&block)?;
+/// Garble a two-input projection gate, returning the output zero-wire and the
+/// ciphertexts to send to the evaluator.
+///
+/// The evaluator decrypts the row given by the colors of its input wires, and
+/// the row where both colors are zero is never sent, so the gate needs one
+/// ciphertext less than the number of rows of `tt`.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn garble_proj2(
+    A: &Wire,
+    B: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    Da: &Wire,
+    Db: &Wire,
+    Dout: &Wire,
+    gate_num: usize,
+) -> (Wire, Vec<Block>) {
+    let qa = A.modulus();
+    let qb = B.modulus();
+    let mut gate = vec![Block::default(); qa as usize * qb as usize - 1];
+    let g = tweak(gate_num);
+
+    // output zero-wire, chosen such that the row with colors (0, 0) decrypts
+    // to the hash of its input wires
+    let alpha = (qa - A.color()) % qa;
+    let beta = (qb - B.color()) % qb;
+    let val = tt[(alpha * qb + beta) as usize];
+    let C = A
+        .plus(&Da.cmul(alpha))
+        .hashback2(&B.plus(&Db.cmul(beta)), g, q_out)
+        .plus_mov(&Dout.cmul((q_out - val) % q_out));
+
+    // precompute `let C_ = C.plus(&Dout.cmul(tt[x as usize]))`
+    let C_precomputed = {
+        let mut C_ = C.clone();
+        (0..q_out)
+            .map(|x| {
+                if x > 0 {
+                    C_.plus_eq(Dout);
+                }
+                C_.as_block()
+            })
+            .collect::<Vec<Block>>()
+    };
+
+    let mut A_ = A.clone();
+    for a in 0..qa {
+        if a > 0 {
+            A_.plus_eq(Da);
+        }
+        let mut B_ = B.clone();
+        for b in 0..qb {
+            if b > 0 {
+                B_.plus_eq(Db);
+            }
+            let ix = A_.color() as usize * qb as usize + B_.color() as usize;
+            if ix == 0 {
+                continue;
+            }
+            let val = tt[a as usize * qb as usize + b as usize];
+            gate[ix - 1] = A_.hash2(&B_, g) ^ C_precomputed[val as usize];
         }
-        Ok(None)
     }
+    (C, gate)
 }
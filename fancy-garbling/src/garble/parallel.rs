@@ -0,0 +1,423 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Level scheduling for multi-threaded garbling and evaluation of `Circuit`s.
+//!
+//! Gates are grouped by their multiplicative depth: all non-free gates at a
+//! given depth are independent of each other, and can be garbled or evaluated
+//! in parallel. Garbled tables (and constant wires) are still sent in gate
+//! order, exactly as `Circuit::eval` would send them, so that a multi-threaded
+//! garbler can talk to a single-threaded evaluator and vice versa.
+
+use super::{garbler::mul_table_size, three_halves, BinaryScheme};
+use crate::{
+    circuit::{Circuit, CircuitRef, Gate},
+    errors::FancyError,
+    fancy::HasModulus,
+    wire::Wire,
+};
+use scuttlebutt::Block;
+
+/// Minimum number of non-free gates given to each thread. Spawning threads for
+/// fewer gates than this costs more than it saves.
+const MIN_GATES_PER_THREAD: usize = 128;
+
+/// What the garbler sends for a gate.
+#[derive(Clone)]
+pub(super) enum Table {
+    /// Ciphertexts of a half-gate or projection gate, or a constant wire.
+    Blocks(Vec<Block>),
+    /// A three halves AND gate.
+    ThreeHalves(three_halves::Table),
+}
+
+/// The gates at a given multiplicative depth.
+#[derive(Default)]
+pub(super) struct Level {
+    /// Non-free gates at this depth, which only depend on earlier levels.
+    pub(super) nonfree: Vec<usize>,
+    /// Free gates at this depth, in gate order. These may depend on the
+    /// non-free gates at this depth.
+    pub(super) free: Vec<usize>,
+    /// The number of entries of the stream needed to finish this level.
+    pub(super) stream_end: usize,
+}
+
+/// A level schedule for a circuit.
+pub(super) struct Schedule {
+    /// The gates producing the arguments of each gate.
+    args: Vec<[usize; 2]>,
+    /// The index of each non-free gate among all non-free gates.
+    rank: Vec<usize>,
+    nnonfree: usize,
+    /// The gates for which the garbler sends something, in gate order.
+    pub(super) stream: Vec<usize>,
+    pub(super) levels: Vec<Level>,
+    /// The gates producing the outputs.
+    pub(super) outputs: Vec<usize>,
+}
+
+impl Schedule {
+    /// Compute the level schedule of `circuit`.
+    pub(super) fn new(circuit: &Circuit) -> Result<Self, FancyError> {
+        let n = circuit.gates.len();
+        // Map from cache slot to the gate which last wrote it.
+        let mut writers: Vec<Option<usize>> = vec![None; n];
+        let mut args = Vec::with_capacity(n);
+        let mut depth: Vec<usize> = Vec::with_capacity(n);
+        let mut rank = vec![0; n];
+        let mut nnonfree = 0;
+        let mut stream = Vec::new();
+        let mut levels: Vec<Level> = Vec::new();
+
+        let arg = |writers: &[Option<usize>], r: &CircuitRef| {
+            writers
+                .get(r.ix)
+                .and_then(|w| *w)
+                .ok_or(FancyError::UninitializedValue)
+        };
+
+        for (i, gate) in circuit.gates.iter().enumerate() {
+            let (xs, out) = match *gate {
+                Gate::GarblerInput { .. } | Gate::EvaluatorInput { .. } | Gate::Constant { .. } => {
+                    (None, None)
+                }
+                Gate::Add { xref, yref, out }
+                | Gate::Sub { xref, yref, out }
+                | Gate::Mul {
+                    xref, yref, out, ..
+                }
+                | Gate::Proj2 {
+                    xref, yref, out, ..
+                } => (Some([arg(&writers, &xref)?, arg(&writers, &yref)?]), out),
+                Gate::Cmul { xref, out, .. } | Gate::Proj { xref, out, .. } => {
+                    let x = arg(&writers, &xref)?;
+                    (Some([x, x]), out)
+                }
+            };
+            let nonfree = matches!(
+                gate,
+                Gate::Mul { .. } | Gate::Proj { .. } | Gate::Proj2 { .. }
+            );
+            let d = xs.map_or(0, |[x, y]| std::cmp::max(depth[x], depth[y])) + nonfree as usize;
+
+            if levels.len() <= d {
+                levels.resize_with(d + 1, Level::default);
+            }
+            if nonfree {
+                rank[i] = nnonfree;
+                nnonfree += 1;
+                levels[d].nonfree.push(i);
+            } else {
+                levels[d].free.push(i);
+            }
+            if nonfree || matches!(gate, Gate::Constant { .. }) {
+                stream.push(i);
+                levels[d].stream_end = stream.len();
+            }
+
+            args.push(xs.unwrap_or([i, i]));
+            depth.push(d);
+            writers[out.unwrap_or(i)] = Some(i);
+        }
+
+        // Finishing a level may require reading past entries belonging to
+        // later levels, but never less than what earlier levels needed.
+        let mut end = 0;
+        for level in levels.iter_mut() {
+            end = std::cmp::max(end, level.stream_end);
+            level.stream_end = end;
+        }
+
+        let outputs = circuit
+            .output_refs
+            .iter()
+            .map(|r| arg(&writers, r))
+            .collect::<Result<Vec<usize>, FancyError>>()?;
+
+        Ok(Schedule {
+            args,
+            rank,
+            nnonfree,
+            stream,
+            levels,
+            outputs,
+        })
+    }
+
+    /// The number of non-free gates in the circuit.
+    pub(super) fn num_nonfree_gates(&self) -> usize {
+        self.nnonfree
+    }
+
+    /// The index of non-free gate `i` among all non-free gates.
+    pub(super) fn rank(&self, i: usize) -> usize {
+        self.rank[i]
+    }
+
+    /// The first argument of gate `i`.
+    pub(super) fn arg<'a>(&self, wires: &'a [Option<Wire>], i: usize) -> &'a Wire {
+        wires[self.args[i][0]]
+            .as_ref()
+            .expect("arguments are computed in earlier levels")
+    }
+
+    /// The arguments of two-input projection gate `i`, in order.
+    pub(super) fn proj2_args<'a>(
+        &self,
+        wires: &'a [Option<Wire>],
+        i: usize,
+    ) -> (&'a Wire, &'a Wire) {
+        let [x, y] = self.args[i];
+        let x = wires[x]
+            .as_ref()
+            .expect("arguments are computed in earlier levels");
+        let y = wires[y]
+            .as_ref()
+            .expect("arguments are computed in earlier levels");
+        (x, y)
+    }
+
+    /// The arguments of multiplication gate `i`, with the larger modulus first.
+    pub(super) fn mul_args<'a>(&self, wires: &'a [Option<Wire>], i: usize) -> (&'a Wire, &'a Wire) {
+        let [x, y] = self.args[i];
+        let x = wires[x]
+            .as_ref()
+            .expect("arguments are computed in earlier levels");
+        let y = wires[y]
+            .as_ref()
+            .expect("arguments are computed in earlier levels");
+        if x.modulus() < y.modulus() {
+            (y, x)
+        } else {
+            (x, y)
+        }
+    }
+
+    /// The moduli of the arguments of multiplication gate `i`, larger first.
+    pub(super) fn mul_moduli(&self, circuit: &Circuit, i: usize) -> (u16, u16) {
+        let [x, y] = self.args[i];
+        let (p, q) = (circuit.modulus(x), circuit.modulus(y));
+        (std::cmp::max(p, q), std::cmp::min(p, q))
+    }
+
+    /// Whether gate `i` is a multiplication garbled using the three halves
+    /// scheme.
+    pub(super) fn three_halves(&self, circuit: &Circuit, i: usize, scheme: BinaryScheme) -> bool {
+        matches!(circuit.gates[i], Gate::Mul { .. })
+            && scheme == BinaryScheme::ThreeHalves
+            && self.mul_moduli(circuit, i) == (2, 2)
+    }
+
+    /// The number of blocks the garbler sends for gate `i`, if it does not
+    /// use the three halves scheme.
+    pub(super) fn table_size(&self, circuit: &Circuit, i: usize) -> usize {
+        match circuit.gates[i] {
+            Gate::Constant { .. } => 1,
+            Gate::Mul { .. } => {
+                let (q, qb) = self.mul_moduli(circuit, i);
+                mul_table_size(q, qb)
+            }
+            Gate::Proj { .. } => circuit.modulus(self.args[i][0]) as usize - 1,
+            Gate::Proj2 { .. } => {
+                let [x, y] = self.args[i];
+                circuit.modulus(x) as usize * circuit.modulus(y) as usize - 1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Compute the linear gate `i` from its arguments.
+    pub(super) fn eval_free(
+        &self,
+        circuit: &Circuit,
+        i: usize,
+        wires: &[Option<Wire>],
+    ) -> Result<Wire, FancyError> {
+        let [x, y] = self.args[i];
+        let x = wires[x].as_ref().ok_or(FancyError::UninitializedValue)?;
+        let y = wires[y].as_ref().ok_or(FancyError::UninitializedValue)?;
+        match circuit.gates[i] {
+            Gate::Add { .. } | Gate::Sub { .. } if x.modulus() != y.modulus() => {
+                Err(FancyError::UnequalModuli)
+            }
+            Gate::Add { .. } => Ok(x.plus(y)),
+            Gate::Sub { .. } => Ok(x.minus(y)),
+            Gate::Cmul { c, .. } => Ok(x.cmul(c)),
+            _ => unreachable!("not a linear gate"),
+        }
+    }
+}
+
+/// Apply `f` to each item, splitting the work among up to `nthreads` threads.
+/// The results are returned in the same order as `items`.
+pub(super) fn par_map<A, T, F>(items: &[A], nthreads: usize, f: F) -> Vec<T>
+where
+    A: Sync,
+    T: Send,
+    F: Fn(&A) -> T + Sync,
+{
+    let nchunks = std::cmp::min(nthreads, items.len() / MIN_GATES_PER_THREAD);
+    if nchunks <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk_size = items.len().div_ceil(nchunks);
+    let f = &f;
+    crossbeam::scope(|s| {
+        let handles = items
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move |_| chunk.iter().map(f).collect::<Vec<T>>()))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("worker thread panicked"))
+            .collect()
+    })
+    .expect("worker thread panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        circuit::{Circuit, CircuitBuilder},
+        fancy::{BundleGadgets, CrtGadgets, Fancy},
+        util::{self, RngExt},
+        BinaryScheme,
+        Evaluator,
+        Garbler,
+        Wire,
+    };
+    use itertools::Itertools;
+    use rand::thread_rng;
+    use scuttlebutt::{unix_channel_pair, AesRng};
+
+    // Garble and evaluate `c` using `scheme`, using `Circuit::eval` when the
+    // number of threads is `None`.
+    fn parallel_test(
+        c: &Circuit,
+        gb_threads: Option<usize>,
+        ev_threads: Option<usize>,
+        scheme: BinaryScheme,
+    ) {
+        let mut rng = thread_rng();
+        let gb_mods = (0..c.num_garbler_inputs())
+            .map(|i| c.garbler_input_mod(i))
+            .collect_vec();
+        let ev_mods = (0..c.num_evaluator_inputs())
+            .map(|i| c.evaluator_input_mod(i))
+            .collect_vec();
+        let gb_inps = gb_mods.iter().map(|q| rng.gen_u16() % q).collect_vec();
+        let ev_inps = ev_mods.iter().map(|q| rng.gen_u16() % q).collect_vec();
+        let should_be = c.eval_plain(&gb_inps, &ev_inps).unwrap();
+
+        let (sender, receiver) = unix_channel_pair();
+
+        crossbeam::scope(|s| {
+            let (gb_mods, ev_mods) = (&gb_mods, &ev_mods);
+            s.spawn(move |_| {
+                let mut gb = Garbler::with_scheme(sender, AesRng::new(), scheme).unwrap();
+                let (gb_zero, gb_enc) = gb.encode_many_wires(&gb_inps, gb_mods).unwrap();
+                let (ev_zero, ev_enc) = gb.encode_many_wires(&ev_inps, ev_mods).unwrap();
+                for w in gb_enc.iter().chain(ev_enc.iter()) {
+                    gb.send_wire(w).unwrap();
+                }
+                match gb_threads {
+                    Some(n) => gb.eval_circuit_parallel(c, &gb_zero, &ev_zero, n),
+                    None => c.eval(&mut gb, &gb_zero, &ev_zero),
+                }
+                .unwrap();
+            });
+
+            let mut ev = Evaluator::with_scheme(receiver, scheme).unwrap();
+            let gb_wires = gb_mods
+                .iter()
+                .map(|q| ev.read_wire(*q).unwrap())
+                .collect::<Vec<Wire>>();
+            let ev_wires = ev_mods
+                .iter()
+                .map(|q| ev.read_wire(*q).unwrap())
+                .collect::<Vec<Wire>>();
+            let result = match ev_threads {
+                Some(n) => ev.eval_circuit_parallel(c, &gb_wires, &ev_wires, n),
+                None => c.eval(&mut ev, &gb_wires, &ev_wires),
+            }
+            .unwrap()
+            .unwrap();
+            assert_eq!(result, should_be);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn aes() {
+        let c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        parallel_test(&c, Some(4), None, BinaryScheme::HalfGates);
+        parallel_test(&c, None, Some(4), BinaryScheme::HalfGates);
+        parallel_test(&c, Some(4), Some(3), BinaryScheme::HalfGates);
+        parallel_test(&c, Some(1), Some(1), BinaryScheme::HalfGates);
+    }
+
+    #[test]
+    fn aes_three_halves() {
+        let c = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        parallel_test(&c, None, None, BinaryScheme::ThreeHalves);
+        parallel_test(&c, Some(4), None, BinaryScheme::ThreeHalves);
+        parallel_test(&c, None, Some(4), BinaryScheme::ThreeHalves);
+        parallel_test(&c, Some(4), Some(3), BinaryScheme::ThreeHalves);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let mut rng = thread_rng();
+        for _ in 0..4 {
+            let q = util::modulus_with_width(16);
+            let p = 3 + rng.gen_u16() % 6;
+            let mut b = CircuitBuilder::new();
+            let xs = (0..256).map(|_| b.crt_garbler_input(q)).collect_vec();
+            let ys = (0..256).map(|_| b.crt_evaluator_input(q)).collect_vec();
+            let zs = xs
+                .iter()
+                .zip(ys.iter())
+                .map(|(x, y)| b.crt_mul(x, y).unwrap())
+                .collect_vec();
+            let mut s = b.crt_constant_bundle(42, q).unwrap();
+            for z in zs.iter() {
+                s = b.crt_add(&s, z).unwrap();
+            }
+            let r = b.crt_relu(&s, "100%", None).unwrap();
+            b.output_bundle(&r).unwrap();
+
+            // unequal moduli multiplications
+            let u = b.garbler_input(p);
+            let v = b.evaluator_input(p + 1 + rng.gen_u16() % 7);
+            let w = b.mul(&v, &u).unwrap();
+            b.output(&w).unwrap();
+            let u = b.garbler_input(11 + p);
+            let v = b.evaluator_input(13);
+            let w = b.mul(&u, &v).unwrap();
+            b.output(&w).unwrap();
+
+            // two-input projections
+            let tt = (0..p * 7).map(|_| rng.gen_u16() % 5).collect_vec();
+            let ws = (0..256)
+                .map(|_| {
+                    let u = b.garbler_input(p);
+                    let v = b.evaluator_input(7);
+                    b.proj2(&u, &v, 5, Some(tt.clone())).unwrap()
+                })
+                .collect_vec();
+            let w = b.add_many(&ws).unwrap();
+            b.output(&w).unwrap();
+            let c = b.finish();
+
+            parallel_test(&c, Some(4), None, BinaryScheme::HalfGates);
+            parallel_test(&c, None, Some(4), BinaryScheme::HalfGates);
+            parallel_test(&c, Some(2), Some(5), BinaryScheme::HalfGates);
+            parallel_test(&c, Some(2), None, BinaryScheme::ThreeHalves);
+            parallel_test(&c, None, Some(5), BinaryScheme::ThreeHalves);
+        }
+    }
+}
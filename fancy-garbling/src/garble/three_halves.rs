@@ -0,0 +1,301 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Garbling of binary AND gates using the "three halves" scheme of Rosulek and
+//! Roy (RR21), which is compatible with free-XOR and sends `1.5κ + 8` bits per
+//! gate instead of the `2κ` bits of half-gates.
+//!
+//! Wire-labels are split into a left half (containing the color bit) and a
+//! right half. Given input labels `A` and `B` with colors `i` and `j`, the
+//! evaluator computes the output label as
+//!
+//! ```text
+//! [H(A) + H(A+B); H(B) + H(A+B)] + V_ij [G0; G1; G2] + M_ij [A_L; A_R; B_L; B_R]
+//! ```
+//!
+//! where `H` produces half-labels, `G0, G1, G2` are the half-sized ciphertexts,
+//! `V_ij` is a fixed 2x3 matrix, and `M_ij` is a 2x4 matrix which depends on
+//! two "control bits" that the garbler encrypts separately for each row. The
+//! control bits are masked with a random `r` known only to the garbler, so the
+//! evaluator's control bits are uniform regardless of the wire values.
+//!
+//! The constraints on `V_ij` and `M_ij` are those of Rosulek and Roy, "Three
+//! Halves Make a Whole? Beating the Half-Gates Lower Bound for Garbled
+//! Circuits" (CRYPTO 2021). The constants below are one solution of them in
+//! the notation of this module, derived as follows. Let `R_ij` be the
+//! output label of row `ij` without the `V_ij` term, minus the value of the
+//! output times `Δ`. The garbler takes the output zero-wire `C` from row `00`,
+//! and the ciphertexts from rows `01` and `10`, which leaves three equations:
+//!
+//! ```text
+//! R01_L + R10_R = R00_L + R00_R
+//! R11_L + R10_L + R10_R = R00_R
+//! R11_R + R01_L + R01_R = R00_L
+//! ```
+//!
+//! The hashes cancel in each of these, since by free-XOR `A_0 + B_1 = A_1 +
+//! B_0`. Writing `A_a = A_0 + aΔ` and `B_b = B_0 + bΔ`, what remains is linear
+//! in the halves of `A_0`, `B_0` and `Δ`, and must vanish identically for
+//! every choice of the colors `α` and `β` of the zero-wires and of the mask
+//! `r`. The `matrices` test checks that it does.
+
+use crate::{fancy::HasModulus, util::tweak2, wire::Wire};
+use scuttlebutt::Block;
+
+/// The number of bytes sent for each AND gate: three half-labels and the
+/// encrypted control bits of the four rows.
+pub(super) const TABLE_SIZE: usize = 25;
+
+/// The garbled table of an AND gate.
+pub(super) type Table = [u8; TABLE_SIZE];
+
+/// The part of `M_ij` which does not depend on the control bits, indexed by
+/// `2i + j`. The low nibble of each entry selects the halves of `A` and `B`
+/// added to the left half of the output, and the high nibble those added to
+/// the right half, in the order `A_L, A_R, B_L, B_R`.
+const ROW_MATRIX: [u8; 4] = [0x30, 0x38, 0x08, 0x00];
+
+/// The matrices added to `M_ij` by the first and second control bit.
+const CONTROL_MATRIX: [u8; 2] = [0x6b, 0xbd];
+
+/// The control bits of row `2i + j` are `r ^ CONTROL_OFFSET[2α + β][2i + j]`,
+/// where `α` and `β` are the colors of the input zero-wires.
+const CONTROL_OFFSET: [[u8; 4]; 4] = [[1, 0, 1, 0], [3, 0, 0, 3], [0, 0, 2, 2], [0, 2, 1, 3]];
+
+/// Split a wire-label into its left and right halves.
+fn halves(w: &Wire) -> (u64, u64) {
+    let x = u128::from(w.as_block());
+    (x as u64, (x >> 64) as u64)
+}
+
+/// The hashes used for a row whose input labels are `A` and `B`.
+struct RowHashes {
+    /// The hash contribution to the output label.
+    label: (u64, u64),
+    /// The mask for the row's control bits.
+    mask: u8,
+}
+
+/// Hash the input labels `A` and `B` of AND gate `gate_num`.
+fn hash_row(A: &Wire, B: &Wire, gate_num: usize) -> RowHashes {
+    let g = gate_num as u64;
+    let ha = u128::from(A.hash(tweak2(g, 0)));
+    let hb = u128::from(B.hash(tweak2(g, 1)));
+    let hx = u128::from(A.plus(B).hash(tweak2(g, 2)));
+    RowHashes {
+        label: ((ha ^ hx) as u64, (hb ^ hx) as u64),
+        mask: ((ha ^ hb) >> 64) as u8 & 3,
+    }
+}
+
+/// The matrix `M_ij` of row `ij` with control bits `c`, encoded as in
+/// `ROW_MATRIX`.
+fn matrix(ij: usize, c: u8) -> u8 {
+    let mut m = ROW_MATRIX[ij];
+    for (k, x) in CONTROL_MATRIX.iter().enumerate() {
+        if c >> k & 1 == 1 {
+            m ^= x;
+        }
+    }
+    m
+}
+
+/// Compute `M_ij [A_L; A_R; B_L; B_R]` for row `ij` with control bits `c`.
+fn mix(ij: usize, c: u8, A: &Wire, B: &Wire) -> (u64, u64) {
+    let m = matrix(ij, c);
+    let (al, ar) = halves(A);
+    let (bl, br) = halves(B);
+    let select = |m: u8| {
+        [al, ar, bl, br]
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| m >> k & 1 == 1)
+            .fold(0, |acc, (_, x)| acc ^ x)
+    };
+    (select(m & 0xF), select(m >> 4))
+}
+
+fn to_wire(l: u64, r: u64) -> Wire {
+    Wire::from_block(Block::from(u128::from(l) | u128::from(r) << 64), 2)
+}
+
+/// Garble an AND gate, returning the output zero-wire and the garbled table.
+///
+/// `r` must be two uniformly random bits.
+pub(super) fn garble_and(A: &Wire, B: &Wire, D: &Wire, gate_num: usize, r: u8) -> (Wire, Table) {
+    debug_assert!(A.modulus() == 2 && B.modulus() == 2);
+    let alpha = A.color() as usize;
+    let beta = B.color() as usize;
+    let offsets = &CONTROL_OFFSET[2 * alpha + beta];
+
+    let As = [A.clone(), A.plus(D)];
+    let Bs = [B.clone(), B.plus(D)];
+    let (dl, dr) = halves(D);
+
+    // The output label each row would compute if the ciphertexts were zero,
+    // minus the value of the output.
+    let mut rows = [(0, 0); 4];
+    let mut control = 0;
+    for (ij, row) in rows.iter_mut().enumerate() {
+        let a = (ij >> 1) ^ alpha;
+        let b = (ij & 1) ^ beta;
+        let c = r ^ offsets[ij];
+        let h = hash_row(&As[a], &Bs[b], gate_num);
+        let m = mix(ij, c, &As[a], &Bs[b]);
+        let (vl, vr) = if a & b == 1 { (dl, dr) } else { (0, 0) };
+        *row = (h.label.0 ^ m.0 ^ vl, h.label.1 ^ m.1 ^ vr);
+        control |= (c ^ h.mask) << (2 * ij);
+    }
+
+    // Row 00 adds no ciphertexts, so it determines the output zero-wire. Rows
+    // 01 and 10 then determine the ciphertexts, and the remaining equations
+    // hold by construction of the matrices (see the module documentation).
+    let (cl, cr) = rows[0];
+    let G2 = rows[2].1 ^ cr;
+    let G0 = rows[2].0 ^ cl ^ G2;
+    let G1 = rows[1].1 ^ cr ^ G2;
+    debug_assert_eq!(rows[1].0 ^ cl, G2);
+    debug_assert_eq!(rows[3], (cl ^ G0, cr ^ G1));
+
+    let mut table = [0; TABLE_SIZE];
+    table[0..8].copy_from_slice(&G0.to_le_bytes());
+    table[8..16].copy_from_slice(&G1.to_le_bytes());
+    table[16..24].copy_from_slice(&G2.to_le_bytes());
+    table[24] = control;
+    (to_wire(cl, cr), table)
+}
+
+/// Evaluate an AND gate using its garbled table.
+pub(super) fn eval_and(A: &Wire, B: &Wire, table: &Table, gate_num: usize) -> Wire {
+    let i = A.color() as usize;
+    let j = B.color() as usize;
+    let ij = 2 * i + j;
+    let G = |k: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&table[8 * k..8 * (k + 1)]);
+        u64::from_le_bytes(bytes)
+    };
+
+    let h = hash_row(A, B, gate_num);
+    let c = (table[24] >> (2 * ij)) & 3 ^ h.mask;
+    let m = mix(ij, c, A, B);
+    let mut l = h.label.0 ^ m.0;
+    let mut r = h.label.1 ^ m.1;
+    if i == 1 {
+        l ^= G(0);
+    }
+    if j == 1 {
+        r ^= G(1);
+    }
+    if i != j {
+        l ^= G(2);
+        r ^= G(2);
+    }
+    to_wire(l, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scuttlebutt::AesRng;
+
+    #[test]
+    fn matrices() {
+        for alpha in 0..2 {
+            for beta in 0..2 {
+                for r in 0..4 {
+                    // Each half of `R_ij` as its coefficients of `A_0L, A_0R,
+                    // B_0L, B_0R, Δ_L, Δ_R`, ignoring the hashes.
+                    let rows = (0..4)
+                        .map(|ij| {
+                            let a = ((ij >> 1) ^ alpha) as u8;
+                            let b = ((ij & 1) ^ beta) as u8;
+                            let m = matrix(ij, r ^ CONTROL_OFFSET[2 * alpha + beta][ij]);
+                            let half = |n: u8, side: u8| {
+                                let n = n & 0xF;
+                                let d = (n & 3) * a ^ (n >> 2) * b ^ (a & b) << side;
+                                n | d << 4
+                            };
+                            (half(m, 0), half(m >> 4, 1))
+                        })
+                        .collect::<Vec<_>>();
+                    assert_eq!(rows[1].0 ^ rows[2].1, rows[0].0 ^ rows[0].1);
+                    assert_eq!(rows[3].0 ^ rows[2].0 ^ rows[2].1, rows[0].1);
+                    assert_eq!(rows[3].1 ^ rows[1].0 ^ rows[1].1, rows[0].0);
+                }
+            }
+        }
+    }
+
+    /// Random zero-wires with colors `alpha` and `beta`.
+    fn inputs(rng: &mut AesRng, D: &Wire, alpha: u16, beta: u16) -> (Wire, Wire) {
+        let mut A = Wire::rand(rng, 2);
+        let mut B = Wire::rand(rng, 2);
+        if A.color() != alpha {
+            A = A.plus(D);
+        }
+        if B.color() != beta {
+            B = B.plus(D);
+        }
+        (A, B)
+    }
+
+    #[test]
+    fn and_gate() {
+        let mut rng = AesRng::new();
+        for gate_num in 0..16 {
+            let D = Wire::rand_delta(&mut rng, 2);
+            for alpha_beta in 0..4 {
+                for r in 0..4 {
+                    let (A, B) = inputs(&mut rng, &D, alpha_beta >> 1, alpha_beta & 1);
+                    let (C, table) = garble_and(&A, &B, &D, gate_num, r);
+                    for x in 0..2 {
+                        for y in 0..2 {
+                            let X = A.plus(&D.cmul(x));
+                            let Y = B.plus(&D.cmul(y));
+                            let Z = eval_and(&X, &Y, &table, gate_num);
+                            assert_eq!(Z, C.plus(&D.cmul(x & y)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tamper() {
+        let mut rng = AesRng::new();
+        let D = Wire::rand_delta(&mut rng, 2);
+        let (A, B) = inputs(&mut rng, &D, 0, 0);
+        let (C, table) = garble_and(&A, &B, &D, 0, 0);
+        for x in 0..2 {
+            for y in 0..2 {
+                let X = A.plus(&D.cmul(x));
+                let Y = B.plus(&D.cmul(y));
+                // The bytes read by this row: its control bits, and one byte
+                // of each ciphertext it adds.
+                let ij = 2 * x as usize + y as usize;
+                let mut bits = vec![(24, 1 << (2 * ij)), (24, 2 << (2 * ij))];
+                if x == 1 {
+                    bits.push((0, 1));
+                }
+                if y == 1 {
+                    bits.push((8, 1));
+                }
+                if x != y {
+                    bits.push((16, 1));
+                }
+                for (k, bit) in bits {
+                    let mut bad = table;
+                    bad[k] ^= bit;
+                    let Z = eval_and(&X, &Y, &bad, 0);
+                    assert_ne!(Z, C);
+                    assert_ne!(Z, C.plus(&D));
+                }
+            }
+        }
+    }
+}
@@ -5,9 +5,16 @@
 // See LICENSE for licensing information.
 
 //! `Informer` runs a fancy computation and learns information from it.
+//!
+//! Parts of the computation can be wrapped in named scopes with
+//! `Informer::scope`, in which case the statistics also break the gate and
+//! ciphertext counts down by scope.
 
-use crate::fancy::{Fancy, FancyInput, FancyReveal, HasModulus};
-use std::collections::{HashMap, HashSet};
+use crate::{
+    fancy::{Fancy, FancyInput, FancyReveal, HasModulus},
+    garble::mul_table_size,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Implements `Fancy`. Used to learn information about a `Fancy` computation in
 /// a lightweight way.
@@ -15,6 +22,144 @@ pub struct Informer<F: Fancy> {
     /// The underlying fancy object.
     pub underlying: F,
     stats: InformerStats,
+    /// Indices of the open scopes, from the outermost one.
+    open_scopes: Vec<usize>,
+}
+
+/// Counts of gates and ciphertexts.
+#[derive(Clone, Debug, Default)]
+struct Counts {
+    nadds: usize,
+    nsubs: usize,
+    ncmuls: usize,
+    nmuls: usize,
+    nprojs: usize,
+    nproj2s: usize,
+    nciphertexts: usize,
+}
+
+impl Counts {
+    fn to_json(&self) -> String {
+        format!(
+            "\"adds\":{},\"subs\":{},\"cmuls\":{},\"muls\":{},\"projs\":{},\"proj2s\":{},\
+             \"ciphertexts\":{}",
+            self.nadds,
+            self.nsubs,
+            self.ncmuls,
+            self.nmuls,
+            self.nprojs,
+            self.nproj2s,
+            self.nciphertexts
+        )
+    }
+}
+
+/// Quote and escape `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The statistics of a named scope of the computation. The counts of a scope
+/// include those of the scopes nested in it.
+#[derive(Clone, Debug)]
+pub struct ScopeStats {
+    name: String,
+    counts: Counts,
+    children: Vec<ScopeStats>,
+}
+
+impl ScopeStats {
+    /// The name of the scope.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The scopes opened inside this scope, in the order they were first
+    /// opened.
+    pub fn children(&self) -> &[ScopeStats] {
+        &self.children
+    }
+
+    /// Number of additions in the scope.
+    pub fn num_adds(&self) -> usize {
+        self.counts.nadds
+    }
+
+    /// Number of subtractions in the scope.
+    pub fn num_subs(&self) -> usize {
+        self.counts.nsubs
+    }
+
+    /// Number of scalar multiplications in the scope.
+    pub fn num_cmuls(&self) -> usize {
+        self.counts.ncmuls
+    }
+
+    /// Number of multiplications in the scope.
+    pub fn num_muls(&self) -> usize {
+        self.counts.nmuls
+    }
+
+    /// Number of projections in the scope.
+    pub fn num_projs(&self) -> usize {
+        self.counts.nprojs
+    }
+
+    /// Number of two-input projections in the scope.
+    pub fn num_proj2s(&self) -> usize {
+        self.counts.nproj2s
+    }
+
+    /// Number of ciphertexts in the scope.
+    pub fn num_ciphertexts(&self) -> usize {
+        self.counts.nciphertexts
+    }
+
+    fn fmt_tree(&self, f: &mut std::fmt::Formatter, depth: usize) -> std::fmt::Result {
+        writeln!(
+            f,
+            "    {:indent$}{}: {} ciphertexts, {} muls, {} projs, {} proj2s, {} adds, {} subs, \
+             {} cmuls",
+            "",
+            self.name,
+            self.counts.nciphertexts,
+            self.counts.nmuls,
+            self.counts.nprojs,
+            self.counts.nproj2s,
+            self.counts.nadds,
+            self.counts.nsubs,
+            self.counts.ncmuls,
+            indent = 2 * depth
+        )?;
+        for child in self.children.iter() {
+            child.fmt_tree(f, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> String {
+        format!(
+            "{{\"name\":{},{},\"scopes\":[{}]}}",
+            json_string(&self.name),
+            self.counts.to_json(),
+            self.children
+                .iter()
+                .map(ScopeStats::to_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        )
+    }
 }
 
 /// The statistics revealed by the informer.
@@ -24,13 +169,9 @@ pub struct InformerStats {
     evaluator_input_moduli: Vec<u16>,
     constants: HashSet<(u16, u16)>,
     outputs: Vec<u16>,
-    nadds: usize,
-    nsubs: usize,
-    ncmuls: usize,
-    nmuls: usize,
-    nprojs: usize,
-    nciphertexts: usize,
+    counts: Counts,
     moduli: HashMap<u16, usize>,
+    scopes: Vec<ScopeStats>,
 }
 
 impl InformerStats {
@@ -71,32 +212,71 @@ impl InformerStats {
 
     /// Number of additions in the fancy computation.
     pub fn num_adds(&self) -> usize {
-        self.nadds
+        self.counts.nadds
     }
 
     /// Number of subtractions in the fancy computation.
     pub fn num_subs(&self) -> usize {
-        self.nsubs
+        self.counts.nsubs
     }
 
     /// Number of scalar multiplications in the fancy computation.
     pub fn num_cmuls(&self) -> usize {
-        self.ncmuls
+        self.counts.ncmuls
     }
 
     /// Number of multiplications in the fancy computation.
     pub fn num_muls(&self) -> usize {
-        self.nmuls
+        self.counts.nmuls
     }
 
     /// Number of projections in the fancy computation.
     pub fn num_projs(&self) -> usize {
-        self.nprojs
+        self.counts.nprojs
+    }
+
+    /// Number of two-input projections in the fancy computation.
+    pub fn num_proj2s(&self) -> usize {
+        self.counts.nproj2s
     }
 
     /// Number of ciphertexts in the fancy computation.
     pub fn num_ciphertexts(&self) -> usize {
-        self.nciphertexts
+        self.counts.nciphertexts
+    }
+
+    /// The top-level scopes of the computation, in the order they were first
+    /// opened.
+    pub fn scopes(&self) -> &[ScopeStats] {
+        &self.scopes
+    }
+
+    /// The statistics as a JSON object, with the scopes as a tree of objects
+    /// under `"scopes"`.
+    pub fn to_json(&self) -> String {
+        let moduli = self
+            .moduli
+            .iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .map(|(q, n)| format!("\"{}\":{}", q, n))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"garbler_inputs\":{},\"evaluator_inputs\":{},\"outputs\":{},\
+             \"output_ciphertexts\":{},\"constants\":{},{},\"moduli\":{{{}}},\"scopes\":[{}]}}",
+            self.num_garbler_inputs(),
+            self.num_evaluator_inputs(),
+            self.num_outputs(),
+            self.num_output_ciphertexts(),
+            self.num_consts(),
+            self.counts.to_json(),
+            moduli.join(","),
+            self.scopes
+                .iter()
+                .map(ScopeStats::to_json)
+                .collect::<Vec<_>>()
+                .join(",")
+        )
     }
 }
 
@@ -104,7 +284,7 @@ impl std::fmt::Display for InformerStats {
     /// Print information about the fancy computation.
     ///
     /// For example, below is the output when run on `circuits/AES-non-expanded.txt`:
-    /// ```
+    /// ```text
     /// computation info:
     ///   garbler inputs:                  128 // comms cost: 16 Kb
     ///   evaluator inputs:                128 // comms cost: 48 Kb
@@ -115,6 +295,7 @@ impl std::fmt::Display for InformerStats {
     ///   subtractions:                   1692
     ///   cmuls:                             0
     ///   projections:                       0
+    ///   proj2s:                            0
     ///   multiplications:                6800
     ///   ciphertexts:                   13600 // comms cost: 1.66 Mb (1700.00 Kb)
     ///   total comms cost:            1.75 Mb // 1700.00 Kb
@@ -169,6 +350,7 @@ impl std::fmt::Display for InformerStats {
         writeln!(f, "  subtractions:       {:16}", self.num_subs())?;
         writeln!(f, "  cmuls:              {:16}", self.num_cmuls())?;
         writeln!(f, "  projections:        {:16}", self.num_projs())?;
+        writeln!(f, "  proj2s:             {:16}", self.num_proj2s())?;
         writeln!(f, "  multiplications:    {:16}", self.num_muls())?;
         let cs = self.num_ciphertexts();
         let kb = cs as f64 * 128.0 / 1000.0;
@@ -183,6 +365,12 @@ impl std::fmt::Display for InformerStats {
         let mb = total / 1000.0;
         writeln!(f, "  total communication:  {:11.2} Mb", mb)?;
         writeln!(f, "  wire moduli: {:#?}", self.moduli)?;
+        if !self.scopes.is_empty() {
+            writeln!(f, "  scopes:")?;
+            for scope in self.scopes.iter() {
+                scope.fmt_tree(f, 0)?;
+            }
+        }
         Ok(())
     }
 }
@@ -197,14 +385,11 @@ impl<F: Fancy> Informer<F> {
                 evaluator_input_moduli: Vec::new(),
                 constants: HashSet::new(),
                 outputs: Vec::new(),
-                nadds: 0,
-                nsubs: 0,
-                ncmuls: 0,
-                nmuls: 0,
-                nprojs: 0,
-                nciphertexts: 0,
+                counts: Counts::default(),
                 moduli: HashMap::new(),
+                scopes: Vec::new(),
             },
+            open_scopes: Vec::new(),
         }
     }
 
@@ -213,6 +398,49 @@ impl<F: Fancy> Informer<F> {
         self.stats.clone()
     }
 
+    /// Run `f`, attributing the gates it creates to a scope named `name`.
+    ///
+    /// Scopes nest, and opening a scope with the same name as an earlier scope
+    /// with the same parent adds to that scope.
+    pub fn scope<T, G: FnOnce(&mut Self) -> T>(&mut self, name: &str, f: G) -> T {
+        let siblings = self.open_scope_children();
+        let ix = match siblings.iter().position(|s| s.name == name) {
+            Some(ix) => ix,
+            None => {
+                siblings.push(ScopeStats {
+                    name: name.to_string(),
+                    counts: Counts::default(),
+                    children: Vec::new(),
+                });
+                siblings.len() - 1
+            }
+        };
+        self.open_scopes.push(ix);
+        let result = f(self);
+        self.open_scopes.pop();
+        result
+    }
+
+    /// The children of the innermost open scope, or the top-level scopes if
+    /// no scope is open.
+    fn open_scope_children(&mut self) -> &mut Vec<ScopeStats> {
+        let mut scopes = &mut self.stats.scopes;
+        for &ix in self.open_scopes.iter() {
+            scopes = &mut scopes[ix].children;
+        }
+        scopes
+    }
+
+    /// Apply `update` to the global counts and to those of every open scope.
+    fn count<U: Fn(&mut Counts)>(&mut self, update: U) {
+        update(&mut self.stats.counts);
+        let mut scopes = &mut self.stats.scopes;
+        for &ix in self.open_scopes.iter() {
+            update(&mut scopes[ix].counts);
+            scopes = &mut scopes[ix].children;
+        }
+    }
+
     fn update_moduli(&mut self, q: u16) {
         let entry = self.stats.moduli.entry(q).or_insert(0);
         *entry += 1;
@@ -261,21 +489,21 @@ impl<F: Fancy> Fancy for Informer<F> {
 
     fn add(&mut self, x: &Self::Item, y: &Self::Item) -> Result<Self::Item, Self::Error> {
         let result = self.underlying.add(x, y)?;
-        self.stats.nadds += 1;
+        self.count(|c| c.nadds += 1);
         self.update_moduli(x.modulus());
         Ok(result)
     }
 
     fn sub(&mut self, x: &Self::Item, y: &Self::Item) -> Result<Self::Item, Self::Error> {
         let result = self.underlying.sub(x, y)?;
-        self.stats.nsubs += 1;
+        self.count(|c| c.nsubs += 1);
         self.update_moduli(x.modulus());
         Ok(result)
     }
 
     fn cmul(&mut self, x: &Self::Item, y: u16) -> Result<Self::Item, Self::Error> {
         let result = self.underlying.cmul(x, y)?;
-        self.stats.ncmuls += 1;
+        self.count(|c| c.ncmuls += 1);
         self.update_moduli(x.modulus());
         Ok(result)
     }
@@ -285,12 +513,11 @@ impl<F: Fancy> Fancy for Informer<F> {
             return self.mul(y, x);
         }
         let result = self.underlying.mul(x, y)?;
-        self.stats.nmuls += 1;
-        self.stats.nciphertexts += x.modulus() as usize + y.modulus() as usize - 2;
-        if x.modulus() != y.modulus() {
-            // there is an extra ciphertext to support nonequal inputs
-            self.stats.nciphertexts += 1;
-        }
+        let nciphertexts = mul_table_size(x.modulus(), y.modulus());
+        self.count(|c| {
+            c.nmuls += 1;
+            c.nciphertexts += nciphertexts;
+        });
         self.update_moduli(x.modulus());
         Ok(result)
     }
@@ -302,8 +529,28 @@ impl<F: Fancy> Fancy for Informer<F> {
         tt: Option<Vec<u16>>,
     ) -> Result<Self::Item, Self::Error> {
         let result = self.underlying.proj(x, q, tt)?;
-        self.stats.nprojs += 1;
-        self.stats.nciphertexts += x.modulus() as usize - 1;
+        let nciphertexts = x.modulus() as usize - 1;
+        self.count(|c| {
+            c.nprojs += 1;
+            c.nciphertexts += nciphertexts;
+        });
+        self.update_moduli(q);
+        Ok(result)
+    }
+
+    fn proj2(
+        &mut self,
+        x: &Self::Item,
+        y: &Self::Item,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        let result = self.underlying.proj2(x, y, q, tt)?;
+        let nciphertexts = x.modulus() as usize * y.modulus() as usize - 1;
+        self.count(|c| {
+            c.nproj2s += 1;
+            c.nciphertexts += nciphertexts;
+        });
         self.update_moduli(q);
         Ok(result)
     }
@@ -320,3 +567,59 @@ impl<F: Fancy + FancyReveal> FancyReveal for Informer<F> {
         self.underlying.reveal(x)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dummy::Dummy;
+
+    #[test]
+    fn scopes() {
+        let mut informer = Informer::new(Dummy::new());
+        let x = informer.encode(1, 3).unwrap();
+        let y = informer.encode(2, 3).unwrap();
+        let z = informer
+            .scope("layer", |f| {
+                let xy = f.mul(&x, &y)?;
+                let z = f.scope("relu", |f| f.proj(&xy, 3, Some(vec![0, 1, 0])))?;
+                f.add(&z, &x)
+            })
+            .unwrap();
+        informer
+            .scope("layer", |f| f.scope("relu", |f| f.mul(&z, &z)))
+            .unwrap();
+        informer.scope("out\"put\n", |f| f.output(&z)).unwrap();
+
+        let stats = informer.stats();
+        assert_eq!(stats.num_muls(), 2);
+        assert_eq!(stats.num_ciphertexts(), 10);
+        assert_eq!(stats.scopes().len(), 2);
+        let layer = &stats.scopes()[0];
+        assert_eq!(layer.name(), "layer");
+        assert_eq!(layer.num_muls(), 2);
+        assert_eq!(layer.num_adds(), 1);
+        assert_eq!(layer.num_ciphertexts(), 10);
+        assert_eq!(layer.children().len(), 1);
+        let relu = &layer.children()[0];
+        assert_eq!(relu.name(), "relu");
+        assert_eq!((relu.num_muls(), relu.num_projs()), (1, 1));
+        assert_eq!(relu.num_ciphertexts(), 6);
+        assert!(stats
+            .to_string()
+            .contains("      relu: 6 ciphertexts, 1 muls, 1 projs, 0 proj2s, 0 adds"));
+
+        let json = stats.to_json();
+        assert!(json.starts_with(
+            "{\"garbler_inputs\":2,\"evaluator_inputs\":0,\"outputs\":1,\
+             \"output_ciphertexts\":3,\"constants\":0,\"adds\":1,"
+        ));
+        assert!(json.contains(
+            "\"scopes\":[{\"name\":\"layer\",\"adds\":1,\"subs\":0,\"cmuls\":0,\"muls\":2,\
+             \"projs\":1,\"proj2s\":0,\"ciphertexts\":10,\"scopes\":[{\"name\":\"relu\","
+        ));
+        assert!(json.ends_with(
+            "{\"name\":\"out\\\"put\\u000a\",\"adds\":0,\"subs\":0,\"cmuls\":0,\"muls\":0,\
+             \"projs\":0,\"proj2s\":0,\"ciphertexts\":0,\"scopes\":[]}]}"
+        ));
+    }
+}
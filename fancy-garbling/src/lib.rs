@@ -28,9 +28,12 @@ pub mod errors;
 mod fancy;
 mod garble;
 pub mod informer;
+pub mod neural_net;
 mod parser;
+pub mod secure;
 pub mod twopac;
 pub mod util;
 mod wire;
+pub mod zk;
 
 pub use crate::{errors::FancyError, fancy::*, garble::*, wire::*};
@@ -0,0 +1,632 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Neural network models over fixed-point CRT bundles, which can be loaded
+//! from a simple text format and evaluated by any `Fancy` object.
+//!
+//! A model description is a sequence of whitespace-separated words, where
+//! `#` starts a comment running to the end of the line. It starts with the
+//! shape of the input image and the number of fractional bits of its
+//! fixed-point numbers, followed by the layers in order:
+//!
+//! ```text
+//! input <channels> <height> <width>
+//! frac_bits <bits>
+//! conv2d <out_channels> <kernel_height> <kernel_width> <stride> <padding>
+//!     weights <out_channels * channels * kernel_height * kernel_width numbers>
+//!     biases <out_channels numbers>
+//! avgpool <size> <stride>
+//! maxpool <size> <stride>
+//! dense <outputs>
+//!     weights <outputs * inputs numbers>
+//!     biases <outputs numbers>
+//! relu
+//! sigmoid pwl <segments>
+//! tanh poly <degree>
+//! ```
+//!
+//! The weights are listed in the order of `Conv2d::weights` and, for dense
+//! layers, one output after the other. A dense layer takes every value of its
+//! input image, and outputs an image with one row and column for each channel.
+//! The activations `sigmoid` and `tanh` take either `pwl <segments>` or
+//! `poly <degree>`, which correspond to the `Approximation`s.
+//!
+//! At most 63 fractional bits are supported. Every number must be finite and
+//! fit in a fixed-point modulus below `2^127` with twice the fractional bits,
+//! as the biases are encoded that way.
+
+use crate::{
+    dummy::Dummy,
+    errors::{DummyError, FancyError, ModelError},
+    fancy::{
+        self,
+        ActivationGadgets,
+        Approximation,
+        Conv2d,
+        CrtBundle,
+        CrtGadgets,
+        Dense,
+        Fancy,
+        FancyInput,
+        NeuralGadgets,
+        Pool,
+        Shape,
+    },
+    informer::{Informer, InformerStats},
+};
+use itertools::Itertools;
+use std::{fs, str::FromStr};
+
+/// A layer of a neural network.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layer {
+    /// A fully connected layer over the whole input image.
+    Dense(Dense),
+    /// A two-dimensional convolution.
+    Conv2d(Conv2d),
+    /// Average pooling.
+    AvgPool(Pool),
+    /// Max pooling.
+    MaxPool(Pool),
+    /// The rectified linear unit `max(x, 0)` on each value.
+    Relu,
+    /// The sigmoid on each value.
+    Sigmoid(Approximation),
+    /// The hyperbolic tangent on each value.
+    Tanh(Approximation),
+}
+
+/// A neural network over fixed-point numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    /// The shape of the input image.
+    pub input: Shape,
+    /// The number of fractional bits of the fixed-point numbers.
+    pub frac_bits: usize,
+    /// The layers, in order.
+    pub layers: Vec<Layer>,
+}
+
+impl Model {
+    /// Load a model from the description in the file `filename`.
+    pub fn parse(filename: &str) -> Result<Self, ModelError> {
+        fs::read_to_string(filename)?.parse()
+    }
+
+    /// Evaluate the model on the input image `xs`, using accuracy `accuracy`
+    /// for the comparisons, and return the output image.
+    pub fn eval<F: Fancy>(
+        &self,
+        f: &mut F,
+        xs: &[CrtBundle<F::Item>],
+        accuracy: &str,
+    ) -> Result<Vec<CrtBundle<F::Item>>, F::Error> {
+        let frac_bits = self.frac_bits;
+        let mut shape = self.input;
+        let mut xs = xs.to_vec();
+        for layer in self.layers.iter() {
+            xs = match layer {
+                Layer::Dense(layer) => {
+                    shape = Shape::new(layer.weights.len(), 1, 1);
+                    f.crt_dense(layer, &xs, frac_bits)?
+                }
+                Layer::Conv2d(layer) => {
+                    let (zs, out) = f.crt_conv2d(layer, &xs, shape, frac_bits)?;
+                    shape = out;
+                    zs
+                }
+                Layer::AvgPool(pool) => {
+                    let (zs, out) = f.crt_avg_pool(pool, &xs, shape, frac_bits)?;
+                    shape = out;
+                    zs
+                }
+                Layer::MaxPool(pool) => {
+                    let (zs, out) = f.crt_max_pool(pool, &xs, shape, accuracy)?;
+                    shape = out;
+                    zs
+                }
+                Layer::Relu => xs
+                    .iter()
+                    .map(|x| f.crt_relu(x, accuracy, None))
+                    .collect::<Result<_, _>>()?,
+                Layer::Sigmoid(approximation) => xs
+                    .iter()
+                    .map(|x| f.crt_sigmoid(x, frac_bits, *approximation, accuracy))
+                    .collect::<Result<_, _>>()?,
+                Layer::Tanh(approximation) => xs
+                    .iter()
+                    .map(|x| f.crt_tanh(x, frac_bits, *approximation, accuracy))
+                    .collect::<Result<_, _>>()?,
+            };
+        }
+        Ok(xs)
+    }
+
+    /// Evaluate the model on the input image `xs` as in `eval`, and return a
+    /// bundle holding the index of its largest output.
+    pub fn classify<F: Fancy>(
+        &self,
+        f: &mut F,
+        xs: &[CrtBundle<F::Item>],
+        accuracy: &str,
+    ) -> Result<CrtBundle<F::Item>, F::Error> {
+        let zs = self.eval(f, xs, accuracy)?;
+        f.crt_argmax(&zs, accuracy)
+    }
+
+    /// Compute the cost of classifying an input with `classify` under
+    /// composite modulus `q` and outputting the result, without evaluating
+    /// the model.
+    pub fn cost(&self, q: u128, accuracy: &str) -> Result<InformerStats, DummyError> {
+        let mut informer = Informer::new(Dummy::new());
+        let xs = informer.crt_receive_many(self.input.size(), q)?;
+        let z = self.classify(&mut informer, &xs, accuracy)?;
+        informer.crt_output(&z)?;
+        Ok(informer.stats())
+    }
+}
+
+impl FromStr for Model {
+    type Err = ModelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = Words::new(s);
+        words.expect("input")?;
+        let input = Shape::new(words.usize()?, words.usize()?, words.usize()?);
+        match input.checked_size() {
+            Some(0) => return Err(words.error("the input is empty")),
+            None => return Err(words.error("the input is too large")),
+            Some(_) => (),
+        }
+        words.expect("frac_bits")?;
+        let frac_bits = words.usize()?;
+        if frac_bits > MAX_FRAC_BITS {
+            return Err(words.error(&format!(
+                "at most {} fractional bits are supported",
+                MAX_FRAC_BITS
+            )));
+        }
+        // Biases are encoded with twice the fractional bits, and every number
+        // must fit in a modulus below 2^127.
+        let max = 2f64.powi(126 - 2 * frac_bits as i32);
+
+        let mut shape = input;
+        let mut layers = Vec::new();
+        while let Some(word) = words.next() {
+            let layer = match word {
+                "dense" => {
+                    let (inputs, outputs) = (shape.size(), words.usize()?);
+                    words.expect("weights")?;
+                    let n = words.product(&[outputs, inputs])?;
+                    let weights = words.f64s(n, max)?;
+                    words.expect("biases")?;
+                    let biases = words.f64s(outputs, max)?;
+                    shape = Shape::new(outputs, 1, 1);
+                    Layer::Dense(Dense {
+                        weights: weights.chunks(inputs).map(|row| row.to_vec()).collect(),
+                        biases,
+                    })
+                }
+                "conv2d" => {
+                    let out_channels = words.usize()?;
+                    let kernel = (words.usize()?, words.usize()?);
+                    let (stride, padding) = (words.usize()?, words.usize()?);
+                    words.check_window(shape, kernel, padding, stride)?;
+                    words.expect("weights")?;
+                    let n = words.product(&[out_channels, shape.channels, kernel.0, kernel.1])?;
+                    let weights = words.f64s(n, max)?;
+                    words.expect("biases")?;
+                    let biases = words.f64s(out_channels, max)?;
+                    let layer = Conv2d {
+                        out_channels,
+                        kernel,
+                        stride,
+                        padding,
+                        weights,
+                        biases,
+                    };
+                    shape = layer.output_shape(shape);
+                    Layer::Conv2d(layer)
+                }
+                "avgpool" | "maxpool" => {
+                    let pool = Pool {
+                        size: words.usize()?,
+                        stride: words.usize()?,
+                    };
+                    words.check_window(shape, (pool.size, pool.size), 0, pool.stride)?;
+                    shape = pool.output_shape(shape);
+                    if word == "maxpool" {
+                        Layer::MaxPool(pool)
+                    } else if frac_bits == 0 {
+                        return Err(words.error("average pooling requires fractional bits"));
+                    } else {
+                        Layer::AvgPool(pool)
+                    }
+                }
+                "relu" => Layer::Relu,
+                "sigmoid" => Layer::Sigmoid(words.approximation()?),
+                "tanh" => Layer::Tanh(words.approximation()?),
+                word => return Err(words.error(&format!("unknown layer '{}'", word))),
+            };
+            match shape.checked_size() {
+                Some(0) => return Err(words.error("the layer has no outputs")),
+                None => return Err(words.error("the layer has too many outputs")),
+                Some(_) => (),
+            }
+            layers.push(layer);
+        }
+        Ok(Model {
+            input,
+            frac_bits,
+            layers,
+        })
+    }
+}
+
+/// The largest supported number of fractional bits.
+const MAX_FRAC_BITS: usize = 63;
+
+/// The words of a model description, along with their line numbers.
+struct Words<'a> {
+    words: std::vec::IntoIter<(usize, &'a str)>,
+    line: usize,
+}
+
+impl<'a> Words<'a> {
+    fn new(s: &'a str) -> Self {
+        let words = s
+            .lines()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                let line = line.split('#').next().unwrap_or("");
+                line.split_whitespace().map(move |word| (i + 1, word))
+            })
+            .collect_vec();
+        Words {
+            words: words.into_iter(),
+            line: 1,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.words.next().map(|(line, word)| {
+            self.line = line;
+            word
+        })
+    }
+
+    fn error(&self, message: &str) -> ModelError {
+        ModelError::ParseError(self.line, message.to_string())
+    }
+
+    fn word(&mut self, expected: &str) -> Result<&'a str, ModelError> {
+        self.next()
+            .ok_or_else(|| self.error(&format!("expected {}, found the end", expected)))
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), ModelError> {
+        let word = self.word(&format!("'{}'", keyword))?;
+        if word != keyword {
+            return Err(self.error(&format!("expected '{}', found '{}'", keyword, word)));
+        }
+        Ok(())
+    }
+
+    fn usize(&mut self) -> Result<usize, ModelError> {
+        let word = self.word("an integer")?;
+        word.parse()
+            .map_err(|_| self.error(&format!("expected an integer, found '{}'", word)))
+    }
+
+    /// Read `n` numbers, each of absolute value at most `max`.
+    fn f64s(&mut self, n: usize, max: f64) -> Result<Vec<f64>, ModelError> {
+        (0..n)
+            .map(|_| {
+                let word = self.word("a number")?;
+                let x = word
+                    .parse::<f64>()
+                    .map_err(|_| self.error(&format!("expected a number, found '{}'", word)))?;
+                if !x.is_finite() || x.abs() > max {
+                    return Err(self.error(&format!("the number '{}' is out of range", word)));
+                }
+                Ok(x)
+            })
+            .collect()
+    }
+
+    /// Multiply the dimensions `ns` of a layer's weights.
+    fn product(&self, ns: &[usize]) -> Result<usize, ModelError> {
+        ns.iter()
+            .try_fold(1usize, |acc, &n| acc.checked_mul(n))
+            .ok_or_else(|| self.error("the layer has too many weights"))
+    }
+
+    fn approximation(&mut self) -> Result<Approximation, ModelError> {
+        match self.word("'pwl' or 'poly'")? {
+            "pwl" => Ok(Approximation::PiecewiseLinear {
+                segments: self.usize()?,
+            }),
+            "poly" => Ok(Approximation::Polynomial {
+                degree: self.usize()?,
+            }),
+            word => Err(self.error(&format!("expected 'pwl' or 'poly', found '{}'", word))),
+        }
+    }
+
+    fn check_window(
+        &self,
+        shape: Shape,
+        window: (usize, usize),
+        padding: usize,
+        stride: usize,
+    ) -> Result<(), ModelError> {
+        fancy::check_window::<FancyError>(shape, window, padding, stride)
+            .map_err(|e| self.error(&e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fancy::FancyReveal, util};
+
+    const MODEL: &str = "
+        # two 3x3 filters on a 4x4 image
+        input 1 4 4
+        frac_bits 8
+        conv2d 2 3 3 1 1
+            weights 0.5 0 0  0 1 0  0 0 -0.5
+                    0 0.25 0  0.25 -1 0.25  0 0.25 0
+            biases 0.125 -0.125
+        relu
+        maxpool 2 2
+        dense 3
+            weights 1 0 0 0  0.5 0.5 0 0
+                    0 1 0 0  0 0.5 0.5 0
+                    -1 0 0 1  0 0 0 1
+            biases 0 0.5 -0.25
+        sigmoid pwl 32
+    ";
+
+    /// Evaluate `model` on `xs` in floating point.
+    fn eval_plain(model: &Model, xs: &[f64]) -> Vec<f64> {
+        let mut shape = model.input;
+        let mut xs = xs.to_vec();
+        for layer in model.layers.iter() {
+            xs = match layer {
+                Layer::Dense(layer) => {
+                    shape = Shape::new(layer.weights.len(), 1, 1);
+                    layer
+                        .weights
+                        .iter()
+                        .zip(layer.biases.iter())
+                        .map(|(row, b)| {
+                            b + row.iter().zip(xs.iter()).map(|(w, x)| w * x).sum::<f64>()
+                        })
+                        .collect()
+                }
+                Layer::Conv2d(layer) => {
+                    let out = layer.output_shape(shape);
+                    let (kh, kw) = layer.kernel;
+                    let mut zs = Vec::new();
+                    for o in 0..out.channels {
+                        for y in 0..out.height {
+                            for x in 0..out.width {
+                                let mut z = layer.biases[o];
+                                for ((c, i), j) in (0..shape.channels)
+                                    .cartesian_product(0..kh)
+                                    .cartesian_product(0..kw)
+                                {
+                                    let u =
+                                        (y * layer.stride + i) as isize - layer.padding as isize;
+                                    let v =
+                                        (x * layer.stride + j) as isize - layer.padding as isize;
+                                    if u >= 0
+                                        && v >= 0
+                                        && u < shape.height as isize
+                                        && v < shape.width as isize
+                                    {
+                                        let w = layer.weights
+                                            [((o * shape.channels + c) * kh + i) * kw + j];
+                                        z += w * xs[(c * shape.height + u as usize) * shape.width
+                                            + v as usize];
+                                    }
+                                }
+                                zs.push(z);
+                            }
+                        }
+                    }
+                    shape = out;
+                    zs
+                }
+                Layer::AvgPool(pool) | Layer::MaxPool(pool) => {
+                    let out = pool.output_shape(shape);
+                    let mut zs = Vec::new();
+                    for c in 0..out.channels {
+                        for y in 0..out.height {
+                            for x in 0..out.width {
+                                let window = (0..pool.size)
+                                    .cartesian_product(0..pool.size)
+                                    .map(|(i, j)| {
+                                        let (u, v) = (y * pool.stride + i, x * pool.stride + j);
+                                        xs[(c * shape.height + u) * shape.width + v]
+                                    })
+                                    .collect_vec();
+                                zs.push(match layer {
+                                    Layer::MaxPool(_) => {
+                                        window.into_iter().fold(f64::MIN, f64::max)
+                                    }
+                                    _ => window.iter().sum::<f64>() / window.len() as f64,
+                                });
+                            }
+                        }
+                    }
+                    shape = out;
+                    zs
+                }
+                Layer::Relu => xs.iter().map(|x| x.max(0.0)).collect(),
+                Layer::Sigmoid(_) => xs.iter().map(|x| 1.0 / (1.0 + (-x).exp())).collect(),
+                Layer::Tanh(_) => xs.iter().map(|x| x.tanh()).collect(),
+            };
+        }
+        xs
+    }
+
+    #[test]
+    fn parse_and_eval() {
+        let model: Model = MODEL.parse().unwrap();
+        assert_eq!(model.input, Shape::new(1, 4, 4));
+        assert_eq!(model.frac_bits, 8);
+        assert_eq!(model.layers.len(), 5);
+        assert_eq!(
+            model.layers[4],
+            Layer::Sigmoid(Approximation::PiecewiseLinear { segments: 32 })
+        );
+
+        let q = util::modulus_with_nprimes(9);
+        for xs in [
+            [
+                1.0, -2.0, 0.5, 3.0, 0.0, 1.5, -1.0, 2.0, 2.5, 0.25, -0.75, 1.0, -3.0, 2.0, 1.0,
+                0.5,
+            ],
+            [0.0; 16],
+        ]
+        .iter()
+        {
+            let mut d = Dummy::new();
+            let inputs = xs
+                .iter()
+                .map(|&x| d.fixed_encode(x, model.frac_bits, q).unwrap())
+                .collect_vec();
+            let zs = model.eval(&mut d, &inputs, "100%").unwrap();
+            let should_be = eval_plain(&model, xs);
+            assert_eq!(zs.len(), 3);
+            for (z, should_be) in zs.iter().zip(should_be.iter()) {
+                let z = d.fixed_reveal(z, model.frac_bits).unwrap();
+                assert!((z - should_be).abs() < 0.05, "{} != {}", z, should_be);
+            }
+            let class = model.classify(&mut d, &inputs, "100%").unwrap();
+            let class = d.crt_reveal(&class).unwrap() as usize;
+            let max = should_be.iter().cloned().fold(f64::MIN, f64::max);
+            assert!(should_be[class] > max - 0.05);
+        }
+    }
+
+    #[test]
+    fn avgpool_and_tanh() {
+        let model: Model = "
+            input 1 4 4
+            frac_bits 8
+            avgpool 2 2
+            dense 2
+                weights 1 -1 0.5 0  0 0.5 -0.5 1
+                biases 0 0.25
+            tanh pwl 32
+        "
+        .parse()
+        .unwrap();
+        let q = util::modulus_with_nprimes(9);
+        let xs = [
+            1.0, -2.0, 0.5, 3.0, 0.0, 1.5, -1.0, 2.0, 2.5, 0.25, -0.75, 1.0, -3.0, 2.0, 1.0, 0.5,
+        ];
+        let mut d = Dummy::new();
+        let inputs = xs
+            .iter()
+            .map(|&x| d.fixed_encode(x, model.frac_bits, q).unwrap())
+            .collect_vec();
+        let zs = model.eval(&mut d, &inputs, "100%").unwrap();
+        let should_be = eval_plain(&model, &xs);
+        assert_eq!(zs.len(), 2);
+        for (z, should_be) in zs.iter().zip(should_be.iter()) {
+            let z = d.fixed_reveal(z, model.frac_bits).unwrap();
+            assert!((z - should_be).abs() < 0.05, "{} != {}", z, should_be);
+        }
+    }
+
+    #[test]
+    fn strided_conv2d() {
+        let model: Model = "
+            input 2 5 5
+            frac_bits 8
+            conv2d 2 3 3 2 1
+                weights 0.5 0 0  0 1 0  0 0 -0.5   0 0.25 0  0.25 -1 0.25  0 0.25 0
+                        1 0 -1  0 0 0  -1 0 1      0 0 0  0 0.5 0  0 0 0
+                biases 0.125 -0.125
+            relu
+        "
+        .parse()
+        .unwrap();
+        let q = util::modulus_with_nprimes(9);
+        let xs = (0..50)
+            .map(|i| ((i * 7) % 11) as f64 / 4.0 - 1.25)
+            .collect_vec();
+        let mut d = Dummy::new();
+        let inputs = xs
+            .iter()
+            .map(|&x| d.fixed_encode(x, model.frac_bits, q).unwrap())
+            .collect_vec();
+        let zs = model.eval(&mut d, &inputs, "100%").unwrap();
+        let should_be = eval_plain(&model, &xs);
+        assert_eq!(zs.len(), 2 * 3 * 3);
+        for (z, should_be) in zs.iter().zip(should_be.iter()) {
+            let z = d.fixed_reveal(z, model.frac_bits).unwrap();
+            assert!((z - should_be).abs() < 0.05, "{} != {}", z, should_be);
+        }
+    }
+
+    #[test]
+    fn cost() {
+        let model: Model = MODEL.parse().unwrap();
+        let q = util::modulus_with_nprimes(9);
+        let stats = model.cost(q, "100%").unwrap();
+        assert_eq!(stats.num_garbler_inputs(), 16 * 9);
+        assert_eq!(stats.num_outputs(), 9);
+        assert!(stats.num_muls() > 0 && stats.num_projs() > 0);
+
+        // a model without the final activation costs less
+        let mut smaller = model.clone();
+        smaller.layers.pop();
+        let smaller = smaller.cost(q, "100%").unwrap();
+        assert!(smaller.num_ciphertexts() < stats.num_ciphertexts());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error_line = |s: &str| match s.parse::<Model>() {
+            Err(ModelError::ParseError(line, _)) => line,
+            _ => panic!("expected a parse error"),
+        };
+        assert_eq!(error_line("frac_bits 8"), 1);
+        assert_eq!(error_line("input 1 2 2\nfrac_bits x"), 2);
+        assert_eq!(error_line("input 1 2 2\nfrac_bits 0\n\nsoftmax"), 4);
+        assert_eq!(error_line("input 1 2 2\nfrac_bits 0\navgpool 2 2"), 3);
+        assert_eq!(error_line("input 1 2 2\nfrac_bits 0\nmaxpool 3 1"), 3);
+        assert_eq!(
+            error_line("input 1 2 2\nfrac_bits 0\ndense 2\nweights 1 2 3 4 5 6 7\nbiases 1 2"),
+            5
+        );
+        assert_eq!(error_line("input 1 2 2\nfrac_bits 0\ntanh cubic 3"), 3);
+        let dense = |numbers: &str| format!("input 1 1 1\nfrac_bits 8\ndense 1\n{}", numbers);
+        assert_eq!(error_line(&dense("weights nan\nbiases 0")), 4);
+        assert_eq!(error_line(&dense("weights 1\nbiases -inf")), 5);
+        assert_eq!(error_line(&dense("weights 1e300\nbiases 0")), 4);
+        assert!(dense("weights 1e30\nbiases 0").parse::<Model>().is_ok());
+        assert_eq!(error_line("input 1 1 1\nfrac_bits 64"), 2);
+        // Sizes which overflow a usize are rejected.
+        assert_eq!(
+            error_line("input 1 2 2\nfrac_bits 0\ndense 9223372036854775807\nweights 1"),
+            4
+        );
+        assert_eq!(error_line("input 4294967296 4294967296 2\nfrac_bits 0"), 1);
+        assert_eq!(
+            error_line("input 1 2 2\nfrac_bits 0\nconv2d 1 1 1 1 9223372036854775808"),
+            3
+        );
+        assert!("input 1 2 2 frac_bits 4 # no layers"
+            .parse::<Model>()
+            .is_ok());
+    }
+}
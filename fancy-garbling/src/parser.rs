@@ -4,17 +4,21 @@
 // Copyright © 2019 Galois, Inc.
 // See LICENSE for licensing information.
 
-//! Functions for parsing and running a circuit file based on the format given
+//! Functions for parsing and running a circuit file based on the formats given
 //! here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+//!
+//! Both the old Bristol format and the newer "Bristol Fashion" format are
+//! supported. Binary circuits can also be written out in Bristol Fashion.
 
 use crate::{
     circuit::{Circuit, CircuitRef, Gate},
     errors::CircuitParserError as Error,
+    fancy::HasModulus,
 };
 use regex::{Captures, Regex};
 use std::{
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, BufWriter, Write},
     str::FromStr,
 };
 
@@ -173,9 +177,340 @@ impl Circuit {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Bristol Fashion
+
+fn parse_tokens(tokens: &[&str]) -> Result<Vec<usize>, Error> {
+    tokens
+        .iter()
+        .map(|s| FromStr::from_str(s).map_err(Error::from))
+        .collect()
+}
+
+/// Read the next non-empty line, failing if the file ends early.
+fn next_nonempty_line<R: BufRead>(reader: &mut R) -> Result<String, Error> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::ParseLineError("unexpected end of file".to_string()));
+        }
+        if !line.trim().is_empty() {
+            return Ok(line);
+        }
+    }
+}
+
+/// Parse a header line of the form `n w_1 ... w_n`, returning the `w_i`.
+fn parse_widths(line: &str) -> Result<Vec<usize>, Error> {
+    let tokens = line.split_whitespace().collect::<Vec<&str>>();
+    let nums = parse_tokens(&tokens)?;
+    if nums.is_empty() || nums.len() != nums[0] + 1 {
+        return Err(Error::ParseLineError(line.to_string()));
+    }
+    Ok(nums[1..].to_vec())
+}
+
+impl Circuit {
+    /// Generates a new `Circuit` from file `filename` in the "Bristol Fashion"
+    /// format given here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+    ///
+    /// Bristol Fashion circuits have any number of input values, each with its
+    /// own bit width. The first `ngarbler_values` input values become garbler
+    /// inputs and the remaining ones become evaluator inputs. All output values
+    /// are concatenated into `output_refs` in the order given by the file.
+    pub fn parse_bristol_fashion(filename: &str, ngarbler_values: usize) -> Result<Self, Error> {
+        let f = File::open(filename)?;
+        Self::read_bristol_fashion(BufReader::new(f), ngarbler_values)
+    }
+
+    fn read_bristol_fashion<R: BufRead>(
+        mut reader: R,
+        ngarbler_values: usize,
+    ) -> Result<Self, Error> {
+        // Parse first line: ngates nwires
+        let line = next_nonempty_line(&mut reader)?;
+        let tokens = line.split_whitespace().collect::<Vec<&str>>();
+        if tokens.len() != 2 {
+            return Err(Error::ParseLineError(line.to_string()));
+        }
+        let nums = parse_tokens(&tokens)?;
+        let (ngates, nwires) = (nums[0], nums[1]);
+
+        // Parse second and third lines: niv ni_1 ... ni_niv and nov no_1 ... no_nov
+        let input_widths = parse_widths(&next_nonempty_line(&mut reader)?)?;
+        let output_widths = parse_widths(&next_nonempty_line(&mut reader)?)?;
+        if ngarbler_values > input_widths.len() {
+            return Err(Error::ParseLineError(format!(
+                "{} garbler input values requested but the circuit only has {}",
+                ngarbler_values,
+                input_widths.len()
+            )));
+        }
+        let ngarbler_inputs: usize = input_widths[..ngarbler_values].iter().sum();
+        let ninputs: usize = input_widths.iter().sum();
+        let noutputs: usize = output_widths.iter().sum();
+        if ninputs + noutputs > nwires {
+            return Err(Error::ParseLineError(line.to_string()));
+        }
+
+        let mut circ = Self::new(Some(ngates + ninputs + 1));
+
+        // Wire `ninputs` is reserved for the constant one, so every wire in the
+        // file at or above that index is shifted up by one.
+        let wire = |w: usize| -> Result<CircuitRef, Error> {
+            if w >= nwires {
+                return Err(Error::ParseLineError(format!("wire {} out of range", w)));
+            }
+            let ix = if w < ninputs { w } else { w + 1 };
+            Ok(CircuitRef { ix, modulus: 2 })
+        };
+
+        for i in 0..ninputs {
+            let r = CircuitRef { ix: i, modulus: 2 };
+            if i < ngarbler_inputs {
+                circ.gates.push(Gate::GarblerInput { id: i });
+                circ.garbler_input_refs.push(r);
+            } else {
+                circ.gates.push(Gate::EvaluatorInput {
+                    id: i - ngarbler_inputs,
+                });
+                circ.evaluator_input_refs.push(r);
+            }
+        }
+        circ.gates.push(Gate::Constant { val: 1 });
+        let oneref = CircuitRef {
+            ix: ninputs,
+            modulus: 2,
+        };
+        circ.const_refs.push(oneref);
+
+        for i in 0..noutputs {
+            circ.output_refs.push(wire(nwires - noutputs + i)?);
+        }
+
+        let mut nread = 0;
+        for line in reader.lines() {
+            let line = line?;
+            let tokens = line.split_whitespace().collect::<Vec<&str>>();
+            if tokens.is_empty() {
+                continue;
+            }
+            nread += 1;
+            let (typ, args) = tokens.split_last().unwrap();
+            let nums = parse_tokens(args)?;
+            if nums.len() < 2 || nums.len() != 2 + nums[0] + nums[1] {
+                return Err(Error::ParseLineError(line.to_string()));
+            }
+            let (nin, nout) = (nums[0], nums[1]);
+            let ins = &nums[2..2 + nin];
+            let outs = &nums[2 + nin..];
+            match (*typ, nin, nout) {
+                ("XOR", 2, 1) => circ.gates.push(Gate::Add {
+                    xref: wire(ins[0])?,
+                    yref: wire(ins[1])?,
+                    out: Some(wire(outs[0])?.ix),
+                }),
+                ("AND", 2, 1) => {
+                    let id = circ.num_nonfree_gates;
+                    circ.num_nonfree_gates += 1;
+                    circ.gates.push(Gate::Mul {
+                        xref: wire(ins[0])?,
+                        yref: wire(ins[1])?,
+                        id,
+                        out: Some(wire(outs[0])?.ix),
+                    })
+                }
+                ("INV", 1, 1) => circ.gates.push(Gate::Sub {
+                    xref: oneref,
+                    yref: wire(ins[0])?,
+                    out: Some(wire(outs[0])?.ix),
+                }),
+                ("EQW", 1, 1) => circ.gates.push(Gate::Cmul {
+                    xref: wire(ins[0])?,
+                    c: 1,
+                    out: Some(wire(outs[0])?.ix),
+                }),
+                ("EQ", 1, 1) if ins[0] < 2 => circ.gates.push(Gate::Cmul {
+                    xref: oneref,
+                    c: ins[0] as u16,
+                    out: Some(wire(outs[0])?.ix),
+                }),
+                ("MAND", _, _) if nin == 2 * nout => {
+                    for k in 0..nout {
+                        let id = circ.num_nonfree_gates;
+                        circ.num_nonfree_gates += 1;
+                        circ.gates.push(Gate::Mul {
+                            xref: wire(ins[k])?,
+                            yref: wire(ins[nout + k])?,
+                            id,
+                            out: Some(wire(outs[k])?.ix),
+                        });
+                    }
+                }
+                ("XOR", ..)
+                | ("AND", ..)
+                | ("INV", ..)
+                | ("EQW", ..)
+                | ("EQ", ..)
+                | ("MAND", ..) => {
+                    return Err(Error::ParseLineError(line.to_string()));
+                }
+                (s, ..) => return Err(Error::ParseGateError(s.to_string())),
+            }
+        }
+        if nread != ngates {
+            return Err(Error::ParseLineError(format!(
+                "the header gives {} gates but the file has {}",
+                ngates, nread
+            )));
+        }
+        circ.gate_moduli = vec![2; circ.gates.len()];
+        Ok(circ)
+    }
+
+    /// Write this circuit to file `filename` in the "Bristol Fashion" format
+    /// given here: <https://homes.esat.kuleuven.be/~nsmart/MPC/>.
+    ///
+    /// Only circuits where every wire is mod 2 can be written. The garbler
+    /// inputs and the evaluator inputs are written as two separate input
+    /// values, in that order, so the result can be read back in with
+    /// `Circuit::parse_bristol_fashion(filename, 1)`. The outputs are written
+    /// as a single output value, each output being copied into its final wire
+    /// by an `EQW` gate.
+    pub fn write_bristol_fashion(&self, filename: &str) -> Result<(), Error> {
+        let f = File::create(filename)?;
+        let mut writer = BufWriter::new(f);
+        self.to_bristol_fashion(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn to_bristol_fashion<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let check = |r: &CircuitRef| {
+            if r.modulus() == 2 {
+                Ok(())
+            } else {
+                Err(Error::UnsupportedModulus(r.modulus()))
+            }
+        };
+        if let Some(&q) = self.gate_moduli.iter().find(|&&q| q != 2) {
+            return Err(Error::UnsupportedModulus(q));
+        }
+
+        let ngarbler_inputs = self.num_garbler_inputs();
+        let ninputs = ngarbler_inputs + self.num_evaluator_inputs();
+
+        // Map from the index of a gate's output in `Circuit::eval` to its wire
+        // in the file. Inputs come first, as Bristol Fashion requires.
+        let mut wires: Vec<Option<usize>> = vec![None; self.gates.len()];
+        let lookup = |wires: &[Option<usize>], r: &CircuitRef| -> Result<usize, Error> {
+            check(r)?;
+            wires
+                .get(r.ix)
+                .cloned()
+                .flatten()
+                .ok_or(Error::UninitializedWire(r.ix))
+        };
+        let mut next_wire = ninputs;
+        let mut lines = Vec::with_capacity(self.gates.len() + self.noutputs());
+
+        for (i, gate) in self.gates.iter().enumerate() {
+            let (zref, line) = match *gate {
+                Gate::GarblerInput { id } => {
+                    wires[i] = Some(id);
+                    continue;
+                }
+                Gate::EvaluatorInput { id } => {
+                    wires[i] = Some(ngarbler_inputs + id);
+                    continue;
+                }
+                Gate::Constant { val } => (None, format!("1 1 {} {} EQ", val & 1, next_wire)),
+                Gate::Add { xref, yref, out } | Gate::Sub { xref, yref, out } => {
+                    let x = lookup(&wires, &xref)?;
+                    let y = lookup(&wires, &yref)?;
+                    (out, format!("2 1 {} {} {} XOR", x, y, next_wire))
+                }
+                Gate::Cmul { xref, c, out } => {
+                    let x = lookup(&wires, &xref)?;
+                    if c & 1 == 1 {
+                        (out, format!("1 1 {} {} EQW", x, next_wire))
+                    } else {
+                        (out, format!("1 1 0 {} EQ", next_wire))
+                    }
+                }
+                Gate::Mul {
+                    xref, yref, out, ..
+                } => {
+                    let x = lookup(&wires, &xref)?;
+                    let y = lookup(&wires, &yref)?;
+                    (out, format!("2 1 {} {} {} AND", x, y, next_wire))
+                }
+                Gate::Proj {
+                    xref, ref tt, out, ..
+                } => {
+                    let x = lookup(&wires, &xref)?;
+                    let line = match (tt[0], tt[1]) {
+                        (0, 1) => format!("1 1 {} {} EQW", x, next_wire),
+                        (1, 0) => format!("1 1 {} {} INV", x, next_wire),
+                        (c, _) => format!("1 1 {} {} EQ", c, next_wire),
+                    };
+                    (out, line)
+                }
+                Gate::Proj2 { .. } => return Err(Error::UnsupportedGate(gate.to_string())),
+            };
+            let ix = zref.unwrap_or(i);
+            if ix >= wires.len() {
+                wires.resize(ix + 1, None);
+            }
+            wires[ix] = Some(next_wire);
+            next_wire += 1;
+            lines.push(line);
+        }
+
+        for r in self.output_refs.iter() {
+            let x = lookup(&wires, r)?;
+            lines.push(format!("1 1 {} {} EQW", x, next_wire));
+            next_wire += 1;
+        }
+
+        writeln!(writer, "{} {}", lines.len(), next_wire)?;
+        writeln!(
+            writer,
+            "2 {} {}",
+            ngarbler_inputs,
+            self.num_evaluator_inputs()
+        )?;
+        writeln!(writer, "1 {}", self.noutputs())?;
+        writeln!(writer)?;
+        for line in lines.iter() {
+            writeln!(writer, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{circuit::Circuit, classic::garble};
+    use crate::{
+        circuit::{Circuit, CircuitBuilder},
+        classic::garble,
+        errors::CircuitParserError,
+        util::RngExt,
+        Fancy,
+    };
+    use rand::thread_rng;
+
+    const BRISTOL_FASHION_TEST: &str = "6 11
+2 2 2
+2 1 2
+
+2 1 0 2 4 XOR
+4 2 0 1 2 3 5 6 MAND
+1 1 4 7 INV
+1 1 1 8 EQ
+1 1 7 9 EQW
+2 1 6 5 10 XOR
+";
 
     #[test]
     fn test_parser() {
@@ -214,4 +549,116 @@ mod tests {
         let ev = en.encode_evaluator_inputs(&vec![0u16; 128]);
         gc.eval(&mut circ, &gb, &ev).unwrap();
     }
+
+    #[test]
+    fn test_bristol_fashion_parser() {
+        let circ = Circuit::read_bristol_fashion(BRISTOL_FASHION_TEST.as_bytes(), 1).unwrap();
+        assert_eq!(circ.num_garbler_inputs(), 2);
+        assert_eq!(circ.num_evaluator_inputs(), 2);
+        assert_eq!(circ.noutputs(), 3);
+        assert_eq!(circ.num_nonfree_gates, 2);
+        for a in 0..4 {
+            for b in 0..4 {
+                let (a0, a1, b0, b1) = (a & 1, a >> 1, b & 1, b >> 1);
+                let output = circ.eval_plain(&[a0, a1], &[b0, b1]).unwrap();
+                assert_eq!(output, vec![1, 1 ^ a0 ^ b0, (a1 & b1) ^ (a0 & b0)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_bad_gate() {
+        let circ = "1 3\n1 2\n1 1\n\n2 1 0 1 2 NAND\n";
+        match Circuit::read_bristol_fashion(circ.as_bytes(), 1) {
+            Err(CircuitParserError::ParseGateError(s)) => assert_eq!(s, "NAND"),
+            _ => panic!("NAND should not parse"),
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_bad_counts() {
+        // The header gives the wrong number of gates.
+        for circ in [
+            "2 3\n1 2\n1 1\n\n2 1 0 1 2 XOR\n",
+            "0 3\n1 2\n1 1\n\n2 1 0 1 2 XOR\n",
+        ]
+        .iter()
+        {
+            match Circuit::read_bristol_fashion(circ.as_bytes(), 1) {
+                Err(CircuitParserError::ParseLineError(s)) => assert!(s.contains("gates")),
+                _ => panic!("a wrong gate count should not parse"),
+            }
+        }
+        // Gates refer to wires past the number in the header.
+        for circ in [
+            "1 3\n1 2\n1 1\n\n2 1 0 3 2 XOR\n",
+            "1 3\n1 2\n1 1\n\n1 1 0 3 INV\n",
+        ]
+        .iter()
+        {
+            match Circuit::read_bristol_fashion(circ.as_bytes(), 1) {
+                Err(CircuitParserError::ParseLineError(s)) => assert_eq!(s, "wire 3 out of range"),
+                _ => panic!("an out of range wire should not parse"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_roundtrip() {
+        let mut rng = thread_rng();
+        let circ = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let mut buf = Vec::new();
+        circ.to_bristol_fashion(&mut buf).unwrap();
+        let circ_ = Circuit::read_bristol_fashion(buf.as_slice(), 1).unwrap();
+        assert_eq!(circ_.num_garbler_inputs(), 128);
+        assert_eq!(circ_.num_evaluator_inputs(), 128);
+        assert_eq!(circ_.num_nonfree_gates, 6800);
+        for _ in 0..4 {
+            let pt = (0..128)
+                .map(|_| rng.gen_bool() as u16)
+                .collect::<Vec<u16>>();
+            let key = (0..128)
+                .map(|_| rng.gen_bool() as u16)
+                .collect::<Vec<u16>>();
+            assert_eq!(
+                circ.eval_plain(&pt, &key).unwrap(),
+                circ_.eval_plain(&pt, &key).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_bristol_fashion_writer() {
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(2);
+        let y = b.evaluator_input(2);
+        let one = b.constant(1, 2).unwrap();
+        let z = b.mul(&x, &y).unwrap();
+        let w = b.proj(&z, 2, Some(vec![1, 0])).unwrap();
+        let v = b.sub(&w, &one).unwrap();
+        b.output(&v).unwrap();
+        b.output(&x).unwrap();
+        let circ = b.finish();
+
+        let mut buf = Vec::new();
+        circ.to_bristol_fashion(&mut buf).unwrap();
+        let circ_ = Circuit::read_bristol_fashion(buf.as_slice(), 1).unwrap();
+        for x in 0..2 {
+            for y in 0..2 {
+                assert_eq!(
+                    circ.eval_plain(&[x], &[y]).unwrap(),
+                    circ_.eval_plain(&[x], &[y]).unwrap()
+                );
+            }
+        }
+
+        let mut b = CircuitBuilder::new();
+        let x = b.garbler_input(3);
+        b.output(&x).unwrap();
+        let circ = b.finish();
+        match circ.to_bristol_fashion(&mut Vec::new()) {
+            Err(CircuitParserError::UnsupportedModulus(3)) => (),
+            _ => panic!("mod 3 circuits cannot be written in Bristol Fashion"),
+        }
+    }
 }
@@ -0,0 +1,1155 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Typed secure integers over any `Fancy` backend, with operator overloading.
+//!
+//! A `Context` is a shared handle to the backend. Secure values keep a copy of
+//! it, so gadget code reads like ordinary Rust:
+//!
+//! ```
+//! use fancy_garbling::{
+//!     dummy::Dummy,
+//!     secure::{Context, UInt},
+//! };
+//!
+//! let ctx = Context::new(Dummy::new());
+//! let x = UInt::<_, 8>::encode(&ctx, 200);
+//! let y = UInt::<_, 8>::encode(&ctx, 100);
+//! let sum = &x + &y;
+//! let max = x.lt(&y).select(&y, &x);
+//! assert_eq!(sum.output().unwrap(), Some(44));
+//! assert_eq!(max.output().unwrap(), Some(200));
+//! ```
+//!
+//! `UInt<F, N>` and `Int<F, N>` are unsigned and two's complement integers of
+//! `N` bits, wrapping a `BinaryBundle`, and `CrtInt<F>` wraps a `CrtBundle`.
+//! Rust's comparison operators must return `bool`, so comparisons are methods
+//! such as `lt` and `eq`, which return a secure `Bit`.
+//!
+//! Operators cannot return a `Result` either. Instead, the first error raised
+//! by an operation is kept by the `Context`, and values computed after it are
+//! left unset. The error is returned by the next method that reads a value
+//! out, such as `output`, `reveal` or `bundle`, or by `Context::check`.
+
+use crate::{
+    errors::FancyError,
+    fancy::{
+        BinaryBundle,
+        BinaryGadgets,
+        BundleGadgets,
+        CrtBundle,
+        CrtGadgets,
+        Fancy,
+        FancyInput,
+        FancyReveal,
+        HasModulus,
+    },
+};
+use std::{
+    cell::RefCell,
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
+};
+
+/// Shared handle to a `Fancy` backend, which records the first error raised by
+/// an operation on the values computed with it.
+pub struct Context<F: Fancy>(Rc<RefCell<State<F>>>);
+
+struct State<F: Fancy> {
+    fancy: F,
+    error: Option<F::Error>,
+}
+
+impl<F: Fancy> Clone for Context<F> {
+    fn clone(&self) -> Self {
+        Context(Rc::clone(&self.0))
+    }
+}
+
+impl<F: Fancy> Context<F> {
+    /// Create a context computing on `fancy`.
+    pub fn new(fancy: F) -> Self {
+        Context(Rc::new(RefCell::new(State { fancy, error: None })))
+    }
+
+    /// Run `op` on the backend directly, e.g. to create inputs or use gadgets
+    /// without a typed wrapper. Secure values must not be used inside `op`.
+    pub fn with<T>(&self, op: impl FnOnce(&mut F) -> T) -> T {
+        op(&mut self.0.borrow_mut().fancy)
+    }
+
+    /// Return the first error raised since the last call, if any.
+    pub fn check(&self) -> Result<(), F::Error> {
+        match self.0.borrow_mut().error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Return the backend, or this context if values still refer to it.
+    pub fn into_inner(self) -> Result<F, Context<F>> {
+        Rc::try_unwrap(self.0)
+            .map(|state| state.into_inner().fancy)
+            .map_err(Context)
+    }
+
+    /// Run `op` unless an earlier operation failed, recording its error.
+    fn run<T>(&self, op: impl FnOnce(&mut F) -> Result<T, F::Error>) -> Option<T> {
+        let mut state = self.0.borrow_mut();
+        if state.error.is_some() {
+            return None;
+        }
+        match op(&mut state.fancy) {
+            Ok(x) => Some(x),
+            Err(e) => {
+                state.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn run1<A, T>(
+        &self,
+        x: Option<&A>,
+        op: impl FnOnce(&mut F, &A) -> Result<T, F::Error>,
+    ) -> Option<T> {
+        x.and_then(|x| self.run(|f| op(f, x)))
+    }
+
+    /// Run `op` on `x` and `y`, where `y` was computed with the context
+    /// `rhs`, which must be this one.
+    fn run2<A, T>(
+        &self,
+        x: Option<&A>,
+        rhs: &Context<F>,
+        y: Option<&A>,
+        op: impl FnOnce(&mut F, &A, &A) -> Result<T, F::Error>,
+    ) -> Option<T> {
+        if !self.check_same(&[rhs]) {
+            return None;
+        }
+        match (x, y) {
+            (Some(x), Some(y)) => self.run(|f| op(f, x, y)),
+            _ => None,
+        }
+    }
+
+    /// Record an error unless all of `others` are this context, since values
+    /// from another backend cannot be combined with ours.
+    fn check_same(&self, others: &[&Context<F>]) -> bool {
+        if others.iter().all(|c| Rc::ptr_eq(&self.0, &c.0)) {
+            return true;
+        }
+        self.run(|_| -> Result<(), F::Error> {
+            Err(F::Error::from(FancyError::InvalidArg(
+                "values from different contexts cannot be combined".to_string(),
+            )))
+        });
+        false
+    }
+
+    /// Read out of a value, returning the recorded error if there is one or
+    /// the value is unset.
+    fn read<A, T>(
+        &self,
+        x: Option<&A>,
+        op: impl FnOnce(&mut F, &A) -> Result<T, F::Error>,
+    ) -> Result<T, F::Error> {
+        self.check()?;
+        let x = x.ok_or_else(|| {
+            F::Error::from(FancyError::InvalidArg(
+                "value depends on an operation that failed".to_string(),
+            ))
+        })?;
+        op(&mut self.0.borrow_mut().fancy, x)
+    }
+}
+
+/// Values that a secure `Bit` can choose between.
+pub trait Select<F: Fancy>: Sized {
+    /// Return `if_true` if `cond` is set and `if_false` otherwise.
+    fn select(cond: &Bit<F>, if_true: &Self, if_false: &Self) -> Self;
+}
+
+/// Implement a binary operator for owned and borrowed operands, in terms of
+/// the method `binop` of the type.
+macro_rules! binop {
+    ([$($generics:tt)*] $ty:ty, $trait:ident, $method:ident, $op:expr) => {
+        impl<'a, 'b, $($generics)*> $trait<&'b $ty> for &'a $ty {
+            type Output = $ty;
+            fn $method(self, rhs: &'b $ty) -> $ty {
+                self.binop(rhs, $op)
+            }
+        }
+        impl<'b, $($generics)*> $trait<&'b $ty> for $ty {
+            type Output = $ty;
+            fn $method(self, rhs: &'b $ty) -> $ty {
+                (&self).$method(rhs)
+            }
+        }
+        impl<'a, $($generics)*> $trait<$ty> for &'a $ty {
+            type Output = $ty;
+            fn $method(self, rhs: $ty) -> $ty {
+                self.$method(&rhs)
+            }
+        }
+        impl<$($generics)*> $trait for $ty {
+            type Output = $ty;
+            fn $method(self, rhs: $ty) -> $ty {
+                (&self).$method(&rhs)
+            }
+        }
+    };
+}
+
+/// Implement a unary operator for owned and borrowed operands, in terms of
+/// the method `unop` of the type.
+macro_rules! unop {
+    ([$($generics:tt)*] $ty:ty, $trait:ident, $method:ident, $op:expr) => {
+        impl<'a, $($generics)*> $trait for &'a $ty {
+            type Output = $ty;
+            fn $method(self) -> $ty {
+                self.unop($op)
+            }
+        }
+        impl<$($generics)*> $trait for $ty {
+            type Output = $ty;
+            fn $method(self) -> $ty {
+                (&self).$method()
+            }
+        }
+    };
+}
+
+/// Implement a shift by a public amount for owned and borrowed operands.
+macro_rules! shiftop {
+    ([$($generics:tt)*] $ty:ty, $trait:ident, $method:ident, $op:expr) => {
+        impl<'a, $($generics)*> $trait<usize> for &'a $ty {
+            type Output = $ty;
+            fn $method(self, n: usize) -> $ty {
+                self.unop(|f, x| $op(f, x, n))
+            }
+        }
+        impl<$($generics)*> $trait<usize> for $ty {
+            type Output = $ty;
+            fn $method(self, n: usize) -> $ty {
+                (&self).$method(n)
+            }
+        }
+    };
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// bits
+
+/// A secure bit.
+pub struct Bit<F: Fancy> {
+    ctx: Context<F>,
+    wire: Option<F::Item>,
+}
+
+impl<F: Fancy> Clone for Bit<F> {
+    fn clone(&self) -> Self {
+        Bit::new(&self.ctx, self.wire.clone())
+    }
+}
+
+impl<F: Fancy> Bit<F> {
+    /// Create the public constant `value`.
+    pub fn constant(ctx: &Context<F>, value: bool) -> Self {
+        Bit::new(ctx, ctx.run(|f| f.constant(value as u16, 2)))
+    }
+
+    /// Wrap a wire, which must have modulus 2.
+    pub fn from_wire(ctx: &Context<F>, wire: F::Item) -> Self {
+        let wire = ctx.run(|_| {
+            if wire.modulus() != 2 {
+                return Err(F::Error::from(FancyError::InvalidArg(format!(
+                    "a bit must have modulus 2, got {}",
+                    wire.modulus()
+                ))));
+            }
+            Ok(wire)
+        });
+        Bit::new(ctx, wire)
+    }
+
+    /// Return the underlying wire.
+    pub fn wire(&self) -> Result<F::Item, F::Error> {
+        self.ctx.read(self.wire.as_ref(), |_, w| Ok(w.clone()))
+    }
+
+    /// Output the bit.
+    pub fn output(&self) -> Result<Option<bool>, F::Error> {
+        self.ctx
+            .read(self.wire.as_ref(), |f, w| f.output(w))
+            .map(|x| x.map(|x| x == 1))
+    }
+
+    /// Return `if_true` if this bit is set and `if_false` otherwise.
+    pub fn select<T: Select<F>>(&self, if_true: &T, if_false: &T) -> T {
+        T::select(self, if_true, if_false)
+    }
+
+    fn new(ctx: &Context<F>, wire: Option<F::Item>) -> Self {
+        Bit {
+            ctx: ctx.clone(),
+            wire,
+        }
+    }
+
+    fn unop(&self, op: impl FnOnce(&mut F, &F::Item) -> Result<F::Item, F::Error>) -> Self {
+        Bit::new(&self.ctx, self.ctx.run1(self.wire.as_ref(), op))
+    }
+
+    fn binop(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(&mut F, &F::Item, &F::Item) -> Result<F::Item, F::Error>,
+    ) -> Self {
+        Bit::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.wire.as_ref(), &rhs.ctx, rhs.wire.as_ref(), op),
+        )
+    }
+}
+
+impl<F: Fancy + FancyInput<Item = <F as Fancy>::Item, Error = <F as Fancy>::Error>> Bit<F> {
+    /// Encode the input `value`.
+    pub fn encode(ctx: &Context<F>, value: bool) -> Self {
+        Bit::new(ctx, ctx.run(|f| f.encode(value as u16, 2)))
+    }
+
+    /// Receive an input from the other party.
+    pub fn receive(ctx: &Context<F>) -> Self {
+        Bit::new(ctx, ctx.run(|f| f.receive(2)))
+    }
+}
+
+impl<F: FancyReveal> Bit<F> {
+    /// Reveal the bit to all parties.
+    pub fn reveal(&self) -> Result<bool, F::Error> {
+        self.ctx
+            .read(self.wire.as_ref(), |f, w| f.reveal(w))
+            .map(|x| x == 1)
+    }
+}
+
+impl<F: Fancy> Select<F> for Bit<F> {
+    fn select(cond: &Bit<F>, if_true: &Self, if_false: &Self) -> Self {
+        if !cond.ctx.check_same(&[&if_true.ctx, &if_false.ctx]) {
+            return Bit::new(&cond.ctx, None);
+        }
+        let wire = match (&cond.wire, &if_true.wire, &if_false.wire) {
+            (Some(b), Some(x), Some(y)) => cond.ctx.run(|f| f.mux(b, y, x)),
+            _ => None,
+        };
+        Bit::new(&cond.ctx, wire)
+    }
+}
+
+binop!([F: Fancy] Bit<F>, BitAnd, bitand, |f, x, y| f.and(x, y));
+binop!([F: Fancy] Bit<F>, BitOr, bitor, |f, x, y| f.or(x, y));
+binop!([F: Fancy] Bit<F>, BitXor, bitxor, |f, x, y| f.xor(x, y));
+unop!([F: Fancy] Bit<F>, Not, not, |f, x| f.negate(x));
+
+////////////////////////////////////////////////////////////////////////////////
+// binary integers
+
+/// A secure unsigned integer of `N` bits. Arithmetic wraps around.
+pub struct UInt<F: Fancy, const N: usize> {
+    ctx: Context<F>,
+    bits: Option<BinaryBundle<F::Item>>,
+}
+
+/// A secure two's complement integer of `N` bits. Arithmetic wraps around.
+pub struct Int<F: Fancy, const N: usize> {
+    ctx: Context<F>,
+    bits: Option<BinaryBundle<F::Item>>,
+}
+
+impl<F: Fancy, const N: usize> Clone for UInt<F, N> {
+    fn clone(&self) -> Self {
+        UInt::new(&self.ctx, self.bits.clone())
+    }
+}
+
+impl<F: Fancy, const N: usize> Clone for Int<F, N> {
+    fn clone(&self) -> Self {
+        Int::new(&self.ctx, self.bits.clone())
+    }
+}
+
+impl<F: Fancy, const N: usize> UInt<F, N> {
+    /// Create the public constant `value`.
+    pub fn constant(ctx: &Context<F>, value: u128) -> Self {
+        UInt::new(ctx, ctx.run(|f| f.bin_constant_bundle(value, N)))
+    }
+
+    /// Wrap a binary bundle, which must have `N` bits.
+    pub fn from_bundle(ctx: &Context<F>, bundle: BinaryBundle<F::Item>) -> Self {
+        UInt::new(
+            ctx,
+            ctx.run(|_| check_size(bundle, N).map_err(F::Error::from)),
+        )
+    }
+
+    /// Return the underlying binary bundle.
+    pub fn bundle(&self) -> Result<BinaryBundle<F::Item>, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |_, x| Ok(x.clone()))
+    }
+
+    /// Output the integer, which must have at most 128 bits.
+    pub fn output(&self) -> Result<Option<u128>, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |f, x| f.bin_output(x))
+    }
+
+    /// Compute `self < rhs`.
+    pub fn lt(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.bin_lt(x, y))
+    }
+
+    /// Compute `self <= rhs`.
+    pub fn le(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.bin_geq(y, x))
+    }
+
+    /// Compute `self > rhs`.
+    pub fn gt(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.bin_lt(y, x))
+    }
+
+    /// Compute `self >= rhs`.
+    pub fn ge(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.bin_geq(x, y))
+    }
+
+    /// Compute `self == rhs`.
+    pub fn eq(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, equal)
+    }
+
+    /// Compute `self != rhs`.
+    pub fn ne(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, not_equal)
+    }
+
+    fn new(ctx: &Context<F>, bits: Option<BinaryBundle<F::Item>>) -> Self {
+        UInt {
+            ctx: ctx.clone(),
+            bits,
+        }
+    }
+
+    fn unop(
+        &self,
+        op: impl FnOnce(&mut F, &BinaryBundle<F::Item>) -> Result<BinaryBundle<F::Item>, F::Error>,
+    ) -> Self {
+        UInt::new(&self.ctx, self.ctx.run1(self.bits.as_ref(), op))
+    }
+
+    fn binop(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(
+            &mut F,
+            &BinaryBundle<F::Item>,
+            &BinaryBundle<F::Item>,
+        ) -> Result<BinaryBundle<F::Item>, F::Error>,
+    ) -> Self {
+        UInt::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bits.as_ref(), &rhs.ctx, rhs.bits.as_ref(), op),
+        )
+    }
+
+    fn compare(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(
+            &mut F,
+            &BinaryBundle<F::Item>,
+            &BinaryBundle<F::Item>,
+        ) -> Result<F::Item, F::Error>,
+    ) -> Bit<F> {
+        Bit::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bits.as_ref(), &rhs.ctx, rhs.bits.as_ref(), op),
+        )
+    }
+}
+
+impl<F: Fancy, const N: usize> Int<F, N> {
+    /// Create the public constant `value`.
+    pub fn constant(ctx: &Context<F>, value: i128) -> Self {
+        Int::new(ctx, ctx.run(|f| f.bin_constant_bundle(value as u128, N)))
+    }
+
+    /// Wrap a binary bundle, which must have `N` bits.
+    pub fn from_bundle(ctx: &Context<F>, bundle: BinaryBundle<F::Item>) -> Self {
+        Int::new(
+            ctx,
+            ctx.run(|_| check_size(bundle, N).map_err(F::Error::from)),
+        )
+    }
+
+    /// Return the underlying binary bundle.
+    pub fn bundle(&self) -> Result<BinaryBundle<F::Item>, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |_, x| Ok(x.clone()))
+    }
+
+    /// Output the integer, which must have at most 128 bits.
+    pub fn output(&self) -> Result<Option<i128>, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |f, x| {
+            check_signed_width(N)?;
+            Ok(f.bin_output(x)?.map(sign_extend::<N>))
+        })
+    }
+
+    /// Compute `self < rhs`.
+    pub fn lt(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| signed_lt(f, x, y))
+    }
+
+    /// Compute `self <= rhs`.
+    pub fn le(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| {
+            let z = signed_lt(f, y, x)?;
+            f.negate(&z)
+        })
+    }
+
+    /// Compute `self > rhs`.
+    pub fn gt(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| signed_lt(f, y, x))
+    }
+
+    /// Compute `self >= rhs`.
+    pub fn ge(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| {
+            let z = signed_lt(f, x, y)?;
+            f.negate(&z)
+        })
+    }
+
+    /// Compute `self == rhs`.
+    pub fn eq(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, equal)
+    }
+
+    /// Compute `self != rhs`.
+    pub fn ne(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, not_equal)
+    }
+
+    fn new(ctx: &Context<F>, bits: Option<BinaryBundle<F::Item>>) -> Self {
+        Int {
+            ctx: ctx.clone(),
+            bits,
+        }
+    }
+
+    fn unop(
+        &self,
+        op: impl FnOnce(&mut F, &BinaryBundle<F::Item>) -> Result<BinaryBundle<F::Item>, F::Error>,
+    ) -> Self {
+        Int::new(&self.ctx, self.ctx.run1(self.bits.as_ref(), op))
+    }
+
+    fn binop(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(
+            &mut F,
+            &BinaryBundle<F::Item>,
+            &BinaryBundle<F::Item>,
+        ) -> Result<BinaryBundle<F::Item>, F::Error>,
+    ) -> Self {
+        Int::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bits.as_ref(), &rhs.ctx, rhs.bits.as_ref(), op),
+        )
+    }
+
+    fn compare(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(
+            &mut F,
+            &BinaryBundle<F::Item>,
+            &BinaryBundle<F::Item>,
+        ) -> Result<F::Item, F::Error>,
+    ) -> Bit<F> {
+        Bit::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bits.as_ref(), &rhs.ctx, rhs.bits.as_ref(), op),
+        )
+    }
+}
+
+impl<F, const N: usize> UInt<F, N>
+where
+    F: Fancy + FancyInput<Item = <F as Fancy>::Item, Error = <F as Fancy>::Error>,
+{
+    /// Encode the input `value`.
+    pub fn encode(ctx: &Context<F>, value: u128) -> Self {
+        UInt::new(ctx, ctx.run(|f| f.bin_encode(value, N)))
+    }
+
+    /// Receive an input from the other party.
+    pub fn receive(ctx: &Context<F>) -> Self {
+        UInt::new(ctx, ctx.run(|f| f.bin_receive(N)))
+    }
+}
+
+impl<F, const N: usize> Int<F, N>
+where
+    F: Fancy + FancyInput<Item = <F as Fancy>::Item, Error = <F as Fancy>::Error>,
+{
+    /// Encode the input `value`.
+    pub fn encode(ctx: &Context<F>, value: i128) -> Self {
+        Int::new(ctx, ctx.run(|f| f.bin_encode(value as u128, N)))
+    }
+
+    /// Receive an input from the other party.
+    pub fn receive(ctx: &Context<F>) -> Self {
+        Int::new(ctx, ctx.run(|f| f.bin_receive(N)))
+    }
+}
+
+impl<F: FancyReveal, const N: usize> UInt<F, N> {
+    /// Reveal the integer to all parties.
+    pub fn reveal(&self) -> Result<u128, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |f, x| f.bin_reveal(x))
+    }
+}
+
+impl<F: FancyReveal, const N: usize> Int<F, N> {
+    /// Reveal the integer to all parties.
+    pub fn reveal(&self) -> Result<i128, F::Error> {
+        self.ctx.read(self.bits.as_ref(), |f, x| {
+            check_signed_width(N)?;
+            Ok(sign_extend::<N>(f.bin_reveal(x)?))
+        })
+    }
+}
+
+impl<F: Fancy, const N: usize> Select<F> for UInt<F, N> {
+    fn select(cond: &Bit<F>, if_true: &Self, if_false: &Self) -> Self {
+        UInt::new(
+            &cond.ctx,
+            select(
+                cond,
+                &if_true.ctx,
+                if_true.bits.as_ref(),
+                &if_false.ctx,
+                if_false.bits.as_ref(),
+            ),
+        )
+    }
+}
+
+impl<F: Fancy, const N: usize> Select<F> for Int<F, N> {
+    fn select(cond: &Bit<F>, if_true: &Self, if_false: &Self) -> Self {
+        Int::new(
+            &cond.ctx,
+            select(
+                cond,
+                &if_true.ctx,
+                if_true.bits.as_ref(),
+                &if_false.ctx,
+                if_false.bits.as_ref(),
+            ),
+        )
+    }
+}
+
+binop!([F: Fancy, const N: usize] UInt<F, N>, Add, add, |f, x, y| f.bin_addition_no_carry(x, y));
+binop!([F: Fancy, const N: usize] UInt<F, N>, Sub, sub, |f, x, y| Ok(f.bin_subtraction(x, y)?.0));
+binop!([F: Fancy, const N: usize] UInt<F, N>, Mul, mul, |f, x, y| {
+    f.bin_multiplication_lower_half(x, y)
+});
+binop!([F: Fancy, const N: usize] UInt<F, N>, Div, div, |f, x, y| f.bin_div(x, y));
+binop!([F: Fancy, const N: usize] UInt<F, N>, Rem, rem, |f, x, y| f.bin_rem(x, y));
+binop!([F: Fancy, const N: usize] UInt<F, N>, BitAnd, bitand, |f, x, y| f.bin_and(x, y));
+binop!([F: Fancy, const N: usize] UInt<F, N>, BitOr, bitor, |f, x, y| f.bin_or(x, y));
+binop!([F: Fancy, const N: usize] UInt<F, N>, BitXor, bitxor, |f, x, y| f.bin_xor(x, y));
+unop!([F: Fancy, const N: usize] UInt<F, N>, Not, not, negate_bits);
+shiftop!([F: Fancy, const N: usize] UInt<F, N>, Shl, shl, shift_left);
+shiftop!([F: Fancy, const N: usize] UInt<F, N>, Shr, shr, shift_right);
+
+binop!([F: Fancy, const N: usize] Int<F, N>, Add, add, |f, x, y| f.bin_addition_no_carry(x, y));
+binop!([F: Fancy, const N: usize] Int<F, N>, Sub, sub, |f, x, y| Ok(f.bin_subtraction(x, y)?.0));
+binop!([F: Fancy, const N: usize] Int<F, N>, Mul, mul, |f, x, y| {
+    f.bin_multiplication_lower_half(x, y)
+});
+binop!([F: Fancy, const N: usize] Int<F, N>, Div, div, |f, x, y| f.bin_div_signed(x, y));
+binop!([F: Fancy, const N: usize] Int<F, N>, Rem, rem, |f, x, y| f.bin_rem_signed(x, y));
+binop!([F: Fancy, const N: usize] Int<F, N>, BitAnd, bitand, |f, x, y| f.bin_and(x, y));
+binop!([F: Fancy, const N: usize] Int<F, N>, BitOr, bitor, |f, x, y| f.bin_or(x, y));
+binop!([F: Fancy, const N: usize] Int<F, N>, BitXor, bitxor, |f, x, y| f.bin_xor(x, y));
+unop!([F: Fancy, const N: usize] Int<F, N>, Not, not, negate_bits);
+unop!([F: Fancy, const N: usize] Int<F, N>, Neg, neg, |f, x| f.bin_twos_complement(x));
+shiftop!([F: Fancy, const N: usize] Int<F, N>, Shl, shl, shift_left);
+shiftop!([F: Fancy, const N: usize] Int<F, N>, Shr, shr, shift_right_arithmetic);
+
+fn check_size<W: Clone + HasModulus>(
+    x: BinaryBundle<W>,
+    nbits: usize,
+) -> Result<BinaryBundle<W>, FancyError> {
+    if x.size() != nbits {
+        return Err(FancyError::InvalidArg(format!(
+            "expected a bundle of {} bits, got {}",
+            nbits,
+            x.size()
+        )));
+    }
+    Ok(x)
+}
+
+/// Check that a signed integer of `nbits` bits can be read as an `i128`.
+fn check_signed_width(nbits: usize) -> Result<(), FancyError> {
+    if nbits == 0 || nbits > 128 {
+        return Err(FancyError::InvalidArg(format!(
+            "cannot read a {}-bit signed integer as an i128",
+            nbits
+        )));
+    }
+    Ok(())
+}
+
+/// Interpret the low `N` bits of `x` as a two's complement integer, where `N`
+/// must pass `check_signed_width`.
+fn sign_extend<const N: usize>(x: u128) -> i128 {
+    let shift = 128 - N as u32;
+    ((x as i128) << shift) >> shift
+}
+
+fn select<F: Fancy>(
+    cond: &Bit<F>,
+    true_ctx: &Context<F>,
+    if_true: Option<&BinaryBundle<F::Item>>,
+    false_ctx: &Context<F>,
+    if_false: Option<&BinaryBundle<F::Item>>,
+) -> Option<BinaryBundle<F::Item>> {
+    if !cond.ctx.check_same(&[true_ctx, false_ctx]) {
+        return None;
+    }
+    match (&cond.wire, if_true, if_false) {
+        (Some(b), Some(x), Some(y)) => cond
+            .ctx
+            .run(|f| f.multiplex(b, y, x))
+            .map(BinaryBundle::from),
+        _ => None,
+    }
+}
+
+fn equal<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    y: &BinaryBundle<F::Item>,
+) -> Result<F::Item, F::Error> {
+    let z = not_equal(f, x, y)?;
+    f.negate(&z)
+}
+
+fn not_equal<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    y: &BinaryBundle<F::Item>,
+) -> Result<F::Item, F::Error> {
+    let z = f.bin_xor(x, y)?;
+    f.or_many(z.wires())
+}
+
+/// Compare two's complement integers, by flipping their sign bits and
+/// comparing them as unsigned integers.
+fn signed_lt<F: BinaryGadgets + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    y: &BinaryBundle<F::Item>,
+) -> Result<F::Item, F::Error> {
+    let x = flip_sign(f, x)?;
+    let y = flip_sign(f, y)?;
+    f.bin_lt(&x, &y)
+}
+
+fn flip_sign<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    let mut ws = x.wires().to_vec();
+    let sign = ws.pop().unwrap();
+    ws.push(f.negate(&sign)?);
+    Ok(BinaryBundle::new(ws))
+}
+
+fn negate_bits<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    x.wires()
+        .iter()
+        .map(|w| f.negate(w))
+        .collect::<Result<Vec<_>, _>>()
+        .map(BinaryBundle::new)
+}
+
+fn shift_left<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    n: usize,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    let zero = f.constant(0, 2)?;
+    let ws = x.wires();
+    Ok(BinaryBundle::new(
+        (0..ws.len())
+            .map(|i| i.checked_sub(n).map_or(&zero, |j| &ws[j]).clone())
+            .collect(),
+    ))
+}
+
+fn shift_right<F: Fancy + ?Sized>(
+    f: &mut F,
+    x: &BinaryBundle<F::Item>,
+    n: usize,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    let zero = f.constant(0, 2)?;
+    Ok(shift_right_with(x.wires(), n, &zero))
+}
+
+fn shift_right_arithmetic<F: Fancy + ?Sized>(
+    _: &mut F,
+    x: &BinaryBundle<F::Item>,
+    n: usize,
+) -> Result<BinaryBundle<F::Item>, F::Error> {
+    let sign = x.wires().last().unwrap();
+    Ok(shift_right_with(x.wires(), n, sign))
+}
+
+fn shift_right_with<W: Clone + HasModulus>(ws: &[W], n: usize, fill: &W) -> BinaryBundle<W> {
+    BinaryBundle::new(
+        (0..ws.len())
+            .map(|i| {
+                i.checked_add(n)
+                    .and_then(|j| ws.get(j))
+                    .unwrap_or(fill)
+                    .clone()
+            })
+            .collect(),
+    )
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// crt integers
+
+/// A secure integer modulo the composite modulus of a CRT bundle. Comparisons
+/// treat values of at least half the modulus as negative.
+pub struct CrtInt<F: Fancy> {
+    ctx: Context<F>,
+    bundle: Option<CrtBundle<F::Item>>,
+}
+
+impl<F: Fancy> Clone for CrtInt<F> {
+    fn clone(&self) -> Self {
+        CrtInt::new(&self.ctx, self.bundle.clone())
+    }
+}
+
+impl<F: Fancy> CrtInt<F> {
+    /// Create the public constant `value` modulo `modulus`.
+    pub fn constant(ctx: &Context<F>, value: u128, modulus: u128) -> Self {
+        CrtInt::new(ctx, ctx.run(|f| f.crt_constant_bundle(value, modulus)))
+    }
+
+    /// Wrap a CRT bundle.
+    pub fn from_bundle(ctx: &Context<F>, bundle: CrtBundle<F::Item>) -> Self {
+        CrtInt::new(ctx, Some(bundle))
+    }
+
+    /// Return the underlying CRT bundle.
+    pub fn bundle(&self) -> Result<CrtBundle<F::Item>, F::Error> {
+        self.ctx.read(self.bundle.as_ref(), |_, x| Ok(x.clone()))
+    }
+
+    /// Output the integer.
+    pub fn output(&self) -> Result<Option<u128>, F::Error> {
+        self.ctx.read(self.bundle.as_ref(), |f, x| f.crt_output(x))
+    }
+
+    /// Compute `self < rhs` with the given `accuracy`, as in `crt_sign`. The
+    /// difference of the operands must lie within half the modulus.
+    pub fn lt(&self, rhs: &Self, accuracy: &str) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.crt_lt(x, y, accuracy))
+    }
+
+    /// Compute `self >= rhs` with the given `accuracy`, as in `crt_sign`. The
+    /// difference of the operands must lie within half the modulus.
+    pub fn ge(&self, rhs: &Self, accuracy: &str) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.crt_geq(x, y, accuracy))
+    }
+
+    /// Compute `self == rhs`.
+    pub fn eq(&self, rhs: &Self) -> Bit<F> {
+        self.compare(rhs, |f, x, y| f.eq_bundles(x, y))
+    }
+
+    fn new(ctx: &Context<F>, bundle: Option<CrtBundle<F::Item>>) -> Self {
+        CrtInt {
+            ctx: ctx.clone(),
+            bundle,
+        }
+    }
+
+    fn unop(
+        &self,
+        op: impl FnOnce(&mut F, &CrtBundle<F::Item>) -> Result<CrtBundle<F::Item>, F::Error>,
+    ) -> Self {
+        CrtInt::new(&self.ctx, self.ctx.run1(self.bundle.as_ref(), op))
+    }
+
+    fn binop(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(
+            &mut F,
+            &CrtBundle<F::Item>,
+            &CrtBundle<F::Item>,
+        ) -> Result<CrtBundle<F::Item>, F::Error>,
+    ) -> Self {
+        CrtInt::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bundle.as_ref(), &rhs.ctx, rhs.bundle.as_ref(), op),
+        )
+    }
+
+    fn compare(
+        &self,
+        rhs: &Self,
+        op: impl FnOnce(&mut F, &CrtBundle<F::Item>, &CrtBundle<F::Item>) -> Result<F::Item, F::Error>,
+    ) -> Bit<F> {
+        Bit::new(
+            &self.ctx,
+            self.ctx
+                .run2(self.bundle.as_ref(), &rhs.ctx, rhs.bundle.as_ref(), op),
+        )
+    }
+}
+
+impl<F: Fancy + FancyInput<Item = <F as Fancy>::Item, Error = <F as Fancy>::Error>> CrtInt<F> {
+    /// Encode the input `value` modulo `modulus`.
+    pub fn encode(ctx: &Context<F>, value: u128, modulus: u128) -> Self {
+        CrtInt::new(ctx, ctx.run(|f| f.crt_encode(value, modulus)))
+    }
+
+    /// Receive an input modulo `modulus` from the other party.
+    pub fn receive(ctx: &Context<F>, modulus: u128) -> Self {
+        CrtInt::new(ctx, ctx.run(|f| f.crt_receive(modulus)))
+    }
+}
+
+impl<F: FancyReveal> CrtInt<F> {
+    /// Reveal the integer to all parties.
+    pub fn reveal(&self) -> Result<u128, F::Error> {
+        self.ctx.read(self.bundle.as_ref(), |f, x| f.crt_reveal(x))
+    }
+}
+
+impl<F: Fancy> Select<F> for CrtInt<F> {
+    fn select(cond: &Bit<F>, if_true: &Self, if_false: &Self) -> Self {
+        if !cond.ctx.check_same(&[&if_true.ctx, &if_false.ctx]) {
+            return CrtInt::new(&cond.ctx, None);
+        }
+        let bundle = match (&cond.wire, &if_true.bundle, &if_false.bundle) {
+            (Some(b), Some(x), Some(y)) => cond.ctx.run(|f| f.crt_multiplex(b, y, x)),
+            _ => None,
+        };
+        CrtInt::new(&cond.ctx, bundle)
+    }
+}
+
+binop!([F: Fancy] CrtInt<F>, Add, add, |f, x, y| f.crt_add(x, y));
+binop!([F: Fancy] CrtInt<F>, Sub, sub, |f, x, y| f.crt_sub(x, y));
+binop!([F: Fancy] CrtInt<F>, Mul, mul, |f, x, y| f.crt_mul(x, y));
+unop!([F: Fancy] CrtInt<F>, Neg, neg, |f, x| {
+    x.wires()
+        .iter()
+        .map(|w| f.cmul(w, w.modulus() - 1))
+        .collect::<Result<Vec<_>, _>>()
+        .map(CrtBundle::new)
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{circuit::CircuitBuilder, dummy::Dummy, util};
+    use rand::{thread_rng, Rng};
+
+    #[test]
+    fn uint_ops() {
+        let mut rng = thread_rng();
+        let ctx = Context::new(Dummy::new());
+        for _ in 0..16 {
+            let (a, b) = (rng.gen::<u16>(), rng.gen_range(1, u16::max_value()));
+            let s = rng.gen_range(0, 20);
+            let x = UInt::<_, 16>::encode(&ctx, a as u128);
+            let y = UInt::<_, 16>::encode(&ctx, b as u128);
+            let out = |z: UInt<Dummy, 16>| z.output().unwrap().unwrap() as u16;
+            let bit = |z: Bit<Dummy>| z.output().unwrap().unwrap();
+            assert_eq!(out(&x + &y), a.wrapping_add(b));
+            assert_eq!(out(&x - &y), a.wrapping_sub(b));
+            assert_eq!(out(&x * &y), a.wrapping_mul(b));
+            assert_eq!(out(&x / &y), a / b);
+            assert_eq!(out(&x % &y), a % b);
+            assert_eq!(out(&x & &y), a & b);
+            assert_eq!(out(&x | &y), a | b);
+            assert_eq!(out(&x ^ &y), a ^ b);
+            assert_eq!(out(!&x), !a);
+            assert_eq!(out(&x << s), a.checked_shl(s as u32).unwrap_or(0));
+            assert_eq!(out(&x >> s), a.checked_shr(s as u32).unwrap_or(0));
+            assert_eq!(bit(x.lt(&y)), a < b);
+            assert_eq!(bit(x.le(&y)), a <= b);
+            assert_eq!(bit(x.gt(&y)), a > b);
+            assert_eq!(bit(x.ge(&y)), a >= b);
+            assert_eq!(bit(x.eq(&y)), a == b);
+            assert!(!bit(x.ne(&x)));
+            assert_eq!(out(x.lt(&y).select(&x, &y)), a.min(b));
+            // Operators compose like ordinary arithmetic.
+            let z = (x.clone() + UInt::constant(&ctx, 3)) * y ^ x;
+            assert_eq!(out(z), (a.wrapping_add(3)).wrapping_mul(b) ^ a);
+        }
+    }
+
+    #[test]
+    fn int_ops() {
+        let mut rng = thread_rng();
+        let ctx = Context::new(Dummy::new());
+        for _ in 0..16 {
+            let (a, b) = (rng.gen::<i16>(), rng.gen_range(1, i16::max_value()));
+            let b = if rng.gen() { -b } else { b };
+            let s = rng.gen_range(0, 20);
+            let x = Int::<_, 16>::encode(&ctx, a as i128);
+            let y = Int::<_, 16>::encode(&ctx, b as i128);
+            let out = |z: Int<Dummy, 16>| z.output().unwrap().unwrap() as i16;
+            let bit = |z: Bit<Dummy>| z.output().unwrap().unwrap();
+            assert_eq!(out(&x + &y), a.wrapping_add(b));
+            assert_eq!(out(&x - &y), a.wrapping_sub(b));
+            assert_eq!(out(&x * &y), a.wrapping_mul(b));
+            assert_eq!(out(&x / &y), a / b);
+            assert_eq!(out(&x % &y), a % b);
+            assert_eq!(out(&x ^ &y), a ^ b);
+            assert_eq!(out(-&x), a.wrapping_neg());
+            assert_eq!(out(&x << s), a.checked_shl(s as u32).unwrap_or(0));
+            assert_eq!(out(&x >> s), a >> s.min(15));
+            assert_eq!(bit(x.lt(&y)), a < b);
+            assert_eq!(bit(x.le(&y)), a <= b);
+            assert_eq!(bit(x.gt(&y)), a > b);
+            assert_eq!(bit(x.ge(&y)), a >= b);
+            assert_eq!(bit(x.eq(&y)), a == b);
+            assert_eq!(out(x.gt(&y).select(&x, &y)), a.max(b));
+        }
+    }
+
+    #[test]
+    fn crt_ops() {
+        let mut rng = thread_rng();
+        let q = util::modulus_with_nprimes(5);
+        let ctx = Context::new(Dummy::new());
+        for _ in 0..16 {
+            // Comparisons need the difference to stay within half the modulus.
+            let (a, b) = (rng.gen_range(0, q / 4), rng.gen_range(0, q / 4));
+            let (a, b) = (if rng.gen() { q - 1 - a } else { a }, b);
+            let x = CrtInt::encode(&ctx, a, q);
+            let y = CrtInt::encode(&ctx, b, q);
+            let out = |z: CrtInt<Dummy>| z.output().unwrap().unwrap();
+            assert_eq!(out(&x + &y), (a + b) % q);
+            assert_eq!(out(&x - &y), (a + q - b) % q);
+            assert_eq!(out(&x * &y), a * b % q);
+            assert_eq!(out(-&x), (q - a) % q);
+            let signed = |v: u128| {
+                if v >= q / 2 {
+                    v as i128 - q as i128
+                } else {
+                    v as i128
+                }
+            };
+            let lt = x.lt(&y, "100%");
+            assert_eq!(lt.output().unwrap().unwrap(), signed(a) < signed(b));
+            assert!(x.eq(&x).output().unwrap().unwrap());
+            assert_eq!(
+                out(lt.select(&x, &y)),
+                if signed(a) < signed(b) { a } else { b }
+            );
+        }
+    }
+
+    #[test]
+    fn errors_propagate() {
+        let ctx = Context::new(Dummy::new());
+        let x = CrtInt::encode(&ctx, 1, util::modulus_with_nprimes(3));
+        let y = CrtInt::encode(&ctx, 1, util::modulus_with_nprimes(4));
+        let z = &x + &y;
+        let w = &z * &x;
+        assert!(w.output().is_err());
+        // The error is reported once, and later reads of unset values fail.
+        assert!(ctx.check().is_ok());
+        assert!(z.output().is_err());
+        assert_eq!(x.output().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn mixed_contexts_are_errors() {
+        let ctx = Context::new(Dummy::new());
+        let other = Context::new(Dummy::new());
+        let x = UInt::<_, 8>::encode(&ctx, 1);
+        let y = UInt::<_, 8>::encode(&other, 2);
+        assert!((&x + &y).output().is_err());
+        assert!(ctx.check().is_ok());
+        assert!(x.lt(&y).output().is_err());
+        let b = Bit::encode(&ctx, true);
+        assert!(b.select(&y, &x).output().is_err());
+        assert!(b.select(&Bit::encode(&other, false), &b).output().is_err());
+        let q = util::modulus_with_nprimes(3);
+        let z = CrtInt::encode(&other, 1, q);
+        assert!((&CrtInt::encode(&ctx, 1, q) * &z).output().is_err());
+        assert!(b.select(&z, &z).output().is_err());
+        // Nothing was recorded in the context of the other operand.
+        assert!(other.check().is_ok());
+        assert_eq!(x.output().unwrap(), Some(1));
+    }
+
+    #[test]
+    fn int_widths_outside_i128_are_errors() {
+        let ctx = Context::new(Dummy::new());
+        assert!(Int::<_, 0>::encode(&ctx, 0).output().is_err());
+        assert!(Int::<_, 129>::encode(&ctx, -1).output().is_err());
+        assert_eq!(Int::<_, 128>::encode(&ctx, -1).output().unwrap(), Some(-1));
+    }
+
+    #[test]
+    fn circuit_builder() {
+        let ctx = Context::new(CircuitBuilder::new());
+        {
+            let x = ctx.with(|b| b.bin_garbler_input(8));
+            let y = ctx.with(|b| b.bin_evaluator_input(8));
+            let x = UInt::<_, 8>::from_bundle(&ctx, x);
+            let y = UInt::<_, 8>::from_bundle(&ctx, y);
+            let z = (&x * &y) + x;
+            assert_eq!(z.output().unwrap(), None);
+        }
+        let circ = ctx.into_inner().ok().unwrap().finish();
+        let out = circ
+            .eval_plain(&util::u128_to_bits(7, 8), &util::u128_to_bits(50, 8))
+            .unwrap();
+        assert_eq!(util::u128_from_bits(&out), (7 * 50 + 7) % 256);
+    }
+}
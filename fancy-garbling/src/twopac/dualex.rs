@@ -0,0 +1,408 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of dual-execution two-party computation.
+//!
+//! Each party runs the computation twice, at once: as the garbler of one
+//! semi-honest execution and as the evaluator of the other. Before any output
+//! is returned, the parties run an equality check on the output wire-labels of
+//! both executions. A peer who garbles incorrectly is caught with
+//! `TwopacError::OutputMismatch`, at the cost of possibly learning one bit of
+//! the honest party's input (namely, whether the check passed).
+
+use crate::{
+    errors::TwopacError,
+    twopac::semihonest::{Evaluator, Garbler},
+    Fancy,
+    FancyInput,
+    FancyReveal,
+    HasModulus,
+    Wire,
+};
+use ocelot::ot::{Receiver as OtReceiver, Sender as OtSender};
+use rand::{CryptoRng, Rng, SeedableRng};
+use scuttlebutt::{
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    Block,
+    SemiHonest,
+};
+
+/// The role of a party in dual execution. The two parties must take different
+/// roles, which only determine the order of messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The party which sets up its garbler first.
+    Alice,
+    /// The party which sets up its evaluator first.
+    Bob,
+}
+
+/// A wire in dual execution, consisting of our wire as garbler of one
+/// execution and our wire as evaluator of the other.
+#[derive(Clone, Debug)]
+pub struct DualWire {
+    gb: Wire,
+    ev: Wire,
+}
+
+impl HasModulus for DualWire {
+    fn modulus(&self) -> u16 {
+        self.gb.modulus()
+    }
+}
+
+/// A party in dual-execution two-party computation.
+pub struct Party<C, RNG, OTS, OTR> {
+    garbler: Garbler<C, RNG, OTS>,
+    evaluator: Evaluator<C, RNG, OTR>,
+    channel: C,
+    role: Role,
+    rng: RNG,
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: OtSender<Msg = Block> + SemiHonest,
+        OTR: OtReceiver<Msg = Block> + SemiHonest,
+    > Party<C, RNG, OTS, OTR>
+{
+    /// Make a new `Party`.
+    pub fn new(channel: C, mut rng: RNG, role: Role) -> Result<Self, TwopacError> {
+        let rng_gb = RNG::from_seed(rng.gen());
+        let rng_ev = RNG::from_seed(rng.gen());
+        let (garbler, evaluator) = match role {
+            Role::Alice => {
+                let garbler = Garbler::new(channel.clone(), rng_gb)?;
+                let evaluator = Evaluator::new(channel.clone(), rng_ev)?;
+                (garbler, evaluator)
+            }
+            Role::Bob => {
+                let evaluator = Evaluator::new(channel.clone(), rng_ev)?;
+                let garbler = Garbler::new(channel.clone(), rng_gb)?;
+                (garbler, evaluator)
+            }
+        };
+        Ok(Party {
+            garbler,
+            evaluator,
+            channel,
+            role,
+            rng,
+        })
+    }
+
+    /// Get a reference to the internal channel.
+    pub fn get_channel(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    /// Check that both parties hold the same values. Each party commits to its
+    /// own values, then opens only the randomness of its commitment, which the
+    /// other party checks against its own values. Neither party sends its
+    /// values, so each learns only whether they match. Bob learns this first
+    /// and may abort instead of opening, in which case Alice gets an error
+    /// from the channel rather than `TwopacError::OutputMismatch`.
+    fn check_equal(&mut self, xs: &[Block]) -> Result<(), TwopacError> {
+        let commit = |seed: [u8; 32], xs: &[Block]| {
+            let mut com = ShaCommitment::new(seed);
+            for x in xs.iter() {
+                com.input(x.as_ref());
+            }
+            com.finish()
+        };
+        let seed = self.rng.gen::<[u8; 32]>();
+        let equal = match self.role {
+            Role::Alice => {
+                self.channel.write_bytes(&commit(seed, xs))?;
+                self.channel.flush()?;
+                let com = self.channel.read_vec(32)?;
+                self.channel.write_bytes(&seed)?;
+                self.channel.flush()?;
+                let mut theirs = [0u8; 32];
+                self.channel.read_bytes(&mut theirs)?;
+                commit(theirs, xs)[..] == com[..]
+            }
+            Role::Bob => {
+                let com = self.channel.read_vec(32)?;
+                self.channel.write_bytes(&commit(seed, xs))?;
+                self.channel.flush()?;
+                let mut theirs = [0u8; 32];
+                self.channel.read_bytes(&mut theirs)?;
+                self.channel.write_bytes(&seed)?;
+                self.channel.flush()?;
+                commit(theirs, xs)[..] == com[..]
+            }
+        };
+        if equal {
+            Ok(())
+        } else {
+            Err(TwopacError::OutputMismatch)
+        }
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: OtSender<Msg = Block> + SemiHonest,
+        OTR: OtReceiver<Msg = Block> + SemiHonest,
+    > FancyInput for Party<C, RNG, OTS, OTR>
+{
+    type Item = DualWire;
+    type Error = TwopacError;
+
+    /// Encode our inputs, sending wire-labels for our garbling and obtaining
+    /// wire-labels for the other party's garbling by OT.
+    fn encode_many(&mut self, vals: &[u16], moduli: &[u16]) -> Result<Vec<DualWire>, TwopacError> {
+        let gbs = self.garbler.encode_many(vals, moduli)?;
+        let evs = self.evaluator.encode_many(vals, moduli)?;
+        Ok(gbs
+            .into_iter()
+            .zip(evs)
+            .map(|(gb, ev)| DualWire { gb, ev })
+            .collect())
+    }
+
+    /// Receive the other party's inputs.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<DualWire>, TwopacError> {
+        let evs = self.evaluator.receive_many(moduli)?;
+        let gbs = self.garbler.receive_many(moduli)?;
+        Ok(gbs
+            .into_iter()
+            .zip(evs)
+            .map(|(gb, ev)| DualWire { gb, ev })
+            .collect())
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: OtSender<Msg = Block> + SemiHonest,
+        OTR: OtReceiver<Msg = Block> + SemiHonest,
+    > Fancy for Party<C, RNG, OTS, OTR>
+{
+    type Item = DualWire;
+    type Error = TwopacError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.constant(x, q)?;
+        self.channel.flush()?;
+        let ev = self.evaluator.constant(x, q)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn add(&mut self, x: &DualWire, y: &DualWire) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.add(&x.gb, &y.gb)?;
+        let ev = self.evaluator.add(&x.ev, &y.ev)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn sub(&mut self, x: &DualWire, y: &DualWire) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.sub(&x.gb, &y.gb)?;
+        let ev = self.evaluator.sub(&x.ev, &y.ev)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn cmul(&mut self, x: &DualWire, c: u16) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.cmul(&x.gb, c)?;
+        let ev = self.evaluator.cmul(&x.ev, c)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn mul(&mut self, x: &DualWire, y: &DualWire) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.mul(&x.gb, &y.gb)?;
+        self.channel.flush()?;
+        let ev = self.evaluator.mul(&x.ev, &y.ev)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn proj(
+        &mut self,
+        x: &DualWire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        let gb = self.garbler.proj(&x.gb, q, tt.clone())?;
+        self.channel.flush()?;
+        let ev = self.evaluator.proj(&x.ev, q, tt)?;
+        Ok(DualWire { gb, ev })
+    }
+
+    fn output(&mut self, x: &DualWire) -> Result<Option<u16>, Self::Error> {
+        let zs = self.outputs(std::slice::from_ref(x))?;
+        Ok(zs.map(|zs| zs[0]))
+    }
+
+    /// Output a slice of wires, after checking that both executions agree on
+    /// all of them. Alice sends the output decoding information for her
+    /// garbling before reading Bob's, so that neither party blocks writing.
+    fn outputs(&mut self, xs: &[DualWire]) -> Result<Option<Vec<u16>>, Self::Error> {
+        if self.role == Role::Alice {
+            for x in xs.iter() {
+                self.garbler.output(&x.gb)?;
+            }
+            self.channel.flush()?;
+        }
+        let mut zs = Vec::with_capacity(xs.len());
+        for x in xs.iter() {
+            zs.push(self.evaluator.output(&x.ev)?.unwrap());
+        }
+        if self.role == Role::Bob {
+            for x in xs.iter() {
+                self.garbler.output(&x.gb)?;
+            }
+            self.channel.flush()?;
+        }
+        // If both executions are honest, our garbler's label for the output
+        // and the label we evaluated match the other party's evaluated label
+        // and garbler's label, respectively.
+        let ts = xs
+            .iter()
+            .zip(zs.iter())
+            .map(|(x, z)| {
+                let delta = self.garbler.delta(x.modulus());
+                x.gb.plus(&delta.cmul(*z)).as_block() ^ x.ev.as_block()
+            })
+            .collect::<Vec<Block>>();
+        self.check_equal(&ts)?;
+        Ok(Some(zs))
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: OtSender<Msg = Block> + SemiHonest,
+        OTR: OtReceiver<Msg = Block> + SemiHonest,
+    > FancyReveal for Party<C, RNG, OTS, OTR>
+{
+    /// Reveal a wire to both parties. In dual execution, this is the same as
+    /// outputting it.
+    fn reveal(&mut self, x: &DualWire) -> Result<u16, TwopacError> {
+        let z = self.output(x)?;
+        Ok(z.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dummy::Dummy, util::RngExt, CrtBundle, CrtGadgets};
+    use itertools::Itertools;
+    use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::{unix_channel_pair, AesRng, UnixChannel};
+
+    type Dualex = Party<UnixChannel, AesRng, ChouOrlandiSender, ChouOrlandiReceiver>;
+
+    fn mul_add<F: Fancy>(
+        f: &mut F,
+        a: &F::Item,
+        b: &F::Item,
+    ) -> Result<Option<Vec<u16>>, F::Error> {
+        let c = f.mul(a, b)?;
+        let d = f.add(&c, a)?;
+        let one = f.constant(1, a.modulus())?;
+        let e = f.sub(&d, &one)?;
+        f.outputs(&[c, e])
+    }
+
+    #[test]
+    fn test_mul_add() {
+        let q = 5;
+        for a in 0..q {
+            for b in 0..q {
+                let (sender, receiver) = unix_channel_pair();
+                let handle = std::thread::spawn(move || {
+                    let mut alice = Dualex::new(sender, AesRng::new(), Role::Alice).unwrap();
+                    let x = alice.encode(a, q).unwrap();
+                    let y = alice.receive(q).unwrap();
+                    mul_add(&mut alice, &x, &y).unwrap().unwrap()
+                });
+                let mut bob = Dualex::new(receiver, AesRng::new(), Role::Bob).unwrap();
+                let x = bob.receive(q).unwrap();
+                let y = bob.encode(b, q).unwrap();
+                let zs = mul_add(&mut bob, &x, &y).unwrap().unwrap();
+                let target = vec![a * b % q, (a * b + a + q - 1) % q];
+                assert_eq!(zs, target);
+                assert_eq!(handle.join().unwrap(), target);
+            }
+        }
+    }
+
+    fn relu<F: FancyReveal>(b: &mut F, xs: &[CrtBundle<F::Item>]) -> Vec<u128> {
+        let zs = xs
+            .iter()
+            .map(|x| b.crt_relu(x, "100%", None).unwrap())
+            .collect_vec();
+        zs.iter().map(|z| b.crt_reveal(z).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_relu() {
+        let mut rng = rand::thread_rng();
+        let n = 4;
+        let q = crate::util::modulus_with_width(10);
+        let input = (0..n).map(|_| rng.gen_u128() % q).collect_vec();
+
+        let mut dummy = Dummy::new();
+        let dummy_input = input
+            .iter()
+            .map(|x| dummy.crt_encode(*x, q).unwrap())
+            .collect_vec();
+        let target = relu(&mut dummy, &dummy_input);
+
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut alice = Dualex::new(sender, AesRng::new(), Role::Alice).unwrap();
+            let xs = alice.crt_encode_many(&input, q).unwrap();
+            relu(&mut alice, &xs)
+        });
+        let mut bob = Dualex::new(receiver, AesRng::new(), Role::Bob).unwrap();
+        let xs = bob.crt_receive_many(n, q).unwrap();
+        assert_eq!(relu(&mut bob, &xs), target);
+        assert_eq!(handle.join().unwrap(), target);
+    }
+
+    #[test]
+    fn test_many_outputs() {
+        // Enough output decoding information to fill the channel's buffers.
+        let (n, q) = (1000, 113);
+        let outputs = move |f: &mut Dualex| {
+            let xs = (0..n).map(|i| f.constant(i % q, q).unwrap()).collect_vec();
+            f.outputs(&xs).unwrap().unwrap()
+        };
+        let target = (0..n).map(|i| i % q).collect_vec();
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut alice = Dualex::new(sender, AesRng::new(), Role::Alice).unwrap();
+            outputs(&mut alice)
+        });
+        let mut bob = Dualex::new(receiver, AesRng::new(), Role::Bob).unwrap();
+        assert_eq!(outputs(&mut bob), target);
+        assert_eq!(handle.join().unwrap(), target);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut alice = Dualex::new(sender, AesRng::new(), Role::Alice).unwrap();
+            alice.check_equal(&[Block::from(1)])
+        });
+        let mut bob = Dualex::new(receiver, AesRng::new(), Role::Bob).unwrap();
+        match bob.check_equal(&[Block::from(2)]) {
+            Err(TwopacError::OutputMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match handle.join().unwrap() {
+            Err(TwopacError::OutputMismatch) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+}
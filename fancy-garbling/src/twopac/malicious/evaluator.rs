@@ -0,0 +1,370 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use super::{
+    check_modulus,
+    check_value,
+    fpre::{select, AuthShare, Fpre, Party},
+    hash_row,
+    truth_table,
+    TruthTable,
+    BATCH_SIZE,
+};
+use crate::{
+    errors::{FancyError, TwopacError},
+    Fancy,
+    FancyInput,
+    FancyReveal,
+    HasModulus,
+};
+use ocelot::ot::{FixedKeyInitializer, RandomReceiver, RandomSender};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Block, Malicious, SemiHonest};
+use std::{cell::Cell, cmp::Ordering, rc::Rc};
+
+/// The masked value and wire-label of an AND gate output, filled in once the
+/// gate has been evaluated.
+type Slot = Rc<Cell<Option<(bool, Block)>>>;
+
+/// A wire held by the evaluator.
+#[derive(Clone, Debug)]
+pub struct EvaluatorWire {
+    /// The evaluator's share of the wire mask.
+    share: AuthShare,
+    /// The wire value XORed with the wire mask, not counting `pending`.
+    masked: bool,
+    /// The wire-label encoding the masked value, not counting `pending`.
+    label: Block,
+    /// The outputs of AND gates not yet evaluated which are XORed into this
+    /// wire, sorted by gate index.
+    pending: Vec<(usize, Slot)>,
+}
+
+impl EvaluatorWire {
+    fn new(share: AuthShare, masked: bool, label: Block) -> Self {
+        EvaluatorWire {
+            share,
+            masked,
+            label,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Fold the AND gate outputs which have since been evaluated into the
+    /// masked value and label.
+    fn settle(mut self) -> Self {
+        let (masked, label) = (&mut self.masked, &mut self.label);
+        self.pending.retain(|(_, slot)| match slot.get() {
+            Some((m, l)) => {
+                *masked ^= m;
+                *label ^= l;
+                false
+            }
+            None => true,
+        });
+        self
+    }
+
+    /// Get the masked value and label, which requires every AND gate this
+    /// wire depends on to have been evaluated.
+    fn resolve(&self) -> Result<(bool, Block), TwopacError> {
+        let wire = self.clone().settle();
+        if !wire.pending.is_empty() {
+            return Err(TwopacError::FancyError(FancyError::InvalidArg(
+                "wire depends on an AND gate of another evaluator".to_string(),
+            )));
+        }
+        Ok((wire.masked, wire.label))
+    }
+}
+
+impl HasModulus for EvaluatorWire {
+    fn modulus(&self) -> u16 {
+        2
+    }
+}
+
+/// Maliciously secure evaluator.
+pub struct Evaluator<C, RNG, OTS, OTR> {
+    fpre: Fpre<C, RNG, OTS, OTR>,
+    channel: C,
+    current_gate: usize,
+    /// AND gates not yet evaluated, as the gate index, the input wires, our
+    /// share of the output mask, and the slot for the output.
+    pending: Vec<(usize, EvaluatorWire, EvaluatorWire, AuthShare, Slot)>,
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > Evaluator<C, RNG, OTS, OTR>
+{
+    /// Make a new `Evaluator`.
+    pub fn new(channel: C, rng: RNG) -> Result<Self, TwopacError> {
+        let fpre = Fpre::new(channel.clone(), rng, Party::Evaluator)?;
+        Ok(Self {
+            fpre,
+            channel,
+            current_gate: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Generate enough authenticated bits and AND triples for `ninputs`
+    /// inputs and `nands` AND gates ahead of time. This does not depend on
+    /// the function, and must be matched by a call to `Garbler::preprocess`
+    /// with the same arguments.
+    pub fn preprocess(&mut self, ninputs: usize, nands: usize) -> Result<(), TwopacError> {
+        self.fpre.preprocess(ninputs + nands, nands)
+    }
+
+    /// Get a reference to the internal channel.
+    pub fn get_channel(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    fn random_shares(&mut self, n: usize) -> Result<Vec<AuthShare>, TwopacError> {
+        (0..n).map(|_| self.fpre.random_share()).collect()
+    }
+
+    /// Receive the garbled tables of the pending AND gates, and evaluate them
+    /// in order.
+    fn flush(&mut self) -> Result<(), TwopacError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let xys = pending
+            .iter()
+            .map(|(_, x, y, _, _)| (x.share, y.share))
+            .collect::<Vec<_>>();
+        let sigmas = self.fpre.and_many(&xys)?;
+        let mut tables = Vec::with_capacity(pending.len());
+        for _ in 0..pending.len() {
+            let mut rows = Vec::with_capacity(4);
+            for _ in 0..4 {
+                let bit = self.channel.read_bool()?;
+                let mac = self.channel.read_block()?;
+                let label = self.channel.read_block()?;
+                rows.push((bit, mac, label));
+            }
+            tables.push(rows);
+        }
+        for (((gate, x, y, share, slot), sigma), rows) in
+            pending.into_iter().zip(sigmas).zip(tables)
+        {
+            let (u, x_label) = x.resolve()?;
+            let (v, y_label) = y.resolve()?;
+            let (bit, mac, label) = rows[2 * u as usize + v as usize];
+            let rho = sigma ^ share ^ y.share.scale(u) ^ x.share.scale(v);
+            let rho = self.fpre.xor_const(rho, u & v);
+            let h = hash_row(gate, x_label, y_label);
+            let r = bit ^ h[2].lsb();
+            if mac ^ h[1] != rho.key ^ select(r, self.fpre.delta()) {
+                return Err(TwopacError::CheatingDetected(
+                    "invalid MAC in garbled table".to_string(),
+                ));
+            }
+            slot.set(Some((r ^ rho.bit, label ^ h[0] ^ rho.mac)));
+        }
+        Ok(())
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > FancyInput for Evaluator<C, RNG, OTS, OTR>
+{
+    type Item = EvaluatorWire;
+    type Error = TwopacError;
+
+    /// Receive garbler inputs. We open our share of each wire mask to the
+    /// garbler, who responds with the masked value and its label.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<EvaluatorWire>, TwopacError> {
+        for q in moduli.iter() {
+            check_modulus(*q)?;
+        }
+        let shares = self.random_shares(moduli.len())?;
+        self.fpre.send_shares(&shares)?;
+        shares
+            .into_iter()
+            .map(|share| {
+                let masked = self.channel.read_bool()?;
+                let label = self.channel.read_block()?;
+                Ok(EvaluatorWire::new(share, masked, label))
+            })
+            .collect()
+    }
+
+    /// Encode evaluator inputs. The garbler opens its share of each wire mask
+    /// to us, and we send the masked value in exchange for its label.
+    fn encode_many(
+        &mut self,
+        vals: &[u16],
+        moduli: &[u16],
+    ) -> Result<Vec<EvaluatorWire>, TwopacError> {
+        for (x, q) in vals.iter().zip(moduli.iter()) {
+            check_modulus(*q)?;
+            check_value(*x)?;
+        }
+        let shares = self.random_shares(vals.len())?;
+        let rs = self.fpre.receive_shares(&shares)?;
+        let masked = shares
+            .iter()
+            .zip(vals.iter())
+            .zip(rs)
+            .map(|((share, x), r)| (*x == 1) ^ share.bit ^ r)
+            .collect::<Vec<_>>();
+        for z in masked.iter() {
+            self.channel.write_bool(*z)?;
+        }
+        self.channel.flush()?;
+        let labels = self.channel.read_blocks(vals.len())?;
+        Ok(shares
+            .into_iter()
+            .zip(masked)
+            .zip(labels)
+            .map(|((share, masked), label)| EvaluatorWire::new(share, masked, label))
+            .collect())
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > Fancy for Evaluator<C, RNG, OTS, OTR>
+{
+    type Item = EvaluatorWire;
+    type Error = TwopacError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<Self::Item, Self::Error> {
+        check_modulus(q)?;
+        Ok(EvaluatorWire::new(
+            AuthShare::default(),
+            x % 2 == 1,
+            Block::default(),
+        ))
+    }
+
+    fn add(&mut self, x: &EvaluatorWire, y: &EvaluatorWire) -> Result<Self::Item, Self::Error> {
+        let (x, y) = (x.clone().settle(), y.clone().settle());
+        // Pending outputs appearing in both wires cancel out.
+        let mut pending = Vec::with_capacity(x.pending.len() + y.pending.len());
+        let (mut xs, mut ys) = (
+            x.pending.into_iter().peekable(),
+            y.pending.into_iter().peekable(),
+        );
+        loop {
+            let next = match (xs.peek(), ys.peek()) {
+                (Some(a), Some(b)) => a.0.cmp(&b.0),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => break,
+            };
+            match next {
+                Ordering::Less => pending.extend(xs.next()),
+                Ordering::Greater => pending.extend(ys.next()),
+                Ordering::Equal => {
+                    xs.next();
+                    ys.next();
+                }
+            }
+        }
+        Ok(EvaluatorWire {
+            share: x.share ^ y.share,
+            masked: x.masked ^ y.masked,
+            label: x.label ^ y.label,
+            pending,
+        })
+    }
+
+    fn sub(&mut self, x: &EvaluatorWire, y: &EvaluatorWire) -> Result<Self::Item, Self::Error> {
+        self.add(x, y)
+    }
+
+    fn cmul(&mut self, x: &EvaluatorWire, c: u16) -> Result<Self::Item, Self::Error> {
+        if c % 2 == 1 {
+            Ok(x.clone())
+        } else {
+            self.constant(0, 2)
+        }
+    }
+
+    fn mul(&mut self, x: &EvaluatorWire, y: &EvaluatorWire) -> Result<Self::Item, Self::Error> {
+        let gate = self.current_gate();
+        let share = self.fpre.random_share()?;
+        let slot = Slot::default();
+        self.pending
+            .push((gate, x.clone(), y.clone(), share, slot.clone()));
+        let out = EvaluatorWire {
+            pending: vec![(gate, slot)],
+            ..EvaluatorWire::new(share, false, Block::default())
+        };
+        if self.pending.len() == BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(out)
+    }
+
+    fn proj(
+        &mut self,
+        x: &EvaluatorWire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        check_modulus(q)?;
+        match truth_table(tt)? {
+            TruthTable::Constant(c) => self.constant(c, 2),
+            TruthTable::Identity => Ok(x.clone()),
+            TruthTable::Negation => Ok(EvaluatorWire {
+                masked: !x.masked,
+                ..x.clone()
+            }),
+        }
+    }
+
+    fn output(&mut self, x: &EvaluatorWire) -> Result<Option<u16>, Self::Error> {
+        self.flush()?;
+        let (masked, _) = x.resolve()?;
+        let r = self.fpre.receive_shares(&[x.share])?[0];
+        Ok(Some((masked ^ x.share.bit ^ r) as u16))
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > FancyReveal for Evaluator<C, RNG, OTS, OTR>
+{
+    /// Reveal a wire to both parties, by opening our share of the wire mask
+    /// and sending our label to the garbler.
+    fn reveal(&mut self, x: &EvaluatorWire) -> Result<u16, TwopacError> {
+        let val = self.output(x)?.unwrap();
+        let (_, label) = x.resolve()?;
+        self.fpre.send_shares(&[x.share])?;
+        self.channel.write_block(&label)?;
+        self.channel.flush()?;
+        Ok(val)
+    }
+}
+
+impl<C, RNG, OTS, OTR> SemiHonest for Evaluator<C, RNG, OTS, OTR> {}
+impl<C, RNG, OTS, OTR> Malicious for Evaluator<C, RNG, OTS, OTR> {}
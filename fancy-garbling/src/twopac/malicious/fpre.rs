@@ -0,0 +1,482 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Preprocessing for authenticated garbling: authenticated bits and
+//! authenticated AND triples (cf. WRK17, Sections 3 and 5).
+//!
+//! A bit `x = x_A ⊕ x_B` is shared as follows: each party holds its share of
+//! `x`, a MAC on that share under the other party's global key, and a key for
+//! the other party's share under its own global key. That is, `M_B[x_A] =
+//! K_B[x_A] ⊕ x_A Δ_B` and `M_A[x_B] = K_A[x_B] ⊕ x_B Δ_A`.
+
+use crate::errors::TwopacError;
+use itertools::Itertools;
+use ocelot::ot::{FixedKeyInitializer, RandomReceiver, RandomSender};
+use rand::{seq::SliceRandom, CryptoRng, Rng, SeedableRng};
+use scuttlebutt::{
+    cointoss,
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    AesRng,
+    Block,
+    Malicious,
+    AES_HASH,
+};
+
+/// The number of random authenticated bits generated at a time.
+const NBITS: usize = 4096;
+/// The number of authenticated AND triples generated at a time.
+const NTRIPLES: usize = 4096;
+/// The number of leaky AND triples combined into each authenticated AND
+/// triple. For batches of at least 3100 triples, buckets of size four give 40
+/// bits of statistical security.
+const BUCKET_SIZE: usize = 4;
+
+/// The role of a party in the protocol.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Party {
+    Garbler,
+    Evaluator,
+}
+
+/// One party's share of an authenticated bit.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) struct AuthShare {
+    /// Our share of the bit.
+    pub(super) bit: bool,
+    /// The MAC on our share, under the other party's global key.
+    pub(super) mac: Block,
+    /// Our key for the other party's share, under our global key.
+    pub(super) key: Block,
+}
+
+impl AuthShare {
+    /// Multiply the shared bit by the public bit `c`.
+    #[inline]
+    pub(super) fn scale(self, c: bool) -> Self {
+        if c {
+            self
+        } else {
+            AuthShare::default()
+        }
+    }
+}
+
+impl std::ops::BitXor for AuthShare {
+    type Output = Self;
+
+    #[inline]
+    fn bitxor(self, rhs: Self) -> Self {
+        AuthShare {
+            bit: self.bit ^ rhs.bit,
+            mac: self.mac ^ rhs.mac,
+            key: self.key ^ rhs.key,
+        }
+    }
+}
+
+/// Return `x` if `b` is set, and zero otherwise.
+#[inline]
+pub(super) fn select(b: bool, x: Block) -> Block {
+    if b {
+        x
+    } else {
+        Block::default()
+    }
+}
+
+/// The preprocessing functionality, as seen by one of the parties.
+pub(super) struct Fpre<C, RNG, OTS, OTR> {
+    channel: C,
+    party: Party,
+    delta: Block,
+    ot_sender: OTS,
+    ot_receiver: OTR,
+    rng: RNG,
+    bits: Vec<AuthShare>,
+    triples: Vec<[AuthShare; 3]>,
+    tweak: u128,
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > Fpre<C, RNG, OTS, OTR>
+{
+    /// Pick a global key and set up correlated OT in both directions.
+    pub(super) fn new(mut channel: C, mut rng: RNG, party: Party) -> Result<Self, TwopacError> {
+        let delta = rng.gen::<Block>();
+        let (ot_sender, ot_receiver) = match party {
+            Party::Garbler => {
+                let ot_sender = OTS::init_fixed_key(&mut channel, delta.into(), &mut rng)?;
+                let ot_receiver = OTR::init(&mut channel, &mut rng)?;
+                (ot_sender, ot_receiver)
+            }
+            Party::Evaluator => {
+                let ot_receiver = OTR::init(&mut channel, &mut rng)?;
+                let ot_sender = OTS::init_fixed_key(&mut channel, delta.into(), &mut rng)?;
+                (ot_sender, ot_receiver)
+            }
+        };
+        Ok(Fpre {
+            channel,
+            party,
+            delta,
+            ot_sender,
+            ot_receiver,
+            rng,
+            bits: Vec::new(),
+            triples: Vec::new(),
+            tweak: 0,
+        })
+    }
+
+    /// Our global key.
+    #[inline]
+    pub(super) fn delta(&self) -> Block {
+        self.delta
+    }
+
+    /// XOR the public bit `c` into a shared bit. By convention the garbler's
+    /// share absorbs the constant.
+    #[inline]
+    pub(super) fn xor_const(&self, x: AuthShare, c: bool) -> AuthShare {
+        match self.party {
+            Party::Garbler => AuthShare {
+                bit: x.bit ^ c,
+                ..x
+            },
+            Party::Evaluator => AuthShare {
+                key: x.key ^ select(c, self.delta),
+                ..x
+            },
+        }
+    }
+
+    /// Generate random authenticated bits and authenticated AND triples until
+    /// at least `nbits` bits and `ntriples` triples are available. This does
+    /// not depend on the function being computed, so it can be run before it
+    /// is known.
+    pub(super) fn preprocess(&mut self, nbits: usize, ntriples: usize) -> Result<(), TwopacError> {
+        if self.bits.len() < nbits {
+            let bits = self.random_shares(nbits - self.bits.len())?;
+            self.bits.extend(bits);
+        }
+        while self.triples.len() < ntriples {
+            self.generate_triples()?;
+        }
+        Ok(())
+    }
+
+    /// Get a random authenticated bit.
+    pub(super) fn random_share(&mut self) -> Result<AuthShare, TwopacError> {
+        if self.bits.is_empty() {
+            self.bits = self.random_shares(NBITS)?;
+        }
+        Ok(self.bits.pop().unwrap())
+    }
+
+    /// Compute the ANDs of the pairs of shared bits `xys`, using one
+    /// authenticated AND triple each. The values opened by Beaver's trick are
+    /// exchanged in a single round trip for the whole batch.
+    pub(super) fn and_many(
+        &mut self,
+        xys: &[(AuthShare, AuthShare)],
+    ) -> Result<Vec<AuthShare>, TwopacError> {
+        self.preprocess(0, xys.len())?;
+        let triples = self.triples.split_off(self.triples.len() - xys.len());
+        let des = xys
+            .iter()
+            .zip(triples.iter())
+            .flat_map(|((x, y), [a, b, _])| vec![*x ^ *a, *y ^ *b])
+            .collect_vec();
+        let des = self.open(&des)?;
+        Ok(triples
+            .iter()
+            .zip(des.chunks(2))
+            .map(|([a, b, c], de)| {
+                let (d, e) = (de[0], de[1]);
+                self.xor_const(*c ^ b.scale(d) ^ a.scale(e), d & e)
+            })
+            .collect())
+    }
+
+    /// Send our shares of `xs`, alongside their MACs, to the other party.
+    pub(super) fn send_shares(&mut self, xs: &[AuthShare]) -> Result<(), TwopacError> {
+        for x in xs.iter() {
+            self.channel.write_bool(x.bit)?;
+            self.channel.write_block(&x.mac)?;
+        }
+        self.channel.flush()?;
+        Ok(())
+    }
+
+    /// Receive the other party's shares of `xs`, checking their MACs.
+    pub(super) fn receive_shares(&mut self, xs: &[AuthShare]) -> Result<Vec<bool>, TwopacError> {
+        xs.iter()
+            .map(|x| {
+                let bit = self.channel.read_bool()?;
+                let mac = self.channel.read_block()?;
+                self.check_mac(x, bit, mac)?;
+                Ok(bit)
+            })
+            .collect()
+    }
+
+    /// Open the shared bits `xs` to both parties.
+    pub(super) fn open(&mut self, xs: &[AuthShare]) -> Result<Vec<bool>, TwopacError> {
+        let theirs = match self.party {
+            Party::Garbler => {
+                self.send_shares(xs)?;
+                self.receive_shares(xs)?
+            }
+            Party::Evaluator => {
+                let theirs = self.receive_shares(xs)?;
+                self.send_shares(xs)?;
+                theirs
+            }
+        };
+        Ok(xs.iter().zip(theirs).map(|(x, b)| x.bit ^ b).collect())
+    }
+
+    fn check_mac(&self, x: &AuthShare, bit: bool, mac: Block) -> Result<(), TwopacError> {
+        if mac == x.key ^ select(bit, self.delta) {
+            Ok(())
+        } else {
+            Err(TwopacError::CheatingDetected(
+                "invalid MAC on authenticated bit".to_string(),
+            ))
+        }
+    }
+
+    fn hash(&self, tweak: u128, x: Block) -> Block {
+        AES_HASH.tccr_hash(Block::from(tweak), x)
+    }
+
+    /// Send `mine` and receive the other party's message of the same length.
+    /// The garbler sends first.
+    fn exchange_blocks(&mut self, mine: &[Block]) -> Result<Vec<Block>, TwopacError> {
+        let theirs = match self.party {
+            Party::Garbler => {
+                for b in mine.iter() {
+                    self.channel.write_block(b)?;
+                }
+                self.channel.flush()?;
+                self.channel.read_blocks(mine.len())?
+            }
+            Party::Evaluator => {
+                let theirs = self.channel.read_blocks(mine.len())?;
+                for b in mine.iter() {
+                    self.channel.write_block(b)?;
+                }
+                self.channel.flush()?;
+                theirs
+            }
+        };
+        Ok(theirs)
+    }
+
+    /// Like `exchange_blocks`, for bytes.
+    fn exchange_bytes(&mut self, mine: &[u8]) -> Result<Vec<u8>, TwopacError> {
+        let theirs = match self.party {
+            Party::Garbler => {
+                self.channel.write_bytes(mine)?;
+                self.channel.flush()?;
+                self.channel.read_vec(mine.len())?
+            }
+            Party::Evaluator => {
+                let theirs = self.channel.read_vec(mine.len())?;
+                self.channel.write_bytes(mine)?;
+                self.channel.flush()?;
+                theirs
+            }
+        };
+        Ok(theirs)
+    }
+
+    /// Generate `n` random authenticated bits using correlated OT in both
+    /// directions.
+    fn random_shares(&mut self, n: usize) -> Result<Vec<AuthShare>, TwopacError> {
+        let bits = (0..n).map(|_| self.rng.gen::<bool>()).collect_vec();
+        let (keys, macs) = match self.party {
+            Party::Garbler => {
+                let keys = self
+                    .ot_sender
+                    .send_random(&mut self.channel, n, &mut self.rng)?;
+                let macs =
+                    self.ot_receiver
+                        .receive_random(&mut self.channel, &bits, &mut self.rng)?;
+                (keys, macs)
+            }
+            Party::Evaluator => {
+                let macs =
+                    self.ot_receiver
+                        .receive_random(&mut self.channel, &bits, &mut self.rng)?;
+                let keys = self
+                    .ot_sender
+                    .send_random(&mut self.channel, n, &mut self.rng)?;
+                (keys, macs)
+            }
+        };
+        Ok(bits
+            .into_iter()
+            .zip(macs)
+            .zip(keys)
+            .map(|((bit, mac), (key, _))| AuthShare { bit, mac, key })
+            .collect())
+    }
+
+    /// Generate `n` leaky AND triples `(x, y, z)` with `z = x ∧ y`. A cheating
+    /// party may learn the other party's share of `x`, at the risk of being
+    /// caught.
+    fn leaky_triples(&mut self, n: usize) -> Result<Vec<[AuthShare; 3]>, TwopacError> {
+        let shares = self.random_shares(3 * n)?;
+        let (xs, rest) = shares.split_at(n);
+        let (ys, rs) = rest.split_at(n);
+        let delta = self.delta;
+        let tweak = self.tweak;
+        self.tweak += 2 * n as u128;
+
+        // Half-authenticated AND: compute `v` such that `v_A ⊕ v_B = x_A y_B ⊕
+        // x_B y_A`.
+        let ss = (0..n).map(|_| self.rng.gen::<bool>()).collect_vec();
+        let msg = (0..n)
+            .map(|j| {
+                let t = tweak + 2 * j as u128;
+                let h0 = self.hash(t, xs[j].key).lsb() ^ ss[j];
+                let h1 = self.hash(t, xs[j].key ^ delta).lsb() ^ ss[j] ^ ys[j].bit;
+                h0 as u8 | (h1 as u8) << 1
+            })
+            .collect_vec();
+        let theirs = self.exchange_bytes(&msg)?;
+        let zs = (0..n)
+            .map(|j| {
+                let t = tweak + 2 * j as u128;
+                let h = (theirs[j] >> xs[j].bit as u8) & 1 == 1;
+                let v = ss[j] ^ h ^ self.hash(t, xs[j].mac).lsb();
+                v ^ (xs[j].bit & ys[j].bit)
+            })
+            .collect_vec();
+
+        // Authenticate `z` using the random authenticated bit `r`.
+        let ds = (0..n).map(|j| (rs[j].bit ^ zs[j]) as u8).collect_vec();
+        let theirs = self.exchange_bytes(&ds)?;
+        let zs = (0..n)
+            .map(|j| AuthShare {
+                bit: zs[j],
+                mac: rs[j].mac,
+                key: rs[j].key ^ select(theirs[j] == 1, delta),
+            })
+            .collect_vec();
+
+        // Check that `(x_A ⊕ x_B)(y_A ⊕ y_B)(Δ_A ⊕ Δ_B) = (z_A ⊕ z_B)(Δ_A ⊕
+        // Δ_B)`, where the parties' shares of `y (Δ_A ⊕ Δ_B)` are given by
+        // `Φ := y Δ ⊕ K[y] ⊕ M[y]`, and similarly for `z`.
+        let phis = ys
+            .iter()
+            .map(|y| select(y.bit, delta) ^ y.key ^ y.mac)
+            .collect_vec();
+        let us = (0..n)
+            .map(|j| {
+                let t = tweak + 2 * j as u128 + 1;
+                self.hash(t, xs[j].key) ^ self.hash(t, xs[j].key ^ delta) ^ phis[j]
+            })
+            .collect_vec();
+        let theirs = self.exchange_blocks(&us)?;
+        let omegas = (0..n)
+            .map(|j| {
+                let t = tweak + 2 * j as u128 + 1;
+                let x = xs[j].bit;
+                select(x, phis[j])
+                    ^ self.hash(t, xs[j].key)
+                    ^ self.hash(t, xs[j].mac)
+                    ^ select(x, theirs[j])
+                    ^ select(zs[j].bit, delta)
+                    ^ zs[j].key
+                    ^ zs[j].mac
+            })
+            .collect_vec();
+        self.check_equal(&omegas)?;
+
+        Ok((0..n).map(|j| [xs[j], ys[j], zs[j]]).collect())
+    }
+
+    /// Check that both parties hold the same values. The garbler commits to
+    /// its values before seeing the evaluator's.
+    fn check_equal(&mut self, xs: &[Block]) -> Result<(), TwopacError> {
+        let commit = |seed: [u8; 32]| {
+            let mut com = ShaCommitment::new(seed);
+            for x in xs.iter() {
+                com.input(x.as_ref());
+            }
+            com.finish()
+        };
+        match self.party {
+            Party::Garbler => {
+                let seed = self.rng.gen::<[u8; 32]>();
+                self.channel.write_bytes(&commit(seed))?;
+                self.channel.flush()?;
+                let theirs = self.channel.read_blocks(xs.len())?;
+                if theirs != xs {
+                    return Err(TwopacError::CheatingDetected(
+                        "AND triple check failed".to_string(),
+                    ));
+                }
+                self.channel.write_bytes(&seed)?;
+                self.channel.flush()?;
+            }
+            Party::Evaluator => {
+                let com = self.channel.read_vec(32)?;
+                for x in xs.iter() {
+                    self.channel.write_block(x)?;
+                }
+                self.channel.flush()?;
+                let mut seed = [0u8; 32];
+                self.channel.read_bytes(&mut seed)?;
+                if commit(seed)[..] != com[..] {
+                    return Err(TwopacError::CheatingDetected(
+                        "AND triple check failed".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Generate a batch of authenticated AND triples by randomly bucketing
+    /// leaky triples and combining each bucket.
+    fn generate_triples(&mut self) -> Result<(), TwopacError> {
+        let mut leaky = self.leaky_triples(NTRIPLES * BUCKET_SIZE)?;
+
+        let seed = self.rng.gen::<Block>();
+        let seed = match self.party {
+            Party::Garbler => cointoss::send(&mut self.channel, &[seed])?,
+            Party::Evaluator => cointoss::receive(&mut self.channel, &[seed])?,
+        };
+        leaky.shuffle(&mut AesRng::from_seed(seed[0]));
+
+        // Combining `(x, y, z)` and `(x', y', z')` gives `(x ⊕ x', y, z ⊕ z' ⊕
+        // d x')` where `d = y ⊕ y'`. Since `y` never changes, all the `d`s of
+        // a batch can be opened at once.
+        let ds = leaky
+            .chunks(BUCKET_SIZE)
+            .flat_map(|bucket| bucket[1..].iter().map(move |t| bucket[0][1] ^ t[1]))
+            .collect_vec();
+        let mut ds = self.open(&ds)?.into_iter();
+        for bucket in leaky.chunks(BUCKET_SIZE) {
+            let [mut x, y, mut z] = bucket[0];
+            for t in bucket[1..].iter() {
+                let d = ds.next().unwrap();
+                x = x ^ t[0];
+                z = z ^ t[2] ^ t[0].scale(d);
+            }
+            self.triples.push([x, y, z]);
+        }
+        Ok(())
+    }
+}
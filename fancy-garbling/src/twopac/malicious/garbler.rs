@@ -0,0 +1,288 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use super::{
+    check_modulus,
+    check_value,
+    fpre::{select, AuthShare, Fpre, Party},
+    hash_row,
+    truth_table,
+    TruthTable,
+    BATCH_SIZE,
+};
+use crate::{errors::TwopacError, Fancy, FancyInput, FancyReveal, HasModulus};
+use ocelot::ot::{FixedKeyInitializer, RandomReceiver, RandomSender};
+use rand::{CryptoRng, Rng, SeedableRng};
+use scuttlebutt::{AbstractChannel, Block, Malicious, SemiHonest};
+
+/// A wire held by the garbler.
+#[derive(Clone, Debug)]
+pub struct GarblerWire {
+    /// The garbler's share of the wire mask.
+    share: AuthShare,
+    /// The wire-label encoding a masked value of zero.
+    label: Block,
+}
+
+impl HasModulus for GarblerWire {
+    fn modulus(&self) -> u16 {
+        2
+    }
+}
+
+/// Maliciously secure garbler.
+pub struct Garbler<C, RNG, OTS, OTR> {
+    fpre: Fpre<C, RNG, OTS, OTR>,
+    channel: C,
+    rng: RNG,
+    current_gate: usize,
+    /// AND gates whose garbled tables have not been sent yet, as the gate
+    /// index, the input wires, and the output wire.
+    pending: Vec<(usize, GarblerWire, GarblerWire, GarblerWire)>,
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > Garbler<C, RNG, OTS, OTR>
+{
+    /// Make a new `Garbler`.
+    pub fn new(channel: C, mut rng: RNG) -> Result<Self, TwopacError> {
+        let fpre = Fpre::new(channel.clone(), RNG::from_seed(rng.gen()), Party::Garbler)?;
+        Ok(Self {
+            fpre,
+            channel,
+            rng,
+            current_gate: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Generate enough authenticated bits and AND triples for `ninputs`
+    /// inputs and `nands` AND gates ahead of time. This does not depend on
+    /// the function, and must be matched by a call to
+    /// `Evaluator::preprocess` with the same arguments.
+    pub fn preprocess(&mut self, ninputs: usize, nands: usize) -> Result<(), TwopacError> {
+        self.fpre.preprocess(ninputs + nands, nands)
+    }
+
+    /// Get a reference to the internal channel.
+    pub fn get_channel(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    fn random_wire(&mut self) -> Result<GarblerWire, TwopacError> {
+        Ok(GarblerWire {
+            share: self.fpre.random_share()?,
+            label: self.rng.gen(),
+        })
+    }
+
+    /// Garble the pending AND gates, and send their garbled tables.
+    fn flush(&mut self) -> Result<(), TwopacError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let xys = pending
+            .iter()
+            .map(|(_, x, y, _)| (x.share, y.share))
+            .collect::<Vec<_>>();
+        let sigmas = self.fpre.and_many(&xys)?;
+        let delta = self.fpre.delta();
+        for ((gate, x, y, out), sigma) in pending.into_iter().zip(sigmas) {
+            for u in [false, true].iter() {
+                for v in [false, true].iter() {
+                    let rho = sigma ^ out.share ^ y.share.scale(*u) ^ x.share.scale(*v);
+                    let rho = self.fpre.xor_const(rho, u & v);
+                    let h = hash_row(
+                        gate,
+                        x.label ^ select(*u, delta),
+                        y.label ^ select(*v, delta),
+                    );
+                    self.channel.write_bool(rho.bit ^ h[2].lsb())?;
+                    self.channel.write_block(&(rho.mac ^ h[1]))?;
+                    self.channel
+                        .write_block(&(out.label ^ select(rho.bit, delta) ^ rho.key ^ h[0]))?;
+                }
+            }
+        }
+        self.channel.flush()?;
+        Ok(())
+    }
+
+    /// Send the label encoding the masked value `z`.
+    fn send_label(&mut self, wire: &GarblerWire, z: bool) -> Result<(), TwopacError> {
+        let label = wire.label ^ select(z, self.fpre.delta());
+        self.channel.write_block(&label)?;
+        Ok(())
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > FancyInput for Garbler<C, RNG, OTS, OTR>
+{
+    type Item = GarblerWire;
+    type Error = TwopacError;
+
+    /// Encode garbler inputs. The evaluator opens its share of each wire mask
+    /// to us, and we send the masked value alongside its label.
+    fn encode_many(
+        &mut self,
+        vals: &[u16],
+        moduli: &[u16],
+    ) -> Result<Vec<GarblerWire>, TwopacError> {
+        for (x, q) in vals.iter().zip(moduli.iter()) {
+            check_modulus(*q)?;
+            check_value(*x)?;
+        }
+        let wires = (0..vals.len())
+            .map(|_| self.random_wire())
+            .collect::<Result<Vec<_>, _>>()?;
+        let shares = wires.iter().map(|w| w.share).collect::<Vec<_>>();
+        let ss = self.fpre.receive_shares(&shares)?;
+        for ((wire, x), s) in wires.iter().zip(vals.iter()).zip(ss) {
+            let z = (*x == 1) ^ wire.share.bit ^ s;
+            self.channel.write_bool(z)?;
+            self.send_label(wire, z)?;
+        }
+        self.channel.flush()?;
+        Ok(wires)
+    }
+
+    /// Receive evaluator inputs. We open our share of each wire mask to the
+    /// evaluator, who responds with the masked value.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<GarblerWire>, TwopacError> {
+        for q in moduli.iter() {
+            check_modulus(*q)?;
+        }
+        let wires = moduli
+            .iter()
+            .map(|_| self.random_wire())
+            .collect::<Result<Vec<_>, _>>()?;
+        let shares = wires.iter().map(|w| w.share).collect::<Vec<_>>();
+        self.fpre.send_shares(&shares)?;
+        for wire in wires.iter() {
+            let z = self.channel.read_bool()?;
+            self.send_label(wire, z)?;
+        }
+        self.channel.flush()?;
+        Ok(wires)
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > Fancy for Garbler<C, RNG, OTS, OTR>
+{
+    type Item = GarblerWire;
+    type Error = TwopacError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<Self::Item, Self::Error> {
+        check_modulus(q)?;
+        Ok(GarblerWire {
+            share: AuthShare::default(),
+            label: select(x % 2 == 1, self.fpre.delta()),
+        })
+    }
+
+    fn add(&mut self, x: &GarblerWire, y: &GarblerWire) -> Result<Self::Item, Self::Error> {
+        Ok(GarblerWire {
+            share: x.share ^ y.share,
+            label: x.label ^ y.label,
+        })
+    }
+
+    fn sub(&mut self, x: &GarblerWire, y: &GarblerWire) -> Result<Self::Item, Self::Error> {
+        self.add(x, y)
+    }
+
+    fn cmul(&mut self, x: &GarblerWire, c: u16) -> Result<Self::Item, Self::Error> {
+        if c % 2 == 1 {
+            Ok(x.clone())
+        } else {
+            self.constant(0, 2)
+        }
+    }
+
+    fn mul(&mut self, x: &GarblerWire, y: &GarblerWire) -> Result<Self::Item, Self::Error> {
+        let gate = self.current_gate();
+        let out = self.random_wire()?;
+        self.pending.push((gate, x.clone(), y.clone(), out.clone()));
+        if self.pending.len() == BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(out)
+    }
+
+    fn proj(
+        &mut self,
+        x: &GarblerWire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        check_modulus(q)?;
+        match truth_table(tt)? {
+            TruthTable::Constant(c) => self.constant(c, 2),
+            TruthTable::Identity => Ok(x.clone()),
+            TruthTable::Negation => Ok(GarblerWire {
+                share: x.share,
+                label: x.label ^ self.fpre.delta(),
+            }),
+        }
+    }
+
+    fn output(&mut self, x: &GarblerWire) -> Result<Option<u16>, Self::Error> {
+        self.flush()?;
+        self.fpre.send_shares(&[x.share])?;
+        Ok(None)
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng + SeedableRng<Seed = Block>,
+        OTS: FixedKeyInitializer + RandomSender<Msg = Block> + Malicious,
+        OTR: RandomReceiver<Msg = Block> + Malicious,
+    > FancyReveal for Garbler<C, RNG, OTS, OTR>
+{
+    /// Reveal a wire to both parties. The evaluator opens its share of the
+    /// wire mask and sends its label, which must be one of the two valid
+    /// labels for the wire.
+    fn reveal(&mut self, x: &GarblerWire) -> Result<u16, TwopacError> {
+        self.output(x)?;
+        let s = self.fpre.receive_shares(&[x.share])?[0];
+        let label = self.channel.read_block()?;
+        let z = if label == x.label {
+            false
+        } else if label == x.label ^ self.fpre.delta() {
+            true
+        } else {
+            return Err(TwopacError::CheatingDetected(
+                "invalid output wire-label".to_string(),
+            ));
+        };
+        Ok((z ^ x.share.bit ^ s) as u16)
+    }
+}
+
+impl<C, RNG, OTS, OTR> SemiHonest for Garbler<C, RNG, OTS, OTR> {}
+impl<C, RNG, OTS, OTR> Malicious for Garbler<C, RNG, OTS, OTR> {}
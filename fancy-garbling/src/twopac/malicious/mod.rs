@@ -0,0 +1,230 @@
+// -*- mode: rust; -*-
+//
+// This file is part of twopac.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Implementation of maliciously secure two-party computation, using the
+//! authenticated garbling protocol of Wang, Ranellucci, and Katz (WRK17).
+//!
+//! The garbler and evaluator jointly generate authenticated wire masks and AND
+//! triples using correlated oblivious transfer, such as
+//! `ocelot::ot::{KosDeltaSender, KosDeltaReceiver}`. The triples are produced
+//! in bulk from leaky AND triples by bucketing, independently of the function,
+//! and can be generated up front with `preprocess`; otherwise they are
+//! generated on demand.
+//!
+//! AND gates are garbled in batches of `BATCH_SIZE`: the parties combine the
+//! triples with the masks of every AND gate in the batch in a single round
+//! trip, after which the garbler sends all of their garbled tables. The
+//! garbled table of each AND gate carries the garbler's share of the output
+//! mask alongside a MAC, which the evaluator checks before using the row. Any
+//! deviation from the protocol results in `TwopacError::CheatingDetected`.
+//!
+//! Only circuits over `Z_2` are supported: `add`, `sub`, `cmul`, and `proj`
+//! are free, and `mul` computes an authenticated AND gate.
+
+mod evaluator;
+mod fpre;
+mod garbler;
+
+pub use evaluator::{Evaluator, EvaluatorWire};
+pub use garbler::{Garbler, GarblerWire};
+
+use crate::errors::FancyError;
+use scuttlebutt::{Block, AES_HASH};
+
+/// The number of AND gates garbled together. Outputs and reveals garble any
+/// gates still pending first.
+const BATCH_SIZE: usize = 1024;
+
+/// Check that `q` is two, the only supported modulus.
+fn check_modulus(q: u16) -> Result<(), FancyError> {
+    if q != 2 {
+        return Err(FancyError::InvalidArgMod { got: q, needed: 2 });
+    }
+    Ok(())
+}
+
+/// Check that `x` is a bit.
+fn check_value(x: u16) -> Result<(), FancyError> {
+    if x > 1 {
+        return Err(FancyError::InvalidArg(format!(
+            "input value {} is not a bit",
+            x
+        )));
+    }
+    Ok(())
+}
+
+/// The unary functions on `Z_2`.
+enum TruthTable {
+    Constant(u16),
+    Identity,
+    Negation,
+}
+
+fn truth_table(tt: Option<Vec<u16>>) -> Result<TruthTable, FancyError> {
+    let tt = tt.ok_or(FancyError::NoTruthTable)?;
+    match tt[..] {
+        [a, b] if a == b && a < 2 => Ok(TruthTable::Constant(a)),
+        [0, 1] => Ok(TruthTable::Identity),
+        [1, 0] => Ok(TruthTable::Negation),
+        _ => Err(FancyError::InvalidTruthTable),
+    }
+}
+
+/// Hash the input wire-labels of AND gate `gate` to obtain the masks for the
+/// output label, the MAC, and the masked bit of a garbled row.
+fn hash_row(gate: usize, a: Block, b: Block) -> [Block; 3] {
+    let tweak = |k: u128, side: u128| Block::from(1 << 127 | (gate as u128) << 8 | k << 1 | side);
+    let mut h = [Block::default(); 3];
+    for (k, h) in h.iter_mut().enumerate() {
+        let k = k as u128;
+        *h = AES_HASH.tccr_hash(tweak(k, 0), a) ^ AES_HASH.tccr_hash(tweak(k, 1), b);
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        circuit::Circuit,
+        errors::TwopacError,
+        util,
+        BinaryGadgets,
+        Fancy,
+        FancyInput,
+        FancyReveal,
+    };
+    use itertools::Itertools;
+    use ocelot::ot::{KosDeltaReceiver, KosDeltaSender};
+    use rand::Rng;
+    use scuttlebutt::{unix_channel_pair, AesRng, UnixChannel};
+
+    type Gb = Garbler<UnixChannel, AesRng, KosDeltaSender, KosDeltaReceiver>;
+    type Ev = Evaluator<UnixChannel, AesRng, KosDeltaSender, KosDeltaReceiver>;
+
+    fn gates<F: Fancy>(f: &mut F, x: &F::Item, y: &F::Item) -> Result<Vec<Option<u16>>, F::Error> {
+        let and = f.and(x, y)?;
+        let xor = f.xor(x, y)?;
+        let or = f.or(x, y)?;
+        let nand = f.negate(&and)?;
+        let one = f.constant(1, 2)?;
+        let t = f.and(&xor, &one)?;
+        let z = f.mul(&t, &or)?;
+        [and, xor, or, nand, z]
+            .iter()
+            .map(|w| f.output(w))
+            .collect()
+    }
+
+    #[test]
+    fn test_gates() {
+        for a in 0..2 {
+            for b in 0..2 {
+                let (sender, receiver) = unix_channel_pair();
+                let handle = std::thread::spawn(move || {
+                    let mut gb = Gb::new(sender, AesRng::new()).unwrap();
+                    let x = gb.encode(a, 2).unwrap();
+                    let y = gb.receive(2).unwrap();
+                    gates(&mut gb, &x, &y).unwrap();
+                });
+                let mut ev = Ev::new(receiver, AesRng::new()).unwrap();
+                let x = ev.receive(2).unwrap();
+                let y = ev.encode(b, 2).unwrap();
+                let outputs = gates(&mut ev, &x, &y).unwrap();
+                let outputs = outputs.into_iter().map(Option::unwrap).collect_vec();
+                assert_eq!(outputs, vec![a & b, a ^ b, a | b, 1 - (a & b), a ^ b]);
+                handle.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_reveal() {
+        let mut rng = AesRng::new();
+        let n = 16;
+        let x = rng.gen::<u128>() % (1 << n);
+        let y = rng.gen::<u128>() % (1 << n);
+        let target = (x + y) % (1 << n);
+
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut gb = Gb::new(sender, AesRng::new()).unwrap();
+            let a = gb.bin_encode(x, n).unwrap();
+            let b = gb.bin_receive(n).unwrap();
+            let c = gb.bin_addition_no_carry(&a, &b).unwrap();
+            let bits = c.iter().map(|w| gb.reveal(w).unwrap()).collect_vec();
+            assert_eq!(util::u128_from_bits(&bits), target);
+        });
+        let mut ev = Ev::new(receiver, AesRng::new()).unwrap();
+        let a = ev.bin_receive(n).unwrap();
+        let b = ev.bin_encode(y, n).unwrap();
+        let c = ev.bin_addition_no_carry(&a, &b).unwrap();
+        let bits = c.iter().map(|w| ev.reveal(w).unwrap()).collect_vec();
+        assert_eq!(util::u128_from_bits(&bits), target);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_aes() {
+        let circ = Circuit::parse("circuits/AES-non-expanded.txt").unwrap();
+        let mut rng = AesRng::new();
+        let key = (0..128).map(|_| rng.gen::<bool>() as u16).collect_vec();
+        let pt = (0..128).map(|_| rng.gen::<bool>() as u16).collect_vec();
+        let target = circ.eval_plain(&key, &pt).unwrap();
+
+        let circ_ = circ.clone();
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut gb = Gb::new(sender, AesRng::new()).unwrap();
+            gb.preprocess(256, circ_.num_nonfree_gates).unwrap();
+            let xs = gb.encode_many(&key, &[2; 128]).unwrap();
+            let ys = gb.receive_many(&[2; 128]).unwrap();
+            circ_.eval(&mut gb, &xs, &ys).unwrap();
+        });
+        let mut ev = Ev::new(receiver, AesRng::new()).unwrap();
+        ev.preprocess(256, circ.num_nonfree_gates).unwrap();
+        let xs = ev.receive_many(&[2; 128]).unwrap();
+        let ys = ev.encode_many(&pt, &[2; 128]).unwrap();
+        let result = circ.eval(&mut ev, &xs, &ys).unwrap().unwrap();
+        assert_eq!(result, target);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_bad_mac() {
+        type Fp = fpre::Fpre<UnixChannel, AesRng, KosDeltaSender, KosDeltaReceiver>;
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut fpre = Fp::new(sender, AesRng::new(), fpre::Party::Garbler).unwrap();
+            let mut x = fpre.random_share().unwrap();
+            x.bit = !x.bit;
+            fpre.send_shares(&[x]).unwrap();
+        });
+        let mut fpre = Fp::new(receiver, AesRng::new(), fpre::Party::Evaluator).unwrap();
+        let x = fpre.random_share().unwrap();
+        match fpre.receive_shares(&[x]) {
+            Err(TwopacError::CheatingDetected(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_bad_modulus() {
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut gb = Gb::new(sender, AesRng::new()).unwrap();
+            assert!(gb.constant(1, 3).is_err());
+        });
+        let mut ev = Ev::new(receiver, AesRng::new()).unwrap();
+        match ev.encode(0, 3) {
+            Err(TwopacError::FancyError(FancyError::InvalidArgMod { got: 3, needed: 2 })) => (),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+        handle.join().unwrap();
+    }
+}
@@ -6,4 +6,6 @@
 
 //! Implementations of two-party secure computation.
 
+pub mod dualex;
+pub mod malicious;
 pub mod semihonest;
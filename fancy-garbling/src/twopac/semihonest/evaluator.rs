@@ -124,6 +124,16 @@ impl<C: AbstractChannel, RNG, OT> Fancy for Evaluator<C, RNG, OT> {
         self.evaluator.proj(&x, q, tt).map_err(Self::Error::from)
     }
 
+    fn proj2(
+        &mut self,
+        x: &Wire,
+        y: &Wire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        self.evaluator.proj2(x, y, q, tt).map_err(Self::Error::from)
+    }
+
     fn output(&mut self, x: &Wire) -> Result<Option<u16>, Self::Error> {
         self.evaluator.output(&x).map_err(Self::Error::from)
     }
@@ -145,6 +145,16 @@ impl<C: AbstractChannel, RNG: CryptoRng + Rng, OT> Fancy for Garbler<C, RNG, OT>
         self.garbler.proj(x, q, tt).map_err(Self::Error::from)
     }
 
+    fn proj2(
+        &mut self,
+        x: &Wire,
+        y: &Wire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<Self::Item, Self::Error> {
+        self.garbler.proj2(x, y, q, tt).map_err(Self::Error::from)
+    }
+
     fn output(&mut self, x: &Self::Item) -> Result<Option<u16>, Self::Error> {
         self.garbler.output(x).map_err(Self::Error::from)
     }
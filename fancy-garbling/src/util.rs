@@ -8,6 +8,7 @@
 //!
 //! Note: all number representations in this library are little-endian.
 
+use crate::errors::FancyError;
 #[cfg(feature = "nightly")]
 use core::arch::x86_64::*;
 use itertools::Itertools;
@@ -196,6 +197,20 @@ pub fn u128_from_bits(bs: &[u16]) -> u128 {
     x
 }
 
+/// Convert bytes into "bits" as u16, in order and each byte least significant bit first.
+pub fn bytes_to_bits(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .iter()
+        .flat_map(|&b| u128_to_bits(b as u128, 8))
+        .collect()
+}
+
+/// Convert "bits" as u16 into bytes, the inverse of `bytes_to_bits`. Assumes each "bit" is 0 or
+/// 1 and that their number is a multiple of 8.
+pub fn bytes_from_bits(bs: &[u16]) -> Vec<u8> {
+    bs.chunks(8).map(|b| u128_from_bits(b) as u8).collect()
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // primes & crt
 
@@ -249,6 +264,63 @@ pub fn crt_inv_factor(xs: &[u16], q: u128) -> u128 {
     crt_inv(xs, &factor(q))
 }
 
+/// Encode `x` as a fixed-point number with `frac_bits` fractional bits modulo
+/// `q`, rounding to the nearest representable value. Negative numbers are
+/// represented as `q - |x|`.
+///
+/// Panics if `q` is not less than `2^127`, or if the encoding of `x` does not
+/// lie within `q/2` of zero. See `try_to_fixed` for a fallible version.
+pub fn to_fixed(x: f64, frac_bits: usize, q: u128) -> u128 {
+    try_to_fixed(x, frac_bits, q).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Encode `x` as in `to_fixed`, returning an error if `q` is not less than
+/// `2^127` or the encoding of `x` does not lie within `q/2` of zero.
+pub fn try_to_fixed(x: f64, frac_bits: usize, q: u128) -> Result<u128, FancyError> {
+    check_fixed_modulus(q)?;
+    let y = (x * 2f64.powi(frac_bits as i32)).round();
+    // Out-of-range floats saturate when cast, so they fail the check too.
+    if !y.is_finite() || (y as i128).unsigned_abs() > q / 2 {
+        return Err(FancyError::InvalidArg(format!(
+            "{} with {} fractional bits does not fit mod {}",
+            x, frac_bits, q
+        )));
+    }
+    Ok((y as i128).rem_euclid(q as i128) as u128)
+}
+
+/// Decode a fixed-point number with `frac_bits` fractional bits modulo `q`,
+/// where values greater than `q/2` are negative.
+///
+/// Panics if `q` is not less than `2^127`. See `try_from_fixed` for a
+/// fallible version.
+pub fn from_fixed(x: u128, frac_bits: usize, q: u128) -> f64 {
+    try_from_fixed(x, frac_bits, q).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Decode a fixed-point number as in `from_fixed`, returning an error if `q`
+/// is not less than `2^127`.
+pub fn try_from_fixed(x: u128, frac_bits: usize, q: u128) -> Result<f64, FancyError> {
+    check_fixed_modulus(q)?;
+    let y = if x > q / 2 {
+        x as i128 - q as i128
+    } else {
+        x as i128
+    };
+    Ok(y as f64 / 2f64.powi(frac_bits as i32))
+}
+
+/// Check that signed values modulo `q` fit in an `i128`.
+fn check_fixed_modulus(q: u128) -> Result<(), FancyError> {
+    if q >= 1 << 127 {
+        return Err(FancyError::InvalidArg(format!(
+            "fixed-point modulus {} is too large",
+            q
+        )));
+    }
+    Ok(())
+}
+
 /// Generic algorithm to invert inp_a mod inp_b. As ref so as to support BigInts without
 /// copying.
 pub fn inv(inp_a: i128, inp_b: i128) -> i128 {
@@ -466,6 +538,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn fixed_conversion() {
+        let q = modulus_with_width(32);
+        assert_eq!(to_fixed(1.5, 4, q), 24);
+        assert_eq!(to_fixed(-1.5, 4, q), q - 24);
+        assert_eq!(from_fixed(q - 24, 4, q), -1.5);
+        for &x in [0.0, 0.25, -3.125, 1000.0, -0.0078125].iter() {
+            assert_eq!(from_fixed(to_fixed(x, 7, q), 7, q), x);
+        }
+    }
+
+    #[test]
+    fn try_fixed_conversion() {
+        let q = modulus_with_width(32);
+        assert_eq!(try_to_fixed(-1.5, 4, q).unwrap(), q - 24);
+        for &x in [f64::NAN, f64::INFINITY, 1e30].iter() {
+            assert!(try_to_fixed(x, 8, q).is_err());
+        }
+        assert!(try_to_fixed(1.0, 8, 1 << 127).is_err());
+        assert!(try_from_fixed(0, 8, u128::MAX).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "does not fit")]
+    fn fixed_overflow() {
+        to_fixed(1e30, 8, modulus_with_width(32));
+    }
+
+    #[test]
+    #[should_panic(expected = "too large")]
+    fn fixed_modulus_too_large() {
+        to_fixed(1.0, 8, 1 << 127);
+    }
+
     #[test]
     fn factoring() {
         let mut rng = thread_rng();
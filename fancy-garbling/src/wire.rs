@@ -431,7 +431,26 @@ impl Wire {
     ///
     /// Uses fixed-key AES.
     pub fn hashback(&self, tweak: Block, q: u16) -> Wire {
-        let block = self.hash(tweak);
+        Self::from_hash(self.hash(tweak), q)
+    }
+
+    /// Compute the hash of this wire together with `other`, as used by gates
+    /// with two inputs.
+    ///
+    /// The hash of `other` is nested inside the hash of this wire, so that the
+    /// hashes of different pairs of wires are not related by xor.
+    pub fn hash2(&self, other: &Wire, tweak: Block) -> Block {
+        AES_HASH.tccr_hash(tweak, self.as_block() ^ other.hash(tweak))
+    }
+
+    /// Compute the hash of this wire together with `other`, converting the
+    /// result back to a wire.
+    pub fn hashback2(&self, other: &Wire, tweak: Block, q: u16) -> Wire {
+        Self::from_hash(self.hash2(other, tweak), q)
+    }
+
+    /// Convert the hash `block` to a wire of modulus `q`.
+    fn from_hash(block: Block, q: u16) -> Wire {
         if q == 3 {
             // We have to convert `block` into a valid `Mod3` encoding. We do
             // this by computing the `Mod3` digits using `_unrank`, and then map
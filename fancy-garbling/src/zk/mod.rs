@@ -0,0 +1,391 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+//! Zero-knowledge proofs from privacy-free garbled circuits, following Jawurek,
+//! Kerschbaum, and Orlandi (JKO13).
+//!
+//! The `Prover` and `Verifier` both implement `Fancy`, so any computation
+//! written against the `Fancy` trait can be used as a statement. The prover's
+//! witness is input with `encode`, and the verifier calls `receive` for the
+//! corresponding inputs. The statement is that every wire passed to `output`
+//! is zero.
+//!
+//! The verifier garbles the computation and the prover obtains the labels of
+//! its witness by oblivious transfer. As the prover knows every value in the
+//! computation, the garbling is privacy-free: there is no point-and-permute,
+//! and a gate with an input of modulus `q` requires only `q - 1` ciphertexts
+//! (a single ciphertext for a binary AND gate). Once both parties call
+//! `finish`, the prover commits to its output wire-labels and the verifier
+//! opens its garbling by revealing the seed of its randomness. The prover
+//! checks the garbling, including the constant wire-labels and the verifier's
+//! side of the oblivious transfers, before opening its commitment.
+
+mod prover;
+mod verifier;
+
+pub use prover::{Prover, ProverWire};
+pub use verifier::Verifier;
+
+use crate::{util::tweak, HasModulus, Wire};
+use rand::{Rng, SeedableRng};
+use scuttlebutt::{
+    commitment::{Commitment, ShaCommitment},
+    AbstractChannel,
+    AesRng,
+    Block,
+    AES_HASH,
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+/// The verifier's randomness, which is derived from a seed that is revealed
+/// to the prover at the end of the proof.
+struct VerifierRng {
+    /// Randomness for the verifier's side of oblivious transfer.
+    ot: AesRng,
+    /// Randomness for the prover's input wire-labels.
+    labels: AesRng,
+    /// Randomness for constant wire-labels.
+    constants: AesRng,
+    /// Seed for the deltas.
+    delta_seed: Block,
+    deltas: HashMap<u16, Wire>,
+}
+
+impl VerifierRng {
+    fn new(seed: Block) -> Self {
+        let mut rng = AesRng::from_seed(seed);
+        VerifierRng {
+            ot: AesRng::from_seed(rng.gen()),
+            labels: AesRng::from_seed(rng.gen()),
+            constants: AesRng::from_seed(rng.gen()),
+            delta_seed: rng.gen(),
+            deltas: HashMap::new(),
+        }
+    }
+
+    /// The delta for modulus `q`.
+    fn delta(&mut self, q: u16) -> Wire {
+        let seed = self.delta_seed;
+        self.deltas
+            .entry(q)
+            .or_insert_with(|| {
+                let seed = AES_HASH.tccr_hash(Block::from(q as u128), seed);
+                Wire::rand_delta(&mut AesRng::from_seed(seed), q)
+            })
+            .clone()
+    }
+
+    /// Generate wire-labels for prover inputs of moduli `qs`, returning the
+    /// zero wires and the pairs of blocks to transfer, one for each bit of
+    /// each input.
+    fn input_labels(&mut self, qs: &[u16]) -> (Vec<Wire>, Vec<(Block, Block)>) {
+        let mut wires = Vec::with_capacity(qs.len());
+        let mut inputs = Vec::new();
+        for q in qs.iter() {
+            let delta = self.delta(*q);
+            let mut wire = Wire::zero(*q);
+            for i in 0..input_len(*q) {
+                let zero = Wire::rand(&mut self.labels, *q);
+                let one = zero.plus(&delta);
+                wire.plus_eq(&zero.cmul(1 << i));
+                inputs.push((zero.as_block(), one.as_block()));
+            }
+            wires.push(wire);
+        }
+        (wires, inputs)
+    }
+}
+
+/// The number of bits used to transfer an input of modulus `q`.
+fn input_len(q: u16) -> usize {
+    f32::from(q).log(2.0).ceil() as usize
+}
+
+/// Garble a multiplication gate with `A.modulus() >= B.modulus()`, where the
+/// prover knows the value of `B`. Returns the output zero-wire and the
+/// ciphertexts, one for each nonzero value of `B`.
+fn garble_mul(A: &Wire, B: &Wire, Db: &Wire, gate_num: usize) -> (Wire, Vec<Block>) {
+    let q = A.modulus();
+    let t = tweak(gate_num);
+    let X = B.hashback(t, q);
+    let mut Bb = B.clone();
+    let gate = (1..B.modulus())
+        .map(|b| {
+            Bb.plus_eq(Db);
+            Bb.hashback(t, q).minus(&X).plus(&A.cmul(b)).as_block()
+        })
+        .collect();
+    (X, gate)
+}
+
+/// Evaluate a multiplication gate, where `b` is the value of `B`.
+fn eval_mul(A: &Wire, B: &Wire, b: u16, gate: &[Block], gate_num: usize) -> Wire {
+    let q = A.modulus();
+    let W = B.hashback(tweak(gate_num), q);
+    if b == 0 {
+        W
+    } else {
+        let ct = Wire::from_block(gate[b as usize - 1], q);
+        W.minus(&ct).plus(&A.cmul(b))
+    }
+}
+
+/// Garble a projection gate. Returns the output zero-wire and the ciphertexts,
+/// one for each nonzero value of `A`.
+fn garble_proj(
+    A: &Wire,
+    q_out: u16,
+    tt: &[u16],
+    Din: &Wire,
+    Dout: &Wire,
+    gate_num: usize,
+) -> (Wire, Vec<Block>) {
+    let t = tweak(gate_num);
+    let C = A.hashback(t, q_out).minus(&Dout.cmul(tt[0]));
+    let mut Ax = A.clone();
+    let gate = (1..A.modulus())
+        .map(|x| {
+            Ax.plus_eq(Din);
+            Ax.hashback(t, q_out)
+                .minus(&C)
+                .minus(&Dout.cmul(tt[x as usize]))
+                .as_block()
+        })
+        .collect();
+    (C, gate)
+}
+
+/// Evaluate a projection gate, where `x` is the value of `A`.
+fn eval_proj(A: &Wire, x: u16, q_out: u16, gate: &[Block], gate_num: usize) -> Wire {
+    let W = A.hashback(tweak(gate_num), q_out);
+    if x == 0 {
+        W
+    } else {
+        W.minus(&Wire::from_block(gate[x as usize - 1], q_out))
+    }
+}
+
+/// Commit to a sequence of blocks.
+fn commit(seed: [u8; 32], xs: &[Block]) -> [u8; 32] {
+    let mut com = ShaCommitment::new(seed);
+    for x in xs.iter() {
+        com.input(x.as_ref());
+    }
+    com.finish()
+}
+
+/// A channel which records all bytes sent and received, so that the prover
+/// can later check the verifier's side of oblivious transfer.
+struct RecordingChannel<C> {
+    channel: C,
+    sent: Rc<RefCell<Vec<u8>>>,
+    received: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<C: AbstractChannel> RecordingChannel<C> {
+    fn new(channel: C) -> Self {
+        RecordingChannel {
+            channel,
+            sent: Rc::new(RefCell::new(Vec::new())),
+            received: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+}
+
+impl<C: AbstractChannel> AbstractChannel for RecordingChannel<C> {
+    fn read_bytes(&mut self, bytes: &mut [u8]) -> std::io::Result<()> {
+        self.channel.read_bytes(bytes)?;
+        self.received.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.channel.write_bytes(bytes)?;
+        self.sent.borrow_mut().extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.channel.flush()
+    }
+
+    fn clone(&self) -> Self {
+        RecordingChannel {
+            channel: self.channel.clone(),
+            sent: self.sent.clone(),
+            received: self.received.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        errors::ZkError,
+        util,
+        BinaryBundle,
+        BinaryGadgets,
+        CrtBundle,
+        CrtGadgets,
+        Fancy,
+        FancyInput,
+    };
+    use ocelot::ot::{ChouOrlandiReceiver, ChouOrlandiSender};
+    use scuttlebutt::{unix_channel_pair, UnixChannel};
+
+    type P = Prover<UnixChannel, AesRng, ChouOrlandiSender, ChouOrlandiReceiver>;
+    type V = Verifier<UnixChannel, ChouOrlandiSender>;
+
+    /// Prove that `p * q = n` over `nbits`-bit integers.
+    fn factors<F: Fancy>(
+        f: &mut F,
+        p: &BinaryBundle<F::Item>,
+        q: &BinaryBundle<F::Item>,
+        n: u128,
+    ) -> Result<(), F::Error> {
+        let z = f.bin_multiplication_lower_half(p, q)?;
+        let n = f.bin_constant_bundle(n, z.size())?;
+        let z = f.bin_xor(&z, &n)?;
+        f.bin_output(&z)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_binary() {
+        let nbits = 16;
+        let (p, q) = (251, 241);
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut prover = P::new(sender, AesRng::new()).unwrap();
+            let p = prover.bin_encode(p, nbits).unwrap();
+            let q = prover.bin_encode(q, nbits).unwrap();
+            factors(&mut prover, &p, &q, 251 * 241).unwrap();
+            prover.finish().unwrap();
+        });
+        let mut verifier = V::new(receiver, AesRng::new()).unwrap();
+        let p = verifier.bin_receive(nbits).unwrap();
+        let q = verifier.bin_receive(nbits).unwrap();
+        factors(&mut verifier, &p, &q, 251 * 241).unwrap();
+        verifier.finish().unwrap();
+        handle.join().unwrap();
+    }
+
+    /// Prove that `relu(x) * relu(x) = y` modulo `x.composite_modulus()`.
+    fn square_relu<F: Fancy>(
+        f: &mut F,
+        x: &CrtBundle<F::Item>,
+        y: &CrtBundle<F::Item>,
+    ) -> Result<(), F::Error> {
+        let z = f.crt_relu(x, "100%", None)?;
+        let z = f.crt_mul(&z, &z)?;
+        let z = f.crt_sub(&z, y)?;
+        f.crt_output(&z)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_crt() {
+        let q = util::modulus_with_width(10);
+        for &x in [17, q - 17].iter() {
+            let y = if x < q / 2 { x * x % q } else { 0 };
+            let (sender, receiver) = unix_channel_pair();
+            let handle = std::thread::spawn(move || {
+                let mut prover = P::new(sender, AesRng::new()).unwrap();
+                let x = prover.crt_encode(x, q).unwrap();
+                let y = prover.crt_encode(y, q).unwrap();
+                square_relu(&mut prover, &x, &y).unwrap();
+                prover.finish().unwrap();
+            });
+            let mut verifier = V::new(receiver, AesRng::new()).unwrap();
+            let x = verifier.crt_receive(q).unwrap();
+            let y = verifier.crt_receive(q).unwrap();
+            square_relu(&mut verifier, &x, &y).unwrap();
+            verifier.finish().unwrap();
+            handle.join().unwrap();
+        }
+    }
+
+    /// Compute `x * y - c` for `x` mod 7 and `y` mod 3, with a projection
+    /// between the two.
+    fn mixed<F: Fancy>(f: &mut F, x: &F::Item, y: &F::Item, c: u16) -> Result<(), F::Error> {
+        let y = f.proj(y, 7, Some(vec![0, 2, 4]))?;
+        let z = f.mul(x, &y)?;
+        let c = f.constant(c, 7)?;
+        let z = f.sub(&z, &c)?;
+        f.output(&z)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_mixed_moduli() {
+        for x in 0..7 {
+            for y in 0..3 {
+                let c = x * 2 * y % 7;
+                let (sender, receiver) = unix_channel_pair();
+                let handle = std::thread::spawn(move || {
+                    let mut prover = P::new(sender, AesRng::new()).unwrap();
+                    let x = prover.encode(x, 7).unwrap();
+                    let y = prover.encode(y, 3).unwrap();
+                    mixed(&mut prover, &x, &y, c).unwrap();
+                    prover.finish().unwrap();
+                });
+                let mut verifier = V::new(receiver, AesRng::new()).unwrap();
+                let x = verifier.receive(7).unwrap();
+                let y = verifier.receive(3).unwrap();
+                mixed(&mut verifier, &x, &y, c).unwrap();
+                verifier.finish().unwrap();
+                handle.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_cheating_constant() {
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut prover = P::new(sender, AesRng::new()).unwrap();
+            let x = prover.encode(3, 7).unwrap();
+            let c = prover.constant(3, 7).unwrap();
+            let z = prover.sub(&x, &c).unwrap();
+            prover.output(&z).unwrap();
+            match prover.finish() {
+                Err(ZkError::CheatingDetected) => (),
+                r => panic!("unexpected result: {:?}", r),
+            }
+        });
+        let mut verifier = V::new(receiver, AesRng::new()).unwrap();
+        let x = verifier.receive(7).unwrap();
+        // The verifier sends the label of 4 where both parties expect 3.
+        let c = verifier.constant(4, 7).unwrap();
+        let z = verifier.sub(&x, &c).unwrap();
+        verifier.output(&z).unwrap();
+        assert!(verifier.finish().is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_witness() {
+        let nbits = 16;
+        let (sender, receiver) = unix_channel_pair();
+        let handle = std::thread::spawn(move || {
+            let mut prover = P::new(sender, AesRng::new()).unwrap();
+            let p = prover.bin_encode(251, nbits).unwrap();
+            let q = prover.bin_encode(239, nbits).unwrap();
+            factors(&mut prover, &p, &q, 251 * 241).unwrap();
+            match prover.finish() {
+                Err(ZkError::InvalidWitness) => (),
+                r => panic!("unexpected result: {:?}", r),
+            }
+        });
+        let mut verifier = V::new(receiver, AesRng::new()).unwrap();
+        let p = verifier.bin_receive(nbits).unwrap();
+        let q = verifier.bin_receive(nbits).unwrap();
+        factors(&mut verifier, &p, &q, 251 * 241).unwrap();
+        assert!(verifier.finish().is_err());
+        handle.join().unwrap();
+    }
+}
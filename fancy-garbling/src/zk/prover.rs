@@ -0,0 +1,365 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use super::{
+    commit,
+    eval_mul,
+    eval_proj,
+    garble_mul,
+    garble_proj,
+    input_len,
+    RecordingChannel,
+    VerifierRng,
+};
+use crate::{
+    errors::{FancyError, ZkError},
+    Fancy,
+    FancyInput,
+    HasModulus,
+    Wire,
+};
+use ocelot::ot::{Receiver as OtReceiver, Sender as OtSender};
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Block, Channel, Malicious};
+use std::{io::Cursor, marker::PhantomData};
+
+/// A wire held by the prover, consisting of a wire-label and its value.
+#[derive(Clone, Debug)]
+pub struct ProverWire {
+    label: Wire,
+    value: u16,
+}
+
+impl ProverWire {
+    /// The value of the wire.
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+}
+
+impl HasModulus for ProverWire {
+    fn modulus(&self) -> u16 {
+        self.label.modulus()
+    }
+}
+
+/// A garbled gate received from the verifier, kept around until the verifier
+/// opens its garbling.
+enum Gate {
+    Mul {
+        a: ProverWire,
+        b: ProverWire,
+        gate_num: usize,
+        gate: Vec<Block>,
+    },
+    Proj {
+        x: ProverWire,
+        q: u16,
+        tt: Vec<u16>,
+        gate_num: usize,
+        gate: Vec<Block>,
+    },
+}
+
+/// Zero-knowledge prover.
+///
+/// `OTS` is the verifier's oblivious transfer sender, which the prover uses to
+/// check the verifier's messages once its randomness is revealed.
+pub struct Prover<C, RNG, OTS, OTR> {
+    channel: C,
+    ot_channel: RecordingChannel<C>,
+    ot: OTR,
+    rng: RNG,
+    current_gate: usize,
+    inputs: Vec<Vec<u16>>,
+    constants: Vec<ProverWire>,
+    gates: Vec<Gate>,
+    outputs: Vec<ProverWire>,
+    _ots: PhantomData<OTS>,
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: OtSender<Msg = Block> + Malicious,
+        OTR: OtReceiver<Msg = Block> + Malicious,
+    > Prover<C, RNG, OTS, OTR>
+{
+    /// Make a new `Prover`.
+    pub fn new(channel: C, mut rng: RNG) -> Result<Self, ZkError> {
+        let mut ot_channel = RecordingChannel::new(channel.clone());
+        let ot = OTR::init(&mut ot_channel, &mut rng)?;
+        Ok(Prover {
+            channel,
+            ot_channel,
+            ot,
+            rng,
+            current_gate: 0,
+            inputs: Vec::new(),
+            constants: Vec::new(),
+            gates: Vec::new(),
+            outputs: Vec::new(),
+            _ots: PhantomData,
+        })
+    }
+
+    /// Get a reference to the internal channel.
+    pub fn get_channel(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    /// Complete the proof. Fails with `ZkError::InvalidWitness` if some output
+    /// is nonzero, and with `ZkError::CheatingDetected` if the verifier did
+    /// not garble the computation correctly.
+    pub fn finish(mut self) -> Result<(), ZkError> {
+        if self.outputs.iter().any(|x| x.value != 0) {
+            return Err(ZkError::InvalidWitness);
+        }
+        let labels = self
+            .outputs
+            .iter()
+            .map(|x| x.label.as_block())
+            .collect::<Vec<Block>>();
+        let seed = self.rng.gen::<[u8; 32]>();
+        self.channel.write_bytes(&commit(seed, &labels))?;
+        self.channel.flush()?;
+
+        let verifier_seed = self.channel.read_block()?;
+        self.check(verifier_seed)?;
+
+        self.channel.write_bytes(&seed)?;
+        for label in labels.iter() {
+            self.channel.write_block(label)?;
+        }
+        self.channel.flush()?;
+        Ok(())
+    }
+
+    /// Check that the verifier's messages are consistent with the randomness
+    /// derived from `seed`.
+    fn check(&self, seed: Block) -> Result<(), ZkError> {
+        let mut rng = VerifierRng::new(seed);
+
+        // Replay the verifier's side of oblivious transfer on our messages.
+        let sent = self.ot_channel.sent.borrow().clone();
+        let mut channel = Channel::new(Cursor::new(sent), Vec::new());
+        if replay::<OTS, _>(&mut channel, &mut rng, &self.inputs).is_err() {
+            return Err(ZkError::CheatingDetected);
+        }
+        if *channel.writer().borrow() != *self.ot_channel.received.borrow() {
+            return Err(ZkError::CheatingDetected);
+        }
+
+        // Regenerate the constant wire-labels in the order they were sent.
+        for x in self.constants.iter() {
+            let q = x.modulus();
+            let zero = Wire::rand(&mut rng.constants, q);
+            if x.label != zero.plus(&rng.delta(q).cmul(x.value)) {
+                return Err(ZkError::CheatingDetected);
+            }
+        }
+
+        // Regenerate each garbled gate from the zero-wires of its inputs.
+        for gate in self.gates.iter() {
+            let (gate, expected) = match gate {
+                Gate::Mul {
+                    a,
+                    b,
+                    gate_num,
+                    gate,
+                } => {
+                    let A = zero_wire(&mut rng, a);
+                    let B = zero_wire(&mut rng, b);
+                    let Db = rng.delta(b.modulus());
+                    (gate, garble_mul(&A, &B, &Db, *gate_num).1)
+                }
+                Gate::Proj {
+                    x,
+                    q,
+                    tt,
+                    gate_num,
+                    gate,
+                } => {
+                    let A = zero_wire(&mut rng, x);
+                    let Din = rng.delta(x.modulus());
+                    let Dout = rng.delta(*q);
+                    (gate, garble_proj(&A, *q, tt, &Din, &Dout, *gate_num).1)
+                }
+            };
+            if *gate != expected {
+                return Err(ZkError::CheatingDetected);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The zero-wire of `x`, given the verifier's randomness.
+fn zero_wire(rng: &mut VerifierRng, x: &ProverWire) -> Wire {
+    x.label.minus(&rng.delta(x.modulus()).cmul(x.value))
+}
+
+/// Run the verifier's side of oblivious transfer for the given inputs.
+fn replay<OTS: OtSender<Msg = Block>, C: AbstractChannel>(
+    channel: &mut C,
+    rng: &mut VerifierRng,
+    inputs: &[Vec<u16>],
+) -> Result<(), ocelot::Error> {
+    let mut ot = OTS::init(channel, &mut rng.ot)?;
+    for qs in inputs.iter() {
+        let (_, inputs) = rng.input_labels(qs);
+        ot.send(channel, &inputs, &mut rng.ot)?;
+    }
+    Ok(())
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: OtSender<Msg = Block> + Malicious,
+        OTR: OtReceiver<Msg = Block> + Malicious,
+    > FancyInput for Prover<C, RNG, OTS, OTR>
+{
+    type Item = ProverWire;
+    type Error = ZkError;
+
+    /// Input the prover's witness, obtaining its wire-labels by oblivious
+    /// transfer.
+    fn encode_many(&mut self, vals: &[u16], moduli: &[u16]) -> Result<Vec<ProverWire>, ZkError> {
+        let mut bs = Vec::new();
+        for (x, q) in vals.iter().zip(moduli.iter()) {
+            if x >= q {
+                return Err(ZkError::from(FancyError::InvalidArg(format!(
+                    "input value {} is not less than its modulus {}",
+                    x, q
+                ))));
+            }
+            bs.extend((0..input_len(*q)).map(|i| x & (1 << i) != 0));
+        }
+        let blocks = self.ot.receive(&mut self.ot_channel, &bs, &mut self.rng)?;
+        self.inputs.push(moduli.to_vec());
+        let mut blocks = blocks.into_iter();
+        Ok(vals
+            .iter()
+            .zip(moduli.iter())
+            .map(|(x, q)| {
+                let label = (0..input_len(*q)).fold(Wire::zero(*q), |acc, i| {
+                    let w = Wire::from_block(blocks.next().unwrap(), *q);
+                    acc.plus(&w.cmul(1 << i))
+                });
+                ProverWire { label, value: *x }
+            })
+            .collect())
+    }
+
+    /// The verifier has no inputs.
+    fn receive_many(&mut self, _: &[u16]) -> Result<Vec<ProverWire>, ZkError> {
+        Err(ZkError::from(FancyError::InvalidArg(
+            "the verifier has no inputs".to_string(),
+        )))
+    }
+}
+
+impl<
+        C: AbstractChannel,
+        RNG: CryptoRng + Rng,
+        OTS: OtSender<Msg = Block> + Malicious,
+        OTR: OtReceiver<Msg = Block> + Malicious,
+    > Fancy for Prover<C, RNG, OTS, OTR>
+{
+    type Item = ProverWire;
+    type Error = ZkError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<ProverWire, ZkError> {
+        let label = Wire::from_block(self.channel.read_block()?, q);
+        let wire = ProverWire { label, value: x };
+        self.constants.push(wire.clone());
+        Ok(wire)
+    }
+
+    fn add(&mut self, x: &ProverWire, y: &ProverWire) -> Result<ProverWire, ZkError> {
+        let q = x.modulus();
+        if q != y.modulus() {
+            return Err(ZkError::from(FancyError::UnequalModuli));
+        }
+        Ok(ProverWire {
+            label: x.label.plus(&y.label),
+            value: (x.value + y.value) % q,
+        })
+    }
+
+    fn sub(&mut self, x: &ProverWire, y: &ProverWire) -> Result<ProverWire, ZkError> {
+        let q = x.modulus();
+        if q != y.modulus() {
+            return Err(ZkError::from(FancyError::UnequalModuli));
+        }
+        Ok(ProverWire {
+            label: x.label.minus(&y.label),
+            value: (x.value + q - y.value) % q,
+        })
+    }
+
+    fn cmul(&mut self, x: &ProverWire, c: u16) -> Result<ProverWire, ZkError> {
+        let q = x.modulus();
+        Ok(ProverWire {
+            label: x.label.cmul(c),
+            value: ((x.value as u32 * c as u32) % q as u32) as u16,
+        })
+    }
+
+    fn mul(&mut self, x: &ProverWire, y: &ProverWire) -> Result<ProverWire, ZkError> {
+        if x.modulus() < y.modulus() {
+            return self.mul(y, x);
+        }
+        let q = x.modulus();
+        let gate_num = self.current_gate();
+        let gate = self.channel.read_blocks(y.modulus() as usize - 1)?;
+        let label = eval_mul(&x.label, &y.label, y.value, &gate, gate_num);
+        let value = ((x.value as u32 * y.value as u32) % q as u32) as u16;
+        self.gates.push(Gate::Mul {
+            a: x.clone(),
+            b: y.clone(),
+            gate_num,
+            gate,
+        });
+        Ok(ProverWire { label, value })
+    }
+
+    fn proj(
+        &mut self,
+        x: &ProverWire,
+        q: u16,
+        tt: Option<Vec<u16>>,
+    ) -> Result<ProverWire, ZkError> {
+        let tt = tt.ok_or(FancyError::NoTruthTable)?;
+        if tt.len() < x.modulus() as usize || tt.iter().any(|&y| y >= q) {
+            return Err(ZkError::from(FancyError::InvalidTruthTable));
+        }
+        let gate_num = self.current_gate();
+        let gate = self.channel.read_blocks(x.modulus() as usize - 1)?;
+        let label = eval_proj(&x.label, x.value, q, &gate, gate_num);
+        let value = tt[x.value as usize];
+        self.gates.push(Gate::Proj {
+            x: x.clone(),
+            q,
+            tt,
+            gate_num,
+            gate,
+        });
+        Ok(ProverWire { label, value })
+    }
+
+    fn output(&mut self, x: &ProverWire) -> Result<Option<u16>, ZkError> {
+        self.outputs.push(x.clone());
+        Ok(Some(x.value))
+    }
+}
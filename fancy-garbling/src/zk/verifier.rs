@@ -0,0 +1,162 @@
+// -*- mode: rust; -*-
+//
+// This file is part of `fancy-garbling`.
+// Copyright © 2019 Galois, Inc.
+// See LICENSE for licensing information.
+
+use super::{commit, garble_mul, garble_proj, VerifierRng};
+use crate::{
+    errors::{FancyError, ZkError},
+    Fancy,
+    FancyInput,
+    HasModulus,
+    Wire,
+};
+use ocelot::ot::Sender as OtSender;
+use rand::{CryptoRng, Rng};
+use scuttlebutt::{AbstractChannel, Block, Malicious};
+
+/// Zero-knowledge verifier.
+pub struct Verifier<C, OT> {
+    channel: C,
+    ot: OT,
+    seed: Block,
+    rng: VerifierRng,
+    current_gate: usize,
+    outputs: Vec<Wire>,
+}
+
+impl<C: AbstractChannel, OT: OtSender<Msg = Block> + Malicious> Verifier<C, OT> {
+    /// Make a new `Verifier`.
+    pub fn new<RNG: CryptoRng + Rng>(mut channel: C, mut rng: RNG) -> Result<Self, ZkError> {
+        let seed = rng.gen::<Block>();
+        let mut rng = VerifierRng::new(seed);
+        let ot = OT::init(&mut channel, &mut rng.ot)?;
+        Ok(Verifier {
+            channel,
+            ot,
+            seed,
+            rng,
+            current_gate: 0,
+            outputs: Vec::new(),
+        })
+    }
+
+    /// Get a reference to the internal channel.
+    pub fn get_channel(&mut self) -> &mut C {
+        &mut self.channel
+    }
+
+    fn current_gate(&mut self) -> usize {
+        let current = self.current_gate;
+        self.current_gate += 1;
+        current
+    }
+
+    /// Complete the proof, opening the garbling to the prover. Fails with
+    /// `ZkError::InvalidProof` if the prover does not convince us that every
+    /// output is zero.
+    pub fn finish(mut self) -> Result<(), ZkError> {
+        self.channel.flush()?;
+        let com = self.channel.read_vec(32)?;
+        self.channel.write_block(&self.seed)?;
+        self.channel.flush()?;
+
+        let mut seed = [0u8; 32];
+        self.channel.read_bytes(&mut seed)?;
+        let labels = self.channel.read_blocks(self.outputs.len())?;
+        if commit(seed, &labels)[..] != com[..] {
+            return Err(ZkError::InvalidProof);
+        }
+        for (label, x) in labels.iter().zip(self.outputs.iter()) {
+            if *label != x.as_block() {
+                return Err(ZkError::InvalidProof);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<C: AbstractChannel, OT: OtSender<Msg = Block> + Malicious> FancyInput for Verifier<C, OT> {
+    type Item = Wire;
+    type Error = ZkError;
+
+    /// The verifier has no inputs.
+    fn encode_many(&mut self, _: &[u16], _: &[u16]) -> Result<Vec<Wire>, ZkError> {
+        Err(ZkError::from(FancyError::InvalidArg(
+            "the verifier has no inputs".to_string(),
+        )))
+    }
+
+    /// Receive the prover's witness, sending its wire-labels by oblivious
+    /// transfer.
+    fn receive_many(&mut self, moduli: &[u16]) -> Result<Vec<Wire>, ZkError> {
+        self.channel.flush()?;
+        let (wires, inputs) = self.rng.input_labels(moduli);
+        self.ot.send(&mut self.channel, &inputs, &mut self.rng.ot)?;
+        Ok(wires)
+    }
+}
+
+impl<C: AbstractChannel, OT: OtSender<Msg = Block> + Malicious> Fancy for Verifier<C, OT> {
+    type Item = Wire;
+    type Error = ZkError;
+
+    fn constant(&mut self, x: u16, q: u16) -> Result<Wire, ZkError> {
+        let zero = Wire::rand(&mut self.rng.constants, q);
+        let wire = zero.plus(&self.rng.delta(q).cmul(x));
+        self.channel.write_block(&wire.as_block())?;
+        Ok(zero)
+    }
+
+    fn add(&mut self, x: &Wire, y: &Wire) -> Result<Wire, ZkError> {
+        if x.modulus() != y.modulus() {
+            return Err(ZkError::from(FancyError::UnequalModuli));
+        }
+        Ok(x.plus(y))
+    }
+
+    fn sub(&mut self, x: &Wire, y: &Wire) -> Result<Wire, ZkError> {
+        if x.modulus() != y.modulus() {
+            return Err(ZkError::from(FancyError::UnequalModuli));
+        }
+        Ok(x.minus(y))
+    }
+
+    fn cmul(&mut self, x: &Wire, c: u16) -> Result<Wire, ZkError> {
+        Ok(x.cmul(c))
+    }
+
+    fn mul(&mut self, x: &Wire, y: &Wire) -> Result<Wire, ZkError> {
+        if x.modulus() < y.modulus() {
+            return self.mul(y, x);
+        }
+        let gate_num = self.current_gate();
+        let Db = self.rng.delta(y.modulus());
+        let (z, gate) = garble_mul(x, y, &Db, gate_num);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(z)
+    }
+
+    fn proj(&mut self, x: &Wire, q: u16, tt: Option<Vec<u16>>) -> Result<Wire, ZkError> {
+        let tt = tt.ok_or(FancyError::NoTruthTable)?;
+        if tt.len() < x.modulus() as usize || tt.iter().any(|&y| y >= q) {
+            return Err(ZkError::from(FancyError::InvalidTruthTable));
+        }
+        let gate_num = self.current_gate();
+        let Din = self.rng.delta(x.modulus());
+        let Dout = self.rng.delta(q);
+        let (z, gate) = garble_proj(x, q, &tt, &Din, &Dout, gate_num);
+        for block in gate.iter() {
+            self.channel.write_block(block)?;
+        }
+        Ok(z)
+    }
+
+    fn output(&mut self, x: &Wire) -> Result<Option<u16>, ZkError> {
+        self.outputs.push(x.clone());
+        Ok(None)
+    }
+}